@@ -0,0 +1,54 @@
+// Lets the game keep its settings in the browser's localStorage; see
+// src/local_storage.rs.
+
+var local_storage_memory;
+var local_storage_value = null;
+
+function local_storage_string(ptr, len) {
+    let bytes = new Uint8Array(local_storage_memory.buffer, ptr, len);
+    return new TextDecoder().decode(bytes);
+}
+
+local_storage_set_mem = function (wasm_memory, _wasm_exports) {
+    local_storage_memory = wasm_memory;
+}
+
+local_storage_register_js_plugin = function (importObject) {
+    importObject.env.local_storage_prepare = function (key_ptr, key_len) {
+        let value = null;
+        try {
+            value = window.localStorage.getItem(local_storage_string(key_ptr, key_len));
+        } catch (e) {
+            // Storage can be disabled, e.g. for local files or private windows
+        }
+
+        if (value === null) {
+            local_storage_value = null;
+            return -1;
+        }
+
+        local_storage_value = new TextEncoder().encode(value);
+        return local_storage_value.length;
+    }
+    importObject.env.local_storage_take = function (value_ptr, value_len) {
+        let bytes = new Uint8Array(local_storage_memory.buffer, value_ptr, value_len);
+        bytes.set(local_storage_value.subarray(0, value_len));
+        local_storage_value = null;
+    }
+    importObject.env.local_storage_set = function (key_ptr, key_len, value_ptr, value_len) {
+        let key = local_storage_string(key_ptr, key_len);
+        let value = local_storage_string(value_ptr, value_len);
+        try {
+            window.localStorage.setItem(key, value);
+        } catch (e) {
+            console.warn("Could not save " + key + " to localStorage: " + e);
+        }
+    }
+}
+
+miniquad_add_plugin({
+    register_plugin: local_storage_register_js_plugin,
+    on_init: local_storage_set_mem,
+    name: "local_storage",
+    version: "0.1.0"
+});
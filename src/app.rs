@@ -1,25 +1,36 @@
 use std::collections::VecDeque;
 
+use macroquad::prelude::get_time;
+
 use crate::{
     client::{connect, RemoteConnection},
     draw::{draw_game, Camera, DrawSettings},
-    game_state::objects::ObjectType,
-    game_state::state::GameState,
+    game_state::objects::{trace_port, ObjectType},
+    game_state::rocks::RockGrid,
+    game_state::state::{GameState, UpdateSettings},
+    game_state::water::WallMaterial,
     game_state::wires::WireColor,
     game_state::{
         state::SubmarineTemplate,
-        update::{update_game, Command, UpdateEvent},
+        update::{update_game, Command, SubmarineTimings, SubmarineUpdatedEvent, UpdateEvent},
     },
-    input::{handle_keyboard_input, handle_pointer_input, Dragging},
+    input::{handle_keyboard_input, handle_pointer_input, pan_direction, Dragging, LayerHotkeys},
     resources::{update_resources_from_events, MutableResources, MutableSubResources, Resources},
-    saveload::{load_rocks_from_png, load_template_from_data, save_to_file_data},
+    saveload::{
+        load_rocks_from_png, load_template_from_data, load_window_layout_from_file,
+        save_to_file_data, Prefab,
+    },
     ui::{draw_ui, UiState},
-    SubmarineFileData,
+    warn, SubmarineFileData,
 };
 
 #[cfg(not(target_arch = "wasm32"))]
 use crate::server::{serve, LocalClient, Server};
 
+/// The game falls behind rather than run more than this many updates to catch
+/// up with, which is half a second at normal speed.
+const MAX_CATCH_UP_UPDATES: u32 = 30;
+
 pub struct CyberSubApp {
     pub timings: Timings,
     ui_state: UiState,
@@ -27,6 +38,8 @@ pub struct CyberSubApp {
     game_settings: GameSettings,
     commands: Vec<Command>,
     update_events: Vec<UpdateEvent>,
+    /// Why the player's own commands were rejected, to be shown to them.
+    rejections: Vec<String>,
     update_source: UpdateSource,
     resources: Resources,
     mutable_resources: MutableResources,
@@ -40,12 +53,29 @@ pub(crate) struct GameSettings {
     pub current_submarine: usize,
     pub current_tool: Tool,
     pub quit_game: bool,
+    /// Start over with the world and submarines the game started with, on the
+    /// next update.
+    pub reset_world: bool,
     pub dragging: Option<Dragging>,
+    pub inspected_object: Option<InspectedObject>,
+    /// Objects picked with shift+click in the inspect tool, for bus-connecting.
+    pub selection: Option<ObjectSelection>,
+    /// Wire cells to flash on a submarine, like those of a network found by
+    /// the wiring check.
+    pub flashing_wires: Option<(usize, Vec<(usize, usize)>)>,
+    /// Every object of a type highlighted at once, picked with shift+click in
+    /// the Objects menu.
+    pub highlighted_type: Option<HighlightedType>,
     pub highlighting_settings: bool,
     pub last_update: Option<f64>,
     pub last_draw: Option<f64>,
     pub animation_ticks: u32,
     pub submarine_templates: Vec<(String, SubmarineTemplate)>,
+    pub prefabs: Vec<(String, Prefab)>,
+    /// The world as first loaded, and the templates of the submarines added
+    /// at the start; what resetting the world goes back to.
+    pub initial_rocks: RockGrid,
+    pub initial_submarines: Vec<usize>,
 }
 
 pub(crate) struct NetworkSettings {
@@ -60,6 +90,8 @@ pub(crate) struct NetworkSettings {
     pub network_status: String,
     pub network_error: Option<String>,
     pub download_progress: Option<u8>,
+    /// Tell the player when a teammate does something out of view.
+    pub notify_teammate_actions: bool,
 }
 
 #[derive(Clone, PartialEq, Eq)]
@@ -70,15 +102,36 @@ pub(crate) enum Tool {
     },
     EditWalls {
         add: bool,
+        material: WallMaterial,
     },
     EditWires {
         color: WireColor,
+        /// Route wires around objects with an L or Z shape, instead of laying
+        /// a single straight line.
+        auto_route: bool,
+    },
+    Measure,
+    Inspect,
+    PaintBackground {
+        color: [u8; 4],
+        brush_size: u8,
     },
     PlaceObject(PlacingObject),
     PlaceSubmarine {
         template_id: usize,
         position: Option<(usize, usize)>,
     },
+    PlacePrefab {
+        prefab_id: usize,
+        submarine: usize,
+        position: Option<(usize, usize)>,
+    },
+    /// Click two ports to have a wire routed between them.
+    ConnectPorts {
+        from: Option<PickedPort>,
+        /// Why the last two clicked ports were not connected.
+        warning: Option<String>,
+    },
 }
 
 #[derive(Default)]
@@ -91,6 +144,7 @@ pub struct Timings {
     pub frame_update: u32,
     pub fps: u32,
     pub fps_average: u32,
+    pub submarines: Vec<SubmarineTimings>,
     pub frame_time: u32,
     pub fps_history: VecDeque<(f64, f64)>,
     pub fps_average_history: VecDeque<(f64, f64)>,
@@ -101,6 +155,42 @@ pub(crate) struct PlacingObject {
     pub submarine: usize,
     pub position: Option<(usize, usize)>,
     pub object_type: ObjectType,
+    /// Whether the position was snapped so that a port lands on a wire end.
+    pub snapped: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PickedPort {
+    pub submarine: usize,
+    pub object: usize,
+    /// Index into the object's `wire_ports()`.
+    pub port: usize,
+}
+
+pub(crate) struct ObjectSelection {
+    pub submarine: usize,
+    /// Object indexes, in the order they were selected.
+    pub objects: Vec<usize>,
+}
+
+pub(crate) struct HighlightedType {
+    pub submarine: usize,
+    pub object_type: ObjectType,
+    /// The tool in use when the highlight was made; it is cleared as soon as
+    /// another one is picked, or on the next click in the world.
+    pub tool: Tool,
+}
+
+pub(crate) struct InspectedObject {
+    pub submarine: usize,
+    pub object: usize,
+    /// Index into the object's `wire_ports()` whose wires are highlighted.
+    pub traced_port: Option<usize>,
+    /// The cells highlighted for `traced_port`; None until they are traced,
+    /// which happens again whenever the submarine's wires change.
+    pub traced_cells: Option<Vec<(usize, usize)>>,
+    /// Keep the camera centered on the object, even as its submarine moves.
+    pub camera_attached: bool,
 }
 
 enum UpdateSource {
@@ -110,22 +200,51 @@ enum UpdateSource {
     Remote(RemoteConnection),
 }
 
-impl Default for CyberSubApp {
-    fn default() -> Self {
+impl CyberSubApp {
+    /// In `safe_mode`, the sea dust, caustics and shadows start off. Shaders
+    /// that fail to load are drawn plainly, and the failures are shown once
+    /// the game starts.
+    pub fn new(safe_mode: bool) -> Self {
         let draw_settings = DrawSettings {
             draw_egui: true,
-            draw_sea_dust: true,
-            draw_sea_caustics: true,
+            draw_sea_dust: !safe_mode,
+            draw_sea_caustics: !safe_mode,
+            sea_dust_density: 1.0,
+            precise_sea_dust: true,
             draw_rocks: true,
             draw_background: true,
             draw_objects: true,
+            draw_object_labels: true,
+            draw_ports: false,
             draw_walls: true,
             draw_wires: true,
+            colorblind_wires: false,
+            wire_patterns: false,
+            animate_signals: false,
+            smooth_wires: false,
+            wires_above_objects: false,
             draw_water: true,
+            smooth_water: false,
             draw_sonar: true,
             draw_engine_turbulence: true,
-            draw_shadows: true,
+            max_turbulence_particles: 2000,
+            draw_shadows: !safe_mode,
+            depth_darkening: 0.5,
+            day_length: 0.0,
+            outline_current_submarine: true,
+            solo_inspected_object: false,
+            camera_momentum: true,
+            camera_pan_speed: 60.0,
+            invert_pan_x: false,
+            invert_pan_y: false,
+            interact_radius: 6.0,
             debug_shadows: false,
+            debug_cell_types: false,
+            trace_leaks: false,
+            skip_offscreen_submarines: true,
+            level_of_detail: true,
+            simplified_objects: true,
+            layer_hotkeys: LayerHotkeys::FunctionKeys,
         };
 
         let network_settings = NetworkSettings {
@@ -140,8 +259,19 @@ impl Default for CyberSubApp {
             network_status: "Not connected".to_string(),
             network_error: None,
             download_progress: None,
+            notify_teammate_actions: true,
         };
 
+        let resources = Resources::new();
+
+        let mut ui_state = UiState::default();
+        if !resources.material_errors.is_empty() {
+            ui_state.show_error(format!(
+                "Some visuals could not be loaded and will look plainer:\n{}",
+                resources.material_errors.join("\n")
+            ));
+        }
+
         Self {
             timings: Timings::default(),
             game_settings: GameSettings {
@@ -154,26 +284,43 @@ impl Default for CyberSubApp {
                 current_submarine: 0,
                 current_tool: Tool::Interact,
                 quit_game: false,
+                reset_world: false,
                 dragging: None,
+                inspected_object: None,
+                selection: None,
+                flashing_wires: None,
+                highlighted_type: None,
                 highlighting_settings: false,
                 last_update: None,
                 last_draw: None,
                 animation_ticks: 0,
                 submarine_templates: Vec::new(),
+                prefabs: Vec::new(),
+                initial_rocks: RockGrid::new(0, 0),
+                initial_submarines: Vec::new(),
             },
             commands: Vec::new(),
             update_events: Vec::new(),
+            rejections: Vec::new(),
             update_source: UpdateSource::Local,
             game_state: GameState::default(),
-            ui_state: UiState::default(),
-            resources: Resources::new(),
+            ui_state,
+            resources,
             mutable_resources: MutableResources::new(),
             mutable_sub_resources: Vec::new(),
         }
     }
-}
 
-impl CyberSubApp {
+    /// Reopen the windows that were open when the game was last closed, where
+    /// they were left.
+    pub fn load_window_layout(&mut self) {
+        match load_window_layout_from_file() {
+            Ok(Some(layout)) => self.ui_state.restore_window_layout(layout),
+            Ok(None) => (),
+            Err(err) => warn!("Could not load window layout: {}", err),
+        }
+    }
+
     pub fn load_submarine_template(
         &mut self,
         name: impl Into<String>,
@@ -186,33 +333,82 @@ impl CyberSubApp {
         Ok(self.game_settings.submarine_templates.len() - 1)
     }
 
+    /// Add one of the starting submarines; it comes back when the world is
+    /// reset.
     pub fn add_submarine(&mut self, template_index: usize) {
         let (_name, template) = self
             .game_settings
             .submarine_templates
             .get(template_index)
-            .expect("Template was requested this frame")
-            .clone();
+            .expect("Template was requested this frame");
 
-        let (width, height) = template.size;
+        self.commands.push(create_centered_submarine(
+            template,
+            self.game_state.rock_grid.size(),
+        ));
+        self.game_settings.initial_submarines.push(template_index);
+    }
+
+    /// Replace the game with the world and submarines it started with, keeping
+    /// the update settings. Only the host can do this; in multiplayer, the new
+    /// state is then sent to everyone, as when they joined.
+    fn reset_world(&mut self) -> Result<(), String> {
+        if let UpdateSource::Remote(_) = self.update_source {
+            return Err("Only the host can reset the world.".to_string());
+        }
 
-        // Middle of the world
-        let (rock_width, rock_height) = self.game_state.rock_grid.size();
-        let (middle_x, middle_y) = (
-            (rock_width as i32 / 2) * 16 * 16,
-            (rock_height as i32 / 2) * 16 * 16,
+        let game_settings = &mut self.game_settings;
+
+        let mut game_state = GameState {
+            update_settings: self.game_state.update_settings.clone(),
+            rock_grid: game_settings.initial_rocks.clone(),
+            ..GameState::default()
+        };
+
+        let rock_size = game_state.rock_grid.size();
+        let commands: Vec<_> = game_settings
+            .initial_submarines
+            .iter()
+            .filter_map(|&index| game_settings.submarine_templates.get(index))
+            .map(|(_name, template)| create_centered_submarine(template, rock_size))
+            .collect();
+
+        // Events of the submarines' creation are left out, since everything
+        // is rebuilt from the new state anyway.
+        let mut command_errors = Vec::new();
+        update_game(
+            commands.into_iter(),
+            &mut game_state,
+            &mut Vec::new(),
+            &mut command_errors,
+            &mut Vec::new(),
         );
+        for error in command_errors {
+            warn!("Could not re-add a starting submarine: {}", error.reason);
+        }
+
+        // Whatever was about to happen to the old world no longer applies.
+        self.game_state = game_state;
+        self.commands.clear();
+        game_settings.inspected_object = None;
+        game_settings.selection = None;
+        game_settings.highlighted_type = None;
+        game_settings.flashing_wires = None;
 
-        // Put the middle of the sub at the middle of the world
-        let (pos_x, pos_y) = (
-            middle_x - width as i32 * 16 / 2,
-            middle_y - height as i32 * 16 / 2,
+        update_resources_from_events(
+            std::iter::once(UpdateEvent::GameStateReset),
+            &self.game_state,
+            &mut self.mutable_sub_resources,
+            &mut game_settings.camera,
+            &mut game_settings.current_submarine,
         );
 
-        self.commands.push(Command::CreateSubmarine {
-            submarine_template: Box::new(template),
-            rock_position: (pos_x as usize, pos_y as usize),
-        });
+        #[cfg(not(target_arch = "wasm32"))]
+        if let UpdateSource::LocalServer(server, _local_client) = &mut self.update_source {
+            server.resend_state();
+        }
+
+        Ok(())
     }
 
     pub fn save_submarines(&mut self) -> Result<SubmarineFileData, String> {
@@ -221,12 +417,70 @@ impl CyberSubApp {
         let resources = self.mutable_sub_resources.get(current_submarine);
 
         if let (Some(submarine), Some(resources)) = (submarine, resources) {
-            return save_to_file_data(submarine, resources);
+            return save_to_file_data(submarine, resources, false);
         }
 
         Err("No submarine selected".to_string())
     }
 
+    /// Apply the pending commands, then run this many updates as fast as
+    /// possible, without drawing anything. Returns how long the updates took,
+    /// along with the total water and a checksum of the resulting state.
+    ///
+    /// Updates don't use randomness and always run with the same settings,
+    /// so the same world and submarines give the same checksum on every run.
+    pub fn run_benchmark(&mut self, ticks: u32) -> String {
+        self.game_state.update_settings = UpdateSettings {
+            // Off by default on debug builds, where it is slow
+            update_water: true,
+            ..UpdateSettings::default()
+        };
+
+        let mut events = Vec::new();
+        let mut command_errors = Vec::new();
+        let mut sub_timings = Vec::new();
+
+        let commands = self.commands.drain(..);
+        update_game(
+            commands,
+            &mut self.game_state,
+            &mut events,
+            &mut command_errors,
+            &mut sub_timings,
+        );
+
+        let start = get_time();
+        for _ in 0..ticks {
+            events.clear();
+            update_game(
+                std::iter::empty(),
+                &mut self.game_state,
+                &mut events,
+                &mut command_errors,
+                &mut sub_timings,
+            );
+        }
+        let elapsed = get_time() - start;
+
+        let total_water: u64 = self
+            .game_state
+            .submarines
+            .iter()
+            .map(|submarine| submarine.water_grid.total_water() as u64)
+            .sum();
+
+        format!(
+            "Simulated {} ticks of {} submarines in {:.3}s ({:.0} ticks/s); \
+            total water: {}, checksum: {:016x}",
+            ticks,
+            self.game_state.submarines.len(),
+            elapsed,
+            ticks as f64 / elapsed.max(f64::EPSILON),
+            total_water,
+            self.game_state.checksum(),
+        )
+    }
+
     pub fn start_server(&mut self) {
         self.game_settings.network_settings.start_server = true;
     }
@@ -237,11 +491,22 @@ impl CyberSubApp {
 
     pub fn load_rocks(&mut self, world_bytes: &[u8]) {
         self.game_state.rock_grid = load_rocks_from_png(world_bytes);
+        self.game_settings.initial_rocks = self.game_state.rock_grid.clone();
     }
 
     pub fn update_game(&mut self, game_time: f64) {
         self.game_settings.animation_ticks = 0;
 
+        if std::mem::take(&mut self.game_settings.reset_world) {
+            if let Err(err) = self.reset_world() {
+                self.ui_state.show_error(err);
+            }
+        }
+
+        for sub_timings in &mut self.timings.submarines {
+            *sub_timings = SubmarineTimings::default();
+        }
+
         let last_draw = self.game_settings.last_draw.get_or_insert(game_time);
         let last_update = self.game_settings.last_update.get_or_insert(game_time);
 
@@ -250,8 +515,14 @@ impl CyberSubApp {
             *last_draw = game_time - 0.5;
         }
 
-        if (game_time - *last_update).abs() > 0.5 {
-            *last_update = game_time - 0.5;
+        // Simulation speed is in quarters of 60 updates per second; catching
+        // up is capped in updates rather than time, so that fast-forwarding
+        // can't stall a frame with hundreds of them.
+        let update_interval = 1.0 / self.game_state.update_settings.updates_per_second();
+        let max_catch_up = MAX_CATCH_UP_UPDATES as f64 * update_interval;
+
+        if (game_time - *last_update).abs() > max_catch_up {
+            *last_update = game_time - max_catch_up;
         }
 
         // 60 animation updates per second, regardless of FPS
@@ -259,20 +530,107 @@ impl CyberSubApp {
             *last_draw += 1.0 / 60.0;
 
             self.game_settings.animation_ticks += 1;
+            self.game_settings
+                .camera
+                .coast(self.game_state.rock_grid.size());
         }
 
-        // 60 updates per second, regardless of FPS
+        // 60 updates per second at normal speed, regardless of FPS
         while *last_update < game_time {
-            *last_update += 1.0 / 60.0;
+            *last_update += update_interval;
 
             let commands = self.commands.drain(0..self.commands.len());
             self.update_source.update(
                 &mut self.game_state,
                 commands,
                 &mut self.update_events,
+                &mut self.rejections,
                 &mut self.game_settings.network_settings,
+                &mut self.timings.submarines,
             );
 
+            for reason in self.rejections.drain(..) {
+                self.ui_state.show_error(reason);
+            }
+
+            for event in &self.update_events {
+                match event {
+                    UpdateEvent::ObjectiveMet { description, .. } => {
+                        self.ui_state
+                            .show_error(format!("Objective complete: {}", description));
+                    }
+                    UpdateEvent::SubmarineRemoved { submarine_id } => {
+                        // Keep pointing at the same object, if it is still there
+                        let inspected_object = &mut self.game_settings.inspected_object;
+                        match inspected_object {
+                            Some(inspected) if inspected.submarine > *submarine_id => {
+                                inspected.submarine -= 1;
+                            }
+                            Some(inspected) if inspected.submarine == *submarine_id => {
+                                *inspected_object = None;
+                            }
+                            _ => (),
+                        }
+
+                        let selection = &mut self.game_settings.selection;
+                        match selection {
+                            Some(selected) if selected.submarine > *submarine_id => {
+                                selected.submarine -= 1;
+                            }
+                            Some(selected) if selected.submarine == *submarine_id => {
+                                *selection = None;
+                            }
+                            _ => (),
+                        }
+                    }
+                    UpdateEvent::GameStateReset => {
+                        self.game_settings.inspected_object = None;
+                        self.game_settings.selection = None;
+                    }
+                    UpdateEvent::Submarine {
+                        submarine_id,
+                        submarine_event,
+                    } => {
+                        let inspected_object = &mut self.game_settings.inspected_object;
+                        if let Some(inspected) = inspected_object {
+                            if inspected.submarine == *submarine_id {
+                                match submarine_event {
+                                    SubmarineUpdatedEvent::Objects => *inspected_object = None,
+                                    SubmarineUpdatedEvent::Wires => inspected.traced_cells = None,
+                                    _ => (),
+                                }
+                            }
+                        }
+
+                        // Selected object indexes may now point at other objects
+                        let selection = &mut self.game_settings.selection;
+                        if let (Some(selected), SubmarineUpdatedEvent::Objects) =
+                            (&selection, submarine_event)
+                        {
+                            if selected.submarine == *submarine_id {
+                                *selection = None;
+                            }
+                        }
+                    }
+                    UpdateEvent::TeammateAction {
+                        position,
+                        description,
+                    } => {
+                        let network_settings = &self.game_settings.network_settings;
+                        if network_settings.notify_teammate_actions
+                            && !self.game_settings.camera.is_on_screen(*position)
+                        {
+                            self.ui_state.notify_teammate_action(
+                                *position,
+                                description.clone(),
+                                game_time,
+                            );
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
             update_resources_from_events(
                 self.update_events.drain(..),
                 &self.game_state,
@@ -295,6 +653,42 @@ impl CyberSubApp {
             });
 
         self.game_settings.camera.current_submarine = submarine_camera;
+
+        // Follow an object with the camera; if it's gone, the camera is free
+        // again, and the inspector closes.
+        if let Some(inspected) = &self.game_settings.inspected_object {
+            let focus = self
+                .game_state
+                .submarines
+                .get(inspected.submarine)
+                .and_then(|submarine| Some((submarine, submarine.objects.get(inspected.object)?)));
+
+            match focus {
+                Some((submarine, object)) if inspected.camera_attached => {
+                    let position = submarine.navigation.position;
+                    self.game_settings.camera.center_on_object(position, object);
+                }
+                Some(_) => (),
+                None => self.game_settings.inspected_object = None,
+            }
+        }
+
+        // Traced once rather than on every frame, since networks can be large
+        if let Some(inspected) = &mut self.game_settings.inspected_object {
+            if inspected.traced_cells.is_none() {
+                let submarines = &self.game_state.submarines;
+                inspected.traced_cells =
+                    submarines.get(inspected.submarine).and_then(|submarine| {
+                        let object = submarine.objects.get(inspected.object)?;
+                        let port = object
+                            .object_type
+                            .wire_ports()
+                            .get(inspected.traced_port?)?;
+                        let traced = trace_port(submarine, object, port);
+                        Some(traced.into_iter().map(|(_color, cell)| cell).collect())
+                    });
+            }
+        }
     }
 
     /// Called each time the UI needs repainting, which may be many times per second.
@@ -326,10 +720,26 @@ impl CyberSubApp {
         );
     }
 
-    pub fn handle_keyboard_input(&mut self) {
+    /// The pointer isn't over any submarine while it's over a window, so don't
+    /// keep showing the last cell it was on.
+    pub fn pointer_over_ui(&mut self) {
+        for mutable_resources in &mut self.mutable_sub_resources {
+            mutable_resources.sub_cursor_tile = None;
+        }
+    }
+
+    pub fn handle_keyboard_input(&mut self, frame_time: f32) {
         handle_keyboard_input(
             &mut self.game_settings.camera,
+            // Capped, so that a stalled frame doesn't fling the camera away
+            pan_direction(&self.game_settings.draw_settings)
+                * self.game_settings.draw_settings.camera_pan_speed
+                * frame_time.min(0.1),
+            &mut self.game_settings.draw_settings,
             &mut self.game_settings.current_tool,
+            &self.game_state.submarines,
+            self.game_settings.current_submarine,
+            self.game_state.rock_grid.size(),
         );
     }
 
@@ -345,13 +755,38 @@ impl CyberSubApp {
     }
 }
 
+/// Creates a submarine with its middle at the middle of the world.
+fn create_centered_submarine(template: &SubmarineTemplate, rock_size: (usize, usize)) -> Command {
+    let (width, height) = template.size;
+
+    // Middle of the world
+    let (rock_width, rock_height) = rock_size;
+    let (middle_x, middle_y) = (
+        (rock_width as i32 / 2) * 16 * 16,
+        (rock_height as i32 / 2) * 16 * 16,
+    );
+
+    // Put the middle of the sub at the middle of the world
+    let (pos_x, pos_y) = (
+        middle_x - width as i32 * 16 / 2,
+        middle_y - height as i32 * 16 / 2,
+    );
+
+    Command::CreateSubmarine {
+        submarine_template: Box::new(template.clone()),
+        rock_position: (pos_x as usize, pos_y as usize),
+    }
+}
+
 impl UpdateSource {
     fn update(
         &mut self,
         game_state: &mut GameState,
         commands: impl Iterator<Item = Command>,
         events: &mut Vec<UpdateEvent>,
+        rejections: &mut Vec<String>,
         network_settings: &mut NetworkSettings,
+        sub_timings: &mut Vec<SubmarineTimings>,
     ) {
         #[cfg(not(target_arch = "wasm32"))]
         if network_settings.start_server {
@@ -399,13 +834,21 @@ impl UpdateSource {
 
         match self {
             UpdateSource::Local => {
-                update_game(commands, game_state, events);
+                let mut command_errors = Vec::new();
+                update_game(
+                    commands,
+                    game_state,
+                    events,
+                    &mut command_errors,
+                    sub_timings,
+                );
+                rejections.extend(command_errors.into_iter().map(|error| error.reason));
             }
             #[cfg(not(target_arch = "wasm32"))]
             UpdateSource::LocalServer(server, local_client) => {
                 local_client.send_commands(commands);
                 server.relay_messages();
-                server.tick(game_state, events);
+                server.tick(game_state, events, rejections, sub_timings);
             }
             UpdateSource::Remote(remote_connection) => {
                 match remote_connection.send_messages(commands) {
@@ -417,8 +860,19 @@ impl UpdateSource {
                     }
                 }
 
-                while let Some(commands) = remote_connection.receive_commands(game_state, events) {
-                    update_game(commands, game_state, events);
+                // The commands are everyone's, so errors found here aren't
+                // necessarily this player's; the server sends us our own.
+                let mut command_errors = Vec::new();
+                while let Some(commands) =
+                    remote_connection.receive_commands(game_state, events, rejections)
+                {
+                    update_game(
+                        commands,
+                        game_state,
+                        events,
+                        &mut command_errors,
+                        sub_timings,
+                    );
                 }
             }
         }
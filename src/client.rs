@@ -3,9 +3,12 @@ use std::{convert::TryInto, sync::Arc};
 use quad_net::quad_socket::client::QuadSocket;
 use serde::{Deserialize, Serialize};
 
-use crate::game_state::{
-    state::GameState,
-    update::{Command, UpdateEvent},
+use crate::{
+    game_state::{
+        state::GameState,
+        update::{Command, UpdateEvent},
+    },
+    warn,
 };
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -16,6 +19,19 @@ pub(crate) enum NetEvent {
     RequestState,
     State(Arc<GameState>),
     Hello,
+    /// One of the client's own commands was rejected by the server. Sent only
+    /// to the client that sent the command.
+    CommandRejected {
+        client_id: usize,
+        reason: String,
+    },
+    /// Another client did something worth a look; sent to every client
+    /// except the one that did it.
+    TeammateAction {
+        client_id: usize,
+        position: (i32, i32),
+        description: String,
+    },
 }
 
 pub(crate) struct RemoteConnection {
@@ -108,7 +124,7 @@ impl RemoteConnection {
 
                 match message {
                     Ok(message) => self.recv_message_buffer.push(message),
-                    Err(err) => eprintln!("Message malformed: {}", err),
+                    Err(err) => warn!("Message malformed: {}", err),
                 }
 
                 self.buffer.drain(0..message_size + 4);
@@ -120,6 +136,7 @@ impl RemoteConnection {
         &mut self,
         state: &mut GameState,
         events: &mut Vec<UpdateEvent>,
+        rejections: &mut Vec<String>,
     ) -> Option<impl Iterator<Item = Command> + '_> {
         while let Some(tick_index) = self
             .recv_message_buffer
@@ -140,6 +157,15 @@ impl RemoteConnection {
                     }
                     NetEvent::Tick => return Some(self.recv_command_buffer.drain(..)),
                     NetEvent::Hello => (),
+                    NetEvent::CommandRejected { reason, .. } => rejections.push(reason),
+                    NetEvent::TeammateAction {
+                        position,
+                        description,
+                        ..
+                    } => events.push(UpdateEvent::TeammateAction {
+                        position,
+                        description,
+                    }),
                 }
             }
         }
@@ -0,0 +1,256 @@
+use serde_yaml::Value;
+
+use crate::game_state::{
+    objects::{object_type_name, ObjectType},
+    state::{GameState, SubmarineTemplate, UpdateSettings},
+    update::Command,
+};
+
+const CONSOLE_HELP: &[&str] = &[
+    "help: show this list",
+    "subs: list the submarines",
+    "templates: list the submarine templates that can be spawned",
+    "spawn <template> [<x> <y>]: create a submarine from a template, by number or name, \
+    centered on the given rock cell or the middle of the world",
+    "target <sub> <x> <y>: set a submarine's navigation target to a rock cell",
+    "objects <sub>: list a submarine's objects",
+    "dump <sub> <object>: show an object's state",
+    "interact <sub> <object>: interact with an object, as if clicked",
+    "settings: show the update settings",
+    "set <setting> <value>: change an update setting",
+    "toggle <setting>: turn an update setting on or off",
+];
+
+/// Runs a line typed into the console, and returns what to print back.
+///
+/// Changes to the game are only ever pushed to `commands`, the same as the
+/// rest of the UI does, so that they go through the server in multiplayer.
+pub(crate) fn run_console_command(
+    line: &str,
+    state: &GameState,
+    submarine_templates: &[(String, SubmarineTemplate)],
+    commands: &mut Vec<Command>,
+) -> Result<Vec<String>, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    let (&name, args) = match words.split_first() {
+        Some(split) => split,
+        None => return Ok(Vec::new()),
+    };
+
+    match (name, args) {
+        ("help", []) => Ok(CONSOLE_HELP.iter().map(|line| line.to_string()).collect()),
+        ("subs", []) => Ok(state
+            .submarines
+            .iter()
+            .enumerate()
+            .map(|(index, submarine)| {
+                let (width, height) = submarine.water_grid.size();
+                let (x, y) = submarine.navigation.position;
+                format!(
+                    "{}: {}x{} cells at rock cell {}, {}, with {} objects",
+                    index,
+                    width,
+                    height,
+                    x / 256,
+                    y / 256,
+                    submarine.objects.len(),
+                )
+            })
+            .collect()),
+        ("templates", []) => Ok(submarine_templates
+            .iter()
+            .enumerate()
+            .map(|(index, (name, _template))| format!("{}: {}", index, name))
+            .collect()),
+        ("spawn", [template, position @ ..]) => {
+            let (name, template) = match template.parse::<usize>() {
+                Ok(index) => submarine_templates.get(index),
+                Err(_) => submarine_templates
+                    .iter()
+                    .find(|(name, _)| name == template),
+            }
+            .ok_or_else(|| format!("No submarine template called {}.", template))?;
+
+            let (x, y) = match position {
+                [] => {
+                    let (width, height) = state.rock_grid.size();
+                    (width / 2, height / 2)
+                }
+                [x, y] => rock_cell(state, x, y)?,
+                _ => return Err("Usage: spawn <template> [<x> <y>]".to_string()),
+            };
+
+            // Rock cells are 16 cells, of 16 movement points each.
+            let (width, height) = template.size;
+            let rock_position = (
+                (x * 16 * 16).saturating_sub(width * 16 / 2),
+                (y * 16 * 16).saturating_sub(height * 16 / 2),
+            );
+
+            commands.push(Command::CreateSubmarine {
+                submarine_template: Box::new(template.clone()),
+                rock_position,
+            });
+
+            Ok(vec![format!(
+                "Spawning {} at rock cell {}, {}.",
+                name, x, y
+            )])
+        }
+        ("target", [submarine_id, x, y]) => {
+            let submarine_id = parse_number(submarine_id)?;
+            let submarine = state
+                .submarines
+                .get(submarine_id)
+                .ok_or_else(|| format!("No submarine {}.", submarine_id))?;
+
+            // Navigation targets are set through the submarine's sonar.
+            let object_id = submarine
+                .objects
+                .iter()
+                .position(|object| matches!(object.object_type, ObjectType::Sonar { .. }))
+                .ok_or_else(|| {
+                    format!("Submarine {} has no sonar to navigate with.", submarine_id)
+                })?;
+
+            let (x, y) = rock_cell(state, x, y)?;
+            commands.push(Command::SetSonarTarget {
+                submarine_id,
+                object_id,
+                rock_position: (x * 16 * 16, y * 16 * 16),
+            });
+
+            Ok(vec![format!(
+                "Submarine {} is heading to rock cell {}, {}.",
+                submarine_id, x, y
+            )])
+        }
+        ("objects", [submarine_id]) => {
+            let submarine_id = parse_number(submarine_id)?;
+            let submarine = state
+                .submarines
+                .get(submarine_id)
+                .ok_or_else(|| format!("No submarine {}.", submarine_id))?;
+
+            Ok(submarine
+                .objects
+                .iter()
+                .enumerate()
+                .map(|(index, object)| {
+                    let name = object_type_name(&object.object_type);
+                    let (x, y) = object.position;
+                    match &object.label {
+                        Some(label) => format!("{}: {} \"{}\" at {}, {}", index, name, label, x, y),
+                        None => format!("{}: {} at {}, {}", index, name, x, y),
+                    }
+                })
+                .collect())
+        }
+        ("dump", [submarine_id, object_id]) => {
+            let (submarine_id, object_id) = (parse_number(submarine_id)?, parse_number(object_id)?);
+            let object = state
+                .submarines
+                .get(submarine_id)
+                .and_then(|submarine| submarine.objects.get(object_id))
+                .ok_or_else(|| format!("No object {} on submarine {}.", object_id, submarine_id))?;
+
+            let yaml = serde_yaml::to_string(object)
+                .map_err(|err| format!("Could not show object: {}", err))?;
+            Ok(yaml.lines().map(|line| line.to_string()).collect())
+        }
+        ("interact", [submarine_id, object_id]) => {
+            commands.push(Command::Interact {
+                submarine_id: parse_number(submarine_id)?,
+                object_id: parse_number(object_id)?,
+            });
+            Ok(Vec::new())
+        }
+        ("settings", []) => {
+            let yaml = serde_yaml::to_string(&state.update_settings)
+                .map_err(|err| format!("Could not show settings: {}", err))?;
+            Ok(yaml.lines().map(|line| line.to_string()).collect())
+        }
+        ("set", [setting, value]) => {
+            let value = serde_yaml::from_str(value)
+                .map_err(|err| format!("Could not read {}: {}", value, err))?;
+            change_update_setting(state, setting, |_old_value| Ok(value), commands)
+        }
+        ("toggle", [setting]) => change_update_setting(
+            state,
+            setting,
+            |old_value| match old_value {
+                Value::Bool(enabled) => Ok(Value::Bool(!enabled)),
+                _ => Err(format!("{} is not something to turn on or off.", setting)),
+            },
+            commands,
+        ),
+        _ => Err(format!(
+            "Unknown command or wrong arguments: {}; try help.",
+            line.trim()
+        )),
+    }
+}
+
+fn parse_number(word: &str) -> Result<usize, String> {
+    word.parse()
+        .map_err(|_| format!("Expected a number instead of {}.", word))
+}
+
+/// A rock cell given as two words, which must be inside the world.
+fn rock_cell(state: &GameState, x: &str, y: &str) -> Result<(usize, usize), String> {
+    let (x, y) = (parse_number(x)?, parse_number(y)?);
+    let (width, height) = state.rock_grid.size();
+
+    if x >= width || y >= height {
+        return Err(format!(
+            "Rock cell {}, {} is outside the {}x{} world.",
+            x, y, width, height
+        ));
+    }
+
+    Ok((x, y))
+}
+
+/// Changes one update setting, by its name in saved settings files.
+fn change_update_setting(
+    state: &GameState,
+    setting: &str,
+    new_value: impl FnOnce(&Value) -> Result<Value, String>,
+    commands: &mut Vec<Command>,
+) -> Result<Vec<String>, String> {
+    let mut settings = serde_yaml::to_value(&state.update_settings)
+        .map_err(|err| format!("Could not read settings: {}", err))?;
+
+    let value = match &mut settings {
+        Value::Mapping(mapping) => mapping.get_mut(&Value::String(setting.to_string())),
+        _ => None,
+    }
+    .ok_or_else(|| format!("No update setting called {}; try settings.", setting))?;
+
+    *value = new_value(value)?;
+    let shown_value = show_value(value);
+
+    let update_settings: UpdateSettings = serde_yaml::from_value(settings)
+        .map_err(|err| format!("Could not change {}: {}", setting, err))?;
+    update_settings.validate()?;
+
+    commands.push(Command::ChangeUpdateSettings { update_settings });
+
+    Ok(vec![format!("{} is now {}", setting, shown_value)])
+}
+
+/// A setting's value on a single line.
+fn show_value(value: &Value) -> String {
+    match value {
+        Value::Sequence(items) => {
+            let items: Vec<_> = items.iter().map(show_value).collect();
+            format!("[{}]", items.join(", "))
+        }
+        _ => serde_yaml::to_string(value)
+            .unwrap_or_default()
+            .trim_start_matches("---")
+            .trim()
+            .to_string(),
+    }
+}
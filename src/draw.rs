@@ -1,31 +1,40 @@
-use std::{cell::RefCell, collections::HashSet, mem::swap};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashSet},
+    f32::consts::TAU,
+    mem::swap,
+};
 
 use macroquad::{
     camera::{pop_camera_state, push_camera_state, set_default_camera},
     prelude::{
         clear_background, draw_circle, draw_circle_lines, draw_line, draw_rectangle,
         draw_rectangle_lines, draw_text, draw_texture, draw_texture_ex, draw_triangle, get_time,
-        gl_use_default_material, gl_use_material, render_target, screen_height, screen_width,
-        set_camera, vec2, Camera2D, Color, DrawTextureParams, FilterMode, Image, Rect, Texture2D,
-        Vec2, BLACK, BLANK, DARKBLUE, DARKGRAY, DARKGREEN, PURPLE, RED, SKYBLUE, WHITE, YELLOW,
+        gl_use_default_material, gl_use_material, measure_text, render_target, screen_height,
+        screen_width, set_camera, vec2, Camera2D, Color, DrawTextureParams, FilterMode, Image,
+        Rect, Texture2D, Vec2, BLACK, BLANK, DARKBLUE, DARKGRAY, DARKGREEN, GREEN, PURPLE, RED,
+        SKYBLUE, WHITE, YELLOW,
     },
 };
 
 use crate::{
-    app::{GameSettings, PlacingObject, Tool},
-    game_state::objects::{Object, ObjectType},
+    app::{GameSettings, InspectedObject, PlacingObject, Tool},
+    game_state::objects::{same_listed_type, Object, ObjectType},
     game_state::rocks::RockGrid,
     game_state::sonar::Sonar,
     game_state::water::WallMaterial,
     game_state::water::WaterGrid,
-    game_state::wires::{WireColor, WireGrid},
+    game_state::wires::{WireColor, WireGrid, WireValue},
     game_state::{
-        objects::current_frame,
+        objects::{
+            current_frame, object_category, object_size, port_cell, trace_leaks, LeakSource,
+            ObjectCategory, PortDirection,
+        },
         state::{GameState, Navigation, SubmarineState},
     },
-    input::Dragging,
+    input::{plan_wire_route, Dragging, LayerHotkeys},
     resources::{MutableResources, MutableSubResources, Resources, TurbulenceParticle},
-    saveload::pixels_to_image,
+    saveload::{pixels_to_image, Prefab},
     shadows::{
         add_border_edges, filter_edges_by_direction, filter_edges_by_region, find_shadow_edges,
         find_shadow_triangles, Triangle,
@@ -37,16 +46,133 @@ pub(crate) struct DrawSettings {
     pub draw_egui: bool,
     pub draw_sea_dust: bool,
     pub draw_sea_caustics: bool,
+    /// From 0 to 1, how much of the sea dust is drawn.
+    pub sea_dust_density: f32,
+    /// Only draw the visible part of the sea, with dust coordinates wrapped on
+    /// the CPU; without it, devices lacking highp in fragment shaders draw the
+    /// dust specks much larger than they should be.
+    pub precise_sea_dust: bool,
     pub draw_rocks: bool,
     pub draw_background: bool,
     pub draw_objects: bool,
+    pub draw_object_labels: bool,
+    /// Mark each object's input and output ports with a small dot.
+    pub draw_ports: bool,
     pub draw_walls: bool,
     pub draw_wires: bool,
+    pub colorblind_wires: bool,
+    pub wire_patterns: bool,
+    /// Send pulses along lit wires, moving away from where the signal comes
+    /// from.
+    pub animate_signals: bool,
+    /// Smooth the blocky edges of wires when zoomed in.
+    pub smooth_wires: bool,
+    pub wires_above_objects: bool,
     pub draw_water: bool,
+    /// Fill water cells with a gradient between their neighbours' levels,
+    /// instead of a square sized by the cell's own level.
+    pub smooth_water: bool,
     pub draw_sonar: bool,
     pub draw_engine_turbulence: bool,
+    /// Turbulence particles kept per submarine; the oldest ones past this
+    /// fade out early.
+    pub max_turbulence_particles: u32,
     pub draw_shadows: bool,
+    /// How much darker the sea and rocks get from the surface to the bottom
+    /// of the world, from 0 to 1.
+    pub depth_darkening: f32,
+    /// Length of a full day and night cycle, in seconds; 0 for endless day.
+    pub day_length: f32,
+    pub outline_current_submarine: bool,
+    pub solo_inspected_object: bool,
+    pub camera_momentum: bool,
+    /// Keyboard panning speed, in cells per second.
+    pub camera_pan_speed: f32,
+    /// Pan the other way when dragging or using the keyboard, horizontally
+    /// and vertically.
+    pub invert_pan_x: bool,
+    pub invert_pan_y: bool,
+    /// How far from an object a click still reaches it, in screen pixels.
+    pub interact_radius: f32,
     pub debug_shadows: bool,
+    pub debug_cell_types: bool,
+    /// Show where water reaches the flooding cell under the cursor from.
+    pub trace_leaks: bool,
+    pub skip_offscreen_submarines: bool,
+    pub level_of_detail: bool,
+    /// Draw objects as colored outlines when zoomed far out.
+    pub simplified_objects: bool,
+    pub layer_hotkeys: LayerHotkeys,
+}
+
+/// How much detail wires and water get, picked from how many screen pixels a
+/// cell currently takes up.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub(crate) enum DetailLevel {
+    /// Wires and water are not drawn at all.
+    Minimal,
+    /// Water is drawn as plain levels, without overfill or velocity lines.
+    Reduced,
+    Full,
+}
+
+/// How much bigger than the area being fitted the view is made, as a margin.
+const ZOOM_FIT_MARGIN: f32 = 1.1;
+
+/// How much of its momentum the camera keeps on each animation tick.
+const CAMERA_MOMENTUM_DECAY: f32 = 0.92;
+
+/// How much darker the middle of the night is than the middle of the day.
+const NIGHT_DARKENING: f32 = 0.6;
+
+/// Ambient light never goes below this, so the world stays faintly visible.
+const MIN_AMBIENT_LIGHT: f32 = 0.1;
+
+/// Signal flow pulses travel this many times per second from one stripe to
+/// the next.
+const SIGNAL_FLOW_SPEED: f64 = 1.5;
+
+/// Life lost on each tick by turbulence particles over the cap, on top of the
+/// usual 1; they are gone within 16 ticks instead of popping out at once.
+const OVER_CAP_PARTICLE_FADE: u8 = 8;
+
+/// Smooth water is drawn as this many steps of gradient across each cell.
+const SMOOTH_WATER_STEPS: usize = 3;
+
+const REDUCED_DETAIL_BELOW: f32 = 6.0;
+const MINIMAL_DETAIL_BELOW: f32 = 3.0;
+
+/// Below this many pixels per cell, objects are drawn as outlines instead of
+/// sprites, if enabled.
+const SIMPLIFIED_OBJECTS_BELOW: f32 = 2.0;
+
+// Zooming back in has to go this much further past a threshold before detail
+// is restored, so the view doesn't flicker when sitting right at one.
+const DETAIL_HYSTERESIS: f32 = 1.25;
+
+impl DetailLevel {
+    fn for_cell_size(pixels_per_cell: f32, threshold_scale: f32) -> Self {
+        if pixels_per_cell >= REDUCED_DETAIL_BELOW * threshold_scale {
+            DetailLevel::Full
+        } else if pixels_per_cell >= MINIMAL_DETAIL_BELOW * threshold_scale {
+            DetailLevel::Reduced
+        } else {
+            DetailLevel::Minimal
+        }
+    }
+
+    pub fn next(self, pixels_per_cell: f32) -> Self {
+        let lowered = Self::for_cell_size(pixels_per_cell, 1.0);
+        let raised = Self::for_cell_size(pixels_per_cell, DETAIL_HYSTERESIS);
+
+        if raised > self {
+            raised
+        } else if lowered < self {
+            lowered
+        } else {
+            self
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -58,16 +184,15 @@ pub(crate) struct Camera {
     pub scrolling_from: f32,
     pub pointing_at_world: (f32, f32),
     pub current_submarine: Option<(i32, i32)>,
+    /// Recent drag speed, in cells per second; only set while dragging.
+    pub drag_velocity: Option<(f32, f32)>,
+    /// How far the camera keeps coasting on each animation tick after a drag
+    /// is released, in cells.
+    pub momentum: (f32, f32),
 }
 
 impl Camera {
     pub fn to_macroquad_camera(&self, submarine: Option<(i32, i32)>) -> Camera2D {
-        let zoom = if screen_height() < screen_width() {
-            vec2(screen_height() / screen_width(), -1.0) * 1.3
-        } else {
-            vec2(1.0, -screen_width() / screen_height())
-        };
-
         let mut target = vec2(-self.offset_x as f32, -self.offset_y as f32);
 
         if let Some(submarine) = submarine {
@@ -81,17 +206,185 @@ impl Camera {
         }
 
         Camera2D {
-            zoom: zoom * (1.5 / 50.0) * self.user_zoom(),
+            zoom: Self::base_zoom() * self.user_zoom(),
             target,
             ..Default::default()
         }
     }
 
+    /// The camera's zoom before the user's own zoom is applied; it depends on
+    /// whether the window is in landscape or portrait.
+    fn base_zoom() -> Vec2 {
+        let zoom = if screen_height() < screen_width() {
+            vec2(screen_height() / screen_width(), -1.0) * 1.3
+        } else {
+            vec2(1.0, -screen_width() / screen_height())
+        };
+
+        zoom * (1.5 / 50.0)
+    }
+
+    /// Zoom out just enough for an area of this many cells, and a small margin
+    /// around it, to fit on the screen.
+    pub fn zoom_to_fit(&mut self, (width, height): (f32, f32)) {
+        let base_zoom = Self::base_zoom();
+
+        // The view spans 2 / zoom cells on each axis.
+        let fit_x = 2.0 / (base_zoom.x.abs() * width.max(1.0) * ZOOM_FIT_MARGIN);
+        let fit_y = 2.0 / (base_zoom.y.abs() * height.max(1.0) * ZOOM_FIT_MARGIN);
+        self.set_user_zoom(fit_x.min(fit_y));
+    }
+
+    /// Zoom so that a cell takes up this many screen pixels.
+    pub fn zoom_to_pixels_per_cell(&mut self, pixels: f32) {
+        let base_zoom = Self::base_zoom();
+        self.set_user_zoom(pixels * 2.0 / (base_zoom.x * screen_width()));
+    }
+
+    fn set_user_zoom(&mut self, user_zoom: f32) {
+        // The inverse of user_zoom(), rounded towards zooming out so that a
+        // fitted area is never cut off.
+        let zoom = 64.0 * (1.0 - 1.0 / user_zoom);
+        self.zoom = (zoom.floor() as i32).clamp(-512, 36);
+    }
+
+    /// Center the view on the middle of a world of the given size, in rock
+    /// cells.
+    pub fn center_on_world(&mut self, (width, height): (usize, usize)) {
+        // The offset is relative to the current submarine, if there is one.
+        let (sub_x, sub_y) = self.current_submarine.unwrap_or((0, 0));
+        self.offset_x = sub_x as f32 / 16.0 - width as f32 * 16.0 / 2.0;
+        self.offset_y = sub_y as f32 / 16.0 - height as f32 * 16.0 / 2.0;
+        self.momentum = (0.0, 0.0);
+    }
+
+    /// Point the camera at the middle of a submarine of the given size; the
+    /// camera's offset is relative to the current submarine's position.
+    pub fn center_on_submarine(&mut self, (width, height): (usize, usize)) {
+        self.offset_x = -(width as f32) / 2.0;
+        self.offset_y = -(height as f32) / 2.0;
+        self.momentum = (0.0, 0.0);
+    }
+
+    /// Center the view on a cell of the current submarine.
+    pub fn center_on_cell(&mut self, (x, y): (f32, f32)) {
+        self.offset_x = -x;
+        self.offset_y = -y;
+        self.momentum = (0.0, 0.0);
+    }
+
+    /// Whether a point of the world, in 1/16 cells, is currently on screen.
+    pub fn is_on_screen(&self, (x, y): (i32, i32)) -> bool {
+        let camera = self.to_macroquad_camera(None);
+        let point = camera.world_to_screen(vec2(x as f32 / 16.0, y as f32 / 16.0));
+
+        point.x >= 0.0 && point.y >= 0.0 && point.x <= screen_width() && point.y <= screen_height()
+    }
+
+    /// Coast towards a point of the world, in 1/16 cells, until it's about in
+    /// the middle of the view; smoother than jumping straight to it.
+    pub fn glide_to(&mut self, (x, y): (i32, i32)) {
+        let (sub_x, sub_y) = self.current_submarine.unwrap_or((0, 0));
+        let target_x = (sub_x - x) as f32 / 16.0;
+        let target_y = (sub_y - y) as f32 / 16.0;
+
+        // Coasting covers momentum / (1 - decay) cells in total.
+        self.momentum = (
+            (target_x - self.offset_x) * (1.0 - CAMERA_MOMENTUM_DECAY),
+            (target_y - self.offset_y) * (1.0 - CAMERA_MOMENTUM_DECAY),
+        );
+    }
+
+    /// Center the view on the middle of an object of any submarine, given
+    /// that submarine's position.
+    pub fn center_on_object(&mut self, submarine: (i32, i32), object: &Object) {
+        let (width, height) = object_size(&object.object_type);
+        // Objects are drawn one cell to the right and below their position.
+        self.center_on_cell((
+            object.position.0 as f32 + 1.0 + width as f32 / 2.0,
+            object.position.1 as f32 + 1.0 + height as f32 / 2.0,
+        ));
+
+        // Cells of other submarines are offset by how far they are from the
+        // current one.
+        let (sub_x, sub_y) = self.current_submarine.unwrap_or((0, 0));
+        self.offset_x += (sub_x - submarine.0) as f32 / 16.0;
+        self.offset_y += (sub_y - submarine.1) as f32 / 16.0;
+    }
+
+    /// Coast the camera for one animation tick, slowing it down; stops as soon
+    /// as the view leaves the world, so it can't drift off forever.
+    pub fn coast(&mut self, world_size: (usize, usize)) {
+        if self.momentum == (0.0, 0.0) {
+            return;
+        }
+
+        self.offset_x += self.momentum.0;
+        self.offset_y += self.momentum.1;
+        self.momentum.0 *= CAMERA_MOMENTUM_DECAY;
+        self.momentum.1 *= CAMERA_MOMENTUM_DECAY;
+
+        // The world is 16 cells per rock, and the view is centered on the
+        // negated offset, relative to the current submarine.
+        let (sub_x, sub_y) = self.current_submarine.unwrap_or((0, 0));
+        let center_x = -self.offset_x + sub_x as f32 / 16.0;
+        let center_y = -self.offset_y + sub_y as f32 / 16.0;
+        let (world_width, world_height) = (world_size.0 as f32 * 16.0, world_size.1 as f32 * 16.0);
+
+        let outside_world =
+            center_x < 0.0 || center_y < 0.0 || center_x > world_width || center_y > world_height;
+        let too_slow = self.momentum.0.abs() < 0.01 && self.momentum.1.abs() < 0.01;
+
+        if outside_world || too_slow {
+            self.momentum = (0.0, 0.0);
+        }
+    }
+
+    /// How many screen pixels a single cell takes up at the current zoom.
+    pub fn pixels_per_cell(&self) -> f32 {
+        self.to_macroquad_camera(None).zoom.x * screen_width() / 2.0
+    }
+
     fn user_zoom(&self) -> f32 {
         1.0 / (1.0 - self.zoom as f32 / 64.0)
     }
 }
 
+/// Whether objects should be drawn simplified at the given zoom, given whether
+/// they were on the previous frame; uses the same hysteresis as detail levels.
+fn simplified_objects_at(previously: bool, pixels_per_cell: f32) -> bool {
+    if previously {
+        pixels_per_cell < SIMPLIFIED_OBJECTS_BELOW * DETAIL_HYSTERESIS
+    } else {
+        pixels_per_cell < SIMPLIFIED_OBJECTS_BELOW
+    }
+}
+
+/// Whether any part of the submarine (or its engine turbulence) is visible.
+fn submarine_on_screen(camera: &Camera, submarine: &SubmarineState) -> bool {
+    let world_camera = camera.to_macroquad_camera(None);
+    let top_left = world_camera.screen_to_world(vec2(0.0, 0.0));
+    let bottom_right = world_camera.screen_to_world(vec2(screen_width(), screen_height()));
+    let screen_rect = Rect::new(
+        top_left.x.min(bottom_right.x),
+        top_left.y.min(bottom_right.y),
+        (bottom_right.x - top_left.x).abs(),
+        (bottom_right.y - top_left.y).abs(),
+    );
+
+    // Leave some room for turbulence particles trailing behind engines
+    let margin = 64.0;
+    let (width, height) = submarine.water_grid.size();
+    let submarine_rect = Rect::new(
+        submarine.navigation.position.0 as f32 / 16.0 - margin,
+        submarine.navigation.position.1 as f32 / 16.0 - margin,
+        width as f32 + margin * 2.0,
+        height as f32 + margin * 2.0,
+    );
+
+    screen_rect.overlaps(&submarine_rect)
+}
+
 fn draw_rect_at(pos: Vec2, size: f32, color: Color) {
     draw_rectangle(pos.x - size, pos.y - size, size * 2.0, size * 2.0, color);
 }
@@ -122,16 +415,45 @@ pub(crate) fn draw_game(
 
     set_camera(&camera.to_macroquad_camera(None));
 
+    let detail_level = if draw_settings.level_of_detail {
+        mutable_resources
+            .detail_level
+            .next(camera.pixels_per_cell())
+    } else {
+        DetailLevel::Full
+    };
+    mutable_resources.detail_level = detail_level;
+
+    let simplified_objects = draw_settings.simplified_objects
+        && simplified_objects_at(
+            mutable_resources.simplified_objects,
+            camera.pixels_per_cell(),
+        );
+    mutable_resources.simplified_objects = simplified_objects;
+
+    let world_height = rock_grid.size().1.max(1) as f32 * 16.0;
+    let view_depth = camera.to_macroquad_camera(None).target.y / world_height;
+    let ambient = ambient_light(draw_settings, view_depth);
+
+    // With shadows on, the shadow overlay darkens everything on the screen
+    // including the sea, so it alone applies the ambient light; lamps then
+    // cut through the darkness instead of fighting a second layer of it.
+    let sea_ambient = if draw_settings.draw_shadows {
+        1.0
+    } else {
+        ambient
+    };
+
     if draw_settings.draw_sea_dust || draw_settings.draw_sea_caustics {
         draw_sea(
             camera,
-            draw_settings.draw_sea_dust,
-            draw_settings.draw_sea_caustics,
+            draw_settings,
+            sea_ambient,
             resources,
             rock_grid.size(),
         );
     } else {
-        draw_fake_sea(rock_grid.size());
+        draw_fake_sea(rock_grid.size(), sea_ambient);
     }
 
     if draw_settings.draw_engine_turbulence {
@@ -139,6 +461,8 @@ pub(crate) fn draw_game(
         // they don't go over another submarine
         draw_engine_turbulence(
             submarines,
+            camera,
+            draw_settings,
             game_settings.animation_ticks,
             resources,
             mutable_sub_resources,
@@ -149,6 +473,7 @@ pub(crate) fn draw_game(
         draw_rocks(
             rock_grid,
             &game_state.collisions,
+            sea_ambient,
             resources,
             mutable_resources,
         );
@@ -158,6 +483,7 @@ pub(crate) fn draw_game(
         draw_shadows_on_texture(
             submarines,
             camera,
+            ambient,
             resources,
             mutable_resources,
             mutable_sub_resources,
@@ -167,6 +493,12 @@ pub(crate) fn draw_game(
     push_camera_state();
 
     for (sub_index, submarine) in submarines.iter().enumerate() {
+        // Textures of skipped submarines stay marked as outdated, so they are
+        // brought up to date as soon as the submarine is visible again.
+        if draw_settings.skip_offscreen_submarines && !submarine_on_screen(camera, submarine) {
+            continue;
+        }
+
         set_camera(&camera.to_macroquad_camera(Some(submarine.navigation.position)));
 
         let mutable_resources = mutable_sub_resources
@@ -186,12 +518,25 @@ pub(crate) fn draw_game(
             );
         }
 
-        if draw_settings.draw_wires {
+        // Textures skipped here stay marked as outdated, same as for
+        // off-screen submarines.
+        let show_wires = draw_settings.draw_wires && detail_level > DetailLevel::Minimal;
+
+        if show_wires {
             update_wires_texture(&submarine.wire_grid, resources, mutable_resources);
             update_signals_texture(&submarine.wire_grid, mutable_resources);
-            draw_wires(&submarine.wire_grid, resources, mutable_resources);
+        }
+
+        if show_wires && !draw_settings.wires_above_objects {
+            draw_wires(
+                &submarine.wire_grid,
+                draw_settings,
+                camera.pixels_per_cell(),
+                resources,
+                mutable_resources,
+            );
             if let Some(cursor_tile) = mutable_resources.sub_cursor_tile {
-                draw_wire_plan(dragging, sub_index, cursor_tile);
+                draw_wire_plan(dragging, submarine, sub_index, cursor_tile);
             }
         }
 
@@ -201,7 +546,53 @@ pub(crate) fn draw_game(
                 _ => None,
             };
 
-            draw_objects(&submarine.objects, resources, placing_object);
+            draw_objects(
+                &submarine.objects,
+                simplified_objects,
+                resources,
+                placing_object,
+            );
+
+            if let Tool::PlacePrefab {
+                prefab_id,
+                submarine: prefab_sub,
+                position: Some(position),
+            } = &game_settings.current_tool
+            {
+                if let Some((_name, prefab)) = game_settings.prefabs.get(*prefab_id) {
+                    if *prefab_sub == sub_index {
+                        draw_prefab_ghost(prefab, *position, resources);
+                    }
+                }
+            }
+        }
+
+        if show_wires && draw_settings.wires_above_objects {
+            draw_wires(
+                &submarine.wire_grid,
+                draw_settings,
+                camera.pixels_per_cell(),
+                resources,
+                mutable_resources,
+            );
+            if let Some(cursor_tile) = mutable_resources.sub_cursor_tile {
+                draw_wire_plan(dragging, submarine, sub_index, cursor_tile);
+            }
+        }
+
+        // Too small to make out when zoomed out far enough to simplify objects
+        if draw_settings.draw_objects && draw_settings.draw_ports && !simplified_objects {
+            draw_port_dots(&submarine.objects);
+        }
+
+        if let Tool::Measure = game_settings.current_tool {
+            let sub_camera = camera.to_macroquad_camera(Some(submarine.navigation.position));
+            draw_measurement(
+                dragging,
+                sub_index,
+                mutable_resources.sub_cursor,
+                &sub_camera,
+            );
         }
 
         if draw_settings.draw_sonar {
@@ -215,21 +606,86 @@ pub(crate) fn draw_game(
             );
         }
 
-        if draw_settings.draw_water {
-            draw_water(&submarine.water_grid);
+        if draw_settings.draw_water && detail_level > DetailLevel::Minimal {
+            draw_water(
+                &submarine.water_grid,
+                detail_level,
+                draw_settings.smooth_water,
+            );
+        }
+
+        if draw_settings.debug_cell_types {
+            draw_cell_types(&submarine.water_grid, mutable_resources);
+        }
+
+        if draw_settings.trace_leaks {
+            if let Some(cursor_tile) = mutable_resources.sub_cursor_tile {
+                draw_leak_trace(submarine, cursor_tile);
+            }
+        }
+
+        // Dims everything drawn so far in this submarine, then brings the
+        // inspected object back on top; highlights below are drawn only once,
+        // above the dimming.
+        if let Some(inspected_object) = &game_settings.inspected_object {
+            if draw_settings.solo_inspected_object && inspected_object.submarine == sub_index {
+                draw_solo_object(submarine, inspected_object, resources);
+            }
         }
 
         if draw_settings.draw_objects {
-            if let Tool::EditWires { .. } = game_settings.current_tool {
-                draw_object_connectors(&submarine.objects);
+            match &game_settings.current_tool {
+                Tool::EditWires { .. } => draw_object_connectors(&submarine.objects),
+                Tool::ConnectPorts { from, .. } => {
+                    draw_object_connectors(&submarine.objects);
+
+                    if let Some(from) = from.filter(|from| from.submarine == sub_index) {
+                        draw_picked_port(&submarine.objects, from.object, from.port);
+                    }
+                }
+                _ => (),
             }
 
+            // Only while the tool it was made with is still in use, so it
+            // never shows up under a placement ghost.
+            let highlighted_type = game_settings
+                .highlighted_type
+                .as_ref()
+                .filter(|highlighted| {
+                    highlighted.submarine == sub_index
+                        && highlighted.tool == game_settings.current_tool
+                })
+                .map(|highlighted| &highlighted.object_type);
+
             draw_object_highlights(
                 &submarine.objects,
                 resources,
                 mutable_resources.highlighting_object,
+                highlighted_type,
             );
         }
+
+        if let Some(inspected_object) = &game_settings.inspected_object {
+            if inspected_object.submarine == sub_index {
+                draw_inspected_object(submarine, inspected_object);
+            }
+        }
+
+        if let Some(selection) = &game_settings.selection {
+            if selection.submarine == sub_index {
+                draw_selected_objects(submarine, &selection.objects);
+            }
+        }
+
+        if let Some((flashing_sub, cells)) = &game_settings.flashing_wires {
+            if *flashing_sub == sub_index {
+                draw_flashing_wires(cells);
+            }
+        }
+
+        if draw_settings.outline_current_submarine && sub_index == game_settings.current_submarine {
+            draw_submarine_outline(submarine.water_grid.size());
+        }
     }
 
     pop_camera_state();
@@ -242,21 +698,7 @@ pub(crate) fn draw_game(
         );
     }
 
-    for submarine in &game_state.submarines {
-        for point in &submarine.docking_points {
-            let position = vec2(
-                point.connection_point.0 as f32 / 16.0,
-                point.connection_point.1 as f32 / 16.0,
-            );
-
-            let color = if point.connected_to.is_some() {
-                RED
-            } else {
-                SKYBLUE
-            };
-            draw_circle(position.x, position.y, 1.0, color);
-        }
-    }
+    draw_docking_guides(&game_state.submarines);
 
     draw_submarine_ghost(game_settings, mutable_resources);
 
@@ -266,6 +708,13 @@ pub(crate) fn draw_game(
         draw_shadows_texture(resources, mutable_resources);
     }
 
+    if draw_settings.draw_objects
+        && draw_settings.draw_object_labels
+        && detail_level > DetailLevel::Minimal
+    {
+        draw_object_labels(submarines, camera, draw_settings.skip_offscreen_submarines);
+    }
+
     if !draw_settings.draw_egui {
         set_default_camera();
         draw_ui_alternative(timings, game_settings.highlighting_settings, resources);
@@ -297,55 +746,114 @@ pub(crate) fn draw_ui_alternative(
     draw_text(&text, 40.0, 25.0, 20.0, PURPLE);
 }
 
+/// How bright the world is, from 0 to 1, at the given depth (from 0 at the
+/// surface to 1 at the bottom of the world) and the current time of day.
+fn ambient_light(draw_settings: &DrawSettings, depth: f32) -> f32 {
+    let mut light = 1.0 - draw_settings.depth_darkening * depth.clamp(0.0, 1.0);
+
+    if draw_settings.day_length > 0.0 {
+        let phase = get_time() as f32 / draw_settings.day_length * TAU;
+        // 1 at noon, 0 at midnight
+        let daylight = 0.5 + 0.5 * phase.cos();
+        light *= 1.0 - NIGHT_DARKENING * (1.0 - daylight);
+    }
+
+    light.max(MIN_AMBIENT_LIGHT)
+}
+
 fn draw_sea(
     camera: &Camera,
-    draw_sea_dust: bool,
-    draw_sea_caustics: bool,
+    draw_settings: &DrawSettings,
+    ambient: f32,
     resources: &Resources,
     world_size: (usize, usize),
 ) {
+    let sea_water = match resources.sea_water {
+        Some(sea_water) => sea_water,
+        None => {
+            draw_fake_sea(world_size, ambient);
+            return;
+        }
+    };
+
     let (width, height) = world_size;
-    let time_offset = vec2(0.1, 1.0) * get_time() as f32 * 0.03;
+    let world_rect = Rect::new(0.0, 0.0, (width * 16) as f32, (height * 16) as f32);
+
+    // All three dust layers and the caustics line up again every 6 units of
+    // time offset and every 10 units of camera offset, so wrapping them keeps
+    // them small without any visible jump.
+    let time = get_time() * 0.03;
+    let time_offset = vec2((time * 0.1 % 6.0) as f32, (time % 6.0) as f32);
     let camera_offset = vec2(camera.offset_x, camera.offset_y) / 600.0;
-    resources
-        .sea_water
-        .set_uniform("enable_dust", if draw_sea_dust { 1.0f32 } else { 0.0 });
-    resources.sea_water.set_uniform(
-        "enable_caustics",
-        if draw_sea_caustics { 1.0f32 } else { 0.0 },
+    let camera_offset = vec2(
+        camera_offset.x.rem_euclid(10.0),
+        camera_offset.y.rem_euclid(10.0),
+    );
+
+    let sea_rect = if draw_settings.precise_sea_dust {
+        let world_camera = camera.to_macroquad_camera(None);
+        let top_left = world_camera.screen_to_world(vec2(0.0, 0.0));
+        let bottom_right = world_camera.screen_to_world(vec2(screen_width(), screen_height()));
+        let screen_rect = Rect::new(
+            top_left.x.min(bottom_right.x),
+            top_left.y.min(bottom_right.y),
+            (bottom_right.x - top_left.x).abs(),
+            (bottom_right.y - top_left.y).abs(),
+        );
+
+        match screen_rect.intersect(world_rect) {
+            Some(sea_rect) => sea_rect,
+            None => return,
+        }
+    } else {
+        world_rect
+    };
+
+    // How many times the dust texture repeats over the whole world
+    let dust_tiles = vec2(
+        resources.sea_dust.width() / (width / 16).max(1) as f32,
+        resources.sea_dust.height() / (height / 16).max(1) as f32,
     );
-    resources.sea_water.set_uniform("time_offset", time_offset);
-    resources
-        .sea_water
-        .set_uniform("camera_offset", camera_offset);
-    resources.sea_water.set_uniform("time", get_time() as f32);
-    resources.sea_water.set_uniform(
-        "world_size",
-        vec2((width / 16) as f32, (height / 16) as f32),
+    let dust_origin = vec2(
+        (sea_rect.x / world_rect.w * dust_tiles.x).fract(),
+        (sea_rect.y / world_rect.h * dust_tiles.y).fract(),
     );
-    resources.sea_water.set_uniform(
-        "sea_dust_size",
-        vec2(resources.sea_dust.width(), resources.sea_dust.height()),
+    let dust_scale = vec2(
+        sea_rect.w / world_rect.w * dust_tiles.x,
+        sea_rect.h / world_rect.h * dust_tiles.y,
     );
-    resources
-        .sea_water
-        .set_texture("sea_dust", resources.sea_dust);
-
-    // The world always starts here.
-    let pos = vec2(0.0, 0.0);
 
-    gl_use_material(resources.sea_water);
-    draw_rectangle(
-        pos.x,
-        pos.y,
-        (width * 16) as f32,
-        (height * 16) as f32,
-        WHITE,
+    sea_water.set_uniform(
+        "enable_dust",
+        if draw_settings.draw_sea_dust {
+            1.0f32
+        } else {
+            0.0
+        },
     );
+    sea_water.set_uniform(
+        "enable_caustics",
+        if draw_settings.draw_sea_caustics {
+            1.0f32
+        } else {
+            0.0
+        },
+    );
+    sea_water.set_uniform("time_offset", time_offset);
+    sea_water.set_uniform("camera_offset", camera_offset);
+    sea_water.set_uniform("time", get_time() as f32);
+    sea_water.set_uniform("ambient", ambient);
+    sea_water.set_uniform("dust_density", draw_settings.sea_dust_density);
+    sea_water.set_uniform("dust_origin", dust_origin);
+    sea_water.set_uniform("dust_scale", dust_scale);
+    sea_water.set_texture("sea_dust", resources.sea_dust);
+
+    gl_use_material(sea_water);
+    draw_rectangle(sea_rect.x, sea_rect.y, sea_rect.w, sea_rect.h, WHITE);
     gl_use_default_material();
 }
 
-fn draw_fake_sea(world_size: (usize, usize)) {
+fn draw_fake_sea(world_size: (usize, usize), ambient: f32) {
     let (width, height) = world_size;
 
     draw_rectangle(
@@ -353,7 +861,7 @@ fn draw_fake_sea(world_size: (usize, usize)) {
         0.0,
         (width * 16) as f32,
         (height * 16) as f32,
-        Color::new(0.0235, 0.0235, 0.1255, 1.0),
+        Color::new(0.0235 * ambient, 0.0235 * ambient, 0.1255 * ambient, 1.0),
     );
 }
 
@@ -373,6 +881,11 @@ fn draw_walls(
     collisions: &[(usize, usize)],
     mutable_resources: &mut MutableSubResources,
 ) {
+    let wall_material = match resources.wall_material {
+        Some(wall_material) => wall_material,
+        None => return,
+    };
+
     let (width, height) = grid.size();
 
     let texture = mutable_resources.sub_walls;
@@ -407,19 +920,11 @@ fn draw_walls(
         }
     }
 
-    resources
-        .wall_material
-        .set_texture("wall_texture", resources.wall);
-    resources
-        .wall_material
-        .set_texture("glass_texture", resources.glass);
-    resources
-        .wall_material
-        .set_texture("walls", mutable_resources.sub_walls);
-    resources
-        .wall_material
-        .set_uniform("walls_size", vec2(width as f32, height as f32));
-    gl_use_material(resources.wall_material);
+    wall_material.set_texture("wall_texture", resources.wall);
+    wall_material.set_texture("glass_texture", resources.glass);
+    wall_material.set_texture("walls", mutable_resources.sub_walls);
+    wall_material.set_uniform("walls_size", vec2(width as f32, height as f32));
+    gl_use_material(wall_material);
 
     let pos = to_screen_coords(0, 0);
 
@@ -443,7 +948,83 @@ fn draw_walls(
     }
 }
 
-fn draw_water(grid: &WaterGrid) {
+/// Marks the openings and pumps that water reaches a flooding cell from, and
+/// the cells it passes through on the way.
+fn draw_leak_trace(submarine: &SubmarineState, cell: (usize, usize)) {
+    let leaks = trace_leaks(submarine, cell);
+
+    let path_color = Color::new(1.0, 0.6, 0.1, 0.4);
+    let path_cells: BTreeSet<_> = leaks.iter().flat_map(|leak| leak.path.iter()).collect();
+    for &(x, y) in path_cells {
+        let pos = to_screen_coords(x, y);
+        draw_rectangle(pos.x + 0.25, pos.y + 0.25, 0.5, 0.5, path_color);
+    }
+
+    for leak in &leaks {
+        match leak.source {
+            LeakSource::Opening((x, y)) => {
+                let pos = to_screen_coords(x, y);
+                draw_rectangle_lines(pos.x, pos.y, 1.0, 1.0, 0.2, RED);
+            }
+            LeakSource::Pump(object_id) => {
+                let rect = object_rect(&submarine.objects[object_id]);
+                draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 0.3, RED);
+            }
+        }
+    }
+}
+
+/// Debug overlay that color-codes every cell by its type.
+fn draw_cell_types(grid: &WaterGrid, mutable_resources: &mut MutableSubResources) {
+    let (width, height) = grid.size();
+
+    let texture = mutable_resources.sub_cell_types;
+    let (old_width, old_height) = (texture.width() as usize, texture.height() as usize);
+
+    if mutable_resources.cell_types_updated || width != old_width || height != old_height {
+        mutable_resources.cell_types_updated = false;
+
+        let mut image = Image::gen_image_color(width as u16, height as u16, BLANK);
+
+        for y in 0..height {
+            for x in 0..width {
+                let cell = grid.cell(x, y);
+
+                let color = match cell.wall_material() {
+                    Some(WallMaterial::Normal) => Color::new(1.0, 1.0, 1.0, 0.5),
+                    Some(WallMaterial::Glass) => Color::new(0.0, 1.0, 1.0, 0.5),
+                    // Invisible walls are otherwise not drawn at all
+                    Some(WallMaterial::Invisible) => Color::new(1.0, 0.0, 1.0, 0.8),
+                    None if cell.is_sea() => Color::new(0.0, 0.2, 1.0, 0.4),
+                    None if cell.is_inside() => Color::new(0.0, 1.0, 0.2, 0.25),
+                    None => continue,
+                };
+
+                image.set_pixel(x as u32, y as u32, color);
+            }
+        }
+
+        if old_width != width || old_height != height {
+            mutable_resources.sub_cell_types.delete();
+            mutable_resources.sub_cell_types = Texture2D::from_image(&image);
+            mutable_resources
+                .sub_cell_types
+                .set_filter(FilterMode::Nearest);
+        } else {
+            mutable_resources.sub_cell_types.update(&image);
+        }
+    }
+
+    let top_left = to_screen_coords(0, 0);
+    draw_texture(
+        mutable_resources.sub_cell_types,
+        top_left.x,
+        top_left.y,
+        WHITE,
+    );
+}
+
+fn draw_water(grid: &WaterGrid, detail_level: DetailLevel, smooth: bool) {
     let (width, height) = grid.size();
 
     for i in 0..width {
@@ -459,18 +1040,26 @@ fn draw_water(grid: &WaterGrid) {
             let overlevel = grid.cell(i, j).amount_overfilled();
             let velocity = grid.cell(i, j).velocity();
 
-            let level = if level != 0.0 && level < 0.5 {
-                0.5
-            } else {
-                level
-            };
-
             let size = 0.5;
 
             let transparent_blue = Color::new(0.40, 0.75, 1.00, 0.75);
 
             if level > 0.0 {
-                draw_rect_at(pos, size * level, transparent_blue);
+                if smooth {
+                    draw_smooth_water_cell(grid, i, j, transparent_blue);
+                } else {
+                    // Make tiny levels big enough to see
+                    draw_rect_at(pos, size * level.max(0.5), transparent_blue);
+                }
+
+                if j > 0 {
+                    draw_water_surface(grid, i, j);
+                }
+
+                if detail_level < DetailLevel::Full {
+                    continue;
+                }
+
                 draw_rect_at(pos, size * overlevel, DARKBLUE);
 
                 let velocity = vec2(velocity.0, velocity.1).normalize_or_zero() * 0.35;
@@ -490,6 +1079,87 @@ fn draw_water(grid: &WaterGrid) {
     }
 }
 
+/// Fill a wet cell with a gradient, each of its corners as opaque as the
+/// average level of the inside cells around that corner. Dry cells are never
+/// drawn, so the fade towards them happens on the wet side, and a dry
+/// compartment keeps a clear edge.
+fn draw_smooth_water_cell(grid: &WaterGrid, x: usize, y: usize, color: Color) {
+    let (width, height) = grid.size();
+
+    // The cells sharing the corner at the top-left of the given cell
+    let corner_level = |corner_x: usize, corner_y: usize| {
+        let mut total = 0.0;
+        let mut count = 0;
+
+        for cell_y in corner_y.saturating_sub(1)..(corner_y + 1).min(height) {
+            for cell_x in corner_x.saturating_sub(1)..(corner_x + 1).min(width) {
+                let cell = grid.cell(cell_x, cell_y);
+
+                if cell.is_inside() {
+                    total += cell.amount_filled();
+                    count += 1;
+                }
+            }
+        }
+
+        total / count.max(1) as f32
+    };
+
+    let top_left = corner_level(x, y);
+    let top_right = corner_level(x + 1, y);
+    let bottom_left = corner_level(x, y + 1);
+    let bottom_right = corner_level(x + 1, y + 1);
+
+    let start = to_screen_coords(x, y);
+    let step = 1.0 / SMOOTH_WATER_STEPS as f32;
+
+    for step_y in 0..SMOOTH_WATER_STEPS {
+        for step_x in 0..SMOOTH_WATER_STEPS {
+            // Interpolated at the middle of each step
+            let u = (step_x as f32 + 0.5) * step;
+            let v = (step_y as f32 + 0.5) * step;
+            let top = top_left + (top_right - top_left) * u;
+            let bottom = bottom_left + (bottom_right - bottom_left) * u;
+            let level = top + (bottom - top) * v;
+
+            draw_rectangle(
+                start.x + step_x as f32 * step,
+                start.y + step_y as f32 * step,
+                step,
+                step,
+                Color {
+                    a: color.a * level,
+                    ..color
+                },
+            );
+        }
+    }
+}
+
+/// A line at the top of the water in a cell that has only air above it, as
+/// if the water settled at the bottom of the cell. Overfilled cells are full,
+/// so their line sits right on the cell's top edge.
+fn draw_water_surface(grid: &WaterGrid, x: usize, y: usize) {
+    let above = grid.cell(x, y - 1);
+
+    if !above.is_inside() || above.amount_filled() > 0.0 {
+        return;
+    }
+
+    let start = to_screen_coords(x, y);
+    let surface_y = start.y + 1.0 - grid.cell(x, y).amount_filled();
+    let surface_color = Color::new(0.75, 0.92, 1.00, 0.9);
+
+    draw_line(
+        start.x,
+        surface_y,
+        start.x + 1.0,
+        surface_y,
+        0.15,
+        surface_color,
+    );
+}
+
 fn draw_submarine_ghost(game_settings: &GameSettings, mutable_resources: &mut MutableResources) {
     if let Tool::PlaceSubmarine {
         template_id,
@@ -619,6 +1289,11 @@ fn draw_shadow_pointlight(
     camera: &Camera2D,
     resources: &Resources,
 ) {
+    let pointlight_material = match resources.pointlight_material {
+        Some(pointlight_material) => pointlight_material,
+        None => return,
+    };
+
     let range = 60.0;
 
     let mut edges_in_region = Vec::new();
@@ -640,14 +1315,10 @@ fn draw_shadow_pointlight(
     let screen_cursor = camera.world_to_screen(pointlight);
     let pointlight_size = camera.world_to_screen(pointlight + vec2(range, range)) - screen_cursor;
 
-    resources
-        .pointlight_material
-        .set_uniform("pointlight_size", pointlight_size);
-    resources
-        .pointlight_material
-        .set_uniform("pointlight_position", screen_cursor);
+    pointlight_material.set_uniform("pointlight_size", pointlight_size);
+    pointlight_material.set_uniform("pointlight_position", screen_cursor);
 
-    gl_use_material(resources.pointlight_material);
+    gl_use_material(pointlight_material);
 
     for Triangle(p1, p2, p3) in triangles {
         let gray = Color::new(1.0, 1.0, 1.0, 1.0);
@@ -661,6 +1332,7 @@ fn draw_shadow_pointlight(
 fn draw_shadows_on_texture(
     submarines: &[SubmarineState],
     camera: &Camera,
+    ambient: f32,
     resources: &Resources,
     mutable_resources: &mut MutableResources,
     mutable_sub_resources: &mut [MutableSubResources],
@@ -678,7 +1350,14 @@ fn draw_shadows_on_texture(
         render_target: Some(*shadows),
         ..Default::default()
     });
-    clear_background(DARKGRAY);
+    // Unlit areas; point lights are blended on top towards white, so they
+    // stay just as bright however dark the ambient light gets.
+    clear_background(Color::new(
+        DARKGRAY.r * ambient,
+        DARKGRAY.g * ambient,
+        DARKGRAY.b * ambient,
+        1.0,
+    ));
 
     for (sub_index, submarine) in submarines.iter().enumerate() {
         let camera = camera.to_macroquad_camera(Some(submarine.navigation.position));
@@ -790,6 +1469,11 @@ fn draw_shadows_on_texture(
 }
 
 fn draw_shadows_texture(resources: &Resources, mutable_resources: &mut MutableResources) {
+    let shadow_material = match resources.shadow_material {
+        Some(shadow_material) => shadow_material,
+        None => return,
+    };
+
     let screen_size = vec2(screen_width(), screen_height());
     let screen = &mut mutable_resources.screen;
     let shadows = &mut mutable_resources.shadows;
@@ -804,11 +1488,9 @@ fn draw_shadows_texture(resources: &Resources, mutable_resources: &mut MutableRe
     }
     screen.grab_screen();
 
-    resources.shadow_material.set_texture("screen", *screen);
-    resources
-        .shadow_material
-        .set_texture("shadows", shadows.texture);
-    gl_use_material(resources.shadow_material);
+    shadow_material.set_texture("screen", *screen);
+    shadow_material.set_texture("shadows", shadows.texture);
+    gl_use_material(shadow_material);
 
     draw_rectangle(0.0, 0.0, screen_size.x, screen_size.y, WHITE);
 
@@ -878,12 +1560,64 @@ fn update_wires_texture(
                     continue;
                 }
 
-                let has_neighbours = grid.has_neighbours(*wire_color, x, y);
-
                 let wire_color_frames = 5;
                 let wire_color_frame = *wire_color as u16;
 
                 let wire_type_frames = 7;
+
+                // The wires texture is vertically split into frames by wire
+                // direction, and horizontally split by wire color
+                let frame_width = (resources.wires.width() as u16 / wire_color_frames) as f32;
+                let frame_height = (resources.wires.height() as u16 / wire_type_frames) as f32;
+                let frame_x = (frame_width as u16 * wire_color_frame) as f32;
+
+                let draw_frame = |wire_type_frame: u16| {
+                    let frame_y = (frame_height as u16 * wire_type_frame) as f32;
+
+                    draw_texture_ex(
+                        resources.wires,
+                        pos.x,
+                        pos.y,
+                        WHITE,
+                        DrawTextureParams {
+                            dest_size: Some(vec2(1.0, 1.0)),
+                            source: Some(Rect::new(frame_x, frame_y, frame_width, frame_height)),
+                            ..Default::default()
+                        },
+                    );
+                };
+
+                if let WireValue::Bridge = cell.value(*wire_color) {
+                    // Draw the vertical wire hopping over the horizontal one, by
+                    // cutting the horizontal wire on both sides of the vertical
+                    // one. Each color has its own 1-pixel-wide lane offset.
+                    let lane = wire_color_frame as f32 / 6.0;
+                    let pixel = 1.0 / 6.0;
+
+                    draw_frame(1);
+                    draw_rectangle(
+                        pos.x + lane - pixel,
+                        pos.y + lane,
+                        pixel,
+                        pixel * 2.0,
+                        BLACK,
+                    );
+                    if wire_color_frame < 4 {
+                        draw_rectangle(
+                            pos.x + lane + pixel * 2.0,
+                            pos.y + lane,
+                            pixel,
+                            pixel * 2.0,
+                            BLACK,
+                        );
+                    }
+                    draw_frame(0);
+
+                    continue;
+                }
+
+                let has_neighbours = grid.has_neighbours(*wire_color, x, y);
+
                 let wire_type_frame = match has_neighbours {
                     // [down, right, up, left]
                     [true, false, true, false] => 0,
@@ -899,24 +1633,7 @@ fn update_wires_texture(
                     _ => 6,
                 };
 
-                // The wires texture is vertically split into frames by wire
-                // direction, and horizontally split by wire color
-                let frame_width = (resources.wires.width() as u16 / wire_color_frames) as f32;
-                let frame_height = (resources.wires.height() as u16 / wire_type_frames) as f32;
-                let frame_x = (frame_width as u16 * wire_color_frame) as f32;
-                let frame_y = (frame_height as u16 * wire_type_frame) as f32;
-
-                draw_texture_ex(
-                    resources.wires,
-                    pos.x,
-                    pos.y,
-                    WHITE,
-                    DrawTextureParams {
-                        dest_size: Some(vec2(1.0, 1.0)),
-                        source: Some(Rect::new(frame_x, frame_y, frame_width, frame_height)),
-                        ..Default::default()
-                    },
-                );
+                draw_frame(wire_type_frame);
             }
         }
     }
@@ -941,6 +1658,8 @@ fn update_signals_texture(grid: &WireGrid, mutable_resources: &mut MutableSubRes
     if old_size != grid.size() {
         mutable_resources.sub_signals_image =
             Image::gen_image_color(width as u16, height as u16, BLANK);
+        mutable_resources.sub_signal_flow_image =
+            Image::gen_image_color(width as u16, height as u16, BLANK);
     }
 
     let colors = &[
@@ -951,11 +1670,23 @@ fn update_signals_texture(grid: &WireGrid, mutable_resources: &mut MutableSubRes
     ];
 
     let image = &mut mutable_resources.sub_signals_image;
+    let flow_image = &mut mutable_resources.sub_signal_flow_image;
 
     for y in 0..height {
         for x in 0..width {
             let cell = grid.cell(x, y);
 
+            // Signals lose strength as they travel, so the raw strength tells
+            // how far each cell is from the signal's source.
+            let flow = |wire_color| (cell.value(wire_color).signal() % 256) as u8;
+            let flow_color = Color::from_rgba(
+                flow(WireColor::Purple),
+                flow(WireColor::Brown),
+                flow(WireColor::Blue),
+                flow(WireColor::Green),
+            );
+            flow_image.set_pixel(x as u32, y as u32, flow_color);
+
             for wire_color in colors {
                 let signal = cell.value(*wire_color).signal();
                 let brightness = (signal as f32 / 256.0 + 0.2).clamp(0.0, 1.0);
@@ -983,57 +1714,167 @@ fn update_signals_texture(grid: &WireGrid, mutable_resources: &mut MutableSubRes
     if old_size != grid.size() {
         mutable_resources.sub_signals.delete();
         mutable_resources.sub_signals = Texture2D::from_image(image);
+
+        // Blending with neighbouring cells would shift the pulses sideways
+        mutable_resources.sub_signal_flow.delete();
+        mutable_resources.sub_signal_flow = Texture2D::from_image(flow_image);
+        mutable_resources
+            .sub_signal_flow
+            .set_filter(FilterMode::Nearest);
     } else {
         mutable_resources.sub_signals.update(image);
+        mutable_resources.sub_signal_flow.update(flow_image);
     }
 }
 
-fn draw_wire_plan(dragging: &Option<Dragging>, sub_index: usize, cursor_tile: (usize, usize)) {
+fn draw_wire_plan(
+    dragging: &Option<Dragging>,
+    submarine: &SubmarineState,
+    sub_index: usize,
+    cursor_tile: (usize, usize),
+) {
     if let Some(Dragging::Wires {
+        auto_route,
         dragging_from_tile,
         dragging_from_sub,
         ..
     }) = dragging
     {
         if *dragging_from_sub == sub_index {
-            let (start_x, start_y) = *dragging_from_tile;
-            let (end_x, end_y) = cursor_tile;
+            let route = plan_wire_route(submarine, *dragging_from_tile, cursor_tile, *auto_route);
+            let center = |(x, y): (usize, usize)| vec2(x as f32 + 0.5, y as f32 + 0.5);
 
-            let x_length = (start_x as i32 - end_x as i32).abs();
-            let y_length = (start_y as i32 - end_y as i32).abs();
+            if let (Some(&start), Some(&end)) = (route.first(), route.last()) {
+                draw_circle(center(start).x, center(start).y, 0.2, WHITE);
+                draw_circle(center(end).x, center(end).y, 0.2, WHITE);
+            }
 
-            let (start_x, start_y, end_x, end_y) = if x_length > y_length {
-                (start_x, start_y, end_x, start_y)
-            } else {
-                (start_x, start_y, start_x, end_y)
-            };
-            let start_x = start_x as f32 + 0.5;
-            let start_y = start_y as f32 + 0.5;
-            let end_x = end_x as f32 + 0.5;
-            let end_y = end_y as f32 + 0.5;
+            for leg in route.windows(2) {
+                let (from, to) = (center(leg[0]), center(leg[1]));
+                draw_line(from.x, from.y, to.x, to.y, 0.2, WHITE);
+            }
+        }
+    }
+}
 
-            draw_circle(start_x, start_y, 0.2, WHITE);
-            draw_circle(end_x, end_y, 0.2, WHITE);
-            draw_line(start_x, start_y, end_x, end_y, 0.2, WHITE);
+/// Draws a ruler from where the measurement started to the cursor, labelled with
+/// the distance in cells of the submarine the measurement started on.
+fn draw_measurement(
+    dragging: &Option<Dragging>,
+    sub_index: usize,
+    sub_cursor: (f32, f32),
+    sub_camera: &Camera2D,
+) {
+    if let Some(Dragging::Measure {
+        dragging_from_tile,
+        dragging_from_sub,
+    }) = dragging
+    {
+        if *dragging_from_sub != sub_index {
+            return;
         }
+
+        // The cursor may have left the submarine's grid, so use the raw
+        // sub-relative cursor instead of the cursor tile.
+        let (start_x, start_y) = (dragging_from_tile.0 as i32, dragging_from_tile.1 as i32);
+        let (end_x, end_y) = (sub_cursor.0.floor() as i32, sub_cursor.1.floor() as i32);
+
+        let (delta_x, delta_y) = (end_x - start_x, end_y - start_y);
+        let distance = ((delta_x * delta_x + delta_y * delta_y) as f32).sqrt();
+
+        let start = vec2(start_x as f32 + 0.5, start_y as f32 + 0.5);
+        let end = vec2(end_x as f32 + 0.5, end_y as f32 + 0.5);
+
+        draw_line(start.x, start.y, end.x, start.y, 0.1, SKYBLUE);
+        draw_line(end.x, start.y, end.x, end.y, 0.1, SKYBLUE);
+        draw_line(start.x, start.y, end.x, end.y, 0.2, YELLOW);
+        draw_circle(start.x, start.y, 0.3, YELLOW);
+        draw_circle(end.x, end.y, 0.3, YELLOW);
+
+        let text = format!(
+            "dx: {}, dy: {}, distance: {:.1}",
+            delta_x, delta_y, distance
+        );
+
+        // Draw the label in screen space, so that it stays readable at any
+        // zoom level.
+        let label_position = sub_camera.world_to_screen(end);
+
+        push_camera_state();
+        set_default_camera();
+        draw_text(
+            &text,
+            label_position.x + 12.0,
+            label_position.y - 12.0,
+            20.0,
+            YELLOW,
+        );
+        pop_camera_state();
     }
 }
 
-fn draw_wires(grid: &WireGrid, resources: &Resources, mutable_resources: &MutableSubResources) {
+fn draw_wires(
+    grid: &WireGrid,
+    draw_settings: &DrawSettings,
+    pixels_per_cell: f32,
+    resources: &Resources,
+    mutable_resources: &MutableSubResources,
+) {
+    let wire_material = match resources.wire_material {
+        Some(wire_material) => wire_material,
+        None => return,
+    };
+
     let (width, height) = grid.size();
 
     let pos = to_screen_coords(0, 0);
     let grid_size = vec2(width as f32, height as f32);
 
-    resources
-        .wire_material
-        .set_texture("sub_wires", mutable_resources.sub_wires.texture);
-    resources
-        .wire_material
-        .set_texture("sub_signals", mutable_resources.sub_signals);
-    resources.wire_material.set_uniform("grid_size", grid_size);
+    wire_material.set_texture("sub_wires", mutable_resources.sub_wires.texture);
+    wire_material.set_texture("sub_signals", mutable_resources.sub_signals);
+    wire_material.set_texture("sub_signal_flow", mutable_resources.sub_signal_flow);
+    wire_material.set_uniform("grid_size", grid_size);
+    wire_material.set_uniform(
+        "colorblind",
+        if draw_settings.colorblind_wires {
+            1.0f32
+        } else {
+            0.0
+        },
+    );
+    wire_material.set_uniform(
+        "patterns",
+        if draw_settings.wire_patterns {
+            1.0f32
+        } else {
+            0.0
+        },
+    );
 
-    gl_use_material(resources.wire_material);
+    wire_material.set_uniform(
+        "animate_signals",
+        if draw_settings.animate_signals {
+            1.0f32
+        } else {
+            0.0
+        },
+    );
+    // Only the fractional part matters, and keeping it small avoids losing
+    // precision in the shader.
+    let flow_time = (get_time() * SIGNAL_FLOW_SPEED).fract() as f32;
+    wire_material.set_uniform("flow_time", flow_time);
+    wire_material.set_uniform(
+        "smooth_wires",
+        if draw_settings.smooth_wires {
+            1.0f32
+        } else {
+            0.0
+        },
+    );
+    // Wire cells are 6x6 texels each
+    wire_material.set_uniform("pixels_per_texel", pixels_per_cell / 6.0);
+
+    gl_use_material(wire_material);
 
     draw_texture_ex(
         mutable_resources.sub_wires.texture,
@@ -1058,27 +1899,6 @@ pub(crate) fn object_rect(object: &Object) -> Rect {
     Rect::new(pos.x + 1.0, pos.y + 1.0, size.x, size.y)
 }
 
-pub(crate) fn object_size(object_type: &ObjectType) -> (usize, usize) {
-    match object_type {
-        ObjectType::Door { .. } => (20, 7),
-        ObjectType::VerticalDoor { .. } => (5, 17),
-        ObjectType::Reactor { .. } => (32, 17),
-        ObjectType::Lamp => (5, 4),
-        ObjectType::Gauge { .. } => (7, 7),
-        ObjectType::SmallPump { .. } => (9, 7),
-        ObjectType::LargePump { .. } => (30, 18),
-        ObjectType::JunctionBox { .. } => (6, 8),
-        ObjectType::NavController { .. } => (9, 15),
-        ObjectType::Sonar { .. } => (19, 17),
-        ObjectType::Engine { .. } => (37, 20),
-        ObjectType::Battery { .. } => (8, 10),
-        ObjectType::BundleInput { .. } => (5, 3),
-        ObjectType::BundleOutput { .. } => (5, 3),
-        ObjectType::DockingConnectorTop { .. } => (20, 8),
-        ObjectType::DockingConnectorBottom { .. } => (20, 8),
-    }
-}
-
 fn object_frames(object_type: &ObjectType) -> (u16, u16) {
     match object_type {
         ObjectType::Door { .. } => (24, 2),
@@ -1097,6 +1917,13 @@ fn object_frames(object_type: &ObjectType) -> (u16, u16) {
         ObjectType::BundleOutput { .. } => (8, 1),
         ObjectType::DockingConnectorTop { .. } => (18, 2),
         ObjectType::DockingConnectorBottom { .. } => (18, 2),
+        ObjectType::Keypad { .. } => (10, 1),
+        ObjectType::CollisionSensor { .. } => (2, 1),
+        ObjectType::PowerMeter { .. } => (4, 1),
+        ObjectType::SignalMap { .. } => (2, 1),
+        ObjectType::FloodMeter { .. } => (5, 1),
+        ObjectType::EdgeDetector { .. } => (6, 1),
+        ObjectType::Radio { .. } => (16, 1),
     }
 }
 
@@ -1118,38 +1945,43 @@ fn object_texture(object_type: &ObjectType, resources: &Resources) -> Texture2D
         ObjectType::BundleOutput { .. } => resources.bundle_output,
         ObjectType::DockingConnectorTop { .. } => resources.docking_connector_top,
         ObjectType::DockingConnectorBottom { .. } => resources.docking_connector_bottom,
+        ObjectType::Keypad { .. } => resources.keypad,
+        ObjectType::CollisionSensor { .. } => resources.collision_sensor,
+        ObjectType::PowerMeter { .. } => resources.power_meter,
+        ObjectType::SignalMap { .. } => resources.signal_map,
+        ObjectType::FloodMeter { .. } => resources.flood_meter,
+        ObjectType::EdgeDetector { .. } => resources.edge_detector,
+        ObjectType::Radio { .. } => resources.radio,
     }
 }
 
-fn object_connectors(object_type: &ObjectType) -> &'static [(u32, u32)] {
-    match object_type {
-        ObjectType::Door { .. } => &[(2, 4), (19, 4)],
-        ObjectType::VerticalDoor { .. } => &[],
-        ObjectType::Reactor { .. } => &[(29, 5)],
-        ObjectType::Lamp => &[(3, 1)],
-        ObjectType::Gauge { .. } => &[(4, 2), (4, 6)],
-        ObjectType::SmallPump { .. } => &[(3, 2), (5, 2)],
-        ObjectType::LargePump { .. } => &[(10, 3), (13, 3)],
-        ObjectType::JunctionBox { .. } => &[(3, 2), (5, 3), (5, 4), (5, 5), (5, 6)],
-        ObjectType::NavController { .. } => &[(2, 4), (8, 4), (8, 6)],
-        ObjectType::Sonar { .. } => &[(2, 15)],
-        ObjectType::Engine { .. } => &[(36, 6), (36, 8)],
-        ObjectType::Battery { .. } => &[(2, 4), (7, 4)],
-        ObjectType::BundleInput { .. } => &[(4, 2)],
-        ObjectType::BundleOutput { .. } => &[(4, 2)],
-        ObjectType::DockingConnectorTop { .. } => &[(1, 6), (20, 6)],
-        ObjectType::DockingConnectorBottom { .. } => &[(1, 4), (20, 4)],
+fn draw_objects(
+    objects: &[Object],
+    simplified: bool,
+    resources: &Resources,
+    placing_object: Option<&PlacingObject>,
+) {
+    for object in objects {
+        if simplified {
+            draw_simplified_object(object);
+        } else {
+            draw_object(object, DrawObject::Normal, resources);
+        }
     }
-}
 
-fn draw_objects(objects: &[Object], resources: &Resources, placing_object: Option<&PlacingObject>) {
-    for object in objects {
-        draw_object(object, DrawObject::Normal, resources);
+    // Too small to make out when objects are simplified
+    if !simplified {
+        for object in objects {
+            if object.note.is_some() {
+                draw_note_icon(object);
+            }
+        }
     }
 
     if let Some(PlacingObject {
         position: Some((x, y)),
         object_type,
+        snapped,
         ..
     }) = placing_object
     {
@@ -1157,6 +1989,131 @@ fn draw_objects(objects: &[Object], resources: &Resources, placing_object: Optio
             object_type: object_type.clone(),
             position: (*x as u32, *y as u32),
             powered: false,
+            label: None,
+            note: None,
+            interaction_cooldown: 0,
+        };
+
+        draw_object(&object, DrawObject::Ghost, resources);
+
+        if *snapped {
+            for port in object_type.wire_ports() {
+                let x = (*x as u32 + port.offset.0) as f32 + 0.5;
+                let y = (*y as u32 + port.offset.1) as f32 + 0.5;
+                let transparent_green = Color::new(0.0, 1.0, 0.2, 0.3);
+                draw_circle(x, y, 0.5, transparent_green);
+                draw_circle_lines(x, y, 0.5, 0.1, GREEN);
+            }
+        }
+    }
+}
+
+/// A small sheet of paper on the object's top-right corner, telling that it has
+/// a note; only drawn, so clicking it still clicks whatever is underneath.
+fn draw_note_icon(object: &Object) {
+    let rect = object_rect(object);
+    let (width, height) = (1.0, 1.25);
+    let x = rect.x + rect.w - width;
+    let y = rect.y;
+
+    draw_rectangle(x, y, width, height, Color::new(1.0, 0.9, 0.5, 0.9));
+    draw_rectangle_lines(x, y, width, height, 0.1, Color::new(0.3, 0.25, 0.1, 1.0));
+    for line in 1..=2 {
+        let line_y = y + height * line as f32 / 3.0;
+        draw_line(
+            x + 0.2,
+            line_y,
+            x + width - 0.2,
+            line_y,
+            0.1,
+            Color::new(0.3, 0.25, 0.1, 1.0),
+        );
+    }
+}
+
+/// A box colored by the object's category, bright while the object is
+/// active and dim otherwise; readable even when sprites would be specks.
+fn draw_simplified_object(object: &Object) {
+    let rect = object_rect(object);
+
+    let color = match object_category(&object.object_type) {
+        ObjectCategory::Logic => Color::new(0.75, 0.45, 1.0, 1.0),
+        ObjectCategory::Power => Color::new(1.0, 0.85, 0.2, 1.0),
+        ObjectCategory::Mechanical => Color::new(0.3, 0.9, 1.0, 1.0),
+        ObjectCategory::Structural => Color::new(0.7, 0.7, 0.7, 1.0),
+    };
+
+    let (fill_alpha, outline_alpha) = if object_active(object) {
+        (0.5, 1.0)
+    } else {
+        (0.15, 0.5)
+    };
+
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(color.r, color.g, color.b, fill_alpha),
+    );
+    draw_rectangle_lines(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        1.0,
+        Color::new(color.r, color.g, color.b, outline_alpha),
+    );
+}
+
+/// Whether the object is currently doing something; not every object keeps
+/// its `powered` flag, so some are judged by their own state.
+fn object_active(object: &Object) -> bool {
+    match &object.object_type {
+        ObjectType::Reactor { active } => *active,
+        ObjectType::Battery { charge } => *charge > 0,
+        ObjectType::Engine { speed, .. }
+        | ObjectType::SmallPump { speed, .. }
+        | ObjectType::LargePump { speed, .. } => *speed != 0,
+        _ => object.powered,
+    }
+}
+
+fn draw_prefab_ghost(prefab: &Prefab, (offset_x, offset_y): (usize, usize), resources: &Resources) {
+    let semi_transparent = Color::new(0.0, 0.5, 0.5, 0.3);
+    draw_rectangle(
+        offset_x as f32,
+        offset_y as f32,
+        prefab.size.0 as f32,
+        prefab.size.1 as f32,
+        semi_transparent,
+    );
+
+    for (_color, points) in &prefab.wire_points {
+        for pair in points.windows(2) {
+            let start = vec2(
+                (offset_x + pair[0].0) as f32 + 0.5,
+                (offset_y + pair[0].1) as f32 + 0.5,
+            );
+            let end = vec2(
+                (offset_x + pair[1].0) as f32 + 0.5,
+                (offset_y + pair[1].1) as f32 + 0.5,
+            );
+            draw_line(start.x, start.y, end.x, end.y, 0.2, WHITE);
+        }
+    }
+
+    for object in &prefab.objects {
+        let object = Object {
+            object_type: object.object_type.clone(),
+            position: (
+                offset_x as u32 + object.position.0,
+                offset_y as u32 + object.position.1,
+            ),
+            powered: false,
+            label: None,
+            note: None,
+            interaction_cooldown: 0,
         };
 
         draw_object(&object, DrawObject::Ghost, resources);
@@ -1167,17 +2124,173 @@ fn draw_object_highlights(
     objects: &[Object],
     resources: &Resources,
     highlighting_object: Option<usize>,
+    highlighted_type: Option<&ObjectType>,
 ) {
     for (obj_id, object) in objects.iter().enumerate() {
-        if highlighting_object == Some(obj_id) {
+        let of_highlighted_type = highlighted_type.map_or(false, |object_type| {
+            same_listed_type(object_type, &object.object_type)
+        });
+
+        if highlighting_object == Some(obj_id) || of_highlighted_type {
             draw_object(object, DrawObject::Highlight, resources);
         }
     }
 }
 
+/// Drawn in screen space, so that labels stay readable at any zoom level.
+fn draw_object_labels(
+    submarines: &[SubmarineState],
+    camera: &Camera,
+    skip_offscreen_submarines: bool,
+) {
+    let font_size = 16.0;
+
+    for submarine in submarines {
+        if skip_offscreen_submarines && !submarine_on_screen(camera, submarine) {
+            continue;
+        }
+
+        let sub_camera = camera.to_macroquad_camera(Some(submarine.navigation.position));
+
+        for object in &submarine.objects {
+            let label = match &object.label {
+                Some(label) => label,
+                None => continue,
+            };
+
+            let rect = object_rect(object);
+            let top_middle = sub_camera.world_to_screen(vec2(rect.x + rect.w / 2.0, rect.y));
+            let text_size = measure_text(label, None, font_size as u16, 1.0);
+            let x = top_middle.x - text_size.width / 2.0;
+            let y = top_middle.y - 4.0;
+
+            draw_text(label, x + 1.0, y + 1.0, font_size, BLACK);
+            draw_text(label, x, y, font_size, WHITE);
+        }
+    }
+}
+
+/// Dim the rest of the submarine, so that only the inspected object stands
+/// out.
+fn draw_solo_object(
+    submarine: &SubmarineState,
+    inspected_object: &InspectedObject,
+    resources: &Resources,
+) {
+    let object = match submarine.objects.get(inspected_object.object) {
+        Some(object) => object,
+        None => return,
+    };
+
+    let (width, height) = submarine.water_grid.size();
+    let top_left = to_screen_coords(0, 0);
+    let dim = Color::new(0.0, 0.0, 0.0, 0.6);
+
+    draw_rectangle(top_left.x, top_left.y, width as f32, height as f32, dim);
+    draw_object(object, DrawObject::Normal, resources);
+}
+
+/// Drawn in world space, since the two docking points usually belong to
+/// submarines moving independently of each other.
+fn draw_docking_guides(submarines: &[SubmarineState]) {
+    let to_world = |(x, y): (i32, i32)| vec2(x as f32 / 16.0, y as f32 / 16.0);
+
+    for submarine in submarines {
+        for point in &submarine.docking_points {
+            let position = to_world(point.connection_point);
+
+            if point.connected_to.is_some() {
+                // Locked; a ring around the joined connectors
+                draw_circle(position.x, position.y, 1.0, RED);
+                draw_circle_lines(position.x, position.y, 2.5, 0.4, GREEN);
+                continue;
+            }
+
+            draw_circle(position.x, position.y, 1.0, SKYBLUE);
+
+            if let Some(other_point) = point.in_proximity_to {
+                // Each side draws half of the guide, up to the middle
+                let other_position = to_world(other_point);
+                let middle = (position + other_position) / 2.0;
+                let color = Color::new(1.0, 0.9, 0.2, 0.8);
+
+                draw_line(position.x, position.y, middle.x, middle.y, 0.2, color);
+
+                // A vertical marker at each point, to show how far off the
+                // horizontal alignment is
+                let marker_end = position.y + (middle.y - position.y).signum() * 3.0;
+                draw_line(position.x, position.y, position.x, marker_end, 0.3, color);
+                draw_line(
+                    position.x - 1.5,
+                    marker_end,
+                    position.x + 1.5,
+                    marker_end,
+                    0.3,
+                    color,
+                );
+            }
+        }
+    }
+}
+
+/// Drawn in the submarine's own camera space, so it moves along with it.
+fn draw_submarine_outline((width, height): (usize, usize)) {
+    let top_left = to_screen_coords(0, 0);
+    let outline = Color::new(1.0, 1.0, 1.0, 0.25);
+
+    draw_rectangle_lines(
+        top_left.x,
+        top_left.y,
+        width as f32,
+        height as f32,
+        0.5,
+        outline,
+    );
+}
+
+fn draw_flashing_wires(cells: &[(usize, usize)]) {
+    let alpha = 0.3 + 0.3 * (get_time() as f32 * 6.0).sin();
+    let color = Color::new(1.0, 0.3, 0.1, alpha);
+
+    for &(x, y) in cells {
+        let pos = to_screen_coords(x, y);
+        draw_rectangle(pos.x, pos.y, 1.0, 1.0, color);
+    }
+}
+
+fn draw_selected_objects(submarine: &SubmarineState, selected_objects: &[usize]) {
+    for &object_id in selected_objects {
+        if let Some(object) = submarine.objects.get(object_id) {
+            let rect = object_rect(object);
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 0.3, SKYBLUE);
+        }
+    }
+}
+
+/// Outline the inspected object, and highlight the wires on its traced port.
+fn draw_inspected_object(submarine: &SubmarineState, inspected_object: &InspectedObject) {
+    let object = match submarine.objects.get(inspected_object.object) {
+        Some(object) => object,
+        None => return,
+    };
+
+    let rect = object_rect(object);
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 0.3, YELLOW);
+
+    if let Some(traced_cells) = &inspected_object.traced_cells {
+        let highlight = Color::new(1.0, 1.0, 0.0, 0.4);
+
+        for &(x, y) in traced_cells {
+            let pos = to_screen_coords(x, y);
+            draw_rectangle(pos.x, pos.y, 1.0, 1.0, highlight);
+        }
+    }
+}
+
 fn draw_object_connectors(objects: &[Object]) {
     for object in objects {
-        for &(cell_x, cell_y) in object_connectors(&object.object_type) {
+        for port in object.object_type.wire_ports() {
+            let (cell_x, cell_y) = port.offset;
             let x = object.position.0 + cell_x;
             let y = object.position.1 + cell_y;
             let transparent_blue = Color::new(0.0, 0.2, 1.0, 0.2);
@@ -1187,6 +2300,32 @@ fn draw_object_connectors(objects: &[Object]) {
     }
 }
 
+fn draw_picked_port(objects: &[Object], object_id: usize, port_index: usize) {
+    let object = match objects.get(object_id) {
+        Some(object) => object,
+        None => return,
+    };
+
+    if let Some(port) = object.object_type.wire_ports().get(port_index) {
+        let (x, y) = port_cell(object, port);
+        draw_circle_lines(x as f32 + 0.5, y as f32 + 0.5, 0.8, 0.15, YELLOW);
+    }
+}
+
+fn draw_port_dots(objects: &[Object]) {
+    for object in objects {
+        for port in object.object_type.wire_ports() {
+            let (x, y) = port_cell(object, port);
+            let color = match port.direction {
+                PortDirection::Input => GREEN,
+                PortDirection::Output => Color::new(1.0, 0.6, 0.0, 1.0),
+            };
+            draw_circle(x as f32 + 0.5, y as f32 + 0.5, 0.25, color);
+            draw_circle_lines(x as f32 + 0.5, y as f32 + 0.5, 0.25, 0.05, BLACK);
+        }
+    }
+}
+
 enum DrawObject {
     Normal,
     Highlight,
@@ -1208,23 +2347,21 @@ fn draw_object(object: &Object, draw_type: DrawObject, resources: &Resources) {
     let frame_x = (frame_width as u16 * current_frame_column) as f32;
     let frame_y = (frame_height as u16 * current_frame_line) as f32;
 
-    if let DrawObject::Highlight = draw_type {
+    // Without its shader, the highlighted object is drawn as usual.
+    let hover_highlight = match draw_type {
+        DrawObject::Highlight => resources.hover_highlight,
+        _ => None,
+    };
+
+    if let Some(hover_highlight) = hover_highlight {
         let texture_resolution = vec2(texture.width(), texture.height());
-        resources
-            .hover_highlight
-            .set_uniform("input_resolution", texture_resolution);
-        resources.hover_highlight.set_uniform("frame_y", frame_y);
-        resources.hover_highlight.set_uniform("frame_x", frame_x);
-        resources
-            .hover_highlight
-            .set_uniform("frame_height", frame_height);
-        resources
-            .hover_highlight
-            .set_uniform("frame_width", frame_width);
-        resources
-            .hover_highlight
-            .set_texture("input_texture", texture);
-        gl_use_material(resources.hover_highlight);
+        hover_highlight.set_uniform("input_resolution", texture_resolution);
+        hover_highlight.set_uniform("frame_y", frame_y);
+        hover_highlight.set_uniform("frame_x", frame_x);
+        hover_highlight.set_uniform("frame_height", frame_height);
+        hover_highlight.set_uniform("frame_width", frame_width);
+        hover_highlight.set_texture("input_texture", texture);
+        gl_use_material(hover_highlight);
         let r = draw_rect;
         draw_rectangle(r.x, r.y, r.w, r.h, DARKBLUE);
         gl_use_default_material();
@@ -1290,35 +2427,53 @@ fn update_rocks_texture(grid: &RockGrid, mutable_resources: &mut MutableResource
 
 fn draw_engine_turbulence(
     submarines: &[SubmarineState],
+    camera: &Camera,
+    draw_settings: &DrawSettings,
     animation_ticks: u32,
     resources: &Resources,
     mutable_sub_resources: &mut [MutableSubResources],
 ) {
     for (sub_index, submarine) in submarines.iter().enumerate() {
+        // Particles are purely cosmetic, so off-screen ones are simply paused.
+        if draw_settings.skip_offscreen_submarines && !submarine_on_screen(camera, submarine) {
+            continue;
+        }
+
         for object in &submarine.objects {
             if let ObjectType::Engine { speed, .. } = &object.object_type {
                 let mutable_resources = mutable_sub_resources
                     .get_mut(sub_index)
                     .expect("All submarines should have their own MutableSubResources instance");
 
+                // Forward thrust pushes water out of the propeller on the left
+                // side; reverse thrust pushes it out of the engine's front.
+                let (engine_width, _) = object_size(&object.object_type);
+                let side = if *speed >= 0 {
+                    0.0
+                } else {
+                    engine_width as f32 - 3.0
+                };
                 let pos = vec2(
                     submarine.navigation.position.0 as f32 / 16.0 + object.position.0 as f32,
                     submarine.navigation.position.1 as f32 / 16.0 + object.position.1 as f32,
-                ) + vec2(0.0, 2.0);
+                ) + vec2(side, 2.0);
+
+                // Emission ramps up with thrust, so the wash fades out gradually
+                // as the engine spins down instead of stopping abruptly.
+                let emission_rate = (speed.unsigned_abs() as f32 / 32.0).min(1.0) * 5.0;
 
                 for _tick in 0..animation_ticks {
-                    if *speed != 0 {
-                        for _new_particle in 0..5 {
-                            let frame = (random() * 4.9) as u8;
-                            mutable_resources
-                                .turbulence_particles
-                                .push(TurbulenceParticle {
-                                    position: (pos.x + random() * 3.0, pos.y + random() * 6.0),
-                                    frame,
-                                    speed: *speed as f32 * (random() / 4.0 + 0.75),
-                                    life: (128.0 * (random() / 2.0 + 0.5)) as u8,
-                                });
-                        }
+                    let new_particles = (emission_rate + random()) as u32;
+                    for _new_particle in 0..new_particles {
+                        let frame = (random() * 4.9) as u8;
+                        mutable_resources
+                            .turbulence_particles
+                            .push(TurbulenceParticle {
+                                position: (pos.x + random() * 3.0, pos.y + random() * 6.0),
+                                frame,
+                                speed: *speed as f32 * (random() / 4.0 + 0.75),
+                                life: (128.0 * (random() / 2.0 + 0.5)) as u8,
+                            });
                     }
 
                     for particle in mutable_resources.turbulence_particles.iter_mut() {
@@ -1330,19 +2485,35 @@ fn draw_engine_turbulence(
 
                         particle.life -= 1;
                     }
-                    mutable_resources
-                        .turbulence_particles
-                        .retain(|particle| particle.life != 0);
+
+                    // New particles are pushed at the end, so the oldest ones
+                    // are at the start.
+                    let particles = &mut mutable_resources.turbulence_particles;
+                    let max_particles = draw_settings.max_turbulence_particles as usize;
+                    let over_cap = particles.len().saturating_sub(max_particles);
+                    for particle in particles.iter_mut().take(over_cap) {
+                        particle.life = particle.life.saturating_sub(OVER_CAP_PARTICLE_FADE);
+                    }
+
+                    particles.retain(|particle| particle.life != 0);
                 }
 
                 for particle in mutable_resources.turbulence_particles.iter_mut() {
                     let (x, y) = particle.position;
+                    let alpha = particle.life as f32 / 128.0;
+
+                    // Tint reverse wash so it can be told apart at a glance
+                    let color = if particle.speed >= 0.0 {
+                        Color::new(1.0, 1.0, 1.0, alpha)
+                    } else {
+                        Color::new(1.0, 0.8, 0.6, alpha)
+                    };
 
                     draw_texture_ex(
                         resources.turbulence,
                         x,
                         y,
-                        Color::new(1.0, 1.0, 1.0, particle.life as f32 / 128.0),
+                        color,
                         DrawTextureParams {
                             dest_size: Some(vec2(5.0, 5.0)),
                             source: Some(Rect::new(
@@ -1363,23 +2534,24 @@ fn draw_engine_turbulence(
 fn draw_rocks(
     grid: &RockGrid,
     collisions: &[(usize, usize)],
+    ambient: f32,
     resources: &Resources,
     mutable_resources: &mut MutableResources,
 ) {
+    let rock_material = match resources.rock_material {
+        Some(rock_material) => rock_material,
+        None => return,
+    };
+
     update_rocks_texture(grid, mutable_resources);
 
     let (width, height) = grid.size();
 
-    resources
-        .rock_material
-        .set_texture("rocks_texture", resources.rocks);
-    resources
-        .rock_material
-        .set_texture("sea_rocks", mutable_resources.sea_rocks);
-    resources
-        .rock_material
-        .set_uniform("sea_rocks_size", vec2(width as f32, height as f32));
-    gl_use_material(resources.rock_material);
+    rock_material.set_texture("rocks_texture", resources.rocks);
+    rock_material.set_texture("sea_rocks", mutable_resources.sea_rocks);
+    rock_material.set_uniform("sea_rocks_size", vec2(width as f32, height as f32));
+    rock_material.set_uniform("ambient", ambient);
+    gl_use_material(rock_material);
 
     // The world always starts here.
     let pos = vec2(0.0, 0.0);
@@ -1412,6 +2584,11 @@ fn draw_sonar(
     resources: &Resources,
     mutable_resources: &mut MutableSubResources,
 ) {
+    let sonar_material = match resources.sonar_material {
+        Some(sonar_material) => sonar_material,
+        None => return,
+    };
+
     let resolution = 16.0;
 
     // 13 cells, 6 pixels each
@@ -1515,19 +2692,15 @@ fn draw_sonar(
         let draw_rect = object_rect(object);
         let pos = draw_rect.point() + vec2(4.0, 2.0);
 
-        resources
-            .sonar_material
-            .set_texture("new_sonar_texture", texture);
-        resources.sonar_material.set_texture(
+        sonar_material.set_texture("new_sonar_texture", texture);
+        sonar_material.set_texture(
             "old_sonar_texture",
             mutable_resources.old_sonar_target.texture,
         );
-        resources
-            .sonar_material
-            .set_uniform("sonar_texture_size", sonar_size);
-        resources.sonar_material.set_uniform("pulse", sonar.pulse());
+        sonar_material.set_uniform("sonar_texture_size", sonar_size);
+        sonar_material.set_uniform("pulse", sonar.pulse());
 
-        gl_use_material(resources.sonar_material);
+        gl_use_material(sonar_material);
 
         draw_texture_ex(
             texture,
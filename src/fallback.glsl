@@ -0,0 +1,12 @@
+#version 100
+
+precision lowp float;
+
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec4 fallback_color;
+
+void main() {
+    gl_FragColor = texture2D(Texture, uv) * fallback_color;
+}
@@ -13,6 +13,7 @@
 pub(crate) mod collisions;
 pub(crate) mod objects;
 pub(crate) mod rocks;
+pub(crate) mod scenario;
 pub(crate) mod sonar;
 pub(crate) mod state;
 pub(crate) mod update;
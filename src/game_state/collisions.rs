@@ -43,6 +43,59 @@ pub(crate) fn update_rock_collisions(
     }
 }
 
+/// Bounce a submarine off whatever it collided with on this tick. Only its
+/// speed is changed; `update_position` then moves docked groups as one, so
+/// that they don't get pulled apart.
+///
+/// The submarine never leaves a collision faster than it went in, or than
+/// `separation` if that's higher, so collisions can't add energy over time.
+pub(crate) fn respond_to_collisions(
+    submarine: &mut SubmarineState,
+    restitution: u8,
+    separation: i32,
+) {
+    if submarine.collisions.is_empty() {
+        return;
+    }
+
+    let (width, height) = submarine.water_grid.size();
+    let (width, height) = (width as i64, height as i64);
+    let count = submarine.collisions.len() as i64;
+
+    let (sum_x, sum_y) = submarine
+        .collisions
+        .iter()
+        .fold((0, 0), |(sum_x, sum_y), &(x, y)| {
+            (sum_x + x as i64, sum_y + y as i64)
+        });
+
+    // Where the contacts are on average, relative to the middle of the
+    // submarine; scaled by 2 * count to stay in integers.
+    let contact_x = 2 * sum_x - count * width;
+    let contact_y = 2 * sum_y - count * height;
+
+    // Bounce along whichever axis the contacts are furthest out on, relative
+    // to the submarine's size; a hull scraping along a rock's top is pushed
+    // up, not sideways.
+    let (speed, towards) = if contact_x.abs() * height >= contact_y.abs() * width {
+        (&mut submarine.navigation.speed.0, contact_x.signum() as i32)
+    } else {
+        (&mut submarine.navigation.speed.1, contact_y.signum() as i32)
+    };
+
+    if towards == 0 {
+        return;
+    }
+
+    let mut speed_away = -*speed * towards;
+    if speed_away < 0 {
+        speed_away = -speed_away * restitution.min(16) as i32 / 16;
+    }
+    speed_away = speed_away.max(separation);
+
+    *speed = -speed_away * towards;
+}
+
 pub(crate) fn update_submarine_collisions(
     submarine1: &mut SubmarineState,
     submarine2: &SubmarineState,
@@ -1,8 +1,16 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    mem::discriminant,
+};
+
 use serde::{Deserialize, Serialize};
 
 use crate::game_state::state::{Navigation, SubmarineState};
 
-use super::wires::{StoredSignal, THIN_COLORS};
+use super::{
+    water::{CellTemplate, WaterGrid},
+    wires::{StoredSignal, WireColor, WireGrid, THIN_COLORS},
+};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct Object {
@@ -11,6 +19,16 @@ pub(crate) struct Object {
     pub position: (u32, u32),
 
     pub powered: bool,
+
+    /// A name to show above the object, like "Starboard pump".
+    pub label: Option<String>,
+
+    /// Free text on how the object is meant to be used, for whoever works on
+    /// the design next.
+    pub note: Option<String>,
+
+    /// Updates left until the object takes interactions again.
+    pub interaction_cooldown: u16,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -18,10 +36,21 @@ pub(crate) enum ObjectType {
     Door {
         state: DoorState,
         progress: u8,
+        /// If set, the door only opens when this exact logic value is received.
+        required_code: Option<i8>,
+        /// If set, the door closes by itself after being left open for this
+        /// many updates.
+        auto_close: Option<u16>,
+        /// Updates the door has been fully open for since it was last
+        /// commanded open; see `update_auto_close`.
+        open_for: u16,
     },
     VerticalDoor {
         state: DoorState,
         progress: u8,
+        required_code: Option<i8>,
+        auto_close: Option<u16>,
+        open_for: u16,
     },
     Reactor {
         active: bool,
@@ -29,7 +58,12 @@ pub(crate) enum ObjectType {
     Lamp,
     Gauge {
         value: i8,
+        /// Shows the power on its input instead of a logic value; the value
+        /// is then half of that power, same as a power meter's.
+        power: bool,
     },
+    /// Pumps water in at positive speeds and out at negative ones; the
+    /// speed input is clamped to ±100, which is full speed.
     SmallPump {
         target_speed: i8,
         speed: i8,
@@ -78,12 +112,58 @@ pub(crate) enum ObjectType {
         connected: bool,
         previous_connected: bool,
     },
+    Keypad {
+        entered: i8,
+    },
+    CollisionSensor {
+        triggered: bool,
+    },
+    /// Reports the power on its input as a logic value of half that power,
+    /// without drawing from it.
+    PowerMeter {
+        power: u8,
+    },
+    /// Linearly remaps the last logic value received from the input range
+    /// onto the output range; swapping the output bounds inverts the signal.
+    SignalMap {
+        in_low: i8,
+        in_high: i8,
+        out_low: i8,
+        out_high: i8,
+        value: i8,
+    },
+    /// Reports how flooded the whole submarine is, as a logic value from 0
+    /// (dry) to 127 (every inside cell full).
+    FloodMeter {
+        flooding: u8,
+    },
+    /// Sends a logic pulse of 100 for a single tick whenever its input
+    /// crosses zero in the direction given by `mode`, and 0 otherwise.
+    EdgeDetector {
+        /// The input on the previous tick, saved so that loading a game
+        /// doesn't look like an edge.
+        last: i8,
+        mode: EdgeMode,
+    },
+    /// Sends the logic value on its input to radios on the same channel in
+    /// other submarines within range, and outputs what it hears from them.
+    Radio {
+        channel: u8,
+        /// The value on its input, if anything is connected to it.
+        transmitting: Option<i8>,
+        /// The value heard from the strongest transmitter in range.
+        received: Option<i8>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct ObjectTemplate {
     pub object_type: ObjectTypeTemplate,
     pub position: (u32, u32),
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -93,12 +173,24 @@ pub(crate) enum ObjectTypeTemplate {
         state: DoorState,
         #[serde(default, skip_serializing_if = "is_default")]
         progress: u8,
+        #[serde(default, skip_serializing_if = "is_default")]
+        required_code: Option<i8>,
+        #[serde(default, skip_serializing_if = "is_default")]
+        auto_close: Option<u16>,
+        #[serde(default, skip_serializing_if = "is_default")]
+        open_for: u16,
     },
     VerticalDoor {
         #[serde(default, skip_serializing_if = "is_default")]
         state: DoorState,
         #[serde(default, skip_serializing_if = "is_default")]
         progress: u8,
+        #[serde(default, skip_serializing_if = "is_default")]
+        required_code: Option<i8>,
+        #[serde(default, skip_serializing_if = "is_default")]
+        auto_close: Option<u16>,
+        #[serde(default, skip_serializing_if = "is_default")]
+        open_for: u16,
     },
     Reactor {
         active: bool,
@@ -107,6 +199,8 @@ pub(crate) enum ObjectTypeTemplate {
     Gauge {
         #[serde(default, skip_serializing_if = "is_default")]
         value: i8,
+        #[serde(default, skip_serializing_if = "is_default")]
+        power: bool,
     },
     SmallPump {
         #[serde(default, skip_serializing_if = "is_default")]
@@ -176,6 +270,43 @@ pub(crate) enum ObjectTypeTemplate {
         #[serde(default, skip_serializing_if = "is_default")]
         previous_connected: bool,
     },
+    Keypad {
+        #[serde(default, skip_serializing_if = "is_default")]
+        entered: i8,
+    },
+    CollisionSensor {
+        #[serde(default, skip_serializing_if = "is_default")]
+        triggered: bool,
+    },
+    PowerMeter {
+        #[serde(default, skip_serializing_if = "is_default")]
+        power: u8,
+    },
+    SignalMap {
+        in_low: i8,
+        in_high: i8,
+        out_low: i8,
+        out_high: i8,
+        #[serde(default, skip_serializing_if = "is_default")]
+        value: i8,
+    },
+    FloodMeter {
+        #[serde(default, skip_serializing_if = "is_default")]
+        flooding: u8,
+    },
+    EdgeDetector {
+        #[serde(default, skip_serializing_if = "is_default")]
+        last: i8,
+        #[serde(default, skip_serializing_if = "is_default")]
+        mode: EdgeMode,
+    },
+    Radio {
+        channel: u8,
+        #[serde(default, skip_serializing_if = "is_default")]
+        transmitting: Option<i8>,
+        #[serde(default, skip_serializing_if = "is_default")]
+        received: Option<i8>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -184,6 +315,48 @@ pub(crate) enum DoorState {
     Closing,
 }
 
+/// Which zero crossings an edge detector reacts to.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EdgeMode {
+    /// From zero or below to above zero.
+    Rising,
+    /// From above zero to zero or below.
+    Falling,
+    Both,
+}
+
+/// A cell on an object where wires connect to it.
+#[derive(Clone, Copy)]
+pub(crate) struct WirePort {
+    /// Position relative to the object's position.
+    pub offset: (u32, u32),
+    pub direction: PortDirection,
+    pub signal: PortSignal,
+    pub name: &'static str,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum PortDirection {
+    Input,
+    Output,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum PortSignal {
+    Power,
+    Logic,
+    /// Passes along whatever is on the wire, power or logic.
+    Any,
+}
+
+impl PortSignal {
+    /// Whether a wire from a port sending `self` makes sense on a port
+    /// receiving `other`.
+    pub(crate) fn compatible_with(self, other: PortSignal) -> bool {
+        self == other || self == PortSignal::Any || other == PortSignal::Any
+    }
+}
+
 pub(crate) struct NavControl {
     pub target_speed: (i32, i32),
     pub target_acceleration: (i32, i32),
@@ -200,6 +373,25 @@ impl Default for DoorState {
     }
 }
 
+impl Default for EdgeMode {
+    fn default() -> Self {
+        EdgeMode::Rising
+    }
+}
+
+impl EdgeMode {
+    fn is_edge(self, last: i8, value: i8) -> bool {
+        let rising = last <= 0 && value > 0;
+        let falling = last > 0 && value <= 0;
+
+        match self {
+            EdgeMode::Rising => rising,
+            EdgeMode::Falling => falling,
+            EdgeMode::Both => rising || falling,
+        }
+    }
+}
+
 impl DoorState {
     #[must_use = "This method does not mutate the original object."]
     fn toggle(&self) -> DoorState {
@@ -234,6 +426,9 @@ pub(crate) const OBJECT_TYPES: &[(&str, ObjectType)] = &[
         ObjectType::Door {
             state: DoorState::Closing,
             progress: 0,
+            required_code: None,
+            auto_close: None,
+            open_for: 0,
         },
     ),
     (
@@ -241,11 +436,27 @@ pub(crate) const OBJECT_TYPES: &[(&str, ObjectType)] = &[
         ObjectType::VerticalDoor {
             state: DoorState::Closing,
             progress: 0,
+            required_code: None,
+            auto_close: None,
+            open_for: 0,
         },
     ),
     ("Reactor", ObjectType::Reactor { active: false }),
     ("Lamp", ObjectType::Lamp),
-    ("Gauge", ObjectType::Gauge { value: 0 }),
+    (
+        "Gauge",
+        ObjectType::Gauge {
+            value: 0,
+            power: false,
+        },
+    ),
+    (
+        "Power gauge",
+        ObjectType::Gauge {
+            value: 0,
+            power: true,
+        },
+    ),
     (
         "Small pump",
         ObjectType::SmallPump {
@@ -312,14 +523,743 @@ pub(crate) const OBJECT_TYPES: &[(&str, ObjectType)] = &[
             previous_connected: false,
         },
     ),
+    ("Keypad", ObjectType::Keypad { entered: 0 }),
+    (
+        "Collision sensor",
+        ObjectType::CollisionSensor { triggered: false },
+    ),
+    ("Power meter", ObjectType::PowerMeter { power: 0 }),
+    (
+        "Signal map",
+        ObjectType::SignalMap {
+            in_low: 0,
+            in_high: 127,
+            out_low: 127,
+            out_high: 0,
+            value: 0,
+        },
+    ),
+    ("Flood meter", ObjectType::FloodMeter { flooding: 0 }),
+    (
+        "Edge detector",
+        ObjectType::EdgeDetector {
+            last: 0,
+            mode: EdgeMode::Rising,
+        },
+    ),
+    (
+        "Radio",
+        ObjectType::Radio {
+            channel: 0,
+            transmitting: None,
+            received: None,
+        },
+    ),
+];
+
+const fn input(x: u32, y: u32, signal: PortSignal, name: &'static str) -> WirePort {
+    WirePort {
+        offset: (x, y),
+        direction: PortDirection::Input,
+        signal,
+        name,
+    }
+}
+
+const fn output(x: u32, y: u32, signal: PortSignal, name: &'static str) -> WirePort {
+    WirePort {
+        offset: (x, y),
+        direction: PortDirection::Output,
+        signal,
+        name,
+    }
+}
+
+use PortSignal::{Any, Logic, Power};
+
+const DOOR_PORTS: &[WirePort] = &[
+    input(2, 4, Logic, "open/close"),
+    input(19, 4, Logic, "open/close, or denied if code-locked"),
+];
+/// Vertical doors only take wires once they are code-locked.
+const LOCKED_VERTICAL_DOOR_PORTS: &[WirePort] =
+    &[input(1, 2, Logic, "code"), output(4, 2, Logic, "denied")];
+const REACTOR_PORTS: &[WirePort] = &[output(29, 5, Power, "power")];
+const LAMP_PORTS: &[WirePort] = &[input(3, 1, Power, "power")];
+const GAUGE_PORTS: &[WirePort] = &[input(4, 2, Logic, "value"), output(4, 6, Logic, "value")];
+const POWER_GAUGE_PORTS: &[WirePort] = &[input(4, 2, Power, "power"), output(4, 6, Logic, "level")];
+const SMALL_PUMP_PORTS: &[WirePort] = &[input(3, 2, Power, "power"), input(5, 2, Logic, "speed")];
+const LARGE_PUMP_PORTS: &[WirePort] = &[input(10, 3, Power, "power"), input(13, 3, Logic, "speed")];
+const JUNCTION_BOX_PORTS: &[WirePort] = &[
+    input(3, 2, Any, "input"),
+    output(5, 3, Any, "output 1"),
+    output(5, 4, Any, "output 2"),
+    output(5, 5, Any, "output 3"),
+    output(5, 6, Any, "output 4"),
+];
+const NAV_CONTROLLER_PORTS: &[WirePort] = &[
+    input(2, 4, Power, "power"),
+    output(8, 4, Logic, "pump speed"),
+    output(8, 6, Logic, "engine speed"),
+    input(2, 9, Logic, "target x"),
+    input(2, 11, Logic, "target y"),
+];
+const SONAR_PORTS: &[WirePort] = &[input(2, 15, Power, "power")];
+const ENGINE_PORTS: &[WirePort] = &[input(36, 6, Power, "power"), input(36, 8, Logic, "speed")];
+const BATTERY_PORTS: &[WirePort] = &[input(2, 4, Power, "charge"), output(7, 4, Power, "power")];
+const BUNDLE_INPUT_PORTS: &[WirePort] = &[input(4, 2, Any, "signal")];
+const BUNDLE_OUTPUT_PORTS: &[WirePort] = &[output(4, 2, Any, "signal")];
+/// The left port is 100 for as long as the connector is docked and -100
+/// otherwise, while the right one only pulses 100 when docking and -100 when
+/// undocking.
+const DOCKING_CONNECTOR_TOP_PORTS: &[WirePort] = &[
+    output(1, 6, Logic, "docked"),
+    output(20, 6, Logic, "dock/undock pulse"),
+];
+const DOCKING_CONNECTOR_BOTTOM_PORTS: &[WirePort] = &[
+    output(1, 4, Logic, "docked"),
+    output(20, 4, Logic, "dock/undock pulse"),
+];
+const KEYPAD_PORTS: &[WirePort] = &[output(3, 6, Logic, "code")];
+const COLLISION_SENSOR_PORTS: &[WirePort] = &[output(2, 4, Logic, "collision")];
+const POWER_METER_PORTS: &[WirePort] = &[input(1, 2, Power, "power"), output(5, 2, Logic, "level")];
+const SIGNAL_MAP_PORTS: &[WirePort] = &[input(2, 3, Logic, "input"), output(6, 3, Logic, "output")];
+const FLOOD_METER_PORTS: &[WirePort] = &[output(4, 7, Logic, "flooding")];
+const EDGE_DETECTOR_PORTS: &[WirePort] =
+    &[input(2, 4, Logic, "input"), output(6, 4, Logic, "pulse")];
+const RADIO_PORTS: &[WirePort] = &[
+    input(2, 6, Logic, "transmit"),
+    output(6, 6, Logic, "receive"),
+];
+
+impl ObjectType {
+    /// The cells where wires connect to this object, relative to its position.
+    pub(crate) fn wire_ports(&self) -> &'static [WirePort] {
+        match self {
+            ObjectType::Door { .. } => DOOR_PORTS,
+            ObjectType::VerticalDoor {
+                required_code: Some(_),
+                ..
+            } => LOCKED_VERTICAL_DOOR_PORTS,
+            ObjectType::VerticalDoor { .. } => &[],
+            ObjectType::Reactor { .. } => REACTOR_PORTS,
+            ObjectType::Lamp => LAMP_PORTS,
+            ObjectType::Gauge { power: false, .. } => GAUGE_PORTS,
+            ObjectType::Gauge { power: true, .. } => POWER_GAUGE_PORTS,
+            ObjectType::SmallPump { .. } => SMALL_PUMP_PORTS,
+            ObjectType::LargePump { .. } => LARGE_PUMP_PORTS,
+            ObjectType::JunctionBox { .. } => JUNCTION_BOX_PORTS,
+            ObjectType::NavController { .. } => NAV_CONTROLLER_PORTS,
+            ObjectType::Sonar { .. } => SONAR_PORTS,
+            ObjectType::Engine { .. } => ENGINE_PORTS,
+            ObjectType::Battery { .. } => BATTERY_PORTS,
+            ObjectType::BundleInput { .. } => BUNDLE_INPUT_PORTS,
+            ObjectType::BundleOutput { .. } => BUNDLE_OUTPUT_PORTS,
+            ObjectType::DockingConnectorTop { .. } => DOCKING_CONNECTOR_TOP_PORTS,
+            ObjectType::DockingConnectorBottom { .. } => DOCKING_CONNECTOR_BOTTOM_PORTS,
+            ObjectType::Keypad { .. } => KEYPAD_PORTS,
+            ObjectType::CollisionSensor { .. } => COLLISION_SENSOR_PORTS,
+            ObjectType::PowerMeter { .. } => POWER_METER_PORTS,
+            ObjectType::SignalMap { .. } => SIGNAL_MAP_PORTS,
+            ObjectType::FloodMeter { .. } => FLOOD_METER_PORTS,
+            ObjectType::EdgeDetector { .. } => EDGE_DETECTOR_PORTS,
+            ObjectType::Radio { .. } => RADIO_PORTS,
+        }
+    }
+}
+
+/// Width and height in cells; objects are drawn one cell to the right and
+/// below their position.
+pub(crate) fn object_size(object_type: &ObjectType) -> (usize, usize) {
+    match object_type {
+        ObjectType::Door { .. } => (20, 7),
+        ObjectType::VerticalDoor { .. } => (5, 17),
+        ObjectType::Reactor { .. } => (32, 17),
+        ObjectType::Lamp => (5, 4),
+        ObjectType::Gauge { .. } => (7, 7),
+        ObjectType::SmallPump { .. } => (9, 7),
+        ObjectType::LargePump { .. } => (30, 18),
+        ObjectType::JunctionBox { .. } => (6, 8),
+        ObjectType::NavController { .. } => (9, 15),
+        ObjectType::Sonar { .. } => (19, 17),
+        ObjectType::Engine { .. } => (37, 20),
+        ObjectType::Battery { .. } => (8, 10),
+        ObjectType::BundleInput { .. } => (5, 3),
+        ObjectType::BundleOutput { .. } => (5, 3),
+        ObjectType::DockingConnectorTop { .. } => (20, 8),
+        ObjectType::DockingConnectorBottom { .. } => (20, 8),
+        ObjectType::Keypad { .. } => (5, 7),
+        ObjectType::CollisionSensor { .. } => (5, 5),
+        ObjectType::PowerMeter { .. } => (7, 5),
+        ObjectType::SignalMap { .. } => (7, 5),
+        ObjectType::FloodMeter { .. } => (5, 7),
+        ObjectType::EdgeDetector { .. } => (7, 7),
+        ObjectType::Radio { .. } => (7, 7),
+    }
+}
+
+/// Finds an existing object whose cells would overlap those of a new object of
+/// this type placed at `position`. Docking connectors are allowed to overlap
+/// anything, since they are meant to be stacked against other connectors.
+pub(crate) fn overlapping_object<'a>(
+    objects: &'a [Object],
+    object_type: &ObjectType,
+    position: (u32, u32),
+) -> Option<&'a Object> {
+    let is_docking_connector = |object_type: &ObjectType| {
+        matches!(
+            object_type,
+            ObjectType::DockingConnectorTop { .. } | ObjectType::DockingConnectorBottom { .. }
+        )
+    };
+
+    if is_docking_connector(object_type) {
+        return None;
+    }
+
+    let (width, height) = object_size(object_type);
+    let (x, y) = (position.0 as usize, position.1 as usize);
+
+    objects.iter().find(|object| {
+        if is_docking_connector(&object.object_type) {
+            return false;
+        }
+
+        let (other_width, other_height) = object_size(&object.object_type);
+        let (other_x, other_y) = (object.position.0 as usize, object.position.1 as usize);
+
+        x < other_x + other_width
+            && other_x < x + width
+            && y < other_y + other_height
+            && other_y < y + height
+    })
+}
+
+/// The object type as it is when first placed from the objects menu, but
+/// keeping whatever was configured on it, like door codes, gauge modes, bundle
+/// channels and signal ranges.
+pub(crate) fn reset_object_type(object_type: &ObjectType) -> ObjectType {
+    let mut default_type = OBJECT_TYPES
+        .iter()
+        .find(|(_name, listed_type)| discriminant(listed_type) == discriminant(object_type))
+        .map(|(_name, listed_type)| listed_type.clone())
+        .unwrap_or_else(|| object_type.clone());
+
+    match (&mut default_type, object_type) {
+        (
+            ObjectType::Door {
+                required_code,
+                auto_close,
+                ..
+            },
+            ObjectType::Door {
+                required_code: configured_code,
+                auto_close: configured_auto_close,
+                ..
+            },
+        ) => {
+            *required_code = *configured_code;
+            *auto_close = *configured_auto_close;
+        }
+        (
+            ObjectType::VerticalDoor {
+                required_code,
+                auto_close,
+                ..
+            },
+            ObjectType::VerticalDoor {
+                required_code: configured_code,
+                auto_close: configured_auto_close,
+                ..
+            },
+        ) => {
+            *required_code = *configured_code;
+            *auto_close = *configured_auto_close;
+        }
+        (
+            ObjectType::Gauge { power, .. },
+            ObjectType::Gauge {
+                power: configured_power,
+                ..
+            },
+        ) => *power = *configured_power,
+        (
+            ObjectType::BundleInput { sub_bundle },
+            ObjectType::BundleInput {
+                sub_bundle: configured_sub_bundle,
+            },
+        )
+        | (
+            ObjectType::BundleOutput { sub_bundle },
+            ObjectType::BundleOutput {
+                sub_bundle: configured_sub_bundle,
+            },
+        ) => *sub_bundle = *configured_sub_bundle,
+        (
+            ObjectType::SignalMap {
+                in_low,
+                in_high,
+                out_low,
+                out_high,
+                ..
+            },
+            ObjectType::SignalMap {
+                in_low: configured_in_low,
+                in_high: configured_in_high,
+                out_low: configured_out_low,
+                out_high: configured_out_high,
+                ..
+            },
+        ) => {
+            *in_low = *configured_in_low;
+            *in_high = *configured_in_high;
+            *out_low = *configured_out_low;
+            *out_high = *configured_out_high;
+        }
+        (
+            ObjectType::EdgeDetector { mode, .. },
+            ObjectType::EdgeDetector {
+                mode: configured_mode,
+                ..
+            },
+        ) => *mode = *configured_mode,
+        (
+            ObjectType::Radio { channel, .. },
+            ObjectType::Radio {
+                channel: configured_channel,
+                ..
+            },
+        ) => *channel = *configured_channel,
+        _ => (),
+    }
+
+    default_type
+}
+
+/// What an object type is for, as grouped in the objects menu.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ObjectCategory {
+    Logic,
+    Power,
+    Mechanical,
+    Structural,
+}
+
+pub(crate) const OBJECT_CATEGORIES: &[(&str, ObjectCategory)] = &[
+    ("Logic", ObjectCategory::Logic),
+    ("Power", ObjectCategory::Power),
+    ("Mechanical", ObjectCategory::Mechanical),
+    ("Structural", ObjectCategory::Structural),
 ];
 
-// What an object does on every physics update tick.
-pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut bool) {
+pub(crate) fn object_category(object_type: &ObjectType) -> ObjectCategory {
+    match object_type {
+        ObjectType::Door { .. } => ObjectCategory::Structural,
+        ObjectType::VerticalDoor { .. } => ObjectCategory::Structural,
+        ObjectType::Reactor { .. } => ObjectCategory::Power,
+        ObjectType::Lamp => ObjectCategory::Power,
+        ObjectType::Gauge { power: true, .. } => ObjectCategory::Power,
+        ObjectType::Gauge { power: false, .. } => ObjectCategory::Logic,
+        ObjectType::SmallPump { .. } => ObjectCategory::Mechanical,
+        ObjectType::LargePump { .. } => ObjectCategory::Mechanical,
+        ObjectType::JunctionBox { .. } => ObjectCategory::Power,
+        ObjectType::NavController { .. } => ObjectCategory::Logic,
+        ObjectType::Sonar { .. } => ObjectCategory::Mechanical,
+        ObjectType::Engine { .. } => ObjectCategory::Mechanical,
+        ObjectType::Battery { .. } => ObjectCategory::Power,
+        ObjectType::BundleInput { .. } => ObjectCategory::Logic,
+        ObjectType::BundleOutput { .. } => ObjectCategory::Logic,
+        ObjectType::DockingConnectorTop { .. } => ObjectCategory::Structural,
+        ObjectType::DockingConnectorBottom { .. } => ObjectCategory::Structural,
+        ObjectType::Keypad { .. } => ObjectCategory::Logic,
+        ObjectType::CollisionSensor { .. } => ObjectCategory::Logic,
+        ObjectType::PowerMeter { .. } => ObjectCategory::Power,
+        ObjectType::SignalMap { .. } => ObjectCategory::Logic,
+        ObjectType::FloodMeter { .. } => ObjectCategory::Logic,
+        ObjectType::EdgeDetector { .. } => ObjectCategory::Logic,
+        ObjectType::Radio { .. } => ObjectCategory::Logic,
+    }
+}
+
+/// The name this object type is listed under in the objects menu.
+pub(crate) fn object_type_name(object_type: &ObjectType) -> &'static str {
+    OBJECT_TYPES
+        .iter()
+        .find(|(_name, listed_type)| same_listed_type(listed_type, object_type))
+        .map(|(name, _listed_type)| *name)
+        .unwrap_or("Object")
+}
+
+/// Whether two objects are the same entry in `OBJECT_TYPES`. That's the same
+/// as being the same variant, except for gauges, which are listed once for
+/// each mode.
+pub(crate) fn same_listed_type(first: &ObjectType, second: &ObjectType) -> bool {
+    match (first, second) {
+        (ObjectType::Gauge { power: first, .. }, ObjectType::Gauge { power: second, .. }) => {
+            first == second
+        }
+        _ => discriminant(first) == discriminant(second),
+    }
+}
+
+pub(crate) fn port_cell(object: &Object, port: &WirePort) -> (usize, usize) {
+    (
+        (object.position.0 + port.offset.0) as usize,
+        (object.position.1 + port.offset.1) as usize,
+    )
+}
+
+/// Whether any thin wire touches the port's cell.
+pub(crate) fn port_connected(submarine: &SubmarineState, object: &Object, port: &WirePort) -> bool {
+    let (x, y) = port_cell(object, port);
+    let cell = submarine.wire_grid.cell(x, y);
+
+    THIN_COLORS
+        .iter()
+        .any(|&color| cell.value(color).connected())
+}
+
+/// All wire cells reachable from one of an object's ports. Wires going into
+/// a wire bundle are followed out of every bundle input and output on the
+/// same bundle and sub-bundle, along with the bundle itself.
+pub(crate) fn trace_port(
+    submarine: &SubmarineState,
+    object: &Object,
+    port: &WirePort,
+) -> BTreeSet<(WireColor, (usize, usize))> {
+    let wire_grid = &submarine.wire_grid;
+    let mut traced = BTreeSet::new();
+
+    let trace_from = |traced: &mut BTreeSet<_>, (x, y): (usize, usize)| {
+        for color in THIN_COLORS {
+            for cell in wire_grid.connected_cells(color, x, y) {
+                traced.insert((color, cell));
+            }
+        }
+    };
+
+    trace_from(&mut traced, port_cell(object, port));
+
+    // The bundle's id is on the bundle wire under the object, and its
+    // signal port is on the same cell as the bundle's last wire.
+    let bundles: Vec<_> = submarine
+        .objects
+        .iter()
+        .filter_map(|object| match object.object_type {
+            ObjectType::BundleInput { sub_bundle } | ObjectType::BundleOutput { sub_bundle } => {
+                let (x, y) = (object.position.0 as usize, object.position.1 as usize);
+                let bundle_id = wire_grid.cell(x + 2, y + 2).bundle_id()?;
+                Some((bundle_id, sub_bundle, (x + 2, y + 2), (x + 4, y + 2)))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut followed_bundles = BTreeSet::new();
+
+    loop {
+        let reached_bundle = bundles
+            .iter()
+            .find(|(bundle_id, sub_bundle, _, signal_cell)| {
+                !followed_bundles.contains(&(*bundle_id, *sub_bundle))
+                    && THIN_COLORS
+                        .iter()
+                        .any(|&color| traced.contains(&(color, *signal_cell)))
+            });
+
+        let &(bundle_id, sub_bundle, _, _) = match reached_bundle {
+            Some(bundle) => bundle,
+            None => break,
+        };
+
+        followed_bundles.insert((bundle_id, sub_bundle));
+
+        for &(other_id, other_sub_bundle, bundle_cell, signal_cell) in &bundles {
+            if other_id == bundle_id && other_sub_bundle == sub_bundle {
+                let (x, y) = bundle_cell;
+                for cell in wire_grid.connected_cells(WireColor::Bundle, x, y) {
+                    traced.insert((WireColor::Bundle, cell));
+                }
+
+                trace_from(&mut traced, signal_cell);
+            }
+        }
+    }
+
+    traced
+}
+
+/// A network of connected wires of one color that nothing sends a signal
+/// into.
+pub(crate) struct UnfedWireNetwork {
+    pub color: WireColor,
+    pub cells: Vec<(usize, usize)>,
+    /// How many object ports the network touches; with none, the wire isn't
+    /// connected to anything at all.
+    pub ports: usize,
+}
+
+/// Finds the thin wire networks that no output port feeds. Junction boxes
+/// only pass along what their input is fed, and bundle outputs only what goes
+/// into a bundle input on the same bundle and sub-bundle.
+pub(crate) fn unfed_wire_networks(submarine: &SubmarineState) -> Vec<UnfedWireNetwork> {
+    let wire_grid = &submarine.wire_grid;
+    let (width, height) = wire_grid.size();
+
+    let mut networks: Vec<(WireColor, Vec<(usize, usize)>)> = Vec::new();
+    let mut network_at = BTreeMap::new();
+
+    for color in THIN_COLORS {
+        for y in 0..height {
+            for x in 0..width {
+                if network_at.contains_key(&(color, (x, y)))
+                    || !wire_grid.cell(x, y).value(color).connected()
+                {
+                    continue;
+                }
+
+                let cells = wire_grid.connected_cells(color, x, y);
+                for &cell in &cells {
+                    network_at.insert((color, cell), networks.len());
+                }
+                networks.push((color, cells));
+            }
+        }
+    }
+
+    // The networks on a cell, one per color at most
+    let networks_on = |cell: (usize, usize)| {
+        THIN_COLORS
+            .iter()
+            .filter_map(|&color| network_at.get(&(color, cell)).copied())
+            .collect::<Vec<usize>>()
+    };
+
+    let mut fed = vec![false; networks.len()];
+    let mut ports = vec![0; networks.len()];
+
+    for object in &submarine.objects {
+        let passes_signals_along = matches!(
+            object.object_type,
+            ObjectType::JunctionBox { .. } | ObjectType::BundleOutput { .. }
+        );
+
+        for port in object.object_type.wire_ports() {
+            for network in networks_on(port_cell(object, port)) {
+                ports[network] += 1;
+
+                if port.direction == PortDirection::Output && !passes_signals_along {
+                    fed[network] = true;
+                }
+            }
+        }
+    }
+
+    let bundle_id = |object: &Object| {
+        let (x, y) = (object.position.0 as usize, object.position.1 as usize);
+        wire_grid.cell(x + 2, y + 2).bundle_id()
+    };
+
+    let is_fed = |fed: &[bool], object: &Object, direction: PortDirection| {
+        object
+            .object_type
+            .wire_ports()
+            .iter()
+            .filter(|port| port.direction == direction)
+            .flat_map(|port| networks_on(port_cell(object, port)))
+            .any(|network| fed[network])
+    };
+
+    // Each round may feed networks further down a chain of junction boxes
+    // and bundles, until nothing changes.
+    let mut fed_bundles = BTreeSet::new();
+
+    loop {
+        let mut newly_fed = Vec::new();
+        let fed_bundle_count = fed_bundles.len();
+
+        for object in &submarine.objects {
+            let feeds_outputs = match object.object_type {
+                ObjectType::JunctionBox { .. } => is_fed(&fed, object, PortDirection::Input),
+                ObjectType::BundleInput { sub_bundle } => {
+                    if let Some(bundle_id) = bundle_id(object) {
+                        if is_fed(&fed, object, PortDirection::Input) {
+                            fed_bundles.insert((bundle_id, sub_bundle));
+                        }
+                    }
+                    false
+                }
+                ObjectType::BundleOutput { sub_bundle } => bundle_id(object)
+                    .map_or(false, |bundle_id| {
+                        fed_bundles.contains(&(bundle_id, sub_bundle))
+                    }),
+                _ => false,
+            };
+
+            if feeds_outputs {
+                for port in object.object_type.wire_ports() {
+                    if port.direction == PortDirection::Output {
+                        newly_fed.extend(networks_on(port_cell(object, port)));
+                    }
+                }
+            }
+        }
+
+        newly_fed.retain(|&network| !fed[network]);
+
+        if newly_fed.is_empty() && fed_bundles.len() == fed_bundle_count {
+            break;
+        }
+
+        for network in newly_fed {
+            fed[network] = true;
+        }
+    }
+
+    networks
+        .into_iter()
+        .enumerate()
+        .filter(|(network, _)| !fed[*network])
+        .map(|(network, (color, cells))| UnfedWireNetwork {
+            color,
+            cells,
+            ports: ports[network],
+        })
+        .collect()
+}
+
+/// Where water gets into a compartment from.
+pub(crate) enum LeakSource {
+    /// An inside cell next to the sea.
+    Opening((usize, usize)),
+    /// A pump pumping water in, by object id.
+    Pump(usize),
+}
+
+pub(crate) struct Leak {
+    pub source: LeakSource,
+    /// Inside cells the water takes from the source to the traced cell, both
+    /// included.
+    pub path: Vec<(usize, usize)>,
+}
+
+/// Finds every way water gets into the compartment of a flooding cell, with
+/// one of the shortest paths it takes from there to that cell; nothing if the
+/// cell isn't flooding.
+///
+/// Water from a docked submarine comes in through the invisible walls of
+/// docking connectors, which are walls rather than sea, so sealed docks aren't
+/// reported.
+pub(crate) fn trace_leaks(submarine: &SubmarineState, (x, y): (usize, usize)) -> Vec<Leak> {
+    let water_grid = &submarine.water_grid;
+    let (width, height) = water_grid.size();
+
+    let compartment = match water_grid.compartment_at(x, y) {
+        Some(id) if water_grid.cell(x, y).amount_filled() > 0.0 => id,
+        _ => return Vec::new(),
+    };
+
+    let neighbours = |(x, y): (usize, usize)| {
+        let mut neighbours = Vec::with_capacity(4);
+        if x > 0 {
+            neighbours.push((x - 1, y));
+        }
+        if y > 0 {
+            neighbours.push((x, y - 1));
+        }
+        if x + 1 < width {
+            neighbours.push((x + 1, y));
+        }
+        if y + 1 < height {
+            neighbours.push((x, y + 1));
+        }
+        neighbours
+    };
+
+    // Breadth-first from the traced cell, remembering where each cell was
+    // reached from, to walk back along later.
+    let mut came_from = vec![None; width * height];
+    came_from[y * width + x] = Some((x, y));
+    let mut queue = VecDeque::from(vec![(x, y)]);
+    let mut leaks = Vec::new();
+
+    let path_from = |came_from: &[Option<(usize, usize)>], mut cell: (usize, usize)| {
+        let mut path = vec![cell];
+        while cell != (x, y) {
+            cell = came_from[cell.1 * width + cell.0].expect("Only reached cells are traced");
+            path.push(cell);
+        }
+        path
+    };
+
+    while let Some(cell) = queue.pop_front() {
+        let mut opening = false;
+
+        for neighbour in neighbours(cell) {
+            let (nx, ny) = neighbour;
+
+            if water_grid.cell(nx, ny).is_sea() {
+                opening = true;
+            } else if came_from[ny * width + nx].is_none()
+                && water_grid.compartment_at(nx, ny) == Some(compartment)
+            {
+                came_from[ny * width + nx] = Some(cell);
+                queue.push_back(neighbour);
+            }
+        }
+
+        if opening {
+            leaks.push(Leak {
+                source: LeakSource::Opening(cell),
+                path: path_from(&came_from, cell),
+            });
+        }
+    }
+
+    for (object_id, object) in submarine.objects.iter().enumerate() {
+        if !matches!(pump_flow_rate(&object.object_type), Some(flow) if flow > 0) {
+            continue;
+        }
+
+        let large = matches!(object.object_type, ObjectType::LargePump { .. });
+        let ((outlet_x, outlet_y), side) = pump_outlet(object.position, large);
+
+        let outlet = (0..side)
+            .flat_map(|y| (0..side).map(move |x| (outlet_x + x, outlet_y + y)))
+            .find(|&(x, y)| x < width && y < height && came_from[y * width + x].is_some());
+
+        if let Some(outlet) = outlet {
+            leaks.push(Leak {
+                source: LeakSource::Pump(object_id),
+                path: path_from(&came_from, outlet),
+            });
+        }
+    }
+
+    leaks
+}
+
+/// What an object does on every physics update tick.
+///
+/// Objects that need power check for it on every update, and without it they
+/// cease to act right away:
+/// * Pumps and engines act as if told to stop, and coast down to a stop at
+///   their usual rate; they keep their last command for when power returns.
+/// * Junction boxes no longer pass power on, so the power after them dies out.
+/// * The nav controller sends 0 on its outputs when it loses power, since the
+///   engines and pumps it drives hold on to the last value they received.
+///   Switched off by hand, it sends nothing and leaves its wires alone. Other
+///   objects' logic outputs don't depend on power.
+pub(crate) fn update_objects(
+    submarine: &mut SubmarineState,
+    world_size: (usize, usize),
+    walls_updated: &mut bool,
+) {
     let SubmarineState {
         objects,
         water_grid,
         wire_grid,
+        collided,
         ..
     } = submarine;
 
@@ -327,26 +1267,25 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
         let powered = &mut object.powered;
 
         match &mut object.object_type {
-            ObjectType::Door { state, progress } => {
+            ObjectType::Door {
+                state,
+                progress,
+                required_code,
+                auto_close,
+                open_for,
+            } => {
                 let cell_x = object.position.0 as usize + 2;
                 let cell_y = object.position.1 as usize + 4;
 
-                let logic1 = wire_grid.cell(cell_x, cell_y).receive_logic();
-                let logic2 = wire_grid.cell(cell_x + 17, cell_y).receive_logic();
-
-                *powered = false;
+                *powered = receive_door_logic(
+                    wire_grid,
+                    ((cell_x, cell_y), (cell_x + 17, cell_y)),
+                    state,
+                    *required_code,
+                );
+                let held_open = *powered && *state == DoorState::Opening;
 
-                if let Some(logic_value) = logic1.or(logic2) {
-                    *state = if logic_value > 0 {
-                        DoorState::Opening
-                    } else if logic_value < 0 {
-                        DoorState::Closing
-                    } else {
-                        state.toggle()
-                    };
-
-                    *powered = true;
-                }
+                update_auto_close(state, *progress, *auto_close, open_for, held_open);
 
                 match state {
                     DoorState::Opening => *progress = (*progress + 1).min(15),
@@ -384,7 +1323,29 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
                     }
                 }
             }
-            ObjectType::VerticalDoor { state, progress } => {
+            ObjectType::VerticalDoor {
+                state,
+                progress,
+                required_code,
+                auto_close,
+                open_for,
+            } => {
+                let cell_x = object.position.0 as usize + 1;
+                let cell_y = object.position.1 as usize + 2;
+
+                // Unlocked vertical doors don't listen to wires, as they
+                // never used to.
+                *powered = required_code.is_some()
+                    && receive_door_logic(
+                        wire_grid,
+                        ((cell_x, cell_y), (cell_x + 3, cell_y)),
+                        state,
+                        *required_code,
+                    );
+                let held_open = *powered && *state == DoorState::Opening;
+
+                update_auto_close(state, *progress, *auto_close, open_for, held_open);
+
                 match state {
                     DoorState::Opening => *progress = (*progress + 1).min(15),
                     DoorState::Closing => *progress = progress.saturating_sub(1),
@@ -440,12 +1401,14 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
 
                 *powered = cell.minimum_power(10);
             }
-            ObjectType::Gauge { value } => {
+            ObjectType::Gauge { value, power } => {
                 let cell_x = object.position.0 + 4;
                 let cell_y = object.position.1 + 2;
 
                 let cell = wire_grid.cell(cell_x as usize, cell_y as usize);
-                if let Some(logic_value) = cell.receive_logic() {
+                if *power {
+                    *value = (cell.receive_power().unwrap_or(0) / 2) as i8;
+                } else if let Some(logic_value) = cell.receive_logic() {
                     *value = logic_value;
                 }
                 let cell = wire_grid.cell_mut(cell_x as usize, cell_y as usize + 4);
@@ -461,7 +1424,7 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
 
                 let cell = wire_grid.cell(cell_x as usize + 2, cell_y as usize);
                 if let Some(logic_value) = cell.receive_logic() {
-                    *target_speed = logic_value;
+                    *target_speed = logic_value.clamp(-PUMP_FULL_SPEED, PUMP_FULL_SPEED);
                 }
                 let cell = wire_grid.cell(cell_x as usize, cell_y as usize);
                 let target_speed = if cell.minimum_power(50) {
@@ -470,7 +1433,7 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
                     0
                 };
 
-                *speed = ((*speed as i16 * 9 + target_speed as i16) / 10) as i8;
+                *speed = approach_pump_speed(*speed, target_speed);
 
                 if *speed >= 0 {
                     *progress = progress.wrapping_add((*speed / 4) as u8);
@@ -478,12 +1441,10 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
                     *progress = progress.wrapping_sub((speed.abs() / 4) as u8);
                 }
 
-                let cell_x = object.position.0 + 7;
-                let cell_y = object.position.1 + 5;
-
-                let cell = water_grid.cell_mut(cell_x as usize, cell_y as usize);
+                let ((cell_x, cell_y), _side) = pump_outlet(object.position, false);
+                let cell = water_grid.cell_mut(cell_x, cell_y);
 
-                cell.add_level(*speed as i32 * 3);
+                cell.add_level(*speed as i32 * SMALL_PUMP_FLOW);
             }
             ObjectType::LargePump {
                 target_speed,
@@ -495,7 +1456,7 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
 
                 let cell = wire_grid.cell(cell_x as usize + 3, cell_y as usize);
                 if let Some(logic_value) = cell.receive_logic() {
-                    *target_speed = logic_value;
+                    *target_speed = logic_value.clamp(-PUMP_FULL_SPEED, PUMP_FULL_SPEED);
                 }
                 let cell = wire_grid.cell(cell_x as usize, cell_y as usize);
                 let target_speed = if cell.minimum_power(100) {
@@ -504,7 +1465,7 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
                     0
                 };
 
-                *speed = ((*speed as i16 * 9 + target_speed as i16) / 10) as i8;
+                *speed = approach_pump_speed(*speed, target_speed);
 
                 if *speed >= 0 {
                     *progress = progress.wrapping_add((*speed / 4) as u8);
@@ -512,14 +1473,13 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
                     *progress = progress.wrapping_sub((speed.abs() / 4) as u8);
                 }
 
-                for y in 0..4 {
-                    for x in 0..4 {
-                        let cell_x = object.position.0 + 23 + x;
-                        let cell_y = object.position.1 + 12 + y;
+                let ((outlet_x, outlet_y), side) = pump_outlet(object.position, true);
 
-                        let cell = water_grid.cell_mut(cell_x as usize, cell_y as usize);
+                for y in 0..side {
+                    for x in 0..side {
+                        let cell = water_grid.cell_mut(outlet_x + x, outlet_y + y);
 
-                        cell.add_level(*speed as i32 * 2);
+                        cell.add_level(*speed as i32 * LARGE_PUMP_FLOW);
                     }
                 }
             }
@@ -562,12 +1522,31 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
                 let cell_x = object.position.0 as usize + 2;
                 let cell_y = object.position.1 as usize + 4;
 
-                let nav_control = compute_navigation(&submarine.navigation);
-                let cell = wire_grid.cell(cell_x, cell_y);
-                object.powered = false;
-                if *active && cell.minimum_power(50) {
-                    let (engine_speed, pump_speed) = nav_control.engine_and_pump_speed;
+                let has_power = wire_grid.cell(cell_x, cell_y).minimum_power(50);
+                object.powered = *active && has_power;
 
+                // Each connected target input overrides that axis of the
+                // target set by the sonar, without replacing it.
+                let mut navigation = submarine.navigation.clone();
+                if let Some(target_x) = wire_grid.cell(cell_x, cell_y + 5).receive_logic() {
+                    navigation.target.0 = logic_to_world(target_x, world_size.0);
+                }
+                if let Some(target_y) = wire_grid.cell(cell_x, cell_y + 7).receive_logic() {
+                    navigation.target.1 = logic_to_world(target_y, world_size.1);
+                }
+
+                // Switched off by hand, it leaves the wires to whatever else
+                // is connected to them; only losing power zeroes them.
+                let speeds = if object.powered {
+                    *progress = (*progress + 1) % (8 * 5);
+                    Some(compute_navigation(&navigation).engine_and_pump_speed)
+                } else if !has_power {
+                    Some((0, 0))
+                } else {
+                    None
+                };
+
+                if let Some((engine_speed, pump_speed)) = speeds {
                     wire_grid
                         .cell_mut(cell_x + 6, cell_y + 2)
                         .send_logic(engine_speed.clamp(i8::MIN.into(), i8::MAX.into()) as i8);
@@ -575,10 +1554,6 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
                     wire_grid
                         .cell_mut(cell_x + 6, cell_y)
                         .send_logic(pump_speed.clamp(i8::MIN.into(), i8::MAX.into()) as i8);
-
-                    *progress = (*progress + 1) % (8 * 5);
-
-                    object.powered = true;
                 }
             }
             ObjectType::Sonar {
@@ -729,6 +1704,12 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
                 let cell_x = object.position.0 as usize + 20;
                 let cell_y = object.position.1 as usize + 6;
 
+                // Kept on its own port, so it doesn't mix with the pulse
+                let status_x = object.position.0 as usize + 1;
+                // Not 0 when undocked, since doors toggle on every 0
+                let status = if *connected { 100 } else { -100 };
+                wire_grid.cell_mut(status_x, cell_y).send_logic(status);
+
                 if !*previous_connected && *connected {
                     *state = DoorState::Opening;
                     wire_grid.cell_mut(cell_x, cell_y).send_logic(100);
@@ -775,24 +1756,24 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
                         if above || open_wall {
                             if !cell.is_sea() {
                                 cell.make_sea();
+                                *walls_updated = true;
                             }
                         } else if invisible_wall {
                             if !cell.is_wall() {
                                 cell.make_invisible_wall();
+                                *walls_updated = true;
                             }
                         } else if top_wall || side_wall {
                             if !cell.is_wall() {
                                 cell.make_wall();
+                                *walls_updated = true;
                             }
-                        } else {
-                            if !cell.is_inside() {
-                                cell.make_inside();
-                            }
+                        } else if !cell.is_inside() {
+                            cell.make_inside();
+                            *walls_updated = true;
                         }
                     }
                 }
-
-                *walls_updated = true;
             }
             ObjectType::DockingConnectorBottom {
                 state,
@@ -803,6 +1784,12 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
                 let cell_x = object.position.0 as usize + 20;
                 let cell_y = object.position.1 as usize + 4;
 
+                // Kept on its own port, so it doesn't mix with the pulse
+                let status_x = object.position.0 as usize + 1;
+                // Not 0 when undocked, since doors toggle on every 0
+                let status = if *connected { 100 } else { -100 };
+                wire_grid.cell_mut(status_x, cell_y).send_logic(status);
+
                 if !*previous_connected && *connected {
                     *state = DoorState::Opening;
                     wire_grid.cell_mut(cell_x, cell_y).send_logic(100);
@@ -849,43 +1836,467 @@ pub(crate) fn update_objects(submarine: &mut SubmarineState, walls_updated: &mut
                         if below || open_wall {
                             if !cell.is_sea() {
                                 cell.make_sea();
+                                *walls_updated = true;
                             }
                         } else if invisible_wall {
                             if !cell.is_wall() {
                                 cell.make_invisible_wall();
+                                *walls_updated = true;
                             }
                         } else if bottom_wall || side_wall {
                             if !cell.is_wall() {
                                 cell.make_wall();
+                                *walls_updated = true;
                             }
-                        } else {
-                            if !cell.is_inside() {
-                                cell.make_inside();
-                            }
+                        } else if !cell.is_inside() {
+                            cell.make_inside();
+                            *walls_updated = true;
                         }
                     }
                 }
+            }
+            ObjectType::Keypad { entered } => {
+                let cell_x = object.position.0 as usize + 3;
+                let cell_y = object.position.1 as usize + 6;
+
+                // Sent on every tick rather than once, so that whatever
+                // listens to it keeps seeing the code through every wire
+                // update.
+                wire_grid.cell_mut(cell_x, cell_y).send_logic(*entered);
+                *powered = *entered != 0;
+            }
+            ObjectType::CollisionSensor { triggered } => {
+                let cell_x = object.position.0 as usize + 2;
+                let cell_y = object.position.1 as usize + 4;
+
+                // Collisions are detected after objects are updated, so this
+                // reacts to the ones from the previous tick.
+                *triggered = *collided;
+                *powered = *collided;
+
+                let logic_value = if *collided { 100 } else { 0 };
+                wire_grid.cell_mut(cell_x, cell_y).send_logic(logic_value);
+            }
+            ObjectType::PowerMeter { power } => {
+                let cell_x = object.position.0 as usize + 1;
+                let cell_y = object.position.1 as usize + 2;
+
+                // Only looks at the wire; whatever else is on it still gets
+                // all of the power.
+                *power = wire_grid.cell(cell_x, cell_y).receive_power().unwrap_or(0);
+                *powered = *power != 0;
+
+                // Halved so that a reactor's full 200 power fits in a logic
+                // value, as 100.
+                let logic_value = (*power / 2) as i8;
+                wire_grid
+                    .cell_mut(cell_x + 4, cell_y)
+                    .send_logic(logic_value);
+            }
+            ObjectType::SignalMap {
+                in_low,
+                in_high,
+                out_low,
+                out_high,
+                value,
+            } => {
+                let cell_x = object.position.0 as usize + 2;
+                let cell_y = object.position.1 as usize + 3;
+
+                if let Some(logic_value) = wire_grid.cell(cell_x, cell_y).receive_logic() {
+                    *value = logic_value;
+                }
+
+                let logic_value = map_signal(*value, (*in_low, *in_high), (*out_low, *out_high));
+                *powered = logic_value != 0;
+
+                wire_grid
+                    .cell_mut(cell_x + 4, cell_y)
+                    .send_logic(logic_value);
+            }
+            ObjectType::FloodMeter { flooding } => {
+                let cell_x = object.position.0 as usize + 4;
+                let cell_y = object.position.1 as usize + 7;
+
+                *flooding = flooding_percentage(water_grid);
+                *powered = *flooding != 0;
+
+                wire_grid
+                    .cell_mut(cell_x, cell_y)
+                    .send_logic(*flooding as i8);
+            }
+            ObjectType::EdgeDetector { last, mode } => {
+                let cell_x = object.position.0 as usize + 2;
+                let cell_y = object.position.1 as usize + 4;
+
+                // A disconnected input counts as low.
+                let value = wire_grid.cell(cell_x, cell_y).receive_logic().unwrap_or(0);
+                let edge = mode.is_edge(*last, value);
+                *last = value;
+                *powered = edge;
+
+                // Objects update after wires, so the pulse gets a whole tick
+                // of wire iterations to travel before it is replaced by 0.
+                let logic_value = if edge { 100 } else { 0 };
+                wire_grid
+                    .cell_mut(cell_x + 4, cell_y)
+                    .send_logic(logic_value);
+            }
+            ObjectType::Radio {
+                transmitting,
+                received,
+                ..
+            } => {
+                let cell_x = object.position.0 as usize + 2;
+                let cell_y = object.position.1 as usize + 6;
+
+                // What is heard was decided by `update_radios` on the
+                // previous tick, from what was transmitted then.
+                *transmitting = wire_grid.cell(cell_x, cell_y).receive_logic();
+                *powered = received.is_some();
+
+                if let Some(logic_value) = *received {
+                    wire_grid
+                        .cell_mut(cell_x + 4, cell_y)
+                        .send_logic(logic_value);
+                }
+            }
+        }
+    }
+}
+
+/// A radio's signal, as heard by the radios of other submarines.
+struct RadioTransmission {
+    submarine_id: usize,
+    position: (i32, i32),
+    value: i8,
+}
+
+/// Gathers what every radio transmits on each channel, and passes it on to
+/// radios on the same channel in other submarines within `range` (in 1/16
+/// cells). When several transmitters reach a radio, the nearest one wins; ties
+/// go to the lower submarine index and then to the first radio on it, so a
+/// radio never flickers between two transmitters.
+pub(crate) fn update_radios(submarines: &mut [SubmarineState], range: i32) {
+    let mut channels: BTreeMap<u8, Vec<RadioTransmission>> = BTreeMap::new();
+
+    for (submarine_id, submarine) in submarines.iter().enumerate() {
+        for object in &submarine.objects {
+            if let ObjectType::Radio {
+                channel,
+                transmitting: Some(value),
+                ..
+            } = object.object_type
+            {
+                channels
+                    .entry(channel)
+                    .or_default()
+                    .push(RadioTransmission {
+                        submarine_id,
+                        position: submarine.navigation.position,
+                        value,
+                    });
+            }
+        }
+    }
+
+    let max_distance = range as i64 * range as i64;
+
+    for (submarine_id, submarine) in submarines.iter_mut().enumerate() {
+        let (x, y) = submarine.navigation.position;
+
+        for object in &mut submarine.objects {
+            if let ObjectType::Radio {
+                channel, received, ..
+            } = &mut object.object_type
+            {
+                let transmissions = channels.get(channel).map(Vec::as_slice).unwrap_or(&[]);
+
+                *received = transmissions
+                    .iter()
+                    .filter(|transmission| transmission.submarine_id != submarine_id)
+                    .map(|transmission| {
+                        let dx = (transmission.position.0 - x) as i64;
+                        let dy = (transmission.position.1 - y) as i64;
+                        (dx * dx + dy * dy, transmission)
+                    })
+                    .filter(|(distance, _)| *distance <= max_distance)
+                    .min_by_key(|(distance, transmission)| (*distance, transmission.submarine_id))
+                    .map(|(_, transmission)| transmission.value);
+            }
+        }
+    }
+}
+
+/// Opens or closes a door from the logic on its two ports, and returns whether
+/// any was received. With a code lock, only the first port is an input; the
+/// second one signals when a wrong code was received.
+fn receive_door_logic(
+    wire_grid: &mut WireGrid,
+    (first_port, second_port): ((usize, usize), (usize, usize)),
+    state: &mut DoorState,
+    required_code: Option<i8>,
+) -> bool {
+    if let Some(required_code) = required_code {
+        let logic_value = match wire_grid.cell(first_port.0, first_port.1).receive_logic() {
+            Some(logic_value) => logic_value,
+            None => return false,
+        };
+
+        *state = if logic_value == required_code {
+            DoorState::Opening
+        } else {
+            DoorState::Closing
+        };
+
+        if logic_value != required_code && logic_value != 0 {
+            wire_grid
+                .cell_mut(second_port.0, second_port.1)
+                .send_logic(100);
+        }
+    } else {
+        let logic1 = wire_grid.cell(first_port.0, first_port.1).receive_logic();
+        let logic2 = wire_grid.cell(second_port.0, second_port.1).receive_logic();
+
+        let logic_value = match logic1.or(logic2) {
+            Some(logic_value) => logic_value,
+            None => return false,
+        };
+
+        *state = if logic_value > 0 {
+            DoorState::Opening
+        } else if logic_value < 0 {
+            DoorState::Closing
+        } else {
+            state.toggle()
+        };
+    }
+
+    true
+}
+
+/// Counts the updates a door spends fully open, and starts closing it once
+/// that reaches its `auto_close` timeout. A door that is commanded open again,
+/// or `held_open` by a signal on every update, starts counting from 0 again.
+fn update_auto_close(
+    state: &mut DoorState,
+    progress: u8,
+    auto_close: Option<u16>,
+    open_for: &mut u16,
+    held_open: bool,
+) {
+    let fully_open = *state == DoorState::Opening && progress == 15;
+
+    let timeout = match auto_close {
+        Some(timeout) if fully_open && !held_open => timeout,
+        _ => {
+            *open_for = 0;
+            return;
+        }
+    };
+
+    *open_for = open_for.saturating_add(1);
+
+    if *open_for >= timeout {
+        *state = DoorState::Closing;
+        *open_for = 0;
+    }
+}
+
+/// The auto-close timeout of a door, or `None` for objects that aren't doors.
+pub(crate) fn door_auto_close(object_type: &ObjectType) -> Option<Option<u16>> {
+    match object_type {
+        ObjectType::Door { auto_close, .. } | ObjectType::VerticalDoor { auto_close, .. } => {
+            Some(*auto_close)
+        }
+        _ => None,
+    }
+}
+
+/// The code a door is locked with, if any, or `None` for other objects.
+pub(crate) fn door_code(object_type: &ObjectType) -> Option<Option<i8>> {
+    match object_type {
+        ObjectType::Door { required_code, .. } | ObjectType::VerticalDoor { required_code, .. } => {
+            Some(*required_code)
+        }
+        _ => None,
+    }
+}
+
+/// The code entered on a keypad, or `None` for other objects.
+pub(crate) fn keypad_code(object_type: &ObjectType) -> Option<i8> {
+    match object_type {
+        ObjectType::Keypad { entered } => Some(*entered),
+        _ => None,
+    }
+}
+
+/// The input and output ranges of a signal map, or `None` for other objects.
+pub(crate) fn signal_map_ranges(object_type: &ObjectType) -> Option<((i8, i8), (i8, i8))> {
+    match object_type {
+        ObjectType::SignalMap {
+            in_low,
+            in_high,
+            out_low,
+            out_high,
+            ..
+        } => Some(((*in_low, *in_high), (*out_low, *out_high))),
+        _ => None,
+    }
+}
+
+/// A navigation position along a world axis that is `world_length` rock cells
+/// long, from a logic value going from 0 at one end to 100 at the other.
+fn logic_to_world(logic_value: i8, world_length: usize) -> i32 {
+    // 16 movement points per sub-cell, 16 sub-cells per rock-cell
+    let position = logic_value.clamp(0, 100) as i64 * world_length as i64 * 16 * 16 / 100;
+    position as i32
+}
+
+/// How much water is inside the submarine, from 0 to 127, relative to the
+/// amount that would fill every inside cell.
+fn flooding_percentage(water_grid: &WaterGrid) -> u8 {
+    // A full cell holds 1024; widened so that large submarines can't overflow.
+    let capacity = water_grid.total_inside() as u64 * 1024;
+
+    if capacity == 0 {
+        return 0;
+    }
+
+    let total_water = (water_grid.total_water() as u64).min(capacity);
+    (total_water * 127 / capacity) as u8
+}
 
-                *walls_updated = true;
+/// Reactors heat up the cells they cover to this temperature.
+const REACTOR_TEMPERATURE: i8 = 80;
+
+/// Running engines heat up the cells they cover to this temperature.
+const ENGINE_TEMPERATURE: i8 = 40;
+
+/// Warm up the cells under running reactors and engines.
+pub(crate) fn heat_from_objects(submarine: &mut SubmarineState) {
+    for object in &submarine.objects {
+        let (amount, up_to) = match object.object_type {
+            ObjectType::Reactor { active: true } => (2, REACTOR_TEMPERATURE),
+            ObjectType::Engine { speed, .. } if speed != 0 => (1, ENGINE_TEMPERATURE),
+            _ => continue,
+        };
+
+        let (width, height) = object_size(&object.object_type);
+
+        for y in 0..height {
+            for x in 0..width {
+                submarine
+                    .water_grid
+                    .cell_mut(
+                        object.position.0 as usize + x,
+                        object.position.1 as usize + y,
+                    )
+                    .heat(amount, up_to);
             }
         }
     }
 }
 
+/// Put back the cells that doors and docking connectors carve out of the hull,
+/// the way they are when fully closed or retracted. Returns whether any walls
+/// changed.
+pub(crate) fn restore_object_walls(object: &Object, water_grid: &mut WaterGrid) -> bool {
+    // Which cells the object takes over, relative to its position, and what
+    // they should be turned back into.
+    let closed_cell = |x: usize, y: usize| -> Option<CellTemplate> {
+        match object.object_type {
+            ObjectType::Door { .. } => {
+                ((5..18).contains(&x) && (3..6).contains(&y)).then(|| CellTemplate::Wall)
+            }
+            ObjectType::VerticalDoor { .. } => {
+                (x == 3 && (5..17).contains(&y)).then(|| CellTemplate::Wall)
+            }
+            ObjectType::DockingConnectorTop { .. } => {
+                if !(4..=17).contains(&x) || !(2..=6).contains(&y) {
+                    None
+                } else if y < 5 {
+                    Some(CellTemplate::Sea)
+                } else if y == 5 || x == 4 || x == 17 {
+                    Some(CellTemplate::Wall)
+                } else {
+                    Some(CellTemplate::Inside)
+                }
+            }
+            ObjectType::DockingConnectorBottom { .. } => {
+                if !(4..=17).contains(&x) || !(3..=7).contains(&y) {
+                    None
+                } else if y > 4 {
+                    Some(CellTemplate::Sea)
+                } else if y == 4 || x == 4 || x == 17 {
+                    Some(CellTemplate::Wall)
+                } else {
+                    Some(CellTemplate::Inside)
+                }
+            }
+            _ => None,
+        }
+    };
+
+    let (width, height) = object_size(&object.object_type);
+    let mut walls_updated = false;
+
+    for y in 0..height {
+        for x in 0..width {
+            let template = match closed_cell(x, y) {
+                Some(template) => template,
+                None => continue,
+            };
+
+            let cell = water_grid.cell_mut(
+                object.position.0 as usize + x,
+                object.position.1 as usize + y,
+            );
+
+            match template {
+                CellTemplate::Sea if !cell.is_sea() => cell.make_sea(),
+                CellTemplate::Wall if !cell.is_wall() => cell.make_wall(),
+                CellTemplate::Inside if !cell.is_inside() => cell.make_inside(),
+                _ => continue,
+            }
+
+            walls_updated = true;
+        }
+    }
+
+    walls_updated
+}
+
 // What an object does when left-clicked.
 pub(crate) fn interact_with_object(object: &mut Object) {
     match &mut object.object_type {
-        ObjectType::Door { state, .. } | ObjectType::VerticalDoor { state, .. } => {
+        // Code-locked doors can only be opened through their input.
+        ObjectType::Door {
+            required_code: Some(_),
+            ..
+        }
+        | ObjectType::VerticalDoor {
+            required_code: Some(_),
+            ..
+        } => (),
+        ObjectType::Door {
+            state, open_for, ..
+        }
+        | ObjectType::VerticalDoor {
+            state, open_for, ..
+        } => {
             *state = match state {
                 DoorState::Opening => DoorState::Closing,
                 DoorState::Closing => DoorState::Opening,
-            }
+            };
+            *open_for = 0;
         }
         ObjectType::Reactor { active } => *active = !*active,
         ObjectType::Lamp { .. } => (),
-        ObjectType::Gauge { value } => cycle_i8(value),
-        ObjectType::SmallPump { target_speed, .. } => cycle_i8(target_speed),
-        ObjectType::LargePump { target_speed, .. } => cycle_i8(target_speed),
+        ObjectType::Gauge { power: true, .. } => (),
+        ObjectType::Gauge { value, .. } => cycle_i8(value),
+        ObjectType::SmallPump { target_speed, .. } => cycle_pump_speed(target_speed),
+        ObjectType::LargePump { target_speed, .. } => cycle_pump_speed(target_speed),
         ObjectType::JunctionBox { enabled, .. } => *enabled = !*enabled,
         ObjectType::NavController { active, .. } => *active = !*active,
         ObjectType::Sonar { active, .. } => *active = !*active,
@@ -906,7 +2317,36 @@ pub(crate) fn interact_with_object(object: &mut Object) {
                 DoorState::Closing => DoorState::Opening,
             }
         }
+        // Cycles through the digits 1 to 9, then back to 0 which clears the code.
+        ObjectType::Keypad { entered } => *entered = (*entered + 1) % 10,
+        ObjectType::CollisionSensor { .. } => (),
+        ObjectType::PowerMeter { .. } => (),
+        ObjectType::SignalMap { .. } => (),
+        ObjectType::FloodMeter { .. } => (),
+        ObjectType::EdgeDetector { mode, .. } => {
+            *mode = match mode {
+                EdgeMode::Rising => EdgeMode::Falling,
+                EdgeMode::Falling => EdgeMode::Both,
+                EdgeMode::Both => EdgeMode::Rising,
+            }
+        }
+        ObjectType::Radio { channel, .. } => *channel = (*channel + 1) % 8,
+    }
+}
+
+/// Maps `value` from the `input` range onto the `output` range, saturating at
+/// the bounds of an `i8`.
+fn map_signal(value: i8, input: (i8, i8), output: (i8, i8)) -> i8 {
+    let (in_low, in_high) = (input.0 as i32, input.1 as i32);
+    let (out_low, out_high) = (output.0 as i32, output.1 as i32);
+
+    // An empty input range has nothing to scale by.
+    if in_high == in_low {
+        return output.0;
     }
+
+    let mapped = out_low + (value as i32 - in_low) * (out_high - out_low) / (in_high - in_low);
+    mapped.clamp(i8::MIN as i32, i8::MAX as i32) as i8
 }
 
 fn cycle_i8(value: &mut i8) {
@@ -920,6 +2360,70 @@ fn cycle_i8(value: &mut i8) {
     };
 }
 
+const PUMP_FULL_SPEED: i8 = 100;
+
+/// Water added to the small pump's cell on each update, for each point of
+/// speed.
+const SMALL_PUMP_FLOW: i32 = 3;
+
+/// Water added to each of the large pump's cells on each update, for each
+/// point of speed; it pumps through a square of cells this many on a side.
+const LARGE_PUMP_FLOW: i32 = 2;
+const LARGE_PUMP_SIDE: u32 = 4;
+
+/// The square of cells a pump at `position` moves water through, as its
+/// top-left cell and its side.
+fn pump_outlet(position: (u32, u32), large: bool) -> ((usize, usize), usize) {
+    let (x, y, side) = if large {
+        (23, 12, LARGE_PUMP_SIDE)
+    } else {
+        (7, 5, 1)
+    };
+
+    (
+        (position.0 as usize + x, position.1 as usize + y),
+        side as usize,
+    )
+}
+
+/// Water a pump moves into the submarine on each update, or out of it if
+/// negative; `None` if the object isn't a pump. Follows the pump's actual
+/// speed, which winds down to 0 while it lacks power.
+///
+/// Cells don't take more water than they can hold or give more than they
+/// have, so less may actually move.
+pub(crate) fn pump_flow_rate(object_type: &ObjectType) -> Option<i32> {
+    match object_type {
+        ObjectType::SmallPump { speed, .. } => Some(*speed as i32 * SMALL_PUMP_FLOW),
+        ObjectType::LargePump { speed, .. } => {
+            Some(*speed as i32 * LARGE_PUMP_FLOW * (LARGE_PUMP_SIDE * LARGE_PUMP_SIDE) as i32)
+        }
+        _ => None,
+    }
+}
+
+fn cycle_pump_speed(value: &mut i8) {
+    *value = match *value {
+        0 => 50,
+        50 => 100,
+        100 => -100,
+        -100 => -50,
+        _ => 0,
+    };
+}
+
+/// Move a pump's speed a tenth of the way towards its target, but by at least
+/// one, so that it settles exactly on the target instead of stopping short.
+fn approach_pump_speed(speed: i8, target_speed: i8) -> i8 {
+    let difference = target_speed as i16 - speed as i16;
+    let step = match difference / 10 {
+        0 => difference.signum(),
+        step => step,
+    };
+
+    (speed as i16 + step) as i8
+}
+
 pub(crate) fn current_frame(object: &Object) -> (u16, u16) {
     let current_frame_column = 0;
     let powered = &object.powered;
@@ -951,7 +2455,10 @@ pub(crate) fn current_frame(object: &Object) -> (u16, u16) {
                 0
             }
         }
-        ObjectType::Gauge { value } => match *value {
+        // Power goes from 0 to 255, so it sweeps the needle from the leftmost
+        // frame rather than from the middle.
+        ObjectType::Gauge { value, power: true } => ((*value).max(0) as u16 * 5 / 128).min(4),
+        ObjectType::Gauge { value, .. } => match *value {
             -128..=-96 => 0,
             -95..=-32 => 1,
             -31..=31 => 2,
@@ -1005,6 +2512,24 @@ pub(crate) fn current_frame(object: &Object) -> (u16, u16) {
         ObjectType::DockingConnectorBottom { progress, .. } => {
             (*progress as u16 * 9 / 15).clamp(0, 8) + if *powered { 8 } else { 0 }
         }
+        ObjectType::Keypad { entered } => (*entered).clamp(0, 9) as u16,
+        ObjectType::CollisionSensor { triggered } => *triggered as u16,
+        ObjectType::PowerMeter { power } => match *power {
+            0 => 0,
+            1..=66 => 1,
+            67..=133 => 2,
+            _ => 3,
+        },
+        ObjectType::SignalMap { .. } => *powered as u16,
+        ObjectType::FloodMeter { flooding } => match *flooding {
+            0 => 0,
+            1..=42 => 1,
+            43..=84 => 2,
+            85..=126 => 3,
+            _ => 4,
+        },
+        ObjectType::EdgeDetector { mode, .. } => *mode as u16 * 2 + *powered as u16,
+        ObjectType::Radio { channel, .. } => *channel as u16 * 2 + *powered as u16,
     };
 
     (current_frame, current_frame_column)
@@ -1032,13 +2557,35 @@ pub(crate) fn compute_navigation(navigation: &Navigation) -> NavControl {
 impl ObjectTemplate {
     pub fn from_object(object: &Object) -> Self {
         let object_type = match object.object_type.clone() {
-            ObjectType::Door { state, progress } => ObjectTypeTemplate::Door { state, progress },
-            ObjectType::VerticalDoor { state, progress } => {
-                ObjectTypeTemplate::VerticalDoor { state, progress }
-            }
+            ObjectType::Door {
+                state,
+                progress,
+                required_code,
+                auto_close,
+                open_for,
+            } => ObjectTypeTemplate::Door {
+                state,
+                progress,
+                required_code,
+                auto_close,
+                open_for,
+            },
+            ObjectType::VerticalDoor {
+                state,
+                progress,
+                required_code,
+                auto_close,
+                open_for,
+            } => ObjectTypeTemplate::VerticalDoor {
+                state,
+                progress,
+                required_code,
+                auto_close,
+                open_for,
+            },
             ObjectType::Reactor { active } => ObjectTypeTemplate::Reactor { active },
             ObjectType::Lamp { .. } => ObjectTypeTemplate::Lamp,
-            ObjectType::Gauge { value } => ObjectTypeTemplate::Gauge { value },
+            ObjectType::Gauge { value, power } => ObjectTypeTemplate::Gauge { value, power },
             ObjectType::SmallPump {
                 target_speed,
                 speed,
@@ -1108,23 +2655,78 @@ impl ObjectTemplate {
                 connected,
                 previous_connected,
             },
+            ObjectType::Keypad { entered } => ObjectTypeTemplate::Keypad { entered },
+            ObjectType::CollisionSensor { triggered } => {
+                ObjectTypeTemplate::CollisionSensor { triggered }
+            }
+            ObjectType::PowerMeter { power } => ObjectTypeTemplate::PowerMeter { power },
+            ObjectType::SignalMap {
+                in_low,
+                in_high,
+                out_low,
+                out_high,
+                value,
+            } => ObjectTypeTemplate::SignalMap {
+                in_low,
+                in_high,
+                out_low,
+                out_high,
+                value,
+            },
+            ObjectType::FloodMeter { flooding } => ObjectTypeTemplate::FloodMeter { flooding },
+            ObjectType::EdgeDetector { last, mode } => {
+                ObjectTypeTemplate::EdgeDetector { last, mode }
+            }
+            ObjectType::Radio {
+                channel,
+                transmitting,
+                received,
+            } => ObjectTypeTemplate::Radio {
+                channel,
+                transmitting,
+                received,
+            },
         };
 
         ObjectTemplate {
             object_type,
             position: object.position,
+            label: object.label.clone(),
+            note: object.note.clone(),
         }
     }
 
     pub fn to_object(&self) -> Object {
         let object_type = match self.object_type.clone() {
-            ObjectTypeTemplate::Door { state, progress } => ObjectType::Door { state, progress },
-            ObjectTypeTemplate::VerticalDoor { state, progress } => {
-                ObjectType::VerticalDoor { state, progress }
-            }
+            ObjectTypeTemplate::Door {
+                state,
+                progress,
+                required_code,
+                auto_close,
+                open_for,
+            } => ObjectType::Door {
+                state,
+                progress,
+                required_code,
+                auto_close,
+                open_for,
+            },
+            ObjectTypeTemplate::VerticalDoor {
+                state,
+                progress,
+                required_code,
+                auto_close,
+                open_for,
+            } => ObjectType::VerticalDoor {
+                state,
+                progress,
+                required_code,
+                auto_close,
+                open_for,
+            },
             ObjectTypeTemplate::Reactor { active } => ObjectType::Reactor { active },
             ObjectTypeTemplate::Lamp => ObjectType::Lamp,
-            ObjectTypeTemplate::Gauge { value } => ObjectType::Gauge { value },
+            ObjectTypeTemplate::Gauge { value, power } => ObjectType::Gauge { value, power },
             ObjectTypeTemplate::SmallPump {
                 target_speed,
                 speed,
@@ -1194,12 +2796,350 @@ impl ObjectTemplate {
                 connected,
                 previous_connected,
             },
+            ObjectTypeTemplate::Keypad { entered } => ObjectType::Keypad { entered },
+            ObjectTypeTemplate::CollisionSensor { triggered } => {
+                ObjectType::CollisionSensor { triggered }
+            }
+            ObjectTypeTemplate::PowerMeter { power } => ObjectType::PowerMeter { power },
+            ObjectTypeTemplate::SignalMap {
+                in_low,
+                in_high,
+                out_low,
+                out_high,
+                value,
+            } => ObjectType::SignalMap {
+                in_low,
+                in_high,
+                out_low,
+                out_high,
+                value,
+            },
+            ObjectTypeTemplate::FloodMeter { flooding } => ObjectType::FloodMeter { flooding },
+            ObjectTypeTemplate::EdgeDetector { last, mode } => {
+                ObjectType::EdgeDetector { last, mode }
+            }
+            ObjectTypeTemplate::Radio {
+                channel,
+                transmitting,
+                received,
+            } => ObjectType::Radio {
+                channel,
+                transmitting,
+                received,
+            },
         };
 
         Object {
             object_type,
             position: self.position,
             powered: false,
+            label: self.label.clone(),
+            note: self.note.clone(),
+            interaction_cooldown: 0,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state::{
+        state::{GameState, SubmarineTemplate},
+        update::{update_game, Command},
+    };
+
+    fn object(object_type: ObjectType, position: (u32, u32)) -> Object {
+        Object {
+            object_type,
+            position,
+            powered: false,
+            label: None,
+            note: None,
+            interaction_cooldown: 0,
+        }
+    }
+
+    fn update(game_state: &mut GameState, commands: Vec<Command>) {
+        update_game(
+            commands.into_iter(),
+            game_state,
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        );
+    }
+
+    fn game_with_submarine(template: SubmarineTemplate, update_water: bool) -> GameState {
+        let mut game_state = GameState::default();
+        game_state.update_settings.update_water = update_water;
+        game_state.update_settings.update_sonar = false;
+        game_state.update_settings.update_collision = false;
+
+        update(
+            &mut game_state,
+            vec![Command::CreateSubmarine {
+                submarine_template: Box::new(template),
+                rock_position: (1000, 1000),
+            }],
+        );
+
+        game_state
+    }
+
+    #[test]
+    fn cutting_reactor_power_zeroes_the_nav_controller_outputs() {
+        let (width, height) = (110, 30);
+        let template = SubmarineTemplate {
+            size: (width, height),
+            water_cells: vec![CellTemplate::Inside; width * height],
+            background_pixels: vec![0; width * height * 4],
+            objects: vec![
+                object(ObjectType::Reactor { active: true }, (0, 0)),
+                object(
+                    ObjectType::NavController {
+                        active: true,
+                        progress: 0,
+                    },
+                    (40, 0),
+                ),
+                object(
+                    ObjectType::Engine {
+                        target_speed: 0,
+                        speed: 0,
+                        progress: 0,
+                    },
+                    (60, 5),
+                ),
+            ],
+            wire_points: vec![
+                (WireColor::Brown, vec![(29, 5), (35, 5), (35, 4), (42, 4)]),
+                (WireColor::Green, vec![(48, 6), (48, 13), (96, 13)]),
+            ],
+            wire_bridges: Vec::new(),
+        };
+
+        let mut game_state = game_with_submarine(template, false);
+        // Steer far to the right, so the engine is told to run.
+        let update = |game_state: &mut GameState, commands| {
+            game_state.submarines[0].navigation.target = (100_000, 1000);
+            update(game_state, commands);
+        };
+
+        let engine_wire = |game_state: &GameState| {
+            game_state.submarines[0]
+                .wire_grid
+                .cell(96, 13)
+                .receive_logic()
+        };
+        let engine_target_speed =
+            |game_state: &GameState| match game_state.submarines[0].objects[2].object_type {
+                ObjectType::Engine { target_speed, .. } => target_speed,
+                _ => unreachable!(),
+            };
+
+        for _ in 0..100 {
+            update(&mut game_state, Vec::new());
+        }
+
+        assert!(game_state.submarines[0].objects[1].powered);
+        assert!(engine_wire(&game_state).unwrap_or(0) > 0);
+        assert!(engine_target_speed(&game_state) > 0);
+
+        // Switch the reactor off
+        update(
+            &mut game_state,
+            vec![Command::Interact {
+                submarine_id: 0,
+                object_id: 0,
+            }],
+        );
+        for _ in 0..100 {
+            update(&mut game_state, Vec::new());
+        }
+
+        assert!(!game_state.submarines[0].objects[1].powered);
+        assert_eq!(engine_wire(&game_state), Some(0));
+        assert_eq!(engine_target_speed(&game_state), 0);
+    }
+
+    #[test]
+    fn pump_direction_follows_the_sign_of_its_logic_input() {
+        let (width, height) = (60, 30);
+        let mut water_cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                // Walls need sea around them, or the grid's edges leak.
+                let edge = x.min(width - 1 - x).min(y).min(height - 1 - y);
+                water_cells.push(match edge {
+                    0 => CellTemplate::Sea,
+                    1 => CellTemplate::Wall,
+                    _ if y >= 12 => CellTemplate::Water,
+                    _ => CellTemplate::Inside,
+                });
+            }
+        }
+
+        // A signal map with an empty input range always sends its low
+        // output, which stands in for a switch here.
+        let template = SubmarineTemplate {
+            size: (width, height),
+            water_cells,
+            background_pixels: vec![0; width * height * 4],
+            objects: vec![
+                object(ObjectType::Reactor { active: true }, (1, 1)),
+                object(
+                    ObjectType::SignalMap {
+                        in_low: 0,
+                        in_high: 0,
+                        out_low: -100,
+                        out_high: 0,
+                        value: 0,
+                    },
+                    (40, 2),
+                ),
+                object(
+                    ObjectType::SmallPump {
+                        target_speed: 0,
+                        speed: 0,
+                        progress: 0,
+                    },
+                    (40, 18),
+                ),
+            ],
+            wire_points: vec![
+                (WireColor::Brown, vec![(30, 6), (36, 6), (36, 20), (43, 20)]),
+                (WireColor::Green, vec![(46, 5), (46, 9), (45, 9), (45, 20)]),
+            ],
+            wire_bridges: Vec::new(),
+        };
+
+        let mut game_state = game_with_submarine(template, true);
+
+        let water_flow = |game_state: &mut GameState, logic_value: i8| {
+            update(
+                game_state,
+                vec![Command::SetSignalMapRanges {
+                    submarine_id: 0,
+                    object_id: 1,
+                    input: (0, 0),
+                    output: (logic_value, 0),
+                }],
+            );
+
+            // Let the pump get up to speed first.
+            for _ in 0..60 {
+                update(game_state, Vec::new());
+            }
+
+            let before = game_state.submarines[0].water_grid.total_water() as i64;
+            for _ in 0..30 {
+                update(game_state, Vec::new());
+            }
+            let after = game_state.submarines[0].water_grid.total_water() as i64;
+
+            after - before
+        };
+
+        assert!(water_flow(&mut game_state, -100) < 0);
+        assert!(water_flow(&mut game_state, 100) > 0);
+        assert_eq!(water_flow(&mut game_state, 0), 0);
+
+        match game_state.submarines[0].objects[2].object_type {
+            ObjectType::SmallPump { speed, .. } => assert_eq!(speed, 0),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn code_locked_vertical_door_opens_only_on_its_code() {
+        let (width, height) = (50, 24);
+        let template = SubmarineTemplate {
+            size: (width, height),
+            water_cells: vec![CellTemplate::Inside; width * height],
+            background_pixels: vec![0; width * height * 4],
+            objects: vec![
+                object(ObjectType::Keypad { entered: 0 }, (10, 2)),
+                object(
+                    ObjectType::VerticalDoor {
+                        state: DoorState::Closing,
+                        progress: 0,
+                        required_code: None,
+                        auto_close: None,
+                        open_for: 0,
+                    },
+                    (30, 2),
+                ),
+                object(
+                    ObjectType::Gauge {
+                        value: 0,
+                        power: false,
+                    },
+                    (38, 6),
+                ),
+            ],
+            wire_points: vec![
+                (WireColor::Purple, vec![(13, 8), (31, 8), (31, 4)]),
+                (WireColor::Blue, vec![(34, 4), (42, 4), (42, 8)]),
+            ],
+            wire_bridges: Vec::new(),
+        };
+
+        let mut game_state = game_with_submarine(template, false);
+        let enter_code = |game_state: &mut GameState, entered: i8| {
+            update(
+                game_state,
+                vec![Command::SetKeypadCode {
+                    submarine_id: 0,
+                    object_id: 0,
+                    entered,
+                }],
+            );
+            for _ in 0..50 {
+                update(game_state, Vec::new());
+            }
+        };
+        let door_progress =
+            |game_state: &GameState| match game_state.submarines[0].objects[1].object_type {
+                ObjectType::VerticalDoor { progress, .. } => progress,
+                _ => unreachable!(),
+            };
+        let denied = |game_state: &GameState| match game_state.submarines[0].objects[2].object_type
+        {
+            ObjectType::Gauge { value, .. } => value,
+            _ => unreachable!(),
+        };
+
+        // Without a code lock, the door ignores its wires
+        enter_code(&mut game_state, 42);
+        assert_eq!(door_progress(&game_state), 0);
+
+        update(
+            &mut game_state,
+            vec![Command::SetDoorCode {
+                submarine_id: 0,
+                object_id: 1,
+                required_code: Some(42),
+            }],
+        );
+
+        enter_code(&mut game_state, 7);
+        assert_eq!(door_progress(&game_state), 0);
+        assert_eq!(denied(&game_state), 100);
+
+        enter_code(&mut game_state, 42);
+        assert_eq!(door_progress(&game_state), 15);
+
+        // Clicking a locked door does nothing
+        update(
+            &mut game_state,
+            vec![Command::Interact {
+                submarine_id: 0,
+                object_id: 1,
+            }],
+        );
+        assert_eq!(door_progress(&game_state), 15);
+
+        enter_code(&mut game_state, 0);
+        assert_eq!(door_progress(&game_state), 0);
+    }
+}
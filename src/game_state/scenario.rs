@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    state::{GameState, SubmarineState},
+    update::UpdateEvent,
+};
+
+/// A list of objectives, loaded from a scenario file and checked after every
+/// update.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Scenario {
+    pub name: String,
+    pub objectives: Vec<Objective>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Objective {
+    pub description: String,
+    pub condition: Condition,
+    /// Whether the condition held on the last update.
+    #[serde(default)]
+    pub met: bool,
+}
+
+/// Conditions refer to submarines and objects by their index; one that refers
+/// to something that doesn't exist yet is simply not met. Indexes are kept up
+/// to date as submarines are removed, and conditions on anything removed turn
+/// into `Removed`.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) enum Condition {
+    /// The middle of the submarine is at most `radius` rock cells away from
+    /// `position`, in rock cells.
+    Reach {
+        submarine_id: usize,
+        position: (i32, i32),
+        radius: i32,
+    },
+    /// Less than `percent` of the submarine's inside is filled with water.
+    FloodingBelow { submarine_id: usize, percent: u8 },
+    /// The object is currently powered.
+    ObjectPowered {
+        submarine_id: usize,
+        object_id: usize,
+    },
+    /// Every one of the conditions holds.
+    All(Vec<Condition>),
+    /// Never met; what is left of a condition whose submarine or object was
+    /// removed.
+    Removed,
+}
+
+impl Scenario {
+    /// Keep conditions on the submarines after a removed one pointing at the
+    /// same submarines, as their indexes shift down.
+    pub fn submarine_removed(&mut self, submarine_id: usize) {
+        for objective in &mut self.objectives {
+            objective.condition.submarine_removed(submarine_id);
+        }
+    }
+
+    /// New objects reuse the indexes of cleared ones, so conditions on the
+    /// cleared objects must not carry over to them.
+    pub fn objects_cleared(&mut self, submarine_id: usize) {
+        for objective in &mut self.objectives {
+            objective.condition.objects_cleared(submarine_id);
+        }
+    }
+}
+
+impl Condition {
+    fn submarine_removed(&mut self, removed_id: usize) {
+        let submarine_id = match self {
+            Condition::Reach { submarine_id, .. }
+            | Condition::FloodingBelow { submarine_id, .. }
+            | Condition::ObjectPowered { submarine_id, .. } => submarine_id,
+            Condition::All(conditions) => {
+                for condition in conditions {
+                    condition.submarine_removed(removed_id);
+                }
+                return;
+            }
+            Condition::Removed => return,
+        };
+
+        if *submarine_id > removed_id {
+            *submarine_id -= 1;
+        } else if *submarine_id == removed_id {
+            *self = Condition::Removed;
+        }
+    }
+
+    fn objects_cleared(&mut self, cleared_id: usize) {
+        match self {
+            Condition::ObjectPowered { submarine_id, .. } if *submarine_id == cleared_id => {
+                *self = Condition::Removed;
+            }
+            Condition::All(conditions) => {
+                for condition in conditions {
+                    condition.objects_cleared(cleared_id);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub(crate) fn update_scenario(game_state: &mut GameState, events: &mut Vec<UpdateEvent>) {
+    let scenario = match &mut game_state.scenario {
+        Some(scenario) => scenario,
+        None => return,
+    };
+
+    for (objective_id, objective) in scenario.objectives.iter_mut().enumerate() {
+        let met = condition_met(&objective.condition, &game_state.submarines);
+
+        if met && !objective.met {
+            events.push(UpdateEvent::ObjectiveMet {
+                objective_id,
+                description: objective.description.clone(),
+            });
+        }
+
+        objective.met = met;
+    }
+}
+
+fn condition_met(condition: &Condition, submarines: &[SubmarineState]) -> bool {
+    match condition {
+        Condition::Reach {
+            submarine_id,
+            position,
+            radius,
+        } => {
+            let submarine = match submarines.get(*submarine_id) {
+                Some(submarine) => submarine,
+                None => return false,
+            };
+
+            // 16 movement points per sub-cell, 16 sub-cells per rock-cell;
+            // squares of any i32 position scaled this way still fit in i128.
+            let (width, height) = submarine.water_grid.size();
+            let middle_x = submarine.navigation.position.0 as i128 + width as i128 * 16 / 2;
+            let middle_y = submarine.navigation.position.1 as i128 + height as i128 * 16 / 2;
+
+            let distance_x = middle_x - position.0 as i128 * 16 * 16;
+            let distance_y = middle_y - position.1 as i128 * 16 * 16;
+            let radius = *radius as i128 * 16 * 16;
+
+            distance_x * distance_x + distance_y * distance_y <= radius * radius
+        }
+        Condition::FloodingBelow {
+            submarine_id,
+            percent,
+        } => {
+            let submarine = match submarines.get(*submarine_id) {
+                Some(submarine) => submarine,
+                None => return false,
+            };
+
+            // A full cell holds 1024
+            let water_grid = &submarine.water_grid;
+            let capacity = water_grid.total_inside() as u64 * 1024;
+            let total_water = water_grid.total_water() as u64;
+
+            total_water * 100 < capacity * *percent as u64
+        }
+        Condition::ObjectPowered {
+            submarine_id,
+            object_id,
+        } => submarines
+            .get(*submarine_id)
+            .and_then(|submarine| submarine.objects.get(*object_id))
+            .map(|object| object.powered)
+            .unwrap_or(false),
+        Condition::All(conditions) => conditions
+            .iter()
+            .all(|condition| condition_met(condition, submarines)),
+        Condition::Removed => false,
+    }
+}
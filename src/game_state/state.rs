@@ -1,14 +1,18 @@
+use std::{fmt::Display, ops::RangeInclusive};
+
 use serde::{Deserialize, Serialize};
 
 use super::{
     objects::Object,
     rocks::RockGrid,
+    scenario::Scenario,
     sonar::Sonar,
     water::{CellTemplate, WaterGrid},
-    wires::{WireGrid, WirePoints},
+    wires::{WireBridge, WireGrid, WirePoints},
 };
 
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub(crate) struct UpdateSettings {
     pub update_water: bool,
     pub enable_gravity: bool,
@@ -18,14 +22,77 @@ pub(crate) struct UpdateSettings {
     pub update_objects: bool,
     pub update_position: bool,
     pub update_collision: bool,
+    /// Bounce submarines off rocks and other submarines they collide with;
+    /// otherwise collisions are only detected.
+    pub collision_response: bool,
+    /// Out of 16, how much of its speed into an obstacle a submarine keeps,
+    /// going the other way, when it collides; 0 stops it dead.
+    pub collision_restitution: u8,
+    /// Speed (in 1/256 of a 1/16 cell per update) at which colliding
+    /// submarines are at least pushed away from what they hit.
+    pub collision_separation: i32,
+    /// Distance (in 1/16 cells) at which docking connectors start pulling
+    /// submarines together.
+    pub docking_proximity: i32,
+    /// Distance (in 1/16 cells) at which docking connectors connect; docked
+    /// ones only undock a little further apart than this.
+    pub docking_snap: i32,
+    /// Maximum speed at which docking connectors pull submarines together.
+    pub docking_pull_speed: i32,
+    /// Distance (in 1/16 cells) between submarines at which radios still hear
+    /// each other.
+    pub radio_range: i32,
+    /// How much signal each thin wire color loses per cell; signals start at
+    /// 256, so this decides how far they reach.
+    pub wire_decay: [u16; 4],
+    /// Maximum number of cells a signal can travel from its source, or 0 for
+    /// no limit besides decay.
+    pub max_wire_range: u16,
+    /// How many steps signals propagate through wires on each update.
+    pub wire_iterations: u8,
+    /// Out of 16, how much of its velocity water keeps on each update.
+    pub water_inertia: u8,
+    /// How strongly the submarine's acceleration pushes water around; 0 (the
+    /// default) leaves the water alone.
+    pub water_sloshing: i32,
+    /// Water is simulated in blocks of this many cells on each side; 1 for
+    /// full resolution.
+    pub water_resolution: u8,
+    /// Out of 16, how far water in each connected body moves towards its
+    /// settled level on each update, on top of the regular flow; only used
+    /// while inertia is disabled. 0 turns it off.
+    pub water_equalization: u8,
+    /// Creating more submarines than this is refused, since each one takes up
+    /// a fair amount of memory, which browsers are short on.
+    pub max_submarines: u16,
+    /// Simulate the temperature of the water and air inside submarines, and
+    /// the heat given off by reactors and engines.
+    pub update_temperature: bool,
+    /// Simulation speed in quarters of the normal 60 updates per second. The
+    /// server's setting is the one that counts, since clients only apply the
+    /// updates it sends them.
+    pub sim_speed: u8,
+    /// Updates after an interaction during which an object ignores further
+    /// ones, so that players clicking the same control at once don't make it
+    /// flip back and forth; 0 to take every interaction.
+    pub interaction_cooldown: u16,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct GameState {
     pub update_settings: UpdateSettings,
     pub rock_grid: RockGrid,
+    /// Kept in the order the submarines were created in; removing one shifts
+    /// the ones after it down without reordering them.
+    ///
+    /// Every per-submarine and pairwise update walks this in index order (and
+    /// pairs as lower index first), so the outcome of a tick only depends on
+    /// the state and the commands applied, which networked clients share. Any
+    /// code that adds, removes or moves submarines in it must preserve their
+    /// relative order.
     pub submarines: Vec<SubmarineState>,
     pub collisions: Vec<(usize, usize)>,
+    pub scenario: Option<Scenario>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -37,6 +104,9 @@ pub(crate) struct SubmarineState {
     pub sonar: Sonar,
     pub navigation: Navigation,
     pub collisions: Vec<(usize, usize)>,
+    /// Whether there were any collisions on the previous tick; kept because
+    /// `collisions` is cleared before objects get to see it.
+    pub collided: bool,
     pub docking_points: Vec<DockingPoint>,
 }
 
@@ -57,6 +127,10 @@ pub(crate) struct DockingPoint {
     pub in_proximity_to: Option<(i32, i32)>,
     pub speed_offset: (i32, i32),
     pub direction: DockingDirection,
+    /// The connector this one was docked to on the previous tick, as a
+    /// submarine index and object id; only that pair gets to stay docked past
+    /// the snap distance.
+    pub linked_to: Option<(usize, usize)>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
@@ -72,6 +146,7 @@ pub(crate) struct SubmarineTemplate {
     pub background_pixels: Vec<u8>,
     pub objects: Vec<Object>,
     pub wire_points: Vec<WirePoints>,
+    pub wire_bridges: Vec<WireBridge>,
 }
 
 impl Default for UpdateSettings {
@@ -85,7 +160,77 @@ impl Default for UpdateSettings {
             update_objects: true,
             update_position: true,
             update_collision: true,
+            collision_response: false,
+            collision_restitution: 4,
+            collision_separation: 64,
+            docking_proximity: 128,
+            docking_snap: 4,
+            docking_pull_speed: 2,
+            radio_range: 8192,
+            wire_decay: [1; 4],
+            max_wire_range: 0,
+            wire_iterations: 3,
+            water_inertia: 12,
+            water_sloshing: 0,
+            water_resolution: 1,
+            water_equalization: 0,
+            max_submarines: 64,
+            update_temperature: false,
+            sim_speed: 4,
+            interaction_cooldown: 6,
+        }
+    }
+}
+
+impl UpdateSettings {
+    /// How many updates are run each second at the current `sim_speed`.
+    pub(crate) fn updates_per_second(&self) -> f64 {
+        15.0 * self.sim_speed.max(1) as f64
+    }
+
+    /// Checks that every setting is within the range its slider allows.
+    ///
+    /// Settings also come from the console, from files and from other
+    /// players, and some of the updates panic on values outside those ranges.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        fn check<T: PartialOrd + Display>(
+            name: &str,
+            value: T,
+            range: RangeInclusive<T>,
+        ) -> Result<(), String> {
+            if range.contains(&value) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{} must be between {} and {}, not {}.",
+                    name,
+                    range.start(),
+                    range.end(),
+                    value
+                ))
+            }
         }
+
+        check("sim_speed", self.sim_speed, 1..=32)?;
+        check("water_inertia", self.water_inertia, 0..=15)?;
+        check("water_sloshing", self.water_sloshing, 0..=16)?;
+        check("water_resolution", self.water_resolution, 1..=4)?;
+        check("water_equalization", self.water_equalization, 0..=16)?;
+        check("wire_iterations", self.wire_iterations, 1..=16)?;
+        for &decay in &self.wire_decay {
+            check("wire_decay", decay, 1..=32)?;
+        }
+        check("max_wire_range", self.max_wire_range, 0..=256)?;
+        check("collision_restitution", self.collision_restitution, 0..=16)?;
+        check("collision_separation", self.collision_separation, 0..=512)?;
+        check("docking_proximity", self.docking_proximity, 16..=1024)?;
+        check("docking_snap", self.docking_snap, 1..=64)?;
+        check("docking_pull_speed", self.docking_pull_speed, 1..=16)?;
+        check("radio_range", self.radio_range, 256..=65536)?;
+        check("interaction_cooldown", self.interaction_cooldown, 0..=60)?;
+        check("max_submarines", self.max_submarines, 1..=256)?;
+
+        Ok(())
     }
 }
 
@@ -96,6 +241,19 @@ impl Default for GameState {
             rock_grid: RockGrid::new(0, 0),
             submarines: Vec::new(),
             collisions: Vec::new(),
+            scenario: None,
         }
     }
 }
+
+impl GameState {
+    /// FNV-1a hash of the whole serialized state; equal states give equal
+    /// checksums on every platform, so runs can be compared.
+    pub fn checksum(&self) -> u64 {
+        let bytes = bincode::serialize(self).expect("Game state should always be serializable");
+
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
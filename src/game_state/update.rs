@@ -1,15 +1,23 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::game_state::{
-    collisions::{update_rock_collisions, update_submarine_collisions},
-    objects::{interact_with_object, update_objects, Object, ObjectType},
+    collisions::{respond_to_collisions, update_rock_collisions, update_submarine_collisions},
+    objects::{
+        heat_from_objects, interact_with_object, object_size, object_type_name, overlapping_object,
+        port_cell, port_connected, reset_object_type, restore_object_walls, update_objects,
+        update_radios, Object, ObjectType,
+    },
+    scenario::{update_scenario, Scenario},
     sonar::{update_sonar, Sonar},
     state::{GameState, Navigation, SubmarineState, SubmarineTemplate, UpdateSettings},
-    water::WaterGrid,
+    water::{WallMaterial, WaterGrid},
     wires::{WireColor, WireGrid},
 };
 
 use super::state::{DockingDirection, DockingPoint};
+use crate::trace;
 
 /// A request to mutate state. Created by the UI and player actions.
 #[derive(Serialize, Deserialize, Clone)]
@@ -26,6 +34,9 @@ pub(crate) enum Command {
     ClearWater {
         submarine_id: usize,
     },
+    ClearObjects {
+        submarine_id: usize,
+    },
     ChangeUpdateSettings {
         update_settings: UpdateSettings,
     },
@@ -38,14 +49,90 @@ pub(crate) enum Command {
         submarine_template: Box<SubmarineTemplate>,
         rock_position: (usize, usize),
     },
+    RemoveSubmarine {
+        submarine_id: usize,
+    },
+    MoveSubmarine {
+        submarine_id: usize,
+        position: (i32, i32),
+    },
+    MirrorSubmarine {
+        submarine_id: usize,
+    },
+    SetObjectLabel {
+        submarine_id: usize,
+        object_id: usize,
+        label: Option<String>,
+    },
+    SetObjectNote {
+        submarine_id: usize,
+        object_id: usize,
+        note: Option<String>,
+    },
+    /// Put the object back in the state it's in when first placed, keeping its
+    /// position, label, note and configuration.
+    ResetObject {
+        submarine_id: usize,
+        object_id: usize,
+    },
+    /// Make a door close by itself after being left open for this many
+    /// updates, or never if `None`.
+    SetDoorAutoClose {
+        submarine_id: usize,
+        object_id: usize,
+        auto_close: Option<u16>,
+    },
+    /// Lock a door so that it only opens on this logic value, or unlock it if
+    /// `None`.
+    SetDoorCode {
+        submarine_id: usize,
+        object_id: usize,
+        required_code: Option<i8>,
+    },
+    /// Change the code a keypad sends.
+    SetKeypadCode {
+        submarine_id: usize,
+        object_id: usize,
+        entered: i8,
+    },
+    /// Change which logic values a signal map reads as its low and high
+    /// inputs, and what it sends for them.
+    SetSignalMapRanges {
+        submarine_id: usize,
+        object_id: usize,
+        input: (i8, i8),
+        output: (i8, i8),
+    },
+    /// Start a scenario, or stop the current one if `None`.
+    LoadScenario {
+        scenario: Option<Scenario>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) enum CellCommand {
-    EditWires { add: bool, color: WireColor },
-    EditWalls { add: bool },
-    EditWater { add: bool },
-    AddObject { object_type: ObjectType },
+    EditWires {
+        add: bool,
+        color: WireColor,
+    },
+    ToggleWireBridge {
+        color: WireColor,
+    },
+    EditWalls {
+        add: bool,
+        material: WallMaterial,
+    },
+    EditWater {
+        add: bool,
+    },
+    AddObject {
+        object_type: ObjectType,
+    },
+    /// Paint a square of the background, centered on the cell.
+    PaintBackground {
+        color: [u8; 4],
+        brush_size: u8,
+    },
 }
 
 pub(crate) enum UpdateEvent {
@@ -54,7 +141,31 @@ pub(crate) enum UpdateEvent {
         submarine_event: SubmarineUpdatedEvent,
     },
     SubmarineCreated,
+    SubmarineRemoved {
+        submarine_id: usize,
+    },
     GameStateReset,
+    /// An objective of the current scenario went from unmet to met.
+    ObjectiveMet {
+        objective_id: usize,
+        description: String,
+    },
+    /// Another player did something worth a look; only in multiplayer, and
+    /// not part of the game state.
+    TeammateAction {
+        /// Where it happened in the world, in 1/16 cells.
+        position: (i32, i32),
+        description: String,
+    },
+}
+
+/// A command that was ignored, because it didn't make sense or would have left
+/// the game in a broken state; the reason is meant to be shown to the player
+/// who sent it.
+pub(crate) struct CommandError {
+    /// Which of the commands given to `update_game` it was, counting from 0.
+    pub command_index: usize,
+    pub reason: String,
 }
 
 pub(crate) enum SubmarineUpdatedEvent {
@@ -62,63 +173,136 @@ pub(crate) enum SubmarineUpdatedEvent {
     Walls,
     Wires,
     Signals,
+    Background,
+    /// Objects were added or removed, so object indexes may now point at
+    /// other objects.
+    Objects,
+}
+
+/// Microseconds spent in each update phase of a submarine, accumulated over
+/// all the updates done in a frame.
+#[derive(Default, Clone)]
+pub struct SubmarineTimings {
+    pub water: u32,
+    pub wires: u32,
+    pub objects: u32,
+    pub sonar: u32,
+}
+
+/// Measures elapsed time on native; does nothing on wasm, where `Instant` is
+/// not available.
+struct PhaseTimer {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+}
+
+impl PhaseTimer {
+    fn start() -> Self {
+        PhaseTimer {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    fn add_elapsed(self, total: &mut u32) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            *total = total.saturating_add(self.start.elapsed().as_micros() as u32);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = total;
+    }
 }
 
 pub(crate) fn update_game(
     commands: impl Iterator<Item = Command>,
     game_state: &mut GameState,
     events: &mut Vec<UpdateEvent>,
+    command_errors: &mut Vec<CommandError>,
+    sub_timings: &mut Vec<SubmarineTimings>,
 ) {
     game_state.collisions.clear();
 
-    update_state_from_commands(commands, game_state, events);
+    update_state_from_commands(commands, game_state, events, command_errors);
+
+    for submarine in &mut game_state.submarines {
+        submarine.water_grid.update_compartments_if_outdated();
+    }
 
     let update_settings = &game_state.update_settings;
 
     for submarine in &mut game_state.submarines {
+        submarine.collided = !submarine.collisions.is_empty();
         submarine.collisions.clear();
+
+        for object in &mut submarine.objects {
+            object.interaction_cooldown = object.interaction_cooldown.saturating_sub(1);
+        }
     }
 
-    update_docking_points(&mut game_state.submarines);
+    update_docking_points(&mut game_state.submarines, update_settings);
+
+    sub_timings.resize(game_state.submarines.len(), SubmarineTimings::default());
 
     for (sub_index, submarine) in game_state.submarines.iter_mut().enumerate() {
+        let timings = &mut sub_timings[sub_index];
+
         if update_settings.update_position {
             update_navigation(submarine);
         }
 
         if update_settings.update_water {
-            submarine.water_grid.update(
-                update_settings.enable_gravity,
-                update_settings.enable_inertia,
-            );
+            let timer = PhaseTimer::start();
+            submarine
+                .water_grid
+                .update(update_settings, submarine.navigation.acceleration);
+            timer.add_elapsed(&mut timings.water);
+        }
+        if update_settings.update_temperature {
+            let timer = PhaseTimer::start();
+            heat_from_objects(submarine);
+            submarine.water_grid.update_temperature();
+            timer.add_elapsed(&mut timings.water);
         }
         if update_settings.update_wires {
-            for _ in 0..3 {
-                let mut signals_updated = false;
-                submarine.wire_grid.update(&mut signals_updated);
+            let timer = PhaseTimer::start();
+            let mut signals_updated = false;
+            for _ in 0..update_settings.wire_iterations {
+                submarine.wire_grid.update(
+                    &mut signals_updated,
+                    &update_settings.wire_decay,
+                    update_settings.max_wire_range,
+                );
+            }
 
-                if signals_updated {
-                    events.push(UpdateEvent::Submarine {
-                        submarine_id: sub_index,
-                        submarine_event: SubmarineUpdatedEvent::Signals,
-                    });
-                }
+            if signals_updated {
+                events.push(UpdateEvent::Submarine {
+                    submarine_id: sub_index,
+                    submarine_event: SubmarineUpdatedEvent::Signals,
+                });
             }
 
             submarine.wire_grid.update_bundles();
+            timer.add_elapsed(&mut timings.wires);
         }
         if update_settings.update_objects {
+            let timer = PhaseTimer::start();
             let mut walls_updated = false;
-            update_objects(submarine, &mut walls_updated);
+            update_objects(submarine, game_state.rock_grid.size(), &mut walls_updated);
 
+            // Doors opening or closing merge or split compartments.
             if walls_updated {
+                submarine.water_grid.update_compartments();
+
                 events.push(UpdateEvent::Submarine {
                     submarine_id: sub_index,
                     submarine_event: SubmarineUpdatedEvent::Walls,
                 });
             }
+            timer.add_elapsed(&mut timings.objects);
         }
         if update_settings.update_sonar {
+            let timer = PhaseTimer::start();
             let updated = update_sonar(
                 &mut submarine.sonar,
                 &submarine.navigation,
@@ -132,6 +316,7 @@ pub(crate) fn update_game(
                     submarine_event: SubmarineUpdatedEvent::Sonar,
                 });
             }
+            timer.add_elapsed(&mut timings.sonar);
         }
 
         if update_settings.update_collision {
@@ -144,148 +329,588 @@ pub(crate) fn update_game(
         update_position(&mut game_state.submarines);
     }
 
-    if update_settings.update_collision {
-        for sub1_index in 0..game_state.submarines.len() {
-            for sub2_index in sub1_index + 1..game_state.submarines.len() {
-                let (left, right) = game_state.submarines.split_at_mut(sub2_index);
-                let submarine1 = &mut left[sub1_index];
-                let submarine2 = &mut right[0];
+    if update_settings.update_objects {
+        update_radios(&mut game_state.submarines, update_settings.radio_range);
+    }
 
-                update_submarine_collisions(submarine1, submarine2);
-                update_submarine_collisions(submarine2, submarine1);
+    // Pairs are visited in index order, lower index first; see
+    // `GameState::submarines`.
+    if update_settings.update_collision {
+        // Docked submarines are always touching; those contacts are detected
+        // after the others were responded to, so that docked groups don't
+        // bounce themselves apart.
+        let docked_pairs = update_collisions_between(&mut game_state.submarines);
+
+        if update_settings.collision_response {
+            for submarine in &mut game_state.submarines {
+                respond_to_collisions(
+                    submarine,
+                    update_settings.collision_restitution,
+                    update_settings.collision_separation,
+                );
             }
         }
+
+        for (sub1_index, sub2_index) in docked_pairs {
+            let (left, right) = game_state.submarines.split_at_mut(sub2_index);
+            update_submarine_collisions(&mut left[sub1_index], &right[0]);
+            update_submarine_collisions(&mut right[0], &left[sub1_index]);
+        }
     }
+
+    update_scenario(game_state, events);
 }
 
 fn update_state_from_commands(
     commands: impl Iterator<Item = Command>,
     game_state: &mut GameState,
     events: &mut Vec<UpdateEvent>,
+    command_errors: &mut Vec<CommandError>,
 ) {
-    for command in commands {
-        match command {
-            Command::Interact {
-                submarine_id,
-                object_id,
-            } => {
-                if let Some(submarine) = game_state.submarines.get_mut(submarine_id) {
-                    if let Some(object) = submarine.objects.get_mut(object_id) {
-                        interact_with_object(object);
-                    }
-                };
-            }
-            Command::Cell {
-                submarine_id,
-                cell,
-                cell_command,
-            } => {
-                if let Some(submarine) = game_state.submarines.get_mut(submarine_id) {
-                    let (width, height) = submarine.water_grid.size();
-                    if cell.0 >= width || cell.1 >= height {
-                        continue;
-                    }
+    for (command_index, command) in commands.enumerate() {
+        if let Err(reason) = apply_command(command, game_state, events) {
+            command_errors.push(CommandError {
+                command_index,
+                reason,
+            });
+        }
+    }
+}
 
-                    let water_cell = submarine.water_grid.cell_mut(cell.0, cell.1);
+fn submarine_mut(
+    submarines: &mut [SubmarineState],
+    submarine_id: usize,
+) -> Result<&mut SubmarineState, String> {
+    submarines
+        .get_mut(submarine_id)
+        .ok_or_else(|| format!("There is no submarine #{}.", submarine_id))
+}
 
-                    match &cell_command {
-                        CellCommand::EditWater { add: true } => water_cell.fill(),
-                        CellCommand::EditWater { add: false } => water_cell.empty(),
-                        CellCommand::EditWalls { add: true } => water_cell.make_wall(),
-                        CellCommand::EditWalls { add: false } => water_cell.clear_wall(),
-                        CellCommand::EditWires { add: true, color } => {
-                            submarine.wire_grid.make_wire(cell.0, cell.1, *color)
-                        }
-                        CellCommand::EditWires { add: false, color } => {
-                            submarine.wire_grid.clear_wire(cell.0, cell.1, *color)
-                        }
-                        CellCommand::AddObject { object_type } => {
-                            submarine.objects.push(Object {
-                                object_type: object_type.clone(),
-                                position: (cell.0 as u32, cell.1 as u32),
-                                powered: false,
-                            });
-                        }
-                    }
+fn object_mut(submarine: &mut SubmarineState, object_id: usize) -> Result<&mut Object, String> {
+    submarine
+        .objects
+        .get_mut(object_id)
+        .ok_or_else(|| format!("There is no object #{} on that submarine.", object_id))
+}
 
-                    match &cell_command {
-                        CellCommand::EditWater { .. } | CellCommand::EditWalls { .. } => {
-                            events.push(UpdateEvent::Submarine {
-                                submarine_id,
-                                submarine_event: SubmarineUpdatedEvent::Walls,
-                            });
-                        }
-                        CellCommand::EditWires { .. } => {
-                            events.push(UpdateEvent::Submarine {
-                                submarine_id,
-                                submarine_event: SubmarineUpdatedEvent::Wires,
-                            });
-                        }
-                        CellCommand::AddObject { .. } => (),
+/// Apply a single command, or leave the game state untouched and say why not.
+fn apply_command(
+    command: Command,
+    game_state: &mut GameState,
+    events: &mut Vec<UpdateEvent>,
+) -> Result<(), String> {
+    match command {
+        Command::Interact {
+            submarine_id,
+            object_id,
+        } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            let object = object_mut(submarine, object_id)?;
+
+            // Counted in updates rather than time, so that every client
+            // ignores the same interactions.
+            if object.interaction_cooldown == 0 {
+                interact_with_object(object);
+                object.interaction_cooldown = game_state.update_settings.interaction_cooldown;
+            }
+        }
+        Command::ResetObject {
+            submarine_id,
+            object_id,
+        } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            let object = object_mut(submarine, object_id)?;
+
+            // Docking connectors lose their connected flags here; docking
+            // points are rebuilt from them right after the commands are
+            // applied.
+            object.object_type = reset_object_type(&object.object_type);
+            object.powered = false;
+        }
+        Command::SetDoorAutoClose {
+            submarine_id,
+            object_id,
+            auto_close,
+        } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            let object = object_mut(submarine, object_id)?;
+
+            match &mut object.object_type {
+                ObjectType::Door {
+                    auto_close: door_auto_close,
+                    ..
+                }
+                | ObjectType::VerticalDoor {
+                    auto_close: door_auto_close,
+                    ..
+                } => *door_auto_close = auto_close,
+                _ => return Err("Only doors can close by themselves.".to_string()),
+            }
+        }
+        Command::SetDoorCode {
+            submarine_id,
+            object_id,
+            required_code,
+        } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            let object = object_mut(submarine, object_id)?;
+
+            match &mut object.object_type {
+                ObjectType::Door {
+                    required_code: door_code,
+                    ..
+                }
+                | ObjectType::VerticalDoor {
+                    required_code: door_code,
+                    ..
+                } => *door_code = required_code,
+                _ => return Err("Only doors can be locked with a code.".to_string()),
+            }
+        }
+        Command::SetKeypadCode {
+            submarine_id,
+            object_id,
+            entered,
+        } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            let object = object_mut(submarine, object_id)?;
+
+            match &mut object.object_type {
+                ObjectType::Keypad {
+                    entered: keypad_entered,
+                } => *keypad_entered = entered,
+                _ => return Err("Only keypads have a code to enter.".to_string()),
+            }
+        }
+        Command::SetSignalMapRanges {
+            submarine_id,
+            object_id,
+            input,
+            output,
+        } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            let object = object_mut(submarine, object_id)?;
+
+            match &mut object.object_type {
+                ObjectType::SignalMap {
+                    in_low,
+                    in_high,
+                    out_low,
+                    out_high,
+                    ..
+                } => {
+                    *in_low = input.0;
+                    *in_high = input.1;
+                    *out_low = output.0;
+                    *out_high = output.1;
+                }
+                _ => return Err("Only signal maps have ranges to set.".to_string()),
+            }
+        }
+        Command::LoadScenario { scenario } => {
+            game_state.scenario = scenario;
+        }
+        Command::SetObjectLabel {
+            submarine_id,
+            object_id,
+            label,
+        } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            object_mut(submarine, object_id)?.label = label;
+        }
+        Command::SetObjectNote {
+            submarine_id,
+            object_id,
+            note,
+        } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            object_mut(submarine, object_id)?.note = note;
+        }
+        Command::Cell {
+            submarine_id,
+            cell,
+            cell_command,
+        } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            let (width, height) = submarine.water_grid.size();
+            if cell.0 >= width || cell.1 >= height {
+                return Err(format!(
+                    "Cell {}, {} is outside of the submarine, which is {}x{}.",
+                    cell.0, cell.1, width, height,
+                ));
+            }
+
+            let water_cell = submarine.water_grid.cell_mut(cell.0, cell.1);
+
+            match &cell_command {
+                CellCommand::EditWater { add: true } => water_cell.fill(),
+                CellCommand::EditWater { add: false } => water_cell.empty(),
+                CellCommand::EditWalls {
+                    add: true,
+                    material,
+                } => water_cell.set_wall_material(*material),
+                CellCommand::EditWalls { add: false, .. } => water_cell.clear_wall(),
+                CellCommand::EditWires { add: true, color } => {
+                    submarine.wire_grid.make_wire(cell.0, cell.1, *color)
+                }
+                CellCommand::EditWires { add: false, color } => {
+                    submarine.wire_grid.clear_wire(cell.0, cell.1, *color)
+                }
+                CellCommand::ToggleWireBridge { color } => {
+                    submarine.wire_grid.toggle_bridge(cell.0, cell.1, *color)
+                }
+                CellCommand::AddObject { object_type } => {
+                    let position = (cell.0 as u32, cell.1 as u32);
+
+                    if let Some(other_object) =
+                        overlapping_object(&submarine.objects, object_type, position)
+                    {
+                        return Err(format!(
+                            "Cannot place the {} there, it would overlap the {} at {}, {}.",
+                            object_type_name(object_type),
+                            object_type_name(&other_object.object_type),
+                            other_object.position.0,
+                            other_object.position.1,
+                        ));
                     }
+
+                    submarine.objects.push(Object {
+                        object_type: object_type.clone(),
+                        position,
+                        powered: false,
+                        label: None,
+                        note: None,
+                        interaction_cooldown: 0,
+                    });
+                }
+                CellCommand::PaintBackground { color, brush_size } => {
+                    paint_background(submarine, cell, *color, *brush_size)
                 }
             }
-            Command::ClearWater { submarine_id } => {
-                if let Some(submarine) = game_state.submarines.get_mut(submarine_id) {
-                    submarine.water_grid.clear();
+
+            // Split once after all of this update's commands, not per cell.
+            if let CellCommand::EditWalls { .. } = cell_command {
+                submarine.water_grid.invalidate_compartments();
+            }
+
+            match &cell_command {
+                CellCommand::EditWater { .. } | CellCommand::EditWalls { .. } => {
+                    events.push(UpdateEvent::Submarine {
+                        submarine_id,
+                        submarine_event: SubmarineUpdatedEvent::Walls,
+                    });
+                }
+                CellCommand::EditWires { .. } | CellCommand::ToggleWireBridge { .. } => {
+                    events.push(UpdateEvent::Submarine {
+                        submarine_id,
+                        submarine_event: SubmarineUpdatedEvent::Wires,
+                    });
+                }
+                CellCommand::AddObject { .. } => {
+                    events.push(UpdateEvent::Submarine {
+                        submarine_id,
+                        submarine_event: SubmarineUpdatedEvent::Objects,
+                    });
+                }
+                CellCommand::PaintBackground { .. } => {
+                    events.push(UpdateEvent::Submarine {
+                        submarine_id,
+                        submarine_event: SubmarineUpdatedEvent::Background,
+                    });
                 }
             }
-            Command::ChangeUpdateSettings { update_settings } => {
-                game_state.update_settings = update_settings
+        }
+        Command::ClearWater { submarine_id } => {
+            submarine_mut(&mut game_state.submarines, submarine_id)?
+                .water_grid
+                .clear();
+        }
+        Command::ClearObjects { submarine_id } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+
+            let mut walls_updated = false;
+            for object in &submarine.objects {
+                walls_updated |= restore_object_walls(object, &mut submarine.water_grid);
             }
-            Command::SetSonarTarget {
+
+            submarine.objects.clear();
+
+            if let Some(scenario) = &mut game_state.scenario {
+                scenario.objects_cleared(submarine_id);
+            }
+            submarine.docking_points.clear();
+
+            // Its docking connectors are gone, so undock anything that was
+            // docked to them; `update_docking_points` rebuilds the rest from
+            // the remaining connectors right after the commands.
+            undock_from(&mut game_state.submarines, submarine_id);
+
+            events.push(UpdateEvent::Submarine {
                 submarine_id,
-                object_id,
-                rock_position,
-            } => {
-                if let Some(submarine) = game_state.submarines.get_mut(submarine_id) {
-                    if let Some(object) = submarine.objects.get_mut(object_id) {
-                        if let ObjectType::Sonar {
-                            navigation_target, ..
-                        } = &mut object.object_type
-                        {
-                            *navigation_target = Some(rock_position);
-                        }
+                submarine_event: SubmarineUpdatedEvent::Objects,
+            });
+
+            if walls_updated {
+                submarine_mut(&mut game_state.submarines, submarine_id)?
+                    .water_grid
+                    .update_compartments();
+
+                events.push(UpdateEvent::Submarine {
+                    submarine_id,
+                    submarine_event: SubmarineUpdatedEvent::Walls,
+                });
+            }
+        }
+        Command::ChangeUpdateSettings { update_settings } => {
+            update_settings.validate()?;
+            game_state.update_settings = update_settings
+        }
+        Command::SetSonarTarget {
+            submarine_id,
+            object_id,
+            rock_position,
+        } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            let object = object_mut(submarine, object_id)?;
+            match &mut object.object_type {
+                ObjectType::Sonar {
+                    navigation_target, ..
+                } => *navigation_target = Some(rock_position),
+                other => {
+                    return Err(format!(
+                        "Cannot set a sonar target on a {}.",
+                        object_type_name(other),
+                    ))
+                }
+            }
+        }
+        Command::CreateSubmarine {
+            submarine_template,
+            rock_position,
+        } => {
+            let max_submarines = game_state.update_settings.max_submarines as usize;
+            if game_state.submarines.len() >= max_submarines {
+                return Err(format!(
+                    "Cannot create another submarine, there are already {} out of \
+                    at most {}. Remove one, or raise the limit in the update settings.",
+                    game_state.submarines.len(),
+                    max_submarines,
+                ));
+            }
+
+            let (width, height) = submarine_template.size;
+            let position = (rock_position.0 as i32, rock_position.1 as i32);
+            game_state.submarines.push(SubmarineState {
+                background_pixels: submarine_template.background_pixels,
+                water_grid: WaterGrid::from_cells(width, height, &submarine_template.water_cells),
+                wire_grid: WireGrid::from_wire_points(
+                    width,
+                    height,
+                    &submarine_template.wire_points,
+                    &submarine_template.wire_bridges,
+                ),
+                objects: submarine_template.objects,
+                navigation: Navigation {
+                    position,
+                    target: position,
+                    ..Default::default()
+                },
+                sonar: Sonar::default(),
+                collisions: Vec::new(),
+                collided: false,
+                docking_points: Vec::new(),
+            });
+
+            events.push(UpdateEvent::SubmarineCreated);
+        }
+        Command::RemoveSubmarine { submarine_id } => {
+            submarine_mut(&mut game_state.submarines, submarine_id)?;
+
+            // Not `swap_remove`; the others must keep their relative order,
+            // see `GameState::submarines`.
+            game_state.submarines.remove(submarine_id);
+
+            // Undock anything that was docked to it, and shift the indices of
+            // links to the submarines that came after it.
+            undock_from(&mut game_state.submarines, submarine_id);
+            for submarine in &mut game_state.submarines {
+                for point in &mut submarine.docking_points {
+                    match &mut point.connected_to {
+                        Some((sub_index, _)) if *sub_index > submarine_id => *sub_index -= 1,
+                        _ => (),
                     }
-                };
-            }
-            Command::CreateSubmarine {
-                submarine_template,
-                rock_position,
-            } => {
-                let (width, height) = submarine_template.size;
-                let position = (rock_position.0 as i32, rock_position.1 as i32);
-                game_state.submarines.push(SubmarineState {
-                    background_pixels: submarine_template.background_pixels,
-                    water_grid: WaterGrid::from_cells(
-                        width,
-                        height,
-                        &submarine_template.water_cells,
-                    ),
-                    wire_grid: WireGrid::from_wire_points(
-                        width,
-                        height,
-                        &submarine_template.wire_points,
-                    ),
-                    objects: submarine_template.objects,
-                    navigation: Navigation {
-                        position,
-                        target: position,
-                        ..Default::default()
-                    },
-                    sonar: Sonar::default(),
-                    collisions: Vec::new(),
-                    docking_points: Vec::new(),
+                }
+            }
+
+            if let Some(scenario) = &mut game_state.scenario {
+                scenario.submarine_removed(submarine_id);
+            }
+
+            events.push(UpdateEvent::SubmarineRemoved { submarine_id });
+        }
+        Command::MirrorSubmarine { submarine_id } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            mirror_submarine(submarine)?;
+
+            for submarine_event in [
+                SubmarineUpdatedEvent::Walls,
+                SubmarineUpdatedEvent::Wires,
+                SubmarineUpdatedEvent::Signals,
+                SubmarineUpdatedEvent::Background,
+                SubmarineUpdatedEvent::Objects,
+            ] {
+                events.push(UpdateEvent::Submarine {
+                    submarine_id,
+                    submarine_event,
                 });
+            }
+        }
+        Command::MoveSubmarine {
+            submarine_id,
+            position,
+        } => {
+            let submarine = submarine_mut(&mut game_state.submarines, submarine_id)?;
+            let offset = (
+                position.0 - submarine.navigation.position.0,
+                position.1 - submarine.navigation.position.1,
+            );
+
+            // Move the whole docked group along, so that they stay docked
+            for sub_index in docked_group(&game_state.submarines, submarine_id) {
+                let navigation = &mut game_state.submarines[sub_index].navigation;
+                navigation.position.0 += offset.0;
+                navigation.position.1 += offset.1;
+                navigation.target.0 += offset.0;
+                navigation.target.1 += offset.1;
+                navigation.speed = (0, 0);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn paint_background(
+    submarine: &mut SubmarineState,
+    (x, y): (usize, usize),
+    color: [u8; 4],
+    brush_size: u8,
+) {
+    let (width, height) = submarine.water_grid.size();
+    let brush_size = brush_size.max(1) as usize;
+
+    let left = x.saturating_sub(brush_size / 2);
+    let top = y.saturating_sub(brush_size / 2);
+
+    for y in top..(top + brush_size).min(height) {
+        for x in left..(left + brush_size).min(width) {
+            let pixel = (y * width + x) * 4;
+            submarine.background_pixels[pixel..pixel + 4].copy_from_slice(&color);
+        }
+    }
+}
+
+/// Flip a submarine left to right. Objects have no orientation of their own
+/// yet, so they are moved to the mirrored spot but still face the same way.
+/// The flip is refused, leaving everything as it was, if that would pull a
+/// wired port off its wire, or if an object wouldn't fit.
+fn mirror_submarine(submarine: &mut SubmarineState) -> Result<(), String> {
+    let (width, height) = submarine.water_grid.size();
+
+    let mut mirrored_positions = Vec::with_capacity(submarine.objects.len());
+    for object in &submarine.objects {
+        // Objects are drawn one cell to the right and below their position
+        let (object_width, _) = object_size(&object.object_type);
+        let mirrored_x = width as i64 - 2 - object_width as i64 - object.position.0 as i64;
+
+        if mirrored_x < 0 {
+            return Err(format!(
+                "Cannot mirror the submarine, the {} at {}, {} would not fit.",
+                object_type_name(&object.object_type),
+                object.position.0,
+                object.position.1,
+            ));
+        }
+
+        for port in object.object_type.wire_ports() {
+            if !port_connected(submarine, object, port) {
+                continue;
+            }
+
+            let (port_x, _) = port_cell(object, port);
+            if mirrored_x as usize + port.offset.0 as usize != width - 1 - port_x {
+                return Err(format!(
+                    "Cannot mirror the submarine, the {}'s \"{}\" port would come off its \
+                    wire; disconnect it first.",
+                    object_type_name(&object.object_type),
+                    port.name,
+                ));
+            }
+        }
+
+        mirrored_positions.push(mirrored_x as u32);
+    }
+
+    submarine.water_grid.mirror_horizontally();
+    submarine.wire_grid = submarine.wire_grid.mirrored_horizontally();
+
+    for row in submarine.background_pixels.chunks_exact_mut(width * 4) {
+        for x in 0..width / 2 {
+            for channel in 0..4 {
+                row.swap(x * 4 + channel, (width - 1 - x) * 4 + channel);
+            }
+        }
+    }
+    debug_assert_eq!(submarine.background_pixels.len(), width * height * 4);
+
+    for (object, mirrored_x) in submarine.objects.iter_mut().zip(mirrored_positions) {
+        object.position.0 = mirrored_x;
+    }
+
+    Ok(())
+}
 
-                events.push(UpdateEvent::SubmarineCreated);
+/// Disconnect every docking point linked to the given submarine.
+fn undock_from(submarines: &mut [SubmarineState], submarine_id: usize) {
+    for submarine in submarines {
+        for point in &mut submarine.docking_points {
+            match point.connected_to {
+                Some((sub_index, _)) if sub_index == submarine_id => (),
+                _ => continue,
+            }
+
+            point.connected_to = None;
+            point.in_proximity_to = None;
+            point.linked_to = None;
+
+            let object = &mut submarine.objects[point.connector_object_id];
+            match &mut object.object_type {
+                ObjectType::DockingConnectorTop { connected, .. }
+                | ObjectType::DockingConnectorBottom { connected, .. } => *connected = false,
+                _ => (),
             }
         }
     }
 }
 
-fn update_docking_points(submarines: &mut [SubmarineState]) {
+/// How far past `docking_snap`, in 1/16 cells, two docked connectors may drift
+/// apart before they undock.
+const DOCKING_BREAK_MARGIN: i32 = 4;
+
+fn update_docking_points(submarines: &mut [SubmarineState], update_settings: &UpdateSettings) {
+    let UpdateSettings {
+        docking_proximity,
+        docking_snap,
+        docking_pull_speed,
+        ..
+    } = *update_settings;
+
     for submarine in submarines.iter_mut() {
+        let previous_links: BTreeMap<usize, (usize, usize)> = submarine
+            .docking_points
+            .iter()
+            .filter_map(|point| Some((point.connector_object_id, point.connected_to?)))
+            .collect();
         submarine.docking_points.clear();
 
         for (obj_index, object) in submarine.objects.iter_mut().enumerate() {
@@ -322,12 +947,15 @@ fn update_docking_points(submarines: &mut [SubmarineState]) {
                 in_proximity_to: None,
                 speed_offset: (0, 0),
                 direction,
+                linked_to: previous_links.get(&obj_index).copied(),
             });
         }
     }
 
     // Attempt to nudge subs closer to each other if docking points are powered
-    // and in proximity.
+    // and in proximity. Pairs are visited in index order, lower index first,
+    // and the first pair in proximity claims a docking point; see
+    // `GameState::submarines`.
     for sub1_index in 0..submarines.len() {
         let (left_subs, right_subs) = submarines.split_at_mut(sub1_index + 1);
 
@@ -350,28 +978,42 @@ fn update_docking_points(submarines: &mut [SubmarineState]) {
                     let diff_x = point1.connection_point.0 - point2.connection_point.0;
                     let diff_y = point1.connection_point.1 - point2.connection_point.1;
 
+                    // A pair that was already docked to each other only breaks
+                    // a little past the snap distance, so that the link doesn't
+                    // flicker while the subs settle.
+                    let sub2_index = sub1_index + 1 + sub2_index_offset;
+                    let linked = point1.linked_to == Some((sub2_index, point2.connector_object_id))
+                        && point2.linked_to == Some((sub1_index, point1.connector_object_id));
+                    let snap = if linked {
+                        docking_snap + DOCKING_BREAK_MARGIN
+                    } else {
+                        docking_snap
+                    };
+                    let proximity = docking_proximity.max(snap);
+
                     // Close enough to start pulling the subs together?
-                    if diff_x.abs() >= 128 || diff_y.abs() >= 128 {
+                    if diff_x.abs() >= proximity || diff_y.abs() >= proximity {
                         continue;
                     }
 
+                    trace!("Docking distance: {}, {}", diff_x, diff_y);
+
                     point1.in_proximity_to = Some(point2.connection_point);
                     point2.in_proximity_to = Some(point1.connection_point);
 
-                    let speed_x = diff_x.clamp(-2, 2);
-                    let speed_y = diff_y.clamp(-2, 2);
+                    let speed_x = diff_x.clamp(-docking_pull_speed, docking_pull_speed);
+                    let speed_y = diff_y.clamp(-docking_pull_speed, docking_pull_speed);
 
                     // Maximize chances of reaching the exact connecting point
                     point1.speed_offset = (-speed_x / 2 + speed_x % 2, -speed_y / 2 + speed_y % 2);
                     point2.speed_offset = (speed_x / 2, speed_y / 2);
 
                     // Connected?
-                    if diff_x.abs() >= 4 || diff_y.abs() >= 4 {
+                    if diff_x.abs() >= snap || diff_y.abs() >= snap {
                         continue;
                     }
 
                     // The subs are successfully connected; mark the objects as such too.
-                    let sub2_index = sub1_index + 1 + sub2_index_offset;
                     point1.connected_to = Some((sub2_index, point2.connector_object_id));
                     point2.connected_to = Some((sub1_index, point1.connector_object_id));
 
@@ -428,24 +1070,42 @@ fn update_docking_points(submarines: &mut [SubmarineState]) {
     }
 }
 
-fn update_navigation(submarine: &mut SubmarineState) {
-    let navigation = &mut submarine.navigation;
+/// Intermediate values of the vertical forces on a submarine.
+pub(crate) struct Buoyancy {
+    pub weight: i32,
+    pub buoyancy: i32,
+    pub mass: i32,
+    pub y_acceleration: i32,
+}
 
+pub(crate) fn compute_buoyancy(water_grid: &WaterGrid) -> Buoyancy {
     // Compute weight based on number of walls
-    let weight = submarine.water_grid.total_walls() as i32;
+    let weight = water_grid.total_walls() as i32;
 
     // Compute buoyancy; the numbers are just random stuff that seems to
     // somewhat work for both the Dugong and the Bunyip
     let mut buoyancy = 0;
     buoyancy -= weight * 16;
-    buoyancy += submarine.water_grid.total_inside() as i32 * 13;
-    buoyancy -= submarine.water_grid.total_water() as i32 * 16 / 1024;
+    buoyancy += water_grid.total_inside() as i32 * 13;
+    buoyancy -= water_grid.total_water() as i32 * 16 / 1024;
 
     // Massive submarines are harder to move
     let mass = (weight * weight / 1500 / 1500).max(1);
 
-    let y_acceleration = (buoyancy * weight) / 1024 / 100;
-    navigation.acceleration.1 = -y_acceleration / 8 / mass;
+    let y_acceleration = -(buoyancy * weight) / 1024 / 100 / 8 / mass;
+
+    Buoyancy {
+        weight,
+        buoyancy,
+        mass,
+        y_acceleration,
+    }
+}
+
+fn update_navigation(submarine: &mut SubmarineState) {
+    let navigation = &mut submarine.navigation;
+
+    navigation.acceleration.1 = compute_buoyancy(&submarine.water_grid).y_acceleration;
 
     navigation.speed.0 = (navigation.speed.0 + navigation.acceleration.0).clamp(-2048, 2048);
     navigation.speed.1 = (navigation.speed.1 + navigation.acceleration.1).clamp(-2048, 2048);
@@ -474,6 +1134,52 @@ fn update_navigation(submarine: &mut SubmarineState) {
     }
 }
 
+/// Detect collisions between every pair of submarines that aren't docked to
+/// each other, and return the pairs that are.
+fn update_collisions_between(submarines: &mut [SubmarineState]) -> Vec<(usize, usize)> {
+    let mut docked_pairs = Vec::new();
+
+    for sub1_index in 0..submarines.len() {
+        let group = docked_group(submarines, sub1_index);
+
+        for sub2_index in sub1_index + 1..submarines.len() {
+            if group.contains(&sub2_index) {
+                docked_pairs.push((sub1_index, sub2_index));
+                continue;
+            }
+
+            let (left, right) = submarines.split_at_mut(sub2_index);
+            let submarine1 = &mut left[sub1_index];
+            let submarine2 = &mut right[0];
+
+            update_submarine_collisions(submarine1, submarine2);
+            update_submarine_collisions(submarine2, submarine1);
+        }
+    }
+
+    docked_pairs
+}
+
+/// The submarine and all others docked to it, directly or through others.
+fn docked_group(submarines: &[SubmarineState], submarine_id: usize) -> Vec<usize> {
+    let mut group = vec![submarine_id];
+    let mut index = 0;
+
+    while let Some(&sub_index) = group.get(index) {
+        for point in &submarines[sub_index].docking_points {
+            if let Some((connected_sub_index, _obj_id)) = point.connected_to {
+                if !group.contains(&connected_sub_index) {
+                    group.push(connected_sub_index);
+                }
+            }
+        }
+
+        index += 1;
+    }
+
+    group
+}
+
 fn update_position(submarines: &mut [SubmarineState]) {
     let mut submarine_group = Vec::new();
     let mut group_speed = Vec::new();
@@ -510,3 +1216,91 @@ fn update_position(submarines: &mut [SubmarineState]) {
         submarine.navigation.position.1 += submarine.navigation.docking_override.1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state::{rocks::RockGrid, water::CellTemplate};
+
+    fn update(game_state: &mut GameState, commands: Vec<Command>) {
+        update_game(
+            commands.into_iter(),
+            game_state,
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        );
+    }
+
+    fn create_submarine(flooded: bool, rock_position: (usize, usize)) -> Command {
+        let (width, height) = (20, 12);
+        let mut water_cells = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let edge = x.min(width - 1 - x).min(y).min(height - 1 - y);
+                water_cells.push(match edge {
+                    0 => CellTemplate::Sea,
+                    1 => CellTemplate::Wall,
+                    _ if flooded => CellTemplate::Water,
+                    _ => CellTemplate::Inside,
+                });
+            }
+        }
+
+        Command::CreateSubmarine {
+            submarine_template: Box::new(SubmarineTemplate {
+                size: (width, height),
+                water_cells,
+                background_pixels: vec![0; width * height * 4],
+                objects: Vec::new(),
+                wire_points: Vec::new(),
+                wire_bridges: Vec::new(),
+            }),
+            rock_position,
+        }
+    }
+
+    #[test]
+    fn removing_a_submarine_does_not_change_how_the_others_update() {
+        let mut game_states = [GameState::default(), GameState::default()];
+        for game_state in &mut game_states {
+            game_state.rock_grid = RockGrid::new(64, 64);
+            game_state.update_settings.update_water = true;
+            game_state.update_settings.collision_response = true;
+        }
+
+        // Two submarines head into each other, so that they collide; the first
+        // world also has one more in between them, removed before they do.
+        let top = create_submarine(true, (1000, 1000));
+        let middle = create_submarine(false, (3000, 1000));
+        let bottom = create_submarine(false, (1000, 1000 + 13 * 16));
+
+        let [with_removal, without] = &mut game_states;
+        update(with_removal, vec![top.clone(), middle, bottom.clone()]);
+        update(
+            with_removal,
+            vec![Command::RemoveSubmarine { submarine_id: 1 }],
+        );
+        update(without, vec![top, bottom]);
+        update(without, Vec::new());
+
+        for game_state in [&mut *with_removal, &mut *without].iter_mut() {
+            game_state.submarines[0].navigation.speed = (0, 512);
+            game_state.submarines[1].navigation.speed = (0, -512);
+        }
+
+        assert_eq!(with_removal.checksum(), without.checksum());
+
+        let mut collided = false;
+        for _ in 0..200 {
+            update(with_removal, Vec::new());
+            update(without, Vec::new());
+
+            assert_eq!(with_removal.checksum(), without.checksum());
+            collided |= with_removal.submarines[0].collided;
+        }
+
+        assert!(collided);
+    }
+}
@@ -1,5 +1,12 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+};
+
 use serde::{Deserialize, Serialize};
 
+use super::state::UpdateSettings;
+
 #[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct WaterGrid {
     cells: Vec<WaterCell>,
@@ -9,8 +16,42 @@ pub(crate) struct WaterGrid {
     total_walls: u32,
     total_inside: u32,
     edges: Vec<(usize, usize)>,
+    compartments: Compartments,
+}
+
+/// Bodies of inside cells closed off from each other by walls, which includes
+/// closed doors; see `WaterGrid::update_compartments`.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Compartments {
+    /// The compartment id of each cell, or `NO_COMPARTMENT` for walls and sea.
+    cell_ids: Vec<u16>,
+    /// Sorted by id.
+    list: Vec<Compartment>,
+    next_id: u16,
+    /// Set by `invalidate_compartments`, so that several wall edits in one
+    /// update only split the grid once.
+    #[serde(skip)]
+    outdated: bool,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Compartment {
+    pub id: u16,
+    pub cells: u32,
+}
+
+/// What a compartment holds, in cell levels; a full cell holds 1024.
+pub(crate) struct CompartmentTotals {
+    pub id: u16,
+    pub cells: u32,
+    pub water: u32,
+    /// The room left above the water. There's no oxygen simulation, so this
+    /// is all the air there is.
+    pub air: u32,
 }
 
+const NO_COMPARTMENT: u16 = u16::MAX;
+
 #[derive(Default, Clone, Copy, Serialize, Deserialize)]
 pub(crate) struct WaterCell {
     cell_type: CellType,
@@ -23,6 +64,8 @@ enum CellType {
         level: u32,
         velocity: (i32, i32),
         planned_remaining: u32,
+        /// In degrees Celsius.
+        temperature: i8,
     },
     Wall {
         wall_reflect: [u32; DIRECTIONS],
@@ -31,7 +74,7 @@ enum CellType {
     Sea,
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) enum WallMaterial {
     Normal,
     Glass,
@@ -44,6 +87,7 @@ impl Default for CellType {
             level: 0,
             velocity: (0, 0),
             planned_remaining: 0,
+            temperature: INSIDE_TEMPERATURE,
         }
     }
 }
@@ -61,6 +105,16 @@ pub(crate) enum CellTemplate {
 // Currently static; will eventually be based on sub's depth
 const SEA_LEVEL: u32 = 8192;
 
+/// Temperature of newly made inside cells.
+const INSIDE_TEMPERATURE: i8 = 20;
+
+/// The sea never warms up, and cools down any inside cell open to it.
+const SEA_TEMPERATURE: i8 = 4;
+
+/// Neighbouring cells trade this fraction of their temperature difference on
+/// each update, and at least 1 degree.
+const HEAT_DIFFUSION: i32 = 8;
+
 // Offsets: (y, x), x goes rightwards, y goes downwards
 const NEIGHBOUR_OFFSETS: &[(i32, i32)] = &[
     (1, 0),
@@ -75,6 +129,8 @@ const NEIGHBOUR_OFFSETS: &[(i32, i32)] = &[
 
 const TOP_DIRECTION: usize = 2;
 const BOTTOM_DIRECTION: usize = 0;
+const LEFT_DIRECTION: usize = 3;
+const RIGHT_DIRECTION: usize = 1;
 
 const DIRECTIONS: usize = NEIGHBOUR_OFFSETS.len();
 
@@ -131,6 +187,7 @@ impl WaterGrid {
             total_walls: 0,
             total_inside: 0,
             edges: Vec::new(),
+            compartments: Compartments::default(),
         }
     }
 
@@ -156,6 +213,7 @@ impl WaterGrid {
         }
 
         water_grid.update_edges();
+        water_grid.update_compartments();
 
         water_grid
     }
@@ -190,6 +248,25 @@ impl WaterGrid {
         self.total_inside
     }
 
+    /// Mean temperature of the inside cells, in degrees Celsius.
+    pub fn average_temperature(&self) -> i8 {
+        let mut total_temperature = 0;
+        let mut inside_cells = 0;
+
+        for cell in &self.cells {
+            if let Some(temperature) = cell.temperature() {
+                total_temperature += temperature as i32;
+                inside_cells += 1;
+            }
+        }
+
+        if inside_cells == 0 {
+            return INSIDE_TEMPERATURE;
+        }
+
+        (total_temperature / inside_cells) as i8
+    }
+
     fn neighbours(&self, x: usize, y: usize) -> impl Iterator<Item = &WaterCell> {
         NEIGHBOUR_OFFSETS.iter().map(move |(y_offset, x_offset)| {
             self.cell(
@@ -223,6 +300,7 @@ impl WaterGrid {
                         level,
                         velocity,
                         planned_remaining,
+                        ..
                     } => {
                         *level = 0;
                         *velocity = (0, 0);
@@ -235,7 +313,466 @@ impl WaterGrid {
         }
     }
 
-    pub fn update(&mut self, enable_gravity: bool, enable_inertia: bool) {
+    /// Move water around for one tick. The submarine's `acceleration` pushes
+    /// water the opposite way, so that it sloshes when the hull speeds up.
+    pub fn update(&mut self, update_settings: &UpdateSettings, acceleration: (i32, i32)) {
+        let resolution = update_settings.water_resolution.max(1) as usize;
+
+        if resolution > 1 {
+            self.update_coarse(resolution, update_settings, acceleration);
+        } else {
+            self.update_cells(update_settings, acceleration);
+        }
+
+        // With inertia, water is meant to slosh around instead of settling.
+        if update_settings.water_equalization > 0 && !update_settings.enable_inertia {
+            self.equalize_pressure(
+                update_settings.water_equalization.min(16) as i64,
+                update_settings.enable_gravity,
+            );
+        }
+    }
+
+    /// Spread heat between neighbouring inside cells for one tick. No heat
+    /// goes through walls, and the sea stays at the same temperature, cooling
+    /// down any inside cell next to it.
+    ///
+    /// Neighbours trade heat in place, one pair at a time, so heat is kept
+    /// and never overshoots; each pair's difference shrinks by at least 1
+    /// degree until it is down to 1.
+    pub fn update_temperature(&mut self) {
+        for y in 1..self.height - 1 {
+            for x in 1..self.width - 1 {
+                let index = y * self.width + x;
+
+                self.exchange_heat(index, index + 1);
+                self.exchange_heat(index, index + self.width);
+            }
+        }
+    }
+
+    fn exchange_heat(&mut self, index1: usize, index2: usize) {
+        let temperature = |cell: &WaterCell| match cell.cell_type {
+            CellType::Inside { temperature, .. } => Some(temperature as i32),
+            CellType::Wall { .. } => None,
+            CellType::Sea => Some(SEA_TEMPERATURE as i32),
+        };
+
+        let (temperature1, temperature2) = match (
+            temperature(&self.cells[index1]),
+            temperature(&self.cells[index2]),
+        ) {
+            (Some(temperature1), Some(temperature2)) => (temperature1, temperature2),
+            _ => return,
+        };
+
+        let difference = temperature1 - temperature2;
+        if difference.abs() < 2 {
+            return;
+        }
+
+        let transfer = difference.signum() * (difference.abs() / HEAT_DIFFUSION).max(1);
+
+        for (index, change) in [(index1, -transfer), (index2, transfer)] {
+            if let CellType::Inside { temperature, .. } = &mut self.cells[index].cell_type {
+                *temperature = (*temperature as i32 + change) as i8;
+            }
+        }
+    }
+
+    /// Move water in each body of connected inside cells towards its settled
+    /// state: filling the body up from its lowest row, or spread evenly if
+    /// there's no gravity. `rate` is out of 16.
+    ///
+    /// Water only moves between inside cells of the same body, so it never
+    /// passes through walls, and the total amount of water stays the same.
+    fn equalize_pressure(&mut self, rate: i64, gravity: bool) {
+        for mut body in self.inside_bodies() {
+            // Lowest rows first, which is where water settles
+            body.sort_unstable_by_key(|&index| (std::cmp::Reverse(index / self.width), index));
+            self.equalize_body(&body, rate, gravity);
+        }
+    }
+
+    /// The cell indices of each body of connected inside cells, in the order
+    /// of their first cell.
+    fn inside_bodies(&self) -> Vec<Vec<usize>> {
+        let mut visited = vec![false; self.width * self.height];
+        let mut bodies = Vec::new();
+
+        for start in 0..self.cells.len() {
+            if visited[start] || !self.cells[start].is_inside() {
+                continue;
+            }
+
+            // Flood fill the body; the grid's edges are always sea.
+            visited[start] = true;
+            let mut body = vec![start];
+            let mut pending = vec![start];
+
+            while let Some(index) = pending.pop() {
+                let (x, y) = (index % self.width, index / self.width);
+
+                for (y_offset, x_offset) in NEIGHBOUR_OFFSETS {
+                    let neighbour_x = (x as i32 + x_offset) as usize;
+                    let neighbour_y = (y as i32 + y_offset) as usize;
+
+                    if neighbour_x >= self.width || neighbour_y >= self.height {
+                        continue;
+                    }
+
+                    let neighbour = neighbour_y * self.width + neighbour_x;
+
+                    if !visited[neighbour] && self.cells[neighbour].is_inside() {
+                        visited[neighbour] = true;
+                        body.push(neighbour);
+                        pending.push(neighbour);
+                    }
+                }
+            }
+
+            bodies.push(body);
+        }
+
+        bodies
+    }
+
+    fn equalize_body(&mut self, body: &[usize], rate: i64, gravity: bool) {
+        let levels: Vec<i64> = body
+            .iter()
+            .map(|&index| self.cells[index].level() as i64)
+            .collect();
+        let total: i64 = levels.iter().sum();
+
+        let mut targets = vec![0; body.len()];
+        let mut remaining = total;
+
+        if gravity {
+            let mut row_start = 0;
+
+            while row_start < body.len() && remaining > 0 {
+                let row = body[row_start] / self.width;
+                let row_length = body[row_start..]
+                    .iter()
+                    .take_while(|&&index| index / self.width == row)
+                    .count();
+                let row_water = remaining.min(row_length as i64 * 1024);
+
+                spread_evenly(&mut targets[row_start..row_start + row_length], row_water);
+                remaining -= row_water;
+                row_start += row_length;
+            }
+        }
+
+        // Without gravity, all of it; with it, whatever overfills the body.
+        spread_evenly(&mut targets, remaining);
+
+        let mut new_levels: Vec<i64> = levels
+            .iter()
+            .zip(&targets)
+            .map(|(&level, &target)| level + (target - level) * rate / 16)
+            .collect();
+
+        // Rounding towards the old levels may leave a few units unassigned;
+        // give them back lowest first, or take them from the top.
+        let mut difference = total - new_levels.iter().sum::<i64>();
+
+        for level in new_levels.iter_mut() {
+            if difference <= 0 {
+                break;
+            }
+            *level += 1;
+            difference -= 1;
+        }
+
+        for level in new_levels.iter_mut().rev() {
+            if difference >= 0 {
+                break;
+            }
+            let taken = (*level).min(-difference);
+            *level -= taken;
+            difference += taken;
+        }
+
+        for (&index, &new_level) in body.iter().zip(&new_levels) {
+            let cell = &mut self.cells[index];
+
+            if let CellType::Inside { level, .. } = &mut cell.cell_type {
+                if *level != new_level as u32 {
+                    *level = new_level as u32;
+                    cell.replan();
+                }
+            }
+        }
+    }
+
+    /// Simulate water on a smaller grid, where each cell stands for a block
+    /// of `resolution`×`resolution` cells of this one.
+    ///
+    /// A block is simulated as water if it has any inside cells, and its
+    /// water is spread back evenly over only those cells, leaving its walls
+    /// alone. A block's level is its water over its whole area, as if its
+    /// walls were empty, so that the water that moves between blocks is
+    /// exactly the water that moves between their cells. Blocks whose inside cells touch the sea, or that have no inside
+    /// cells but some sea, act as sea; their inside cells are left as they
+    /// are. Water only moves between two blocks if some open cell of one
+    /// touches an open cell of the other, so walls that fall on a block's edge
+    /// still hold.
+    fn update_coarse(
+        &mut self,
+        resolution: usize,
+        update_settings: &UpdateSettings,
+        acceleration: (i32, i32),
+    ) {
+        // Water bouncing off walls would otherwise get lost in the blocks
+        self.settle_wall_reflections();
+
+        let blocks_x = (self.width + resolution - 1) / resolution;
+        let blocks_y = (self.height + resolution - 1) / resolution;
+
+        let (width, height) = (self.width, self.height);
+        let block_cells = move |block_x: usize, block_y: usize| {
+            let xs = block_x * resolution..((block_x + 1) * resolution).min(width);
+            let ys = block_y * resolution..((block_y + 1) * resolution).min(height);
+            ys.flat_map(move |y| xs.clone().map(move |x| (x, y)))
+        };
+
+        // Blocks are surrounded by an extra ring of sea, because the grid's
+        // own edge cells are forced to be sea, and the outermost blocks may
+        // also contain the submarine's hull.
+        let mut coarse_grid = WaterGrid::new(blocks_x + 2, blocks_y + 2);
+        for x in 0..blocks_x + 2 {
+            coarse_grid.cell_mut(x, 0).make_sea();
+            coarse_grid.cell_mut(x, blocks_y + 1).make_sea();
+        }
+        for y in 0..blocks_y + 2 {
+            coarse_grid.cell_mut(0, y).make_sea();
+            coarse_grid.cell_mut(blocks_x + 1, y).make_sea();
+        }
+
+        // Water that doesn't divide evenly over a block's area
+        let area = (resolution * resolution) as u32;
+        let mut remainders = vec![None; blocks_x * blocks_y];
+
+        for block_y in 0..blocks_y {
+            for block_x in 0..blocks_x {
+                let mut sea = 0;
+                let mut inside = 0;
+                let mut breached = false;
+                let mut level = 0;
+                let mut velocity = (0, 0);
+
+                for (x, y) in block_cells(block_x, block_y) {
+                    match self.cell(x, y).cell_type {
+                        CellType::Inside {
+                            level: cell_level,
+                            velocity: cell_velocity,
+                            ..
+                        } => {
+                            inside += 1;
+                            level += cell_level;
+                            velocity.0 += cell_velocity.0;
+                            velocity.1 += cell_velocity.1;
+                            breached |= x == 0
+                                || y == 0
+                                || x == width - 1
+                                || y == height - 1
+                                || self.neighbours(x, y).any(|cell| cell.is_sea());
+                        }
+                        CellType::Wall { .. } => (),
+                        CellType::Sea => sea += 1,
+                    }
+                }
+
+                let coarse_cell = coarse_grid.cell_mut(block_x + 1, block_y + 1);
+
+                if breached || (inside == 0 && sea != 0) {
+                    coarse_cell.make_sea();
+                } else if inside == 0 {
+                    coarse_cell.make_wall();
+                } else {
+                    coarse_cell.cell_type = CellType::Inside {
+                        level: level / area,
+                        velocity: (velocity.0 / inside as i32, velocity.1 / inside as i32),
+                        planned_remaining: 0,
+                        temperature: INSIDE_TEMPERATURE,
+                    };
+                    coarse_cell.replan();
+
+                    remainders[block_y * blocks_x + block_x] = Some(level % area);
+                }
+            }
+        }
+
+        // Close the edges between blocks where no open cells touch, along
+        // with the edges between the outer ring and any block that isn't sea
+        let open_for = |coarse_cell: &WaterCell, cell: &WaterCell| {
+            (coarse_cell.is_inside() && cell.is_inside()) || (coarse_cell.is_sea() && cell.is_sea())
+        };
+
+        for block_y in 0..blocks_y + 2 {
+            for block_x in 0..blocks_x + 2 {
+                for (direction, (y_offset, x_offset)) in NEIGHBOUR_OFFSETS.iter().enumerate() {
+                    let neighbour_x = block_x as i32 + x_offset;
+                    let neighbour_y = block_y as i32 + y_offset;
+
+                    if neighbour_x < 0
+                        || neighbour_y < 0
+                        || neighbour_x >= blocks_x as i32 + 2
+                        || neighbour_y >= blocks_y as i32 + 2
+                    {
+                        continue;
+                    }
+
+                    let (neighbour_x, neighbour_y) = (neighbour_x as usize, neighbour_y as usize);
+                    let coarse_cell = coarse_grid.cell(block_x, block_y);
+                    let coarse_neighbour = coarse_grid.cell(neighbour_x, neighbour_y);
+
+                    let in_ring = |x: usize, y: usize| {
+                        x == 0 || y == 0 || x == blocks_x + 1 || y == blocks_y + 1
+                    };
+
+                    let open = if in_ring(block_x, block_y) || in_ring(neighbour_x, neighbour_y) {
+                        coarse_cell.is_sea() && coarse_neighbour.is_sea()
+                    } else {
+                        block_cells(block_x - 1, block_y - 1).any(|(x, y)| {
+                            let other_x = (x as i32 + x_offset) as usize;
+                            let other_y = (y as i32 + y_offset) as usize;
+
+                            other_x / resolution == neighbour_x - 1
+                                && other_y / resolution == neighbour_y - 1
+                                && open_for(coarse_cell, self.cell(x, y))
+                                && open_for(coarse_neighbour, self.cell(other_x, other_y))
+                        })
+                    };
+
+                    if open {
+                        continue;
+                    }
+
+                    let coarse_cell = coarse_grid.cell_mut(block_x, block_y);
+                    let blocked = std::mem::take(&mut coarse_cell.planned_transfer[direction]);
+
+                    if let CellType::Inside {
+                        planned_remaining, ..
+                    } = &mut coarse_cell.cell_type
+                    {
+                        *planned_remaining += blocked;
+                    }
+                }
+            }
+        }
+
+        coarse_grid.update_cells(update_settings, acceleration);
+        coarse_grid.settle_wall_reflections();
+
+        for block_y in 0..blocks_y {
+            for block_x in 0..blocks_x {
+                let remainder = match remainders[block_y * blocks_x + block_x] {
+                    Some(remainder) => remainder,
+                    None => continue,
+                };
+
+                let (level, velocity) = match coarse_grid.cell(block_x + 1, block_y + 1).cell_type {
+                    CellType::Inside {
+                        level, velocity, ..
+                    } => (level, velocity),
+                    _ => continue,
+                };
+
+                let inside_cells = block_cells(block_x, block_y)
+                    .filter(|&(x, y)| self.cell(x, y).is_inside())
+                    .collect::<Vec<_>>();
+
+                let block_level = level * area + remainder;
+                let cell_level = block_level / inside_cells.len() as u32;
+                let remainder = block_level % inside_cells.len() as u32;
+
+                for (index, (x, y)) in inside_cells.into_iter().enumerate() {
+                    let extra = if (index as u32) < remainder { 1 } else { 0 };
+                    let cell = &mut self.cells[y * self.width + x];
+                    let temperature = cell.temperature().unwrap_or(INSIDE_TEMPERATURE);
+
+                    cell.cell_type = CellType::Inside {
+                        level: cell_level + extra,
+                        velocity,
+                        planned_remaining: 0,
+                        temperature,
+                    };
+                    cell.replan();
+                }
+            }
+        }
+
+        self.update_totals();
+    }
+
+    /// Give the water that walls are about to reflect straight back to the
+    /// cells it came from.
+    fn settle_wall_reflections(&mut self) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let wall_reflect = match &mut self.cell_mut(x, y).cell_type {
+                    CellType::Wall { wall_reflect, .. } => std::mem::take(wall_reflect),
+                    _ => continue,
+                };
+                self.cell_mut(x, y).replan();
+
+                for (direction, (y_offset, x_offset)) in NEIGHBOUR_OFFSETS.iter().enumerate() {
+                    let reflected = wall_reflect[direction];
+                    let neighbour_x = (x as i32 + x_offset) as usize;
+                    let neighbour_y = (y as i32 + y_offset) as usize;
+
+                    if reflected == 0 || neighbour_x >= self.width || neighbour_y >= self.height {
+                        continue;
+                    }
+
+                    let neighbour = self.cell_mut(neighbour_x, neighbour_y);
+                    if let CellType::Inside { level, .. } = &mut neighbour.cell_type {
+                        *level += reflected;
+                        neighbour.replan();
+                    }
+                }
+            }
+        }
+    }
+
+    fn update_totals(&mut self) {
+        let mut total_water = 0;
+        let mut total_walls = 0;
+        let mut total_inside = 0;
+
+        for cell in &self.cells {
+            match cell.cell_type {
+                CellType::Inside { level, .. } => {
+                    total_water += level;
+                    total_inside += 1;
+                }
+                CellType::Wall { wall_reflect, .. } => {
+                    total_water += wall_reflect.iter().sum::<u32>();
+                    total_walls += 1;
+                }
+                CellType::Sea => (),
+            }
+        }
+
+        self.total_water = total_water;
+        self.total_walls = total_walls;
+        self.total_inside = total_inside;
+    }
+
+    fn update_cells(&mut self, update_settings: &UpdateSettings, acceleration: (i32, i32)) {
+        let UpdateSettings {
+            enable_gravity,
+            enable_inertia,
+            water_inertia,
+            water_sloshing,
+            ..
+        } = *update_settings;
+
+        // Out of 16; how much of a cell's velocity is kept on each update
+        let retained = water_inertia.min(16) as i32;
+
         let mut new_grid = WaterGrid::new(self.width, self.height);
         std::mem::swap(self, &mut new_grid);
         let old_grid = new_grid;
@@ -277,6 +814,7 @@ impl WaterGrid {
                     CellType::Inside {
                         velocity: old_velocity,
                         planned_remaining,
+                        temperature,
                         ..
                     } => {
                         let mut level = planned_remaining;
@@ -298,14 +836,20 @@ impl WaterGrid {
                             velocity.1 += 32;
                         }
 
+                        if enable_inertia {
+                            velocity.0 -= acceleration.0 * water_sloshing;
+                            velocity.1 -= acceleration.1 * water_sloshing;
+                        }
+
                         let velocity = (
-                            (old_velocity.0 * 3 + velocity.0) / 4,
-                            (old_velocity.1 * 3 + velocity.1) / 4,
+                            (old_velocity.0 * retained + velocity.0 * (16 - retained)) / 16,
+                            (old_velocity.1 * retained + velocity.1 * (16 - retained)) / 16,
                         );
                         new_cell.cell_type = CellType::Inside {
                             level,
                             velocity,
                             planned_remaining: 0,
+                            temperature,
                         };
 
                         // Plan water to be sent to neighbouring cells on next update
@@ -335,6 +879,25 @@ impl WaterGrid {
 
         // Edge walls (or walls in general) stay the same on a grid update
         self.edges = old_grid.edges;
+        self.compartments = old_grid.compartments;
+    }
+
+    /// Flip the grid left to right, along with the water moving through it.
+    pub fn mirror_horizontally(&mut self) {
+        for row in self.cells.chunks_exact_mut(self.width) {
+            row.reverse();
+        }
+
+        // Compartments keep their ids, wherever they end up.
+        for row in self.compartments.cell_ids.chunks_exact_mut(self.width) {
+            row.reverse();
+        }
+
+        for cell in &mut self.cells {
+            cell.mirror_horizontally();
+        }
+
+        self.update_edges();
     }
 
     pub fn update_edges(&mut self) {
@@ -355,6 +918,154 @@ impl WaterGrid {
     pub fn edges(&self) -> &[(usize, usize)] {
         &self.edges
     }
+
+    /// Split the inside cells into compartments again, after walls or doors
+    /// changed.
+    ///
+    /// Each new compartment keeps the id of the old one it shares the most
+    /// cells with, unless a bigger part already took it. When a door opens,
+    /// the merged compartment keeps the id of the bigger side; when it closes,
+    /// the bigger side keeps the id and the smaller one gets a new id. This
+    /// way, the compartments nothing happened to never change their id.
+    pub fn update_compartments(&mut self) {
+        let bodies = self.inside_bodies();
+        let compartments = &mut self.compartments;
+        compartments.outdated = false;
+
+        let mut overlaps: BTreeMap<(usize, u16), u32> = BTreeMap::new();
+        for (body_index, body) in bodies.iter().enumerate() {
+            for &index in body {
+                match compartments.cell_ids.get(index) {
+                    Some(&NO_COMPARTMENT) | None => (),
+                    Some(&old_id) => *overlaps.entry((body_index, old_id)).or_default() += 1,
+                }
+            }
+        }
+
+        // Biggest overlaps first; ties go to the lowest id, then to the first
+        // body, so that the same walls always give the same ids.
+        let mut overlaps: Vec<_> = overlaps.into_iter().collect();
+        overlaps.sort_by_key(|&((body_index, old_id), cells)| (Reverse(cells), old_id, body_index));
+
+        let mut body_ids = vec![None; bodies.len()];
+        let mut taken_ids = BTreeSet::new();
+        for ((body_index, old_id), _cells) in overlaps {
+            if body_ids[body_index].is_none() && !taken_ids.contains(&old_id) {
+                body_ids[body_index] = Some(old_id);
+                taken_ids.insert(old_id);
+            }
+        }
+
+        compartments.cell_ids = vec![NO_COMPARTMENT; self.cells.len()];
+        compartments.list.clear();
+
+        for (body, id) in bodies.iter().zip(body_ids) {
+            let id = id.or_else(|| {
+                // Ids wrap around after a very long session; skip the ones
+                // that are still in use.
+                for _ in 0..=u16::MAX {
+                    let id = compartments.next_id;
+                    compartments.next_id = compartments.next_id.wrapping_add(1);
+
+                    if id != NO_COMPARTMENT && taken_ids.insert(id) {
+                        return Some(id);
+                    }
+                }
+
+                None
+            });
+
+            // Every id is taken; the rest of the bodies stay out of any
+            // compartment.
+            let id = match id {
+                Some(id) => id,
+                None => break,
+            };
+
+            for &index in body {
+                compartments.cell_ids[index] = id;
+            }
+
+            compartments.list.push(Compartment {
+                id,
+                cells: body.len() as u32,
+            });
+        }
+
+        compartments
+            .list
+            .sort_unstable_by_key(|compartment| compartment.id);
+    }
+
+    /// Mark the compartments for `update_compartments_if_outdated`, instead of
+    /// splitting the grid again right away.
+    pub fn invalidate_compartments(&mut self) {
+        self.compartments.outdated = true;
+    }
+
+    pub fn update_compartments_if_outdated(&mut self) {
+        if self.compartments.outdated {
+            self.update_compartments();
+        }
+    }
+
+    /// The compartments as of the last `update_compartments`, sorted by id.
+    pub fn compartments(&self) -> &[Compartment] {
+        &self.compartments.list
+    }
+
+    /// The id of the compartment a cell is in, if it's an inside cell.
+    pub fn compartment_at(&self, x: usize, y: usize) -> Option<u16> {
+        match self.compartments.cell_ids.get(y * self.width + x) {
+            Some(&NO_COMPARTMENT) | None => None,
+            Some(&id) => Some(id),
+        }
+    }
+
+    /// How much water and air each compartment holds, in the same order as
+    /// `compartments`.
+    pub fn compartment_totals(&self) -> Vec<CompartmentTotals> {
+        let compartments = &self.compartments.list;
+        let mut totals: Vec<_> = compartments
+            .iter()
+            .map(|compartment| CompartmentTotals {
+                id: compartment.id,
+                cells: compartment.cells,
+                water: 0,
+                air: 0,
+            })
+            .collect();
+
+        for (cell, &id) in self.cells.iter().zip(&self.compartments.cell_ids) {
+            if id == NO_COMPARTMENT {
+                continue;
+            }
+
+            if let Ok(position) =
+                compartments.binary_search_by_key(&id, |compartment| compartment.id)
+            {
+                let level = cell.level();
+                totals[position].water += level;
+                totals[position].air += 1024u32.saturating_sub(level);
+            }
+        }
+
+        totals
+    }
+}
+
+/// Split `amount` over `levels`, adding to them; leftovers go to the first ones.
+fn spread_evenly(levels: &mut [i64], amount: i64) {
+    if levels.is_empty() {
+        return;
+    }
+
+    let share = amount / levels.len() as i64;
+    let leftover = (amount % levels.len() as i64) as usize;
+
+    for (index, level) in levels.iter_mut().enumerate() {
+        *level += share + if index < leftover { 1 } else { 0 };
+    }
 }
 
 impl WaterCell {
@@ -381,6 +1092,7 @@ impl WaterCell {
                 level,
                 velocity,
                 planned_remaining,
+                ..
             } => {
                 // This amount will leave the cell due to overpressure
                 let pressure_surplus = level.max(&mut 1024).wrapping_sub(1024);
@@ -409,8 +1121,14 @@ impl WaterCell {
                     velocity_x = velocity_x * inertia as i32 / total_velocity;
                     velocity_y = velocity_y * inertia as i32 / total_velocity;
 
+                    // Rounding leftovers must leave too, or they would vanish
+                    // from the grid; give them to an axis that is moving.
                     let leftover = inertia as i32 - velocity_x.abs() - velocity_y.abs();
-                    velocity_y += leftover * velocity_y.signum();
+                    if velocity.1 != 0 {
+                        velocity_y += leftover * velocity.1.signum();
+                    } else {
+                        velocity_x += leftover * velocity.0.signum();
+                    }
 
                     for (direction, neighbour_offset) in NEIGHBOUR_OFFSETS.iter().enumerate() {
                         let surplus_x =
@@ -461,6 +1179,23 @@ impl WaterCell {
         self.replan();
     }
 
+    /// In degrees Celsius; only inside cells have a temperature.
+    pub fn temperature(&self) -> Option<i8> {
+        match self.cell_type {
+            CellType::Inside { temperature, .. } => Some(temperature),
+            _ => None,
+        }
+    }
+
+    /// Raise the temperature by `amount`, but not past `up_to`.
+    pub fn heat(&mut self, amount: i8, up_to: i8) {
+        if let CellType::Inside { temperature, .. } = &mut self.cell_type {
+            if *temperature < up_to {
+                *temperature = temperature.saturating_add(amount).min(up_to);
+            }
+        }
+    }
+
     pub fn is_wall(&self) -> bool {
         matches!(self.cell_type, CellType::Wall { .. })
     }
@@ -506,6 +1241,24 @@ impl WaterCell {
         self.replan();
     }
 
+    /// Turns the cell into a wall of the given material. Existing walls keep
+    /// their state and only have their material switched.
+    pub fn set_wall_material(&mut self, material: WallMaterial) {
+        if let CellType::Wall {
+            ref mut wall_material,
+            ..
+        } = self.cell_type
+        {
+            *wall_material = material;
+        } else {
+            self.cell_type = CellType::Wall {
+                wall_reflect: [0; DIRECTIONS],
+                wall_material: material,
+            };
+            self.replan();
+        }
+    }
+
     pub fn make_sea(&mut self) {
         self.cell_type = CellType::Sea;
         self.replan();
@@ -516,6 +1269,7 @@ impl WaterCell {
             level: 0,
             velocity: (0, 0),
             planned_remaining: 0,
+            temperature: INSIDE_TEMPERATURE,
         };
         self.replan();
     }
@@ -549,6 +1303,18 @@ impl WaterCell {
         }
     }
 
+    fn mirror_horizontally(&mut self) {
+        self.planned_transfer.swap(LEFT_DIRECTION, RIGHT_DIRECTION);
+
+        match &mut self.cell_type {
+            CellType::Inside { velocity, .. } => velocity.0 = -velocity.0,
+            CellType::Wall { wall_reflect, .. } => {
+                wall_reflect.swap(LEFT_DIRECTION, RIGHT_DIRECTION)
+            }
+            CellType::Sea => (),
+        }
+    }
+
     pub(crate) fn swap_water_with(&mut self, other_cell: &mut WaterCell) {
         match (&mut self.cell_type, &mut other_cell.cell_type) {
             (
@@ -584,3 +1350,84 @@ impl WaterCell {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sealed submarine surrounded by sea, with a half-flooded upper deck
+    /// whose floor and bulkhead fall across the middle of coarse blocks.
+    fn submarine_grid() -> WaterGrid {
+        let (width, height) = (21, 15);
+        let mut cells = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let edge = x.min(width - 1 - x).min(y).min(height - 1 - y);
+                cells.push(match edge {
+                    0 => CellTemplate::Sea,
+                    1 => CellTemplate::Wall,
+                    _ if y == 7 && x < 15 => CellTemplate::Wall,
+                    _ if x == 9 && y < 7 => CellTemplate::Wall,
+                    _ if y < 7 && x < 9 => CellTemplate::Water,
+                    _ => CellTemplate::Inside,
+                });
+            }
+        }
+
+        WaterGrid::from_cells(width, height, &cells)
+    }
+
+    #[test]
+    fn coarse_updates_keep_the_water() {
+        for resolution in 2..=4 {
+            let mut water_grid = submarine_grid();
+            let update_settings = UpdateSettings {
+                water_resolution: resolution,
+                ..UpdateSettings::default()
+            };
+
+            water_grid.update(&update_settings, (0, 0));
+            let total_water = water_grid.total_water();
+            assert!(total_water > 0);
+
+            for _ in 0..300 {
+                water_grid.update(&update_settings, (0, 0));
+                assert_eq!(water_grid.total_water(), total_water);
+            }
+
+            // The walls are still where they were
+            assert!(water_grid.cell(5, 7).is_wall());
+            assert!(water_grid.cell(9, 3).is_wall());
+            assert!(water_grid.cell(0, 0).is_sea());
+        }
+    }
+
+    #[test]
+    fn sloshing_keeps_the_water() {
+        let mut water_grid = submarine_grid();
+        let update_settings = UpdateSettings {
+            enable_inertia: true,
+            water_sloshing: 16,
+            ..UpdateSettings::default()
+        };
+
+        water_grid.update(&update_settings, (0, 0));
+        let total_water = water_grid.total_water();
+
+        // Speed up, brake, and turn, as a submarine would
+        for tick in 0..300 {
+            let acceleration = match tick / 50 {
+                0 => (8, 0),
+                1 => (-8, 0),
+                2 => (0, 8),
+                3 => (0, -8),
+                4 => (8, -8),
+                _ => (-8, 8),
+            };
+
+            water_grid.update(&update_settings, acceleration);
+            assert_eq!(water_grid.total_water(), total_water);
+        }
+    }
+}
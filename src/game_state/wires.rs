@@ -1,4 +1,7 @@
-use std::{collections::BTreeMap, convert::TryInto};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryInto,
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -42,15 +45,21 @@ pub(crate) enum WireValue {
         value: u8,
         terminal: bool,
         signal: u16,
+        /// Number of cells the signal travelled from its source.
+        hops: u16,
     },
     Logic {
         value: i8,
         terminal: bool,
         signal: u16,
+        hops: u16,
     },
     Bundle {
         bundle_id: u8,
     },
+    /// Lets a horizontal and a vertical wire of the same color cross without
+    /// connecting; signals pass straight through, left-right and top-bottom.
+    Bridge,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -64,10 +73,21 @@ pub(crate) enum WireColor {
 
 pub(crate) type WirePoints = (WireColor, Vec<(usize, usize)>);
 
+/// A cell where two wires of the same color cross without connecting.
+pub(crate) type WireBridge = (WireColor, (usize, usize));
+
 const NEIGHBOUR_OFFSETS: &[(i32, i32)] = &[(1, 0), (0, 1), (-1, 0), (0, -1)];
 
 pub(crate) const WIRE_COLORS: usize = 5;
 
+pub(crate) const ALL_COLORS: [WireColor; WIRE_COLORS] = [
+    WireColor::Bundle,
+    WireColor::Purple,
+    WireColor::Brown,
+    WireColor::Blue,
+    WireColor::Green,
+];
+
 pub(crate) const THIN_COLORS: [WireColor; 4] = [
     WireColor::Purple,
     WireColor::Brown,
@@ -118,7 +138,12 @@ impl WireGrid {
         }
     }
 
-    pub fn from_wire_points(width: usize, height: usize, wire_points: &[WirePoints]) -> Self {
+    pub fn from_wire_points(
+        width: usize,
+        height: usize,
+        wire_points: &[WirePoints],
+        bridges: &[WireBridge],
+    ) -> Self {
         let mut wire_grid = WireGrid::new(width, height);
 
         for (color, wire_points) in wire_points {
@@ -139,9 +164,36 @@ impl WireGrid {
             }
         }
 
+        for &(color, (x, y)) in bridges {
+            if x < width && y < height && !wire_grid.cell(x, y).value(color).is_bridge() {
+                wire_grid.toggle_bridge(x, y, color);
+            }
+        }
+
         wire_grid
     }
 
+    /// A copy of this grid flipped left to right. It is rebuilt from its wire
+    /// points, so bundles get fresh ids and all signals start out cleared.
+    pub fn mirrored_horizontally(&self) -> WireGrid {
+        let width = self.width;
+        let wire_points: Vec<WirePoints> = self
+            .wire_points()
+            .into_iter()
+            .map(|(color, points)| {
+                let points = points.into_iter().map(|(x, y)| (width - 1 - x, y));
+                (color, points.collect())
+            })
+            .collect();
+        let bridges: Vec<WireBridge> = self
+            .bridges()
+            .into_iter()
+            .map(|(color, (x, y))| (color, (width - 1 - x, y)))
+            .collect();
+
+        WireGrid::from_wire_points(self.width, self.height, &wire_points, &bridges)
+    }
+
     pub fn size(&self) -> (usize, usize) {
         (self.width, self.height)
     }
@@ -175,6 +227,32 @@ impl WireGrid {
         }
     }
 
+    /// Turns a wire cell into a bridge, or a bridge back into a plain wire.
+    /// Cells without a wire of this color are left alone.
+    pub fn toggle_bridge(&mut self, x: usize, y: usize, color: WireColor) {
+        if color == WireColor::Bundle {
+            // Bundles are instantaneous and merge on contact; bridging them
+            // would need to keep two bundle ids per cell.
+            return;
+        }
+
+        let value = &mut self.cell_mut(x, y).value[color as usize];
+        if !value.connected() {
+            return;
+        }
+
+        *value = match value {
+            WireValue::Bridge => WireValue::NoSignal { terminal: false },
+            _ => WireValue::Bridge,
+        };
+
+        let is_inside = (1..self.width - 2).contains(&x) && (1..self.height - 1).contains(&y);
+        let connected_wires = &mut self.connected_wires[color as usize];
+        if is_inside && !connected_wires.contains(&(x, y)) {
+            connected_wires.push((x, y));
+        }
+    }
+
     pub fn clear_wire(&mut self, x: usize, y: usize, color: WireColor) {
         if color == WireColor::Bundle {
             // FIXME: Need to split bundles; which needs logic to detect a loop.
@@ -263,7 +341,81 @@ impl WireGrid {
         })
     }
 
-    pub fn update(&mut self, signals_updated: &mut bool) {
+    /// Like `neighbours`, but looks through bridges of the given color, so
+    /// that a bridge only ever joins the two cells on its opposite sides.
+    fn neighbours_through_bridges(
+        &self,
+        wire_color: usize,
+        x: usize,
+        y: usize,
+    ) -> impl Iterator<Item = &WireCell> {
+        NEIGHBOUR_OFFSETS
+            .iter()
+            .filter_map(move |&(y_offset, x_offset)| {
+                let (mut x, mut y) = (x as i32 + x_offset, y as i32 + y_offset);
+
+                loop {
+                    if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                        return None;
+                    }
+
+                    let cell = self.cell(x as usize, y as usize);
+                    if let WireValue::Bridge = cell.value[wire_color] {
+                        x += x_offset;
+                        y += y_offset;
+                    } else {
+                        return Some(cell);
+                    }
+                }
+            })
+    }
+
+    /// All cells of the given color joined to the wire at (x, y), including
+    /// any bridges passed through on the way.
+    pub fn connected_cells(&self, color: WireColor, x: usize, y: usize) -> Vec<(usize, usize)> {
+        let color_index = color as usize;
+        let mut found = BTreeSet::new();
+
+        if !self.cell(x, y).value[color_index].connected() {
+            return Vec::new();
+        }
+
+        let mut pending = vec![(x, y)];
+        found.insert((x, y));
+
+        while let Some((x, y)) = pending.pop() {
+            for &(y_offset, x_offset) in NEIGHBOUR_OFFSETS {
+                let (mut x, mut y) = (x as i32 + x_offset, y as i32 + y_offset);
+
+                loop {
+                    if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                        break;
+                    }
+
+                    let position = (x as usize, y as usize);
+                    let value = &self.cell(position.0, position.1).value[color_index];
+
+                    if let WireValue::Bridge = value {
+                        found.insert(position);
+                        x += x_offset;
+                        y += y_offset;
+                    } else {
+                        if value.connected() && found.insert(position) {
+                            pending.push(position);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        found.into_iter().collect()
+    }
+
+    /// Propagate signals by one cell. `wire_decay` is the signal lost per cell
+    /// for each thin color; idle wires lose one more than that per update.
+    /// Signals die out after `max_range` cells, unless it is 0.
+    pub fn update(&mut self, signals_updated: &mut bool, wire_decay: &[u16; 4], max_range: u16) {
         let old_grid = WireGrid::clone_from(self);
 
         for (wire_color, wires) in self.connected_wires.iter().enumerate() {
@@ -281,16 +433,24 @@ impl WireGrid {
                     continue;
                 }
 
-                let mut new_value = old_value.clone().decay(2);
+                if let WireValue::Bridge = old_value {
+                    // Bridges carry no signal of their own.
+                    continue;
+                }
+
+                // Thin colors come right after Bundle, starting at 1.
+                let decay = wire_decay[wire_color - 1].max(1);
+                let mut new_value = old_value.clone().decay(decay + 1);
                 let mut connected_wires = 0;
 
-                for neighbour in old_grid.neighbours(x, y) {
+                let neighbours = old_grid.neighbours_through_bridges(wire_color, x, y);
+                for neighbour in neighbours {
                     let neighbour_wire_value = &neighbour.value[wire_color];
                     if neighbour_wire_value.connected() {
                         connected_wires += 1;
 
                         if neighbour_wire_value.signal() > new_value.signal() + 3 {
-                            new_value = neighbour_wire_value.decay(1);
+                            new_value = neighbour_wire_value.relay(decay, max_range);
                         }
                     }
                 }
@@ -342,117 +502,145 @@ impl WireGrid {
                     let wire_value = self.cell(x, y).value(color);
 
                     if wire_value.connected() {
+                        // Cells are looked up by whether they're being joined
+                        // horizontally (false) or vertically (true); only bridges
+                        // belong to a different set on each layer.
                         let left_wire_set = if x > 0 {
-                            wire_set_map.get(&(color, x - 1, y))
+                            wire_set_map.get(&(color, x - 1, y, false)).copied()
                         } else {
                             None
                         };
 
                         let top_wire_set = if y > 0 {
-                            wire_set_map.get(&(color, x, y - 1))
+                            wire_set_map.get(&(color, x, y - 1, true)).copied()
                         } else {
                             None
                         };
 
-                        let add_to_set = match (left_wire_set, top_wire_set) {
-                            (None, None) => {
-                                // Make a new set
-                                wire_sets.push((color, Vec::new()));
-                                wire_sets.len() - 1
-                            }
-                            (None, Some(&top_set)) => {
-                                // Reuse the set from the cell above
-                                let old_wires: &mut (WireColor, Vec<(usize, usize)>) =
-                                    &mut wire_sets[top_set];
-                                let last_wire =
-                                    old_wires.1.last().expect("Sets have at least 1 wire");
-
-                                if *last_wire != (x, y - 1) {
-                                    // Make sure to connect to the correct end
-                                    old_wires.1.reverse();
-                                }
-
-                                let last_wire =
-                                    old_wires.1.last().expect("Sets have at least 1 wire");
-
-                                if *last_wire != (x, y - 1) {
-                                    // If this is still the incorrect end, then this is
-                                    // a fork; make a new set.
+                        // A bridge joins its left and right neighbours, and its top
+                        // and bottom neighbours, but never one pair with the other;
+                        // so it's added separately to a horizontal and a vertical set.
+                        let passes: Vec<(Option<usize>, Option<usize>, &[bool])> =
+                            if let WireValue::Bridge = wire_value {
+                                vec![
+                                    (left_wire_set, None, &[false]),
+                                    (None, top_wire_set, &[true]),
+                                ]
+                            } else {
+                                vec![(left_wire_set, top_wire_set, &[false, true])]
+                            };
+
+                        for (left_wire_set, top_wire_set, layers) in passes {
+                            let add_to_set = match (left_wire_set, top_wire_set) {
+                                (None, None) => {
+                                    // Make a new set
                                     wire_sets.push((color, Vec::new()));
                                     wire_sets.len() - 1
-                                } else {
-                                    top_set
                                 }
-                            }
-                            (Some(&left_set), None) => {
-                                // Reuse the set from the cell to the left
-                                let old_wires: &mut (WireColor, Vec<(usize, usize)>) =
-                                    &mut wire_sets[left_set];
-                                let last_wire =
-                                    old_wires.1.last().expect("Sets have at least 1 wire");
-
-                                if *last_wire != (x - 1, y) {
-                                    // Make sure to connect to the correct end
-                                    old_wires.1.reverse();
+                                (None, Some(top_set)) => {
+                                    // Reuse the set from the cell above
+                                    let old_wires: &mut (WireColor, Vec<(usize, usize)>) =
+                                        &mut wire_sets[top_set];
+                                    let last_wire =
+                                        old_wires.1.last().expect("Sets have at least 1 wire");
+
+                                    if *last_wire != (x, y - 1) {
+                                        // Make sure to connect to the correct end
+                                        old_wires.1.reverse();
+                                    }
+
+                                    let last_wire =
+                                        old_wires.1.last().expect("Sets have at least 1 wire");
+
+                                    if *last_wire != (x, y - 1) {
+                                        // If this is still the incorrect end, then this is
+                                        // a fork; make a new set.
+                                        wire_sets.push((color, Vec::new()));
+                                        wire_sets.len() - 1
+                                    } else {
+                                        top_set
+                                    }
                                 }
-                                let last_wire =
-                                    old_wires.1.last().expect("Sets have at least 1 wire");
-
-                                if *last_wire != (x - 1, y) {
-                                    // If this is still the incorrect end, then this is
-                                    // a fork; make a new set.
-                                    wire_sets.push((color, Vec::new()));
-                                    wire_sets.len() - 1
-                                } else {
-                                    left_set
+                                (Some(left_set), None) => {
+                                    // Reuse the set from the cell to the left
+                                    let old_wires: &mut (WireColor, Vec<(usize, usize)>) =
+                                        &mut wire_sets[left_set];
+                                    let last_wire =
+                                        old_wires.1.last().expect("Sets have at least 1 wire");
+
+                                    if *last_wire != (x - 1, y) {
+                                        // Make sure to connect to the correct end
+                                        old_wires.1.reverse();
+                                    }
+                                    let last_wire =
+                                        old_wires.1.last().expect("Sets have at least 1 wire");
+
+                                    if *last_wire != (x - 1, y) {
+                                        // If this is still the incorrect end, then this is
+                                        // a fork; make a new set.
+                                        wire_sets.push((color, Vec::new()));
+                                        wire_sets.len() - 1
+                                    } else {
+                                        left_set
+                                    }
                                 }
-                            }
-                            (Some(&left_set), Some(&top_set)) => {
-                                // Merge the two sets
-                                for &(old_x, old_y) in &wire_sets[top_set].1 {
-                                    // Rewrite the top cells's set to match the top ones
-                                    wire_set_map.insert((color, old_x, old_y), left_set);
+                                (Some(left_set), Some(top_set)) => {
+                                    // Merge the two sets
+                                    for &(old_x, old_y) in &wire_sets[top_set].1 {
+                                        // Rewrite the top cells's set to match the top ones;
+                                        // bridges only on the layer that belongs to this set
+                                        for vertical in [false, true] {
+                                            let key = (color, old_x, old_y, vertical);
+                                            if wire_set_map.get(&key) == Some(&top_set) {
+                                                wire_set_map.insert(key, left_set);
+                                            }
+                                        }
+                                    }
+
+                                    let old_wires: &mut (WireColor, Vec<(usize, usize)>) =
+                                        &mut wire_sets[top_set];
+                                    let last_wire =
+                                        old_wires.1.last().expect("Sets have at least 1 wire");
+
+                                    if *last_wire != (x, y - 1) {
+                                        // Make sure to connect to the correct end
+                                        old_wires.1.reverse();
+                                    }
+
+                                    let last_wire =
+                                        old_wires.1.last().expect("Sets have at least 1 wire");
+                                    if *last_wire != (x, y - 1) {
+                                        // If this is still the incorrect end, then this is
+                                        // a fork; make a new set.
+                                        wire_sets.push((color, Vec::new()));
+                                        wire_sets.len() - 1
+                                    } else {
+                                        // Add the current cell, which will be sandwhiched between the older top
+                                        // and newer left sets.
+                                        for &vertical in layers {
+                                            wire_set_map.insert((color, x, y, vertical), left_set);
+                                        }
+                                        wire_sets[left_set].1.push((x, y));
+
+                                        // Add the top cells to the left set in reversed order
+                                        // This is to keep neighbours contiguous in the list
+                                        let old_wires: Vec<(usize, usize)> =
+                                            wire_sets[top_set].1.iter().copied().rev().collect();
+                                        wire_sets[left_set].1.extend(old_wires);
+
+                                        wire_sets[top_set].1.clear();
+
+                                        // Already added in the middle, don't return anything
+                                        continue;
+                                    }
                                 }
+                            };
 
-                                let old_wires: &mut (WireColor, Vec<(usize, usize)>) =
-                                    &mut wire_sets[top_set];
-                                let last_wire =
-                                    old_wires.1.last().expect("Sets have at least 1 wire");
-
-                                if *last_wire != (x, y - 1) {
-                                    // Make sure to connect to the correct end
-                                    old_wires.1.reverse();
-                                }
-
-                                let last_wire =
-                                    old_wires.1.last().expect("Sets have at least 1 wire");
-                                if *last_wire != (x, y - 1) {
-                                    // If this is still the incorrect end, then this is
-                                    // a fork; make a new set.
-                                    wire_sets.push((color, Vec::new()));
-                                    wire_sets.len() - 1
-                                } else {
-                                    // Add the current cell, which will be sandwhiched between the older top
-                                    // and newer left sets.
-                                    wire_set_map.insert((color, x, y), left_set);
-                                    wire_sets[left_set].1.push((x, y));
-
-                                    // Add the top cells to the left set in reversed order
-                                    // This is to keep neighbours contiguous in the list
-                                    let old_wires: Vec<(usize, usize)> =
-                                        wire_sets[top_set].1.iter().copied().rev().collect();
-                                    wire_sets[left_set].1.extend(old_wires);
-
-                                    wire_sets[top_set].1.clear();
-
-                                    // Already added in the middle, don't return anything
-                                    continue;
-                                }
+                            for &vertical in layers {
+                                wire_set_map.insert((color, x, y, vertical), add_to_set);
                             }
-                        };
-
-                        wire_set_map.insert((color, x, y), add_to_set);
-                        wire_sets[add_to_set].1.push((x, y));
+                            wire_sets[add_to_set].1.push((x, y));
+                        }
                     }
                 }
             }
@@ -475,6 +663,23 @@ impl WireGrid {
         wire_points
     }
 
+    /// Every bridge cell, so that it can be saved alongside the wire points.
+    pub fn bridges(&self) -> Vec<WireBridge> {
+        let mut bridges = Vec::new();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                for &color in &THIN_COLORS {
+                    if self.cell(x, y).value(color).is_bridge() {
+                        bridges.push((color, (x, y)));
+                    }
+                }
+            }
+        }
+
+        bridges
+    }
+
     pub fn wire_bundle_input_mut(&mut self, bundle_id: u8) -> Option<&mut WireBundle> {
         let bundle_id: usize = bundle_id.into();
         self.bundle_inputs.get_mut(bundle_id)
@@ -535,6 +740,7 @@ impl WireCell {
                     WireValue::Power { .. } => (),
                     WireValue::Logic { value, .. } => return Some(value),
                     WireValue::Bundle { .. } => (),
+                    WireValue::Bridge => (),
                 };
             }
         }
@@ -552,6 +758,7 @@ impl WireCell {
                     WireValue::Power { value, .. } => return Some(value),
                     WireValue::Logic { .. } => (),
                     WireValue::Bundle { .. } => (),
+                    WireValue::Bridge => (),
                 }
             };
         }
@@ -574,6 +781,7 @@ impl WireCell {
                     value: logic_value,
                     signal: 256,
                     terminal: true,
+                    hops: 0,
                 };
             }
         }
@@ -588,6 +796,7 @@ impl WireCell {
                     value: power_value,
                     signal: 256,
                     terminal: true,
+                    hops: 0,
                 };
                 // Send to at most one wire.
                 break;
@@ -612,6 +821,7 @@ impl WireValue {
             WireValue::Power { signal, .. } => *signal,
             WireValue::Logic { signal, .. } => *signal,
             WireValue::Bundle { .. } => 0,
+            WireValue::Bridge => 0,
         }
     }
 
@@ -622,6 +832,7 @@ impl WireValue {
             WireValue::Power { terminal, .. } => *terminal,
             WireValue::Logic { terminal, .. } => *terminal,
             WireValue::Bundle { .. } => false,
+            WireValue::Bridge => false,
         }
     }
 
@@ -632,6 +843,7 @@ impl WireValue {
             WireValue::Power { terminal, .. } => *terminal = is_terminal,
             WireValue::Logic { terminal, .. } => *terminal = is_terminal,
             WireValue::Bundle { .. } => (),
+            WireValue::Bridge => (),
         }
     }
 
@@ -644,6 +856,7 @@ impl WireValue {
             value,
             terminal: true,
             signal: 256,
+            hops: 0,
         }
     }
 
@@ -656,6 +869,7 @@ impl WireValue {
             value,
             terminal: true,
             signal: 256,
+            hops: 0,
         }
     }
 
@@ -681,6 +895,28 @@ impl WireValue {
         }
     }
 
+    /// The value a neighbouring cell gets when this signal travels to it, or
+    /// no signal if it would go further than `max_range` cells (0 means
+    /// unlimited).
+    fn relay(&self, decay: u16, max_range: u16) -> WireValue {
+        let mut relayed = self.decay(decay);
+
+        match &mut relayed {
+            WireValue::Power { hops, terminal, .. } | WireValue::Logic { hops, terminal, .. } => {
+                *hops = hops.saturating_add(1);
+
+                if max_range != 0 && *hops > max_range {
+                    relayed = WireValue::NoSignal {
+                        terminal: *terminal,
+                    };
+                }
+            }
+            _ => (),
+        }
+
+        relayed
+    }
+
     fn decay(&self, amount: u16) -> WireValue {
         let new_signal = match self {
             WireValue::NotConnected => WireValue::NotConnected,
@@ -691,23 +927,28 @@ impl WireValue {
                 value,
                 signal,
                 terminal,
+                hops,
             } => WireValue::Power {
                 value: *value,
                 signal: signal.saturating_sub(amount),
                 terminal: *terminal,
+                hops: *hops,
             },
             WireValue::Logic {
                 value,
                 signal,
                 terminal,
+                hops,
             } => WireValue::Logic {
                 value: *value,
                 signal: signal.saturating_sub(amount),
                 terminal: *terminal,
+                hops: *hops,
             },
             WireValue::Bundle { bundle_id } => WireValue::Bundle {
                 bundle_id: *bundle_id,
             },
+            WireValue::Bridge => WireValue::Bridge,
         };
 
         match new_signal {
@@ -728,4 +969,8 @@ impl WireValue {
     pub fn connected(&self) -> bool {
         !matches!(self, &WireValue::NotConnected)
     }
+
+    pub fn is_bridge(&self) -> bool {
+        matches!(self, &WireValue::Bridge)
+    }
 }
@@ -1,46 +1,219 @@
+use std::ptr;
+
 use macroquad::prelude::{
-    is_key_down, is_key_pressed, is_mouse_button_down, is_mouse_button_pressed,
+    get_frame_time, is_key_down, is_key_pressed, is_mouse_button_down, is_mouse_button_pressed,
     is_mouse_button_released, mouse_position, mouse_wheel, KeyCode, MouseButton, Rect, Vec2,
 };
 
 use crate::{
-    app::{GameSettings, Tool},
-    draw::{object_rect, object_size, Camera},
+    app::{GameSettings, InspectedObject, ObjectSelection, PickedPort, Tool},
+    draw::{object_rect, Camera, DrawSettings},
     game_state::{
-        objects::{Object, ObjectType},
+        objects::{
+            object_size, port_cell, trace_port, Object, ObjectType, PortDirection, PortSignal,
+            WirePort,
+        },
         state::{Navigation, SubmarineState},
     },
     game_state::{
         update::{CellCommand, Command},
-        wires::WireColor,
+        wires::{WireColor, WireGrid, WireValue, ALL_COLORS, THIN_COLORS},
     },
     resources::MutableSubResources,
+    saveload::Prefab,
 };
 
+/// Fixed zoom levels to jump to, with the number keys or the settings window.
+#[derive(Clone, Copy)]
+pub(crate) enum ZoomPreset {
+    /// The whole current submarine, centered.
+    FitSubmarine,
+    /// One pixel of the object sprites to each screen pixel.
+    Pixels,
+    /// The whole world, centered.
+    Overview,
+}
+
+pub(crate) const ZOOM_PRESETS: &[(&str, ZoomPreset)] = &[
+    ("Fit submarine", ZoomPreset::FitSubmarine),
+    ("1:1 pixels", ZoomPreset::Pixels),
+    ("Overview", ZoomPreset::Overview),
+];
+
+/// Which keys toggle the draw layers; configurable so that they can be moved
+/// out of the way of other shortcuts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum LayerHotkeys {
+    Off,
+    /// F1 to F7 toggle one layer each, and F8 toggles all of them.
+    FunctionKeys,
+    /// Shift with the number keys 1 to 7 toggles one layer each, and
+    /// Shift+8 toggles all of them.
+    ShiftNumberKeys,
+}
+
+pub(crate) const LAYER_HOTKEYS: &[(&str, LayerHotkeys)] = &[
+    ("Off", LayerHotkeys::Off),
+    ("F1-F8", LayerHotkeys::FunctionKeys),
+    ("Shift+1-8", LayerHotkeys::ShiftNumberKeys),
+];
+
+/// The draw layers toggled by the layer hotkeys, in hotkey order.
+pub(crate) const DRAW_LAYER_NAMES: [&str; 7] = [
+    "rocks",
+    "background",
+    "objects",
+    "walls",
+    "wires",
+    "water",
+    "sonar",
+];
+
+/// The `DrawSettings` flag of each layer in `DRAW_LAYER_NAMES`.
+fn draw_layers(draw_settings: &mut DrawSettings) -> [&mut bool; 7] {
+    let DrawSettings {
+        draw_rocks,
+        draw_background,
+        draw_objects,
+        draw_walls,
+        draw_wires,
+        draw_water,
+        draw_sonar,
+        ..
+    } = draw_settings;
+
+    [
+        draw_rocks,
+        draw_background,
+        draw_objects,
+        draw_walls,
+        draw_wires,
+        draw_water,
+        draw_sonar,
+    ]
+}
+
+/// Hide every layer if they're all shown, otherwise show all of them.
+pub(crate) fn toggle_all_draw_layers(layers: [&mut bool; 7]) {
+    let all_shown = layers.iter().all(|shown| **shown);
+
+    for shown in layers {
+        *shown = !all_shown;
+    }
+}
+
+impl LayerHotkeys {
+    /// The key for each of the `DRAW_LAYER_NAMES`, followed by the key that
+    /// toggles all of them.
+    fn keys(self) -> &'static [KeyCode] {
+        match self {
+            LayerHotkeys::Off => &[],
+            LayerHotkeys::FunctionKeys => &[
+                KeyCode::F1,
+                KeyCode::F2,
+                KeyCode::F3,
+                KeyCode::F4,
+                KeyCode::F5,
+                KeyCode::F6,
+                KeyCode::F7,
+                KeyCode::F8,
+            ],
+            LayerHotkeys::ShiftNumberKeys => &[
+                KeyCode::Key1,
+                KeyCode::Key2,
+                KeyCode::Key3,
+                KeyCode::Key4,
+                KeyCode::Key5,
+                KeyCode::Key6,
+                KeyCode::Key7,
+                KeyCode::Key8,
+            ],
+        }
+    }
+
+    fn modifier_held(self) -> bool {
+        match self {
+            LayerHotkeys::Off => false,
+            LayerHotkeys::FunctionKeys => true,
+            LayerHotkeys::ShiftNumberKeys => {
+                is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift)
+            }
+        }
+    }
+}
+
+fn handle_layer_hotkeys(draw_settings: &mut DrawSettings) {
+    let layer_hotkeys = draw_settings.layer_hotkeys;
+
+    if !layer_hotkeys.modifier_held() {
+        return;
+    }
+
+    let keys = layer_hotkeys.keys();
+    for (key, shown) in keys.iter().zip(draw_layers(draw_settings)) {
+        if is_key_pressed(*key) {
+            *shown = !*shown;
+        }
+    }
+    if let Some(key) = keys.get(DRAW_LAYER_NAMES.len()) {
+        if is_key_pressed(*key) {
+            toggle_all_draw_layers(draw_layers(draw_settings));
+        }
+    }
+}
+
+/// Object sprites have this many pixels per cell.
+const SPRITE_PIXELS_PER_CELL: f32 = 3.0;
+
 pub(crate) enum Dragging {
     Camera,
     Nothing,
     Wires {
         color: WireColor,
+        auto_route: bool,
+        dragging_from_tile: (usize, usize),
+        dragging_from_sub: usize,
+    },
+    Measure {
         dragging_from_tile: (usize, usize),
         dragging_from_sub: usize,
     },
     Tool(Tool),
 }
 
+/// The sign of camera movement on each axis; applied to both keyboard and
+/// mouse panning so that they always agree.
+pub(crate) fn pan_direction(draw_settings: &DrawSettings) -> Vec2 {
+    let sign = |invert| if invert { -1.0 } else { 1.0 };
+    Vec2::new(
+        sign(draw_settings.invert_pan_x),
+        sign(draw_settings.invert_pan_y),
+    )
+}
+
 // Only called when egui doesn't want the keyboard
-pub(crate) fn handle_keyboard_input(camera: &mut Camera, current_tool: &mut Tool) {
+/// Camera panning moves by `pan_distance` cells on each axis, which is
+/// expected to already be scaled by the frame time and `pan_direction`.
+pub(crate) fn handle_keyboard_input(
+    camera: &mut Camera,
+    pan_distance: Vec2,
+    draw_settings: &mut DrawSettings,
+    current_tool: &mut Tool,
+    submarines: &[SubmarineState],
+    current_submarine: usize,
+    world_size: (usize, usize),
+) {
     if is_key_down(KeyCode::A) || is_key_down(KeyCode::Left) {
-        camera.offset_x += 1.0;
+        camera.offset_x += pan_distance.x;
     }
     if is_key_down(KeyCode::D) || is_key_down(KeyCode::Right) {
-        camera.offset_x -= 1.0;
+        camera.offset_x -= pan_distance.x;
     }
     if is_key_down(KeyCode::W) || is_key_down(KeyCode::Up) {
-        camera.offset_y += 1.0;
+        camera.offset_y += pan_distance.y;
     }
     if is_key_down(KeyCode::S) || is_key_down(KeyCode::Down) {
-        camera.offset_y -= 1.0;
+        camera.offset_y -= pan_distance.y;
     }
     if is_key_down(KeyCode::KpAdd) {
         camera.zoom += 1;
@@ -51,6 +224,52 @@ pub(crate) fn handle_keyboard_input(camera: &mut Camera, current_tool: &mut Tool
     if is_key_pressed(KeyCode::Escape) {
         *current_tool = Tool::Interact;
     }
+    if is_key_pressed(KeyCode::Home) {
+        if let Some(submarine) = submarines.get(current_submarine) {
+            camera.center_on_submarine(submarine.water_grid.size());
+        }
+    }
+
+    // Shift+number keys belong to the layer hotkeys, if they're set up that way
+    let layer_hotkeys_held = draw_settings.layer_hotkeys == LayerHotkeys::ShiftNumberKeys
+        && draw_settings.layer_hotkeys.modifier_held();
+
+    handle_layer_hotkeys(draw_settings);
+
+    let preset_keys = if layer_hotkeys_held {
+        &[][..]
+    } else {
+        &[KeyCode::Key1, KeyCode::Key2, KeyCode::Key3][..]
+    };
+    for (key, (_name, preset)) in preset_keys.iter().zip(ZOOM_PRESETS) {
+        if is_key_pressed(*key) {
+            apply_zoom_preset(camera, *preset, submarines, current_submarine, world_size);
+        }
+    }
+}
+
+pub(crate) fn apply_zoom_preset(
+    camera: &mut Camera,
+    zoom_preset: ZoomPreset,
+    submarines: &[SubmarineState],
+    current_submarine: usize,
+    world_size: (usize, usize),
+) {
+    match zoom_preset {
+        ZoomPreset::FitSubmarine => {
+            if let Some(submarine) = submarines.get(current_submarine) {
+                let (width, height) = submarine.water_grid.size();
+                camera.center_on_submarine((width, height));
+                camera.zoom_to_fit((width as f32, height as f32));
+            }
+        }
+        ZoomPreset::Pixels => camera.zoom_to_pixels_per_cell(SPRITE_PIXELS_PER_CELL),
+        ZoomPreset::Overview => {
+            let (width, height) = world_size;
+            camera.center_on_world(world_size);
+            camera.zoom_to_fit((width as f32 * 16.0, height as f32 * 16.0));
+        }
+    }
 }
 
 // Only called when egui doesn't want the mouse/touch pointer
@@ -67,6 +286,14 @@ pub(crate) fn handle_pointer_input(
         ..
     } = game_settings;
 
+    if let Some(highlighted_type) = &game_settings.highlighted_type {
+        if highlighted_type.tool != game_settings.current_tool
+            || is_mouse_button_pressed(MouseButton::Left)
+        {
+            game_settings.highlighted_type = None;
+        }
+    }
+
     let mouse_position = mouse_position();
     let world_camera = camera.to_macroquad_camera(None);
     camera.pointing_at_world = world_camera.screen_to_world(mouse_position.into()).into();
@@ -94,10 +321,25 @@ pub(crate) fn handle_pointer_input(
         let old = world_camera.screen_to_world(Vec2::from(camera.dragging_from));
         let new = world_camera.screen_to_world(Vec2::from(new_position));
 
-        let delta = new - old;
+        let delta = (new - old) * pan_direction(&game_settings.draw_settings);
 
         camera.offset_x += delta.x;
         camera.offset_y += delta.y;
+
+        // Grabbing the camera again stops it from coasting.
+        camera.momentum = (0.0, 0.0);
+
+        // Smoothed over a few frames, so that a single jittery frame right
+        // before releasing doesn't decide the fling.
+        let frame_time = get_frame_time().max(0.001);
+        let velocity = (delta.x / frame_time, delta.y / frame_time);
+        let (old_x, old_y) = camera.drag_velocity.unwrap_or(velocity);
+        camera.drag_velocity = Some(((old_x + velocity.0) / 2.0, (old_y + velocity.1) / 2.0));
+    } else if let Some((velocity_x, velocity_y)) = camera.drag_velocity.take() {
+        if game_settings.draw_settings.camera_momentum {
+            // Coasting happens on animation ticks, at 60 per second.
+            camera.momentum = (velocity_x / 60.0, velocity_y / 60.0);
+        }
     }
 
     camera.dragging_from = mouse_position;
@@ -179,6 +421,11 @@ pub(crate) fn handle_pointer_input(
         mutable_resources.sub_cursor_tile = if inside_grid { Some(grid_coords) } else { None };
     }
 
+    // Clicks near an object reach it too, by a margin that stays the same
+    // size on screen regardless of zoom.
+    let interact_radius =
+        game_settings.draw_settings.interact_radius / camera.pixels_per_cell().max(0.001);
+
     // Do input actions only on one submarine, preferably one with a grid
     // under the mouse.
     let submarines_and_resources = submarines.iter().zip(mutable_sub_resources).enumerate().rev();
@@ -191,6 +438,7 @@ pub(crate) fn handle_pointer_input(
                 mutable_resources,
                 game_settings,
                 sub_cursor_tile,
+                interact_radius,
             ) {
                 break;
             }
@@ -206,19 +454,30 @@ pub(crate) fn handle_pointer_input_on_submarine(
     mutable_resources: &mut MutableSubResources,
     game_settings: &mut GameSettings,
     sub_cursor_tile: (usize, usize),
+    interact_radius: f32,
 ) -> bool {
     let mut actioned = false;
 
     let GameSettings {
         current_tool,
         dragging,
+        prefabs,
+        inspected_object,
+        selection,
         ..
     } = game_settings;
 
     // Highlight current object.
     // Also, some objects react by just hovering over them.
     let clicked = false;
-    interact(commands, submarine, sub_index, mutable_resources, clicked);
+    interact(
+        commands,
+        submarine,
+        sub_index,
+        mutable_resources,
+        interact_radius,
+        clicked,
+    );
 
     // Ghost of object being placed, if any
     if let Tool::PlaceObject(placing_object) = current_tool {
@@ -231,8 +490,27 @@ pub(crate) fn handle_pointer_input_on_submarine(
         let y = y.wrapping_sub(size.1 / 2 + size.1 % 2 + 1);
 
         if x < width && y < height {
+            let snapped_position =
+                snap_to_wire_ports(&placing_object.object_type, &submarine.wire_grid, (x, y));
+
             placing_object.submarine = sub_index;
-            placing_object.position = Some((x, y));
+            placing_object.position = Some(snapped_position.unwrap_or((x, y)));
+            placing_object.snapped = snapped_position.is_some();
+        }
+    }
+
+    let submarine_size = submarine.water_grid.size();
+
+    // Ghost of prefab being placed, if any
+    if let Tool::PlacePrefab {
+        prefab_id,
+        submarine,
+        position,
+    } = current_tool
+    {
+        if let Some((_name, prefab)) = prefabs.get(*prefab_id) {
+            *submarine = sub_index;
+            *position = prefab_placement(prefab, sub_cursor_tile, submarine_size);
         }
     }
 
@@ -243,8 +521,14 @@ pub(crate) fn handle_pointer_input_on_submarine(
         *dragging = Some(match current_tool {
             Tool::Interact => {
                 let clicked = true;
-                let clicked_object =
-                    interact(commands, submarine, sub_index, mutable_resources, clicked);
+                let clicked_object = interact(
+                    commands,
+                    submarine,
+                    sub_index,
+                    mutable_resources,
+                    interact_radius,
+                    clicked,
+                );
 
                 if clicked_object {
                     Dragging::Nothing
@@ -276,13 +560,137 @@ pub(crate) fn handle_pointer_input_on_submarine(
                 Dragging::Nothing
             }
             Tool::PlaceSubmarine { .. } => Dragging::Nothing,
-            Tool::EditWires { color } => Dragging::Wires {
-                color: *color,
+            Tool::PlacePrefab {
+                prefab_id,
+                position: Some(position),
+                ..
+            } => {
+                if let Some((_name, prefab)) = prefabs.get(*prefab_id) {
+                    place_prefab(commands, prefab, sub_index, *position);
+                }
+
+                let place_more_prefabs =
+                    is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+                if !place_more_prefabs {
+                    *current_tool = Tool::Interact;
+                }
+
+                Dragging::Nothing
+            }
+            Tool::PlacePrefab { position: None, .. } => Dragging::Nothing,
+            Tool::EditWires { color, auto_route } => {
+                let toggle_bridge =
+                    is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+
+                if toggle_bridge {
+                    commands.push(Command::Cell {
+                        cell_command: CellCommand::ToggleWireBridge { color: *color },
+                        cell: sub_cursor_tile,
+                        submarine_id: sub_index,
+                    });
+
+                    Dragging::Nothing
+                } else {
+                    Dragging::Wires {
+                        color: *color,
+                        auto_route: *auto_route,
+                        dragging_from_tile: sub_cursor_tile,
+                        dragging_from_sub: sub_index,
+                    }
+                }
+            }
+            Tool::Measure => Dragging::Measure {
                 dragging_from_tile: sub_cursor_tile,
                 dragging_from_sub: sub_index,
             },
+            Tool::Inspect => {
+                let add_to_selection =
+                    is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+
+                if let (Some(object), true) =
+                    (mutable_resources.highlighting_object, add_to_selection)
+                {
+                    toggle_selected_object(selection, sub_index, object);
+
+                    Dragging::Nothing
+                } else if let Some(object) = mutable_resources.highlighting_object {
+                    // Clicking the object the camera follows again keeps
+                    // following it.
+                    let camera_attached = matches!(
+                        inspected_object,
+                        Some(inspected) if inspected.submarine == sub_index
+                            && inspected.object == object
+                            && inspected.camera_attached
+                    );
+
+                    *inspected_object = Some(InspectedObject {
+                        submarine: sub_index,
+                        object,
+                        traced_port: None,
+                        traced_cells: None,
+                        camera_attached,
+                    });
+
+                    Dragging::Nothing
+                } else {
+                    // Let other subs see the click, same as for Interact.
+                    actioned = false;
+
+                    Dragging::Camera
+                }
+            }
+            Tool::ConnectPorts { from, warning } => {
+                if let Some((object, port)) = port_at(submarine, sub_cursor_tile) {
+                    let clicked = PickedPort {
+                        submarine: sub_index,
+                        object,
+                        port,
+                    };
+
+                    match from.take() {
+                        // Clicking the same port again lets go of it.
+                        Some(picked) if picked == clicked => (),
+                        Some(picked) if picked.submarine == sub_index => {
+                            let wire = plan_port_wire(
+                                submarine,
+                                (picked.object, picked.port),
+                                (object, port),
+                            );
+
+                            match wire {
+                                Ok((color, cells)) => {
+                                    for cell in cells {
+                                        commands.push(Command::Cell {
+                                            cell_command: CellCommand::EditWires {
+                                                add: true,
+                                                color,
+                                            },
+                                            cell,
+                                            submarine_id: sub_index,
+                                        });
+                                    }
+                                    *warning = None;
+                                }
+                                Err(err) => *warning = Some(err),
+                            }
+                        }
+                        _ => {
+                            *from = Some(clicked);
+                            *warning = None;
+                        }
+                    }
+
+                    Dragging::Nothing
+                } else {
+                    // Let other subs see the click, same as for Interact.
+                    actioned = false;
+
+                    Dragging::Camera
+                }
+            }
             tool @ Tool::EditWater { .. } => Dragging::Tool(tool.clone()),
             tool @ Tool::EditWalls { .. } => Dragging::Tool(tool.clone()),
+            tool @ Tool::PaintBackground { .. } => Dragging::Tool(tool.clone()),
         });
     }
 
@@ -291,10 +699,17 @@ pub(crate) fn handle_pointer_input_on_submarine(
         let cell_command = match *tool {
             Tool::Interact => None,
             Tool::EditWater { add } => Some(CellCommand::EditWater { add }),
-            Tool::EditWalls { add } => Some(CellCommand::EditWalls { add }),
+            Tool::EditWalls { add, material } => Some(CellCommand::EditWalls { add, material }),
             Tool::EditWires { .. } => None,
+            Tool::Measure => None,
+            Tool::Inspect => None,
+            Tool::PaintBackground { color, brush_size } => {
+                Some(CellCommand::PaintBackground { color, brush_size })
+            }
             Tool::PlaceObject(_) => None,
             Tool::PlaceSubmarine { .. } => None,
+            Tool::PlacePrefab { .. } => None,
+            Tool::ConnectPorts { .. } => None,
         };
 
         if let Some(cell_command) = cell_command {
@@ -310,6 +725,7 @@ pub(crate) fn handle_pointer_input_on_submarine(
     if is_mouse_button_released(MouseButton::Left) {
         if let Some(Dragging::Wires {
             color,
+            auto_route,
             dragging_from_tile,
             dragging_from_sub,
         }) = dragging.take()
@@ -317,58 +733,573 @@ pub(crate) fn handle_pointer_input_on_submarine(
             actioned = true;
 
             if dragging_from_sub == sub_index {
-                let (width, height) = submarine.water_grid.size();
-                let (start_x, start_y) = dragging_from_tile;
-                let (end_x, end_y) = sub_cursor_tile;
+                let route =
+                    plan_wire_route(submarine, dragging_from_tile, sub_cursor_tile, auto_route);
 
-                let x_length = (start_x as i32 - end_x as i32).abs();
-                let y_length = (start_y as i32 - end_y as i32).abs();
+                let add = route
+                    .iter()
+                    .any(|&(x, y)| !submarine.wire_grid.cell(x, y).value(color).connected());
 
-                let (mut start_x, mut start_y, mut end_x, mut end_y) = if x_length > y_length {
-                    (start_x, start_y, end_x, start_y)
-                } else {
-                    (start_x, start_y, start_x, end_y)
-                };
+                for cell in route {
+                    let cell_command = CellCommand::EditWires { color, add };
 
-                if start_x > end_x {
-                    std::mem::swap(&mut start_x, &mut end_x);
+                    commands.push(Command::Cell {
+                        cell_command,
+                        cell,
+                        submarine_id: sub_index,
+                    });
                 }
+            }
+        }
+    }
 
-                if start_y > end_y {
-                    std::mem::swap(&mut start_y, &mut end_y)
+    actioned
+}
+
+/// The cells a wire dragged from `start` to `end` is laid on.
+///
+/// Without auto-routing, that's a straight line along whichever axis the drag
+/// went further on. With it, the wire goes all the way to `end` along an L or
+/// Z shape that only crosses objects through their ports; if there's no such
+/// shape, it falls back to the straight line.
+pub(crate) fn plan_wire_route(
+    submarine: &SubmarineState,
+    start: (usize, usize),
+    end: (usize, usize),
+    auto_route: bool,
+) -> Vec<(usize, usize)> {
+    let (width, height) = submarine.water_grid.size();
+
+    if auto_route {
+        let blocked = object_body_cells(&submarine.objects, (width, height));
+        let is_clear = |route: &[(usize, usize)]| {
+            route
+                .iter()
+                .all(|&(x, y)| x < width && y < height && !blocked[y * width + x])
+        };
+
+        let route = route_shapes(start, end).find(|route| is_clear(route));
+
+        if let Some(route) = route {
+            return route;
+        }
+    }
+
+    let x_length = (start.0 as i32 - end.0 as i32).abs();
+    let y_length = (start.1 as i32 - end.1 as i32).abs();
+
+    let end = if x_length > y_length {
+        (end.0, start.1)
+    } else {
+        (start.0, end.1)
+    };
+
+    let mut route = Vec::new();
+    add_wire_segment(&mut route, start, end);
+    route.retain(|&(x, y)| x < width && y < height);
+    route
+}
+
+/// Adds the object to the selection, or removes it if already selected;
+/// selecting on another submarine starts a new selection.
+fn toggle_selected_object(
+    selection: &mut Option<ObjectSelection>,
+    sub_index: usize,
+    object: usize,
+) {
+    let selection = match selection {
+        Some(selection) if selection.submarine == sub_index => selection,
+        _ => selection.insert(ObjectSelection {
+            submarine: sub_index,
+            objects: Vec::new(),
+        }),
+    };
+
+    if let Some(index) = selection
+        .objects
+        .iter()
+        .position(|&selected| selected == object)
+    {
+        selection.objects.remove(index);
+    } else {
+        selection.objects.push(object);
+    }
+}
+
+/// The wires that bus-connect the first selected object's output port at
+/// `output_port` to the input port at `input_port` of every other selected
+/// object.
+///
+/// Wires can't branch, and ports only send and receive on the wires that end
+/// on them, so each receiving object gets its own wire from the driving port,
+/// each in a different thin color; an output sends logic on all of them.
+pub(crate) fn plan_bus_wires(
+    submarine: &SubmarineState,
+    selected_objects: &[usize],
+    output_port: usize,
+    input_port: usize,
+) -> Result<Vec<(WireColor, Vec<(usize, usize)>)>, String> {
+    let objects = selected_objects
+        .iter()
+        .map(|&object_id| submarine.objects.get(object_id))
+        .collect::<Option<Vec<&Object>>>()
+        .ok_or_else(|| "Some of the selected objects are gone.".to_string())?;
+
+    let (driver, receivers) = match objects.split_first() {
+        Some((driver, receivers)) if !receivers.is_empty() => (driver, receivers),
+        _ => {
+            return Err(
+                "Select the object driving the bus first, then the objects it feeds.".to_string(),
+            )
+        }
+    };
+
+    let port_layout = |object: &Object| {
+        object
+            .object_type
+            .wire_ports()
+            .iter()
+            .map(|port| (port.offset, port.direction, port.signal))
+            .collect::<Vec<_>>()
+    };
+
+    let layout = port_layout(receivers[0]);
+
+    if receivers.iter().any(|object| port_layout(object) != layout) {
+        return Err("The objects fed by the bus have different port layouts.".to_string());
+    }
+
+    let from_port = driver
+        .object_type
+        .wire_ports()
+        .get(output_port)
+        .filter(|port| port.direction == PortDirection::Output)
+        .ok_or_else(|| "The driving object has no such output port.".to_string())?;
+    let to_port = receivers[0]
+        .object_type
+        .wire_ports()
+        .get(input_port)
+        .filter(|port| port.direction == PortDirection::Input)
+        .ok_or_else(|| "The objects fed by the bus have no such input port.".to_string())?;
+
+    if !from_port.signal.compatible_with(to_port.signal) {
+        return Err(format!(
+            "\"{}\" and \"{}\" carry different kinds of signal.",
+            from_port.name, to_port.name
+        ));
+    }
+
+    if from_port.signal == PortSignal::Power && receivers.len() > 1 {
+        return Err(
+            "Power only goes out on one wire; split it with a junction box first.".to_string(),
+        );
+    }
+
+    if receivers.len() > THIN_COLORS.len() {
+        return Err(format!(
+            "A port can drive at most {} wires, one of each thin color; feed the rest \
+            through a junction box.",
+            THIN_COLORS.len()
+        ));
+    }
+
+    let mut colors = THIN_COLORS.to_vec();
+    let mut wires = Vec::new();
+
+    for receiver in receivers {
+        let (color, route) =
+            route_port_wire(submarine, (driver, from_port), (receiver, to_port), &colors)?;
+
+        colors.retain(|&unused_color| unused_color != color);
+        wires.push((color, route));
+    }
+
+    Ok(wires)
+}
+
+/// The wire that connects an output port to an input port, clicked in either
+/// order, as `(object, port)` pairs.
+pub(crate) fn plan_port_wire(
+    submarine: &SubmarineState,
+    first_port: (usize, usize),
+    second_port: (usize, usize),
+) -> Result<(WireColor, Vec<(usize, usize)>), String> {
+    let port = |(object_id, port_index): (usize, usize)| {
+        let object = submarine.objects.get(object_id)?;
+        let port = object.object_type.wire_ports().get(port_index)?;
+        Some((object, port))
+    };
+
+    let first_port = port(first_port).ok_or_else(|| "That port is gone.".to_string())?;
+    let second_port = port(second_port).ok_or_else(|| "That port is gone.".to_string())?;
+
+    let (from, to) = match (first_port.1.direction, second_port.1.direction) {
+        (PortDirection::Output, PortDirection::Input) => (first_port, second_port),
+        (PortDirection::Input, PortDirection::Output) => (second_port, first_port),
+        _ => return Err("Connect an output port to an input port.".to_string()),
+    };
+
+    route_port_wire(submarine, from, to, &THIN_COLORS)
+}
+
+/// Routes a new wire from an output port to an input port.
+///
+/// Ports are inside their object, so the wire may cross the two objects it
+/// connects; it takes the first auto-routing shape that crosses no other
+/// object or port, in the first of `colors` that it wouldn't join to any wire
+/// along the way.
+fn route_port_wire(
+    submarine: &SubmarineState,
+    (from_object, from_port): (&Object, &WirePort),
+    (to_object, to_port): (&Object, &WirePort),
+    colors: &[WireColor],
+) -> Result<(WireColor, Vec<(usize, usize)>), String> {
+    let start = port_cell(from_object, from_port);
+    let end = port_cell(to_object, to_port);
+
+    let already_connected = trace_port(submarine, from_object, from_port)
+        .iter()
+        .any(|&(_color, cell)| cell == end);
+
+    if already_connected {
+        return Err(format!(
+            "\"{}\" already reaches \"{}\"; no wire added.",
+            from_port.name, to_port.name
+        ));
+    }
+
+    let (width, height) = submarine.water_grid.size();
+    let other_objects = submarine
+        .objects
+        .iter()
+        .filter(|&object| !ptr::eq(object, from_object) && !ptr::eq(object, to_object));
+    let mut blocked = object_body_cells(other_objects, (width, height));
+
+    // Any other port the wire went through would get connected too.
+    for object in &submarine.objects {
+        for port in object.object_type.wire_ports() {
+            let (x, y) = port_cell(object, port);
+            if (x, y) != start && (x, y) != end && x < width && y < height {
+                blocked[y * width + x] = true;
+            }
+        }
+    }
+
+    let is_clear = |route: &[(usize, usize)]| {
+        route
+            .iter()
+            .all(|&(x, y)| x < width && y < height && !blocked[y * width + x])
+    };
+
+    // A wire joins any wire of the same color that it crosses or runs next to.
+    let wire_grid = &submarine.wire_grid;
+    let clashes = |color: WireColor, route: &[(usize, usize)]| {
+        route.iter().any(|&(x, y)| {
+            let nearby = [
+                (x, y),
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            nearby.iter().any(|&(x, y)| {
+                x < width && y < height && wire_grid.cell(x, y).value(color).connected()
+            })
+        })
+    };
+
+    route_shapes(start, end)
+        .filter(|route| is_clear(route))
+        .find_map(|route| {
+            let color = colors
+                .iter()
+                .copied()
+                .find(|&color| !clashes(color, &route))?;
+            Some((color, route))
+        })
+        .ok_or_else(|| {
+            "Could not find a route around the objects and wires between these ports.".to_string()
+        })
+}
+
+/// The object and port index of the port on `cell`, if there is one.
+fn port_at(submarine: &SubmarineState, cell: (usize, usize)) -> Option<(usize, usize)> {
+    submarine
+        .objects
+        .iter()
+        .enumerate()
+        .find_map(|(object_id, object)| {
+            let port_index = object
+                .object_type
+                .wire_ports()
+                .iter()
+                .position(|port| port_cell(object, port) == cell)?;
+            Some((object_id, port_index))
+        })
+}
+
+/// Cells covered by an object's body, except for its ports.
+fn object_body_cells<'a>(
+    objects: impl IntoIterator<Item = &'a Object>,
+    (width, height): (usize, usize),
+) -> Vec<bool> {
+    let mut blocked = vec![false; width * height];
+
+    for object in objects {
+        let (object_width, object_height) = object_size(&object.object_type);
+        let (object_x, object_y) = (object.position.0 as usize, object.position.1 as usize);
+
+        // Objects are drawn one cell to the right and below their position.
+        for y in object_y + 1..=object_y + object_height {
+            for x in object_x + 1..=object_x + object_width {
+                if x < width && y < height {
+                    blocked[y * width + x] = true;
                 }
+            }
+        }
 
-                let mut add = false;
+        for port in object.object_type.wire_ports() {
+            let x = object_x + port.offset.0 as usize;
+            let y = object_y + port.offset.1 as usize;
 
-                'check: for x in start_x..=end_x {
-                    for y in start_y..=end_y {
-                        if (x < width || y < height)
-                            && !submarine.wire_grid.cell(x, y).value(color).connected()
-                        {
-                            add = true;
-                            break 'check;
-                        }
-                    }
+            if x < width && y < height {
+                blocked[y * width + x] = false;
+            }
+        }
+    }
+
+    blocked
+}
+
+/// Candidate routes from `start` to `end`, simplest first: the two L shapes,
+/// then Z shapes with their middle leg closest to halfway first.
+fn route_shapes(
+    start: (usize, usize),
+    end: (usize, usize),
+) -> impl Iterator<Item = Vec<(usize, usize)>> {
+    let route = |corners: &[(usize, usize)]| {
+        let mut route = Vec::new();
+        for leg in corners.windows(2) {
+            add_wire_segment(&mut route, leg[0], leg[1]);
+        }
+        route
+    };
+
+    let l_shapes = vec![
+        route(&[start, (end.0, start.1), end]),
+        route(&[start, (start.0, end.1), end]),
+    ];
+
+    let by_distance_from_middle = |from: usize, to: usize| {
+        let (low, high) = (from.min(to), from.max(to));
+        let middle = (low + high) / 2;
+        let mut steps: Vec<usize> = (low..=high).collect();
+        steps.sort_by_key(|&step| (step as i32 - middle as i32).abs());
+        steps
+    };
+
+    let horizontal_z = by_distance_from_middle(start.0, end.0)
+        .into_iter()
+        .map(move |x| route(&[start, (x, start.1), (x, end.1), end]));
+    let vertical_z = by_distance_from_middle(start.1, end.1)
+        .into_iter()
+        .map(move |y| route(&[start, (start.0, y), (end.0, y), end]));
+
+    l_shapes.into_iter().chain(horizontal_z).chain(vertical_z)
+}
+
+/// Adds the cells of a straight horizontal or vertical line, skipping the
+/// first one if it's already the last cell of the route.
+fn add_wire_segment(route: &mut Vec<(usize, usize)>, from: (usize, usize), to: (usize, usize)) {
+    let (low_x, high_x) = (from.0.min(to.0), from.0.max(to.0));
+    let (low_y, high_y) = (from.1.min(to.1), from.1.max(to.1));
+
+    let mut cells: Vec<(usize, usize)> = (low_x..=high_x)
+        .flat_map(|x| (low_y..=high_y).map(move |y| (x, y)))
+        .collect();
+
+    if cells.first() != Some(&from) {
+        cells.reverse();
+    }
+
+    for cell in cells {
+        if route.last() != Some(&cell) {
+            route.push(cell);
+        }
+    }
+}
+
+/// Find the nearest position to `free_position` at which one of the object's
+/// ports lands on the loose end of a wire, if any are close enough.
+fn snap_to_wire_ports(
+    object_type: &ObjectType,
+    wire_grid: &WireGrid,
+    free_position: (usize, usize),
+) -> Option<(usize, usize)> {
+    const SNAP_DISTANCE: usize = 6;
+
+    let (width, height) = wire_grid.size();
+    let size = object_size(object_type);
+
+    let is_wire_end = |x: usize, y: usize| {
+        THIN_COLORS.iter().any(|&color| {
+            let neighbours = wire_grid.has_neighbours(color, x, y);
+            wire_grid.cell(x, y).value(color).connected()
+                && neighbours.iter().filter(|&&neighbour| neighbour).count() <= 1
+        })
+    };
+
+    let mut best_snap = None;
+    let mut best_distance = usize::MAX;
+
+    for port in object_type.wire_ports() {
+        let port_x = free_position.0 + port.offset.0 as usize;
+        let port_y = free_position.1 + port.offset.1 as usize;
+
+        // Skip the grid's border, since neighbours are looked up around each cell
+        let min_x = port_x.saturating_sub(SNAP_DISTANCE).max(1);
+        let min_y = port_y.saturating_sub(SNAP_DISTANCE).max(1);
+        let max_x = (port_x + SNAP_DISTANCE).min(width.saturating_sub(2));
+        let max_y = (port_y + SNAP_DISTANCE).min(height.saturating_sub(2));
+
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let distance = x.max(port_x) - x.min(port_x) + y.max(port_y) - y.min(port_y);
+
+                if distance >= best_distance || !is_wire_end(x, y) {
+                    continue;
                 }
 
-                for x in start_x..=end_x {
-                    for y in start_y..=end_y {
-                        if x < width || y < height {
-                            let cell_command = CellCommand::EditWires { color, add };
+                // Too close to the edge for the rest of the object to fit
+                let position = match (
+                    x.checked_sub(port.offset.0 as usize),
+                    y.checked_sub(port.offset.1 as usize),
+                ) {
+                    (Some(x), Some(y)) => (x, y),
+                    _ => continue,
+                };
 
-                            commands.push(Command::Cell {
-                                cell_command,
-                                cell: (x, y),
-                                submarine_id: sub_index,
-                            });
-                        }
-                    }
+                if position.0 + size.0 > width || position.1 + size.1 > height {
+                    continue;
                 }
+
+                best_snap = Some(position);
+                best_distance = distance;
             }
         }
     }
 
-    actioned
+    best_snap
+}
+
+/// Centers the prefab on the cursor, but keeps it entirely inside the grid;
+/// returns None if the prefab is larger than the submarine.
+fn prefab_placement(
+    prefab: &Prefab,
+    cursor_tile: (usize, usize),
+    grid_size: (usize, usize),
+) -> Option<(usize, usize)> {
+    let (width, height) = prefab.size;
+
+    if width > grid_size.0 || height > grid_size.1 {
+        return None;
+    }
+
+    let x = cursor_tile
+        .0
+        .saturating_sub(width / 2)
+        .min(grid_size.0 - width);
+    let y = cursor_tile
+        .1
+        .saturating_sub(height / 2)
+        .min(grid_size.1 - height);
+
+    Some((x, y))
+}
+
+fn place_prefab(
+    commands: &mut Vec<Command>,
+    prefab: &Prefab,
+    submarine_id: usize,
+    (offset_x, offset_y): (usize, usize),
+) {
+    // Expand the wire points into cells; this also restores any bridges.
+    let (width, height) = prefab.size;
+    let wire_grid = WireGrid::from_wire_points(
+        width + 2,
+        height + 2,
+        &prefab.wire_points,
+        &prefab.wire_bridges,
+    );
+
+    for y in 0..height {
+        for x in 0..width {
+            let cell = wire_grid.cell(x, y);
+            let cell_position = (offset_x + x, offset_y + y);
+
+            for color in ALL_COLORS {
+                let value = cell.value(color);
+
+                if !value.connected() {
+                    continue;
+                }
+
+                commands.push(Command::Cell {
+                    submarine_id,
+                    cell: cell_position,
+                    cell_command: CellCommand::EditWires { add: true, color },
+                });
+
+                if let WireValue::Bridge = value {
+                    commands.push(Command::Cell {
+                        submarine_id,
+                        cell: cell_position,
+                        cell_command: CellCommand::ToggleWireBridge { color },
+                    });
+                }
+            }
+        }
+    }
+
+    for object in &prefab.objects {
+        commands.push(Command::Cell {
+            submarine_id,
+            cell: (
+                offset_x + object.position.0 as usize,
+                offset_y + object.position.1 as usize,
+            ),
+            cell_command: CellCommand::AddObject {
+                object_type: object.object_type.clone(),
+            },
+        });
+    }
+}
+
+/// The object under the cursor, or else the nearest one at most `radius`
+/// cells away from it. Among equally near objects the last one wins, since it
+/// is drawn on top of the others.
+fn object_near(submarine: &SubmarineState, mouse_position: Vec2, radius: f32) -> Option<usize> {
+    let mut nearest = None;
+    let mut nearest_distance = radius;
+
+    for (obj_index, object) in submarine.objects.iter().enumerate() {
+        let draw_rect = object_rect(object);
+
+        let distance_x = (draw_rect.x - mouse_position.x)
+            .max(mouse_position.x - (draw_rect.x + draw_rect.w))
+            .max(0.0);
+        let distance_y = (draw_rect.y - mouse_position.y)
+            .max(mouse_position.y - (draw_rect.y + draw_rect.h))
+            .max(0.0);
+        let distance = (distance_x * distance_x + distance_y * distance_y).sqrt();
+
+        if distance <= nearest_distance {
+            nearest = Some(obj_index);
+            nearest_distance = distance;
+        }
+    }
+
+    nearest
 }
 
 fn hovering_over_sonar(object: &Object, hover_position: Vec2) -> Option<(f32, f32)> {
@@ -406,6 +1337,7 @@ fn interact(
     submarine: &SubmarineState,
     sub_index: usize,
     mutable_resources: &mut MutableSubResources,
+    interact_radius: f32,
     clicked: bool,
 ) -> bool {
     mutable_resources.sonar_cursor = None;
@@ -414,13 +1346,10 @@ fn interact(
 
     let mouse_position: Vec2 = mutable_resources.sub_cursor.into();
 
-    for (obj_index, object) in submarine.objects.iter().enumerate() {
+    if let Some(obj_index) = object_near(submarine, mouse_position, interact_radius) {
+        let object = &submarine.objects[obj_index];
         let draw_rect = object_rect(object);
 
-        if !draw_rect.contains(mouse_position) {
-            continue;
-        }
-
         mutable_resources.highlighting_object = Some(obj_index);
 
         let hover_position = mouse_position - draw_rect.point();
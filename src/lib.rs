@@ -2,9 +2,13 @@
 
 mod app;
 mod client;
+mod console;
 mod draw;
 mod game_state;
 mod input;
+#[cfg(target_arch = "wasm32")]
+mod local_storage;
+pub mod logging;
 mod resources;
 mod saveload;
 #[cfg(not(target_arch = "wasm32"))]
@@ -0,0 +1,27 @@
+//! The browser's localStorage, for settings that a native build would keep in
+//! a file. Needs the `local_storage.js` plugin loaded by `docs/index.html`.
+
+extern "C" {
+    fn local_storage_prepare(key: *const u8, key_len: usize) -> i32;
+    fn local_storage_take(value: *mut u8, value_len: usize);
+    fn local_storage_set(key: *const u8, key_len: usize, value: *const u8, value_len: usize);
+}
+
+/// Returns `None` if nothing was stored under `key`.
+pub(crate) fn get(key: &str) -> Option<String> {
+    // The plugin holds on to the value until it is taken, once its length
+    // is known and there's room for it.
+    let length = unsafe { local_storage_prepare(key.as_ptr(), key.len()) };
+    if length < 0 {
+        return None;
+    }
+
+    let mut value = vec![0; length as usize];
+    unsafe { local_storage_take(value.as_mut_ptr(), value.len()) };
+
+    String::from_utf8(value).ok()
+}
+
+pub(crate) fn set(key: &str, value: &str) {
+    unsafe { local_storage_set(key.as_ptr(), key.len(), value.as_ptr(), value.len()) };
+}
@@ -0,0 +1,89 @@
+//! Log messages filtered by a level that can be changed at runtime.
+//!
+//! Messages go through macroquad's logging, which prints to stderr on native
+//! and to the browser's console on wasm. The level is checked before the
+//! message is formatted, so disabled messages cost nothing in hot paths.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use macroquad::logging;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[repr(u8)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+pub(crate) const LOG_LEVELS: [LogLevel; 6] = [
+    LogLevel::Off,
+    LogLevel::Error,
+    LogLevel::Warn,
+    LogLevel::Info,
+    LogLevel::Debug,
+    LogLevel::Trace,
+];
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn log_level() -> LogLevel {
+    LOG_LEVELS[LOG_LEVEL.load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn log_enabled(level: LogLevel) -> bool {
+    level != LogLevel::Off && level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+#[doc(hidden)]
+pub fn log_message(level: LogLevel, message: std::fmt::Arguments<'_>) {
+    match level {
+        LogLevel::Off => (),
+        LogLevel::Error => logging::error!("{}", message),
+        LogLevel::Warn => logging::warn!("{}", message),
+        LogLevel::Info => logging::info!("{}", message),
+        LogLevel::Debug => logging::debug!("{}", message),
+        LogLevel::Trace => logging::trace!("{}", message),
+    }
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::logging::log_enabled($level) {
+            $crate::logging::log_message($level, format_args!($($arg)+));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log!($crate::logging::LogLevel::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log!($crate::logging::LogLevel::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log!($crate::logging::LogLevel::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log!($crate::logging::LogLevel::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::log!($crate::logging::LogLevel::Trace, $($arg)+) };
+}
@@ -2,7 +2,7 @@
 
 use std::{path::Path, time::Instant};
 
-use cybersub::{CyberSubApp, SubmarineFileData};
+use cybersub::{info, CyberSubApp, SubmarineFileData};
 use macroquad::prelude::{
     clear_background, get_fps, get_frame_time, get_time, load_file, next_frame,
     set_pc_assets_folder, Conf, BLACK,
@@ -17,9 +17,57 @@ fn window_conf() -> Conf {
     }
 }
 
+/// Updates simulated by `--benchmark` when no count is given.
+const DEFAULT_BENCHMARK_TICKS: u32 = 10_000;
+
+/// `--benchmark` or `--benchmark=<ticks>` on the command line.
+fn benchmark_ticks() -> Result<Option<u32>, String> {
+    std::env::args()
+        .find_map(|arg| match arg.strip_prefix("--benchmark") {
+            Some("") => Some(Ok(DEFAULT_BENCHMARK_TICKS)),
+            Some(ticks) => {
+                let ticks = ticks.strip_prefix('=')?;
+                Some(
+                    ticks.parse().map_err(|_| {
+                        format!("--benchmark takes a number of updates, not {}", ticks)
+                    }),
+                )
+            }
+            None => None,
+        })
+        .transpose()
+}
+
+/// `--safe-mode` or `--safe-mode=<true or false>` on the command line, or
+/// `?safe-mode` in the page's address on the web.
+fn safe_mode() -> Result<bool, String> {
+    quad_url::get_program_parameters()
+        .iter()
+        .find_map(|arg| match arg.strip_prefix("--safe-mode") {
+            Some("") => Some(Ok(true)),
+            Some(value) => {
+                let value = value.strip_prefix('=')?;
+                Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("--safe-mode takes true or false, not {}", value)),
+                )
+            }
+            None => None,
+        })
+        .unwrap_or(Ok(false))
+}
+
 #[macroquad::main(window_conf)]
 async fn main() -> Result<(), String> {
-    let mut cybersub_app = CyberSubApp::default();
+    let safe_mode = safe_mode()?;
+    let benchmark = benchmark_ticks()?;
+    if safe_mode {
+        info!("Starting in safe mode.");
+    }
+
+    let mut cybersub_app = CyberSubApp::new(safe_mode);
+    cybersub_app.load_window_layout();
 
     if cfg!(not(target_arch = "wasm32")) {
         // Share the world and submarine assets with the WASM directory for Github Pages
@@ -38,11 +86,17 @@ async fn main() -> Result<(), String> {
     cybersub_app.load_submarine_template("Dugong", dugong)?;
     cybersub_app.add_submarine(1);
 
+    if let Some(ticks) = benchmark {
+        info!("Running benchmark.");
+        println!("{}", cybersub_app.run_benchmark(ticks));
+        return Ok(());
+    }
+
     if std::env::args().any(|arg| arg == "--join") {
-        eprintln!("Joining.");
+        info!("Joining.");
         cybersub_app.join_server();
     } else if std::env::args().any(|arg| arg == "--server") {
-        eprintln!("Starting server.");
+        info!("Starting server.");
         cybersub_app.start_server();
     }
 
@@ -78,9 +132,12 @@ async fn main() -> Result<(), String> {
 
             if !egui_ctx.wants_pointer_input() {
                 cybersub_app.handle_pointer_input();
+            } else {
+                cybersub_app.pointer_over_ui();
             }
             if !egui_ctx.wants_keyboard_input() {
-                cybersub_app.handle_keyboard_input();
+                // Real frame time, since the timings' delta is always 0 on wasm
+                cybersub_app.handle_keyboard_input(get_frame_time());
             }
             cybersub_app.timings.input_handling = delta_time();
         });
@@ -158,5 +215,6 @@ async fn load_submarine_files(name: &str) -> Result<SubmarineFileData, String> {
         background,
         objects,
         wires,
+        grids: None,
     })
 }
@@ -7,25 +7,29 @@ use macroquad::{
 };
 
 use crate::{
-    draw::Camera,
+    draw::{Camera, DetailLevel},
     game_state::{
         state::GameState,
         update::{SubmarineUpdatedEvent, UpdateEvent},
     },
     saveload::pixels_to_image,
     shadows::Edge,
+    warn,
 };
 
 pub(crate) struct Resources {
     pub settings: Texture2D,
-    pub sea_water: Material,
-    pub hover_highlight: Material,
-    pub wire_material: Material,
-    pub wall_material: Material,
-    pub rock_material: Material,
-    pub sonar_material: Material,
-    pub shadow_material: Material,
-    pub pointlight_material: Material,
+    /// Shader materials that failed to load are drawn in a plain color
+    /// instead, or are missing in safe mode; whatever a missing material
+    /// draws is then skipped or drawn plainly.
+    pub sea_water: Option<Material>,
+    pub hover_highlight: Option<Material>,
+    pub wire_material: Option<Material>,
+    pub wall_material: Option<Material>,
+    pub rock_material: Option<Material>,
+    pub sonar_material: Option<Material>,
+    pub shadow_material: Option<Material>,
+    pub pointlight_material: Option<Material>,
     pub wires: Texture2D,
     pub sea_dust: Texture2D,
     pub wall: Texture2D,
@@ -48,6 +52,15 @@ pub(crate) struct Resources {
     pub bundle_output: Texture2D,
     pub docking_connector_top: Texture2D,
     pub docking_connector_bottom: Texture2D,
+    pub keypad: Texture2D,
+    pub collision_sensor: Texture2D,
+    pub power_meter: Texture2D,
+    pub signal_map: Texture2D,
+    pub flood_meter: Texture2D,
+    pub edge_detector: Texture2D,
+    pub radio: Texture2D,
+    /// Why each shader material that failed to load did so.
+    pub material_errors: Vec<String>,
 }
 
 pub(crate) struct MutableResources {
@@ -57,17 +70,29 @@ pub(crate) struct MutableResources {
     pub screen: Texture2D,
     pub template_ghost_id: Option<usize>,
     pub template_ghost: Texture2D,
+    pub detail_level: DetailLevel,
+    /// Whether objects were drawn simplified on the last frame.
+    pub simplified_objects: bool,
 }
 
 pub(crate) struct MutableSubResources {
     pub sub_background_image: Image,
     pub sub_background: Texture2D,
+    /// Set while painting; the background is rebuilt once after all of a
+    /// frame's events, rather than once per painted cell.
+    pub background_updated: bool,
     pub sub_walls: Texture2D,
     pub walls_updated: bool,
+    pub sub_cell_types: Texture2D,
+    pub cell_types_updated: bool,
     pub sub_wires: RenderTarget,
     pub wires_updated: bool,
     pub sub_signals_image: Image,
     pub sub_signals: Texture2D,
+    /// Raw signal strength of each wire color, wrapping around at 256; used
+    /// to tell which way a signal flows.
+    pub sub_signal_flow_image: Image,
+    pub sub_signal_flow: Texture2D,
     pub signals_updated: bool,
     pub new_sonar_target: RenderTarget,
     pub old_sonar_target: RenderTarget,
@@ -88,10 +113,66 @@ pub(crate) struct TurbulenceParticle {
     pub life: u8,
 }
 
+/// Loads a shader material. A material that fails to load is replaced by one
+/// that takes the same uniforms and textures, so that it can be used the same
+/// way, but just draws the texture it is drawn with, multiplied by
+/// `fallback_color`; a transparent color leaves optional effects out.
+/// Failures are added to `errors`.
+fn load_shader_material(
+    name: &str,
+    fragment_shader: &str,
+    params: MaterialParams,
+    fallback_color: [f32; 4],
+    errors: &mut Vec<String>,
+) -> Option<Material> {
+    let fallback_params = MaterialParams {
+        uniforms: params
+            .uniforms
+            .iter()
+            .cloned()
+            .chain(std::iter::once((
+                "fallback_color".to_string(),
+                UniformType::Float4,
+            )))
+            .collect(),
+        textures: params.textures.clone(),
+        pipeline_params: params.pipeline_params,
+    };
+
+    let err = match load_material(include_str!("vertex.glsl"), fragment_shader, params) {
+        Ok(material) => return Some(material),
+        Err(err) => err,
+    };
+
+    errors.push(format!("Could not load {} material: {:?}", name, err));
+
+    warn!(
+        "Could not load {} material, drawing it plainly: {:?}",
+        name, err
+    );
+
+    match load_material(
+        include_str!("vertex.glsl"),
+        include_str!("fallback.glsl"),
+        fallback_params,
+    ) {
+        Ok(material) => {
+            material.set_uniform("fallback_color", fallback_color);
+            Some(material)
+        }
+        Err(err) => {
+            warn!("Could not load fallback {} material: {:?}", name, err);
+            None
+        }
+    }
+}
+
 impl Resources {
     pub fn new() -> Self {
-        let sea_water = load_material(
-            include_str!("vertex.glsl"),
+        let mut material_errors = Vec::new();
+
+        let sea_water = load_shader_material(
+            "sea water",
             include_str!("water.glsl"),
             MaterialParams {
                 uniforms: vec![
@@ -100,14 +181,17 @@ impl Resources {
                     ("time_offset".to_string(), UniformType::Float2),
                     ("camera_offset".to_string(), UniformType::Float2),
                     ("time".to_string(), UniformType::Float1),
-                    ("world_size".to_string(), UniformType::Float2),
-                    ("sea_dust_size".to_string(), UniformType::Float2),
+                    ("ambient".to_string(), UniformType::Float1),
+                    ("dust_density".to_string(), UniformType::Float1),
+                    ("dust_origin".to_string(), UniformType::Float2),
+                    ("dust_scale".to_string(), UniformType::Float2),
                 ],
                 textures: vec!["sea_dust".to_string()],
                 ..Default::default()
             },
-        )
-        .expect("Could not load material");
+            [0.0235, 0.0235, 0.1255, 1.0],
+            &mut material_errors,
+        );
 
         fn load_texture(bytes: &[u8]) -> Texture2D {
             let texture = Texture2D::from_file_with_format(bytes, Some(ImageFormat::Png));
@@ -140,6 +224,13 @@ impl Resources {
             load_texture(include_bytes!("../resources/docking_connector_top.png"));
         let docking_connector_bottom =
             load_texture(include_bytes!("../resources/docking_connector_bottom.png"));
+        let keypad = load_texture(include_bytes!("../resources/keypad.png"));
+        let collision_sensor = load_texture(include_bytes!("../resources/collision_sensor.png"));
+        let power_meter = load_texture(include_bytes!("../resources/power_meter.png"));
+        let signal_map = load_texture(include_bytes!("../resources/signal_map.png"));
+        let flood_meter = load_texture(include_bytes!("../resources/flood_meter.png"));
+        let edge_detector = load_texture(include_bytes!("../resources/edge_detector.png"));
+        let radio = load_texture(include_bytes!("../resources/radio.png"));
 
         sea_dust.set_filter(FilterMode::Linear);
 
@@ -157,8 +248,8 @@ impl Resources {
             ..Default::default()
         };
 
-        let hover_highlight = load_material(
-            include_str!("vertex.glsl"),
+        let hover_highlight = load_shader_material(
+            "highlight",
             include_str!("highlight.glsl"),
             MaterialParams {
                 uniforms: vec![
@@ -172,22 +263,36 @@ impl Resources {
                 textures: vec!["input_texture".to_string()],
                 pipeline_params: blend_alpha,
             },
-        )
-        .expect("Could not load door highlight material");
+            [0.5, 0.5, 1.0, 0.5],
+            &mut material_errors,
+        );
 
-        let wire_material = load_material(
-            include_str!("vertex.glsl"),
+        let wire_material = load_shader_material(
+            "wire",
             include_str!("wires.glsl"),
             MaterialParams {
-                uniforms: vec![("grid_size".to_string(), UniformType::Float2)],
-                textures: vec!["sub_wires".to_string(), "sub_signals".to_string()],
+                uniforms: vec![
+                    ("grid_size".to_string(), UniformType::Float2),
+                    ("colorblind".to_string(), UniformType::Float1),
+                    ("patterns".to_string(), UniformType::Float1),
+                    ("animate_signals".to_string(), UniformType::Float1),
+                    ("flow_time".to_string(), UniformType::Float1),
+                    ("smooth_wires".to_string(), UniformType::Float1),
+                    ("pixels_per_texel".to_string(), UniformType::Float1),
+                ],
+                textures: vec![
+                    "sub_wires".to_string(),
+                    "sub_signals".to_string(),
+                    "sub_signal_flow".to_string(),
+                ],
                 pipeline_params: blend_alpha,
             },
-        )
-        .expect("Could not load wire material");
+            [1.0; 4],
+            &mut material_errors,
+        );
 
-        let wall_material = load_material(
-            include_str!("vertex.glsl"),
+        let wall_material = load_shader_material(
+            "wall",
             include_str!("walls.glsl"),
             MaterialParams {
                 uniforms: vec![("walls_size".to_string(), UniformType::Float2)],
@@ -198,22 +303,27 @@ impl Resources {
                 ],
                 pipeline_params: blend_alpha,
             },
-        )
-        .expect("Could not load wall material");
+            [1.0; 4],
+            &mut material_errors,
+        );
 
-        let rock_material = load_material(
-            include_str!("vertex.glsl"),
+        let rock_material = load_shader_material(
+            "rock",
             include_str!("rocks.glsl"),
             MaterialParams {
-                uniforms: vec![("sea_rocks_size".to_string(), UniformType::Float2)],
+                uniforms: vec![
+                    ("sea_rocks_size".to_string(), UniformType::Float2),
+                    ("ambient".to_string(), UniformType::Float1),
+                ],
                 textures: vec!["rocks_texture".to_string(), "sea_rocks".to_string()],
                 pipeline_params: blend_alpha,
             },
-        )
-        .expect("Could not load rock material");
+            [1.0; 4],
+            &mut material_errors,
+        );
 
-        let sonar_material = load_material(
-            include_str!("vertex.glsl"),
+        let sonar_material = load_shader_material(
+            "sonar",
             include_str!("sonar.glsl"),
             MaterialParams {
                 uniforms: vec![
@@ -226,22 +336,24 @@ impl Resources {
                 ],
                 pipeline_params: blend_alpha,
             },
-        )
-        .expect("Could not load sonar material");
+            [1.0; 4],
+            &mut material_errors,
+        );
 
-        let shadow_material = load_material(
-            include_str!("vertex.glsl"),
+        let shadow_material = load_shader_material(
+            "shadow",
             include_str!("shadows.glsl"),
             MaterialParams {
                 uniforms: vec![],
                 textures: vec!["screen".to_string(), "shadows".to_string()],
                 pipeline_params: blend_alpha,
             },
-        )
-        .expect("Could not load shadow material");
+            [0.0; 4],
+            &mut material_errors,
+        );
 
-        let pointlight_material = load_material(
-            include_str!("vertex.glsl"),
+        let pointlight_material = load_shader_material(
+            "point light",
             include_str!("pointlight.glsl"),
             MaterialParams {
                 uniforms: vec![
@@ -251,8 +363,9 @@ impl Resources {
                 textures: vec![],
                 pipeline_params: blend_alpha,
             },
-        )
-        .expect("Could not load point light material");
+            [0.0; 4],
+            &mut material_errors,
+        );
 
         Resources {
             settings,
@@ -286,6 +399,14 @@ impl Resources {
             bundle_output,
             docking_connector_top,
             docking_connector_bottom,
+            keypad,
+            collision_sensor,
+            power_meter,
+            signal_map,
+            flood_meter,
+            edge_detector,
+            radio,
+            material_errors,
         }
     }
 }
@@ -305,6 +426,8 @@ impl MutableResources {
             screen: Texture2D::empty(),
             template_ghost_id: None,
             template_ghost: Texture2D::empty(),
+            detail_level: DetailLevel::Full,
+            simplified_objects: false,
         }
     }
 }
@@ -317,12 +440,17 @@ impl MutableSubResources {
         MutableSubResources {
             sub_background_image,
             sub_background,
+            background_updated: false,
             sub_walls: Texture2D::empty(),
             walls_updated: true,
+            sub_cell_types: Texture2D::empty(),
+            cell_types_updated: true,
             sub_wires: render_target(0, 0),
             wires_updated: true,
             sub_signals_image: Image::empty(),
             sub_signals: Texture2D::empty(),
+            sub_signal_flow_image: Image::empty(),
+            sub_signal_flow: Texture2D::empty(),
             signals_updated: true,
             new_sonar_target: render_target(0, 0),
             old_sonar_target: render_target(0, 0),
@@ -361,6 +489,7 @@ pub(crate) fn update_resources_from_events(
                     }
                     SubmarineUpdatedEvent::Walls => {
                         mutable_sub_resources.walls_updated = true;
+                        mutable_sub_resources.cell_types_updated = true;
                         mutable_sub_resources.shadow_edges_updated = true;
                     }
                     SubmarineUpdatedEvent::Wires => {
@@ -369,6 +498,10 @@ pub(crate) fn update_resources_from_events(
                     SubmarineUpdatedEvent::Signals => {
                         mutable_sub_resources.signals_updated = true;
                     }
+                    SubmarineUpdatedEvent::Objects => (),
+                    SubmarineUpdatedEvent::Background => {
+                        mutable_sub_resources.background_updated = true;
+                    }
                 }
             }
             UpdateEvent::SubmarineCreated => {
@@ -382,9 +515,20 @@ pub(crate) fn update_resources_from_events(
 
                 // Change camera to its middle and set it as current
                 *current_submarine = game_state.submarines.len() - 1;
-                camera.offset_x = -(width as f32) / 2.0;
-                camera.offset_y = -(height as f32) / 2.0;
+                camera.center_on_submarine((width, height));
             }
+            UpdateEvent::SubmarineRemoved { submarine_id } => {
+                mutable_sub_resources.remove(submarine_id);
+
+                if *current_submarine > submarine_id
+                    || *current_submarine >= game_state.submarines.len()
+                {
+                    *current_submarine = current_submarine.saturating_sub(1);
+                }
+            }
+            // Shown by the UI instead.
+            UpdateEvent::ObjectiveMet { .. } => (),
+            UpdateEvent::TeammateAction { .. } => (),
             UpdateEvent::GameStateReset => {
                 // FIXME: Delete textures
                 mutable_sub_resources.clear();
@@ -396,18 +540,38 @@ pub(crate) fn update_resources_from_events(
                     mutable_sub_resources.push(MutableSubResources::new(image))
                 }
 
-                // Get last submarine
-                let submarine = game_state
-                    .submarines
-                    .last()
-                    .expect("Submarine just created");
-                let (width, height) = submarine.water_grid.size();
-
-                // Change camera to its middle and set it as current
-                *current_submarine = game_state.submarines.len() - 1;
-                camera.offset_x = -(width as f32) / 2.0;
-                camera.offset_y = -(height as f32) / 2.0;
+                // Get last submarine; a reset world may have none
+                if let Some(submarine) = game_state.submarines.last() {
+                    // Change camera to its middle and set it as current
+                    *current_submarine = game_state.submarines.len() - 1;
+                    camera.center_on_submarine(submarine.water_grid.size());
+                } else {
+                    *current_submarine = 0;
+                }
             }
         }
     }
+
+    // Done here rather than when drawing, so that saving right after painting
+    // still gets the new background image.
+    for (submarine, mutable_sub_resources) in game_state
+        .submarines
+        .iter()
+        .zip(mutable_sub_resources.iter_mut())
+    {
+        if !mutable_sub_resources.background_updated {
+            continue;
+        }
+
+        let (width, height) = submarine.water_grid.size();
+        let image = pixels_to_image(width, height, &submarine.background_pixels);
+
+        mutable_sub_resources.sub_background.delete();
+        mutable_sub_resources.sub_background = Texture2D::from_image(&image);
+        mutable_sub_resources
+            .sub_background
+            .set_filter(FilterMode::Nearest);
+        mutable_sub_resources.sub_background_image = image;
+        mutable_sub_resources.background_updated = false;
+    }
 }
@@ -1,22 +1,33 @@
-use std::{io::Write, path::Path};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    path::Path,
+};
 
 use flate2::read::GzDecoder;
 use macroquad::prelude::{Image, ImageFormat, BLACK};
 use png::{BitDepth, ColorType, Decoder, Encoder};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    game_state::objects::Object,
+    game_state::objects::{
+        object_size, object_type_name, overlapping_object, port_cell, DoorState, Object,
+        ObjectType, PortDirection,
+    },
     game_state::rocks::{RockGrid, RockType},
-    game_state::state::SubmarineState,
+    game_state::scenario::Scenario,
+    game_state::state::{SubmarineState, UpdateSettings},
     game_state::{
         objects::ObjectTemplate,
-        wires::{WireColor, WireGrid, WirePoints},
+        wires::{WireBridge, WireColor, WireGrid, WirePoints},
     },
     game_state::{
         state::SubmarineTemplate,
         water::{CellTemplate, WallMaterial, WaterGrid},
     },
     resources::MutableSubResources,
+    ui::WindowLayout,
+    warn,
 };
 
 pub struct SubmarineFileData {
@@ -24,13 +35,69 @@ pub struct SubmarineFileData {
     pub background: Vec<u8>,
     pub objects: Vec<u8>,
     pub wires: Vec<u8>,
+    /// Compressed bincode of the water and wire grids; when present, it is
+    /// used instead of `water_grid` and `wires`, which are left empty.
+    pub grids: Option<Vec<u8>>,
+}
+
+/// The bulky parts of a submarine, for the optional binary format.
+#[derive(Serialize, Deserialize)]
+struct GridsTemplate {
+    size: (usize, usize),
+    water_cells: Vec<CellTemplate>,
+    wire_points: Vec<WirePoints>,
+    wire_bridges: Vec<WireBridge>,
+}
+
+/// The contents of wires.yaml. Submarines without bridges keep the plain list
+/// of wire points, which is what older versions wrote and read.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum WiresTemplate {
+    Points(Vec<WirePoints>),
+    PointsAndBridges {
+        wires: Vec<WirePoints>,
+        #[serde(default)]
+        bridges: Vec<WireBridge>,
+    },
+}
+
+/// A sub-assembly of objects and wires that can be added to an existing
+/// submarine. All positions are relative to the prefab's top-left corner.
+#[derive(Serialize, Deserialize)]
+struct PrefabTemplate {
+    #[serde(default)]
+    objects: Vec<ObjectTemplate>,
+    #[serde(default)]
+    wires: Vec<WirePoints>,
+    #[serde(default)]
+    bridges: Vec<WireBridge>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Prefab {
+    pub size: (usize, usize),
+    pub objects: Vec<Object>,
+    pub wire_points: Vec<WirePoints>,
+    pub wire_bridges: Vec<WireBridge>,
 }
 
 pub(crate) fn load_template_from_data(
     file_data: SubmarineFileData,
 ) -> Result<SubmarineTemplate, String> {
-    let water_cells = load_water_cells_from_png(&file_data.water_grid)?;
-    let wire_points = load_wire_points_from_yaml(&file_data.wires)?;
+    let (water_cells, (wire_points, wire_bridges)) = if let Some(grids) = &file_data.grids {
+        let grids = load_grids_from_bin(grids)?;
+        let (width, height) = grids.size;
+        (
+            (width, height, grids.water_cells),
+            (grids.wire_points, grids.wire_bridges),
+        )
+    } else {
+        (
+            load_water_cells_from_png(&file_data.water_grid)?,
+            load_wire_points_from_yaml(&file_data.wires)?,
+        )
+    };
     let objects = load_objects_from_yaml(&file_data.objects)?;
     let background_image =
         Image::from_file_with_format(&file_data.background, Some(ImageFormat::Png));
@@ -47,15 +114,23 @@ pub(crate) fn load_template_from_data(
         background_pixels: background_image.bytes,
         objects,
         wire_points,
+        wire_bridges,
     })
 }
 
 pub(crate) fn save_to_file_data(
     submarine: &SubmarineState,
     resources: &MutableSubResources,
+    binary_grids: bool,
 ) -> Result<SubmarineFileData, String> {
-    let wires = save_wires_to_yaml(&submarine.wire_grid)?;
-    let water_grid = save_water_to_png(&submarine.water_grid)?;
+    let (water_grid, wires, grids) = if binary_grids {
+        let grids = save_grids_to_bin(&submarine.water_grid, &submarine.wire_grid)?;
+        (Vec::new(), Vec::new(), Some(grids))
+    } else {
+        let water_grid = save_water_to_png(&submarine.water_grid)?;
+        let wires = save_wires_to_yaml(&submarine.wire_grid)?;
+        (water_grid, wires, None)
+    };
     let objects = save_objects_to_yaml(&submarine.objects)?;
     let background = image_to_png(&resources.sub_background_image)?;
 
@@ -64,6 +139,7 @@ pub(crate) fn save_to_file_data(
         background,
         wires,
         objects,
+        grids,
     })
 }
 
@@ -73,11 +149,23 @@ pub(crate) fn load_from_directory(path: &str) -> Result<SubmarineFileData, Strin
             .map_err(|err| format!("Could not open file {} in {}: {}", file_name, path, err))
     };
 
+    // Submarines saved with binary grids have no water_grid.png or wires.yaml
+    if Path::new(&format!("{}/grids.bin.gz", path)).exists() {
+        return Ok(SubmarineFileData {
+            water_grid: Vec::new(),
+            background: read_file("background.png")?,
+            objects: read_file("objects.yaml")?,
+            wires: Vec::new(),
+            grids: Some(read_file("grids.bin.gz")?),
+        });
+    }
+
     Ok(SubmarineFileData {
         water_grid: read_file("water_grid.png")?,
         background: read_file("background.png")?,
         objects: read_file("objects.yaml")?,
         wires: read_file("wires.yaml")?,
+        grids: None,
     })
 }
 
@@ -86,13 +174,22 @@ pub(crate) fn save_to_directory(
     file_data: SubmarineFileData,
     overwrite: bool,
 ) -> Result<(), String> {
-    let file_names = &[
-        ("wires.yaml", &file_data.wires),
-        ("water_grid.png", &file_data.water_grid),
+    let mut file_names = vec![
         ("objects.yaml", &file_data.objects),
         ("background.png", &file_data.background),
     ];
 
+    // Loading prefers grids.bin.gz, so files from a save in the other format
+    // would shadow or outlive this one.
+    let stale_file_names: &[&str] = if let Some(grids) = &file_data.grids {
+        file_names.push(("grids.bin.gz", grids));
+        &["wires.yaml", "water_grid.png"]
+    } else {
+        file_names.push(("wires.yaml", &file_data.wires));
+        file_names.push(("water_grid.png", &file_data.water_grid));
+        &["grids.bin.gz"]
+    };
+
     if !Path::new(path).exists() {
         std::fs::create_dir(path)
             .map_err(|err| format!("Could not create directory {}: {}", path, err))?;
@@ -108,6 +205,16 @@ pub(crate) fn save_to_directory(
             .map_err(|err| format!("Could not save {} in {}: {}", file_name, path, err))?;
     }
 
+    for file_name in stale_file_names {
+        let file_path = format!("{}/{}", path, file_name);
+
+        if Path::new(&file_path).exists() {
+            std::fs::remove_file(&file_path).map_err(|err| {
+                format!("Could not remove old {} in {}: {}", file_name, path, err)
+            })?;
+        }
+    }
+
     Ok(())
 }
 
@@ -168,6 +275,64 @@ pub(crate) fn load_grid_from_bin() -> Result<WaterGrid, String> {
     Ok(grid)
 }
 
+fn save_grids_to_bin(water_grid: &WaterGrid, wire_grid: &WireGrid) -> Result<Vec<u8>, String> {
+    use flate2::{write::GzEncoder, Compression};
+
+    let (width, height) = water_grid.size();
+    let mut water_cells = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        for x in 0..width {
+            water_cells.push(cell_template(water_grid, x, y));
+        }
+    }
+
+    let grids = GridsTemplate {
+        size: (width, height),
+        water_cells,
+        wire_points: wire_grid.wire_points(),
+        wire_bridges: wire_grid.bridges(),
+    };
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    bincode::serialize_into(&mut encoder, &grids)
+        .map_err(|err| format!("Could not serialize grids: {}", err))?;
+
+    encoder
+        .finish()
+        .map_err(|err| format!("Could not compress grids: {}", err))
+}
+
+fn load_grids_from_bin(bytes: &[u8]) -> Result<GridsTemplate, String> {
+    let decoder = GzDecoder::new(bytes);
+    let reader = std::io::BufReader::new(decoder);
+
+    let grids: GridsTemplate = bincode::deserialize_from(reader)
+        .map_err(|err| format!("Could not deserialize grids: {}", err))?;
+
+    if grids.water_cells.len() != grids.size.0 * grids.size.1 {
+        return Err("Water grid size does not match its cell count.".to_string());
+    }
+
+    Ok(grids)
+}
+
+fn cell_template(grid: &WaterGrid, x: usize, y: usize) -> CellTemplate {
+    let cell = grid.cell(x, y);
+
+    if let Some(wall_material) = cell.wall_material() {
+        match wall_material {
+            WallMaterial::Normal => CellTemplate::Wall,
+            WallMaterial::Glass => CellTemplate::Glass,
+            WallMaterial::Invisible => CellTemplate::InvisibleWall,
+        }
+    } else if cell.amount_overfilled() > 0.5 {
+        CellTemplate::Sea
+    } else {
+        CellTemplate::Inside
+    }
+}
+
 pub(crate) fn save_water_to_png(grid: &WaterGrid) -> Result<Vec<u8>, String> {
     if cfg!(target_arch = "wasm32") {
         return Err("Saving not yet possible on browsers".to_string());
@@ -188,18 +353,13 @@ pub(crate) fn save_water_to_png(grid: &WaterGrid) -> Result<Vec<u8>, String> {
 
     for y in 0..height {
         for x in 0..width {
-            let cell = grid.cell(x, y);
-
-            let pixel = if let Some(wall_material) = cell.wall_material() {
-                match wall_material {
-                    WallMaterial::Normal => [255, 255, 255, 255],
-                    WallMaterial::Glass => [255, 0, 255, 255],
-                    WallMaterial::Invisible => [255, 255, 0, 255],
-                }
-            } else if cell.amount_overfilled() > 0.5 {
-                [0, 0, 255, 255]
-            } else {
-                [0, 0, 0, 0]
+            let pixel = match cell_template(grid, x, y) {
+                CellTemplate::Wall => [255, 255, 255, 255],
+                CellTemplate::Glass => [255, 0, 255, 255],
+                CellTemplate::InvisibleWall => [255, 255, 0, 255],
+                CellTemplate::Water => [0, 255, 255, 255],
+                CellTemplate::Sea => [0, 0, 255, 255],
+                CellTemplate::Inside => [0, 0, 0, 0],
             };
 
             data.extend_from_slice(&pixel);
@@ -266,18 +426,30 @@ fn load_water_cells_from_png(
     Ok((width, height, water_template))
 }
 
-fn load_wire_points_from_yaml(bytes: &[u8]) -> Result<Vec<WirePoints>, String> {
-    let wire_points: Vec<(WireColor, Vec<(usize, usize)>)> = serde_yaml::from_slice(bytes)
+fn load_wire_points_from_yaml(bytes: &[u8]) -> Result<(Vec<WirePoints>, Vec<WireBridge>), String> {
+    let wires: WiresTemplate = serde_yaml::from_slice(bytes)
         .map_err(|err| format!("Could not load wires from YAML file: {}", err))?;
 
-    Ok(wire_points)
+    Ok(match wires {
+        WiresTemplate::Points(wire_points) => (wire_points, Vec::new()),
+        WiresTemplate::PointsAndBridges { wires, bridges } => (wires, bridges),
+    })
 }
 
 fn save_wires_to_yaml(wire_grid: &WireGrid) -> Result<Vec<u8>, String> {
     let wire_points = wire_grid.wire_points();
+    let bridges = wire_grid.bridges();
+
+    let wires = if bridges.is_empty() {
+        WiresTemplate::Points(wire_points)
+    } else {
+        WiresTemplate::PointsAndBridges {
+            wires: wire_points,
+            bridges,
+        }
+    };
 
-    serde_yaml::to_vec(&wire_points)
-        .map_err(|err| format!("Error saving submarine's wire grid: {}", err))
+    serde_yaml::to_vec(&wires).map_err(|err| format!("Error saving submarine's wire grid: {}", err))
 }
 
 fn load_objects_from_yaml(object_bytes: &[u8]) -> Result<Vec<Object>, String> {
@@ -296,6 +468,820 @@ fn save_objects_to_yaml(objects: &[Object]) -> Result<Vec<u8>, String> {
     serde_yaml::to_vec(&objects).map_err(|err| format!("Error saving objects to yaml: {}", err))
 }
 
+pub(crate) fn load_update_settings_from_file(path: &str) -> Result<UpdateSettings, String> {
+    if cfg!(target_arch = "wasm32") {
+        return Err("Loading not yet possible on browsers".to_string());
+    }
+
+    let file_name = format!("{}.yaml", path);
+    let bytes = std::fs::read(&file_name)
+        .map_err(|err| format!("Could not open file {}: {}", file_name, err))?;
+
+    // Settings missing from the file keep their defaults, so older files
+    // still load after new settings are added.
+    let update_settings: UpdateSettings = serde_yaml::from_slice(&bytes)
+        .map_err(|err| format!("Error loading update settings from yaml: {}", err))?;
+
+    update_settings.validate()?;
+    Ok(update_settings)
+}
+
+pub(crate) fn save_update_settings_to_file(
+    path: &str,
+    update_settings: &UpdateSettings,
+) -> Result<(), String> {
+    if cfg!(target_arch = "wasm32") {
+        return Err("Saving not yet possible on browsers".to_string());
+    }
+
+    let file_name = format!("{}.yaml", path);
+    let bytes = serde_yaml::to_vec(update_settings)
+        .map_err(|err| format!("Error saving update settings to yaml: {}", err))?;
+
+    std::fs::write(&file_name, bytes)
+        .map_err(|err| format!("Could not save file {}: {}", file_name, err))
+}
+
+/// Kept in the working directory, like saved submarines. Browsers have no
+/// working directory, so there it's the key in their localStorage instead.
+const WINDOW_LAYOUT_FILE: &str = "window_layout.yaml";
+
+/// Returns `None` if no layout was saved yet.
+pub(crate) fn load_window_layout_from_file() -> Result<Option<WindowLayout>, String> {
+    #[cfg(target_arch = "wasm32")]
+    let bytes = match crate::local_storage::get(WINDOW_LAYOUT_FILE) {
+        Some(yaml) => yaml.into_bytes(),
+        None => return Ok(None),
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let bytes = match std::fs::read(WINDOW_LAYOUT_FILE) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(format!(
+                "Could not open file {}: {}",
+                WINDOW_LAYOUT_FILE, err
+            ))
+        }
+    };
+
+    serde_yaml::from_slice(&bytes)
+        .map(Some)
+        .map_err(|err| format!("Error loading window layout from yaml: {}", err))
+}
+
+pub(crate) fn save_window_layout_to_file(layout: &WindowLayout) -> Result<(), String> {
+    let yaml = serde_yaml::to_string(layout)
+        .map_err(|err| format!("Error saving window layout to yaml: {}", err))?;
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        crate::local_storage::set(WINDOW_LAYOUT_FILE, &yaml);
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    std::fs::write(WINDOW_LAYOUT_FILE, yaml)
+        .map_err(|err| format!("Could not save file {}: {}", WINDOW_LAYOUT_FILE, err))
+}
+
+pub(crate) fn load_scenario_from_file(path: &str) -> Result<Scenario, String> {
+    let file_name = format!("{}.yaml", path);
+    let bytes = std::fs::read(&file_name)
+        .map_err(|err| format!("Could not open file {}: {}", file_name, err))?;
+
+    serde_yaml::from_slice(&bytes)
+        .map_err(|err| format!("Error loading scenario from yaml: {}", err))
+}
+
+/// Prefix of share codes, so that other text pasted by mistake is recognized.
+/// It has to change whenever `ShareCodeTemplate` does, since bincode can't
+/// tell that an older code has a different layout.
+const SHARE_CODE_PREFIX: &str = "cybersub2:";
+
+/// Prefixes of share codes made by older versions, which can't be read any
+/// more; "cybersub1:" codes held the whole submarine template as bincode.
+const OLD_SHARE_CODE_PREFIXES: &[&str] = &["cybersub1:"];
+
+/// What a share code holds. Objects gain new fields and types over time, so
+/// they are kept as the same YAML as in objects.yaml, where missing fields
+/// get their defaults; the rest is plain grid data.
+#[derive(Serialize, Deserialize)]
+struct ShareCodeTemplate {
+    size: (usize, usize),
+    water_cells: Vec<CellTemplate>,
+    background_pixels: Vec<u8>,
+    objects: Vec<u8>,
+    wire_points: Vec<WirePoints>,
+    wire_bridges: Vec<WireBridge>,
+}
+
+/// Share codes longer than this are unwieldy to paste into chats and forums.
+pub(crate) const SHARE_CODE_WARNING_LENGTH: usize = 64 * 1024;
+
+/// Decompressed share codes larger than this are rejected instead of loaded.
+const SHARE_CODE_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn submarine_to_template(
+    submarine: &SubmarineState,
+    resources: &MutableSubResources,
+) -> SubmarineTemplate {
+    let (width, height) = submarine.water_grid.size();
+    let mut water_cells = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        for x in 0..width {
+            water_cells.push(cell_template(&submarine.water_grid, x, y));
+        }
+    }
+
+    SubmarineTemplate {
+        size: (width, height),
+        water_cells,
+        background_pixels: resources.sub_background_image.bytes.clone(),
+        objects: submarine.objects.clone(),
+        wire_points: submarine.wire_grid.wire_points(),
+        wire_bridges: submarine.wire_grid.bridges(),
+    }
+}
+
+/// Encodes a submarine as compressed bincode in base64, for copy-pasting.
+pub(crate) fn save_to_share_code(template: &SubmarineTemplate) -> Result<String, String> {
+    use flate2::{write::GzEncoder, Compression};
+
+    let share_code_template = ShareCodeTemplate {
+        size: template.size,
+        water_cells: template.water_cells.clone(),
+        background_pixels: template.background_pixels.clone(),
+        objects: save_objects_to_yaml(&template.objects)?,
+        wire_points: template.wire_points.clone(),
+        wire_bridges: template.wire_bridges.clone(),
+    };
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    bincode::serialize_into(&mut encoder, &share_code_template)
+        .map_err(|err| format!("Could not serialize submarine: {}", err))?;
+    let bytes = encoder
+        .finish()
+        .map_err(|err| format!("Could not compress submarine: {}", err))?;
+
+    Ok(format!("{}{}", SHARE_CODE_PREFIX, encode_base64(&bytes)))
+}
+
+pub(crate) fn load_from_share_code(code: &str) -> Result<SubmarineTemplate, String> {
+    // Pasted text often picks up line breaks and spaces along the way.
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+
+    if OLD_SHARE_CODE_PREFIXES
+        .iter()
+        .any(|prefix| code.starts_with(prefix))
+    {
+        return Err(
+            "This share code was made by an older version of the game, and can no longer \
+            be loaded."
+                .to_string(),
+        );
+    }
+
+    let code = code
+        .strip_prefix(SHARE_CODE_PREFIX)
+        .ok_or_else(|| "This is not a submarine share code.".to_string())?;
+    let bytes = decode_base64(code)?;
+
+    let reader = GzDecoder::new(bytes.as_slice()).take(SHARE_CODE_MAX_BYTES);
+    let share_code_template: ShareCodeTemplate = bincode::deserialize_from(reader)
+        .map_err(|err| format!("Share code is damaged or incomplete: {}", err))?;
+
+    let template = SubmarineTemplate {
+        size: share_code_template.size,
+        water_cells: share_code_template.water_cells,
+        background_pixels: share_code_template.background_pixels,
+        objects: load_objects_from_yaml(&share_code_template.objects)?,
+        wire_points: share_code_template.wire_points,
+        wire_bridges: share_code_template.wire_bridges,
+    };
+
+    check_share_code_template(&template)?;
+
+    Ok(template)
+}
+
+/// Share codes come from anyone, so check everything that would otherwise
+/// index outside of the submarine's grids.
+fn check_share_code_template(template: &SubmarineTemplate) -> Result<(), String> {
+    let (width, height) = template.size;
+    let cells = width.checked_mul(height);
+    let pixels = cells.and_then(|cells| cells.checked_mul(4));
+
+    if cells != Some(template.water_cells.len()) || pixels != Some(template.background_pixels.len())
+    {
+        return Err("Share code's grid sizes do not match the submarine size.".to_string());
+    }
+
+    for object in &template.objects {
+        let (object_width, object_height) = object_size(&object.object_type);
+        let (x, y) = (object.position.0 as usize, object.position.1 as usize);
+
+        if x + object_width > width || y + object_height > height {
+            return Err(format!(
+                "Share code has a {} at {}, {}, outside of the submarine.",
+                object_type_name(&object.object_type),
+                x,
+                y,
+            ));
+        }
+    }
+
+    for (_color, points) in &template.wire_points {
+        if let Some((x, y)) = points.iter().find(|(x, y)| *x >= width || *y >= height) {
+            return Err(format!(
+                "Share code has a wire at {}, {}, outside of the submarine.",
+                x, y,
+            ));
+        }
+    }
+
+    for (_color, (x, y)) in &template.wire_bridges {
+        if *x >= width || *y >= height {
+            return Err(format!(
+                "Share code has a wire bridge at {}, {}, outside of the submarine.",
+                x, y,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0, |group, (index, &byte)| {
+            group | (byte as u32) << (16 - index * 8)
+        });
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let sextet = (group >> (18 - index * 6)) & 0x3f;
+                encoded.push(BASE64_ALPHABET[sextet as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+fn decode_base64(encoded: &str) -> Result<Vec<u8>, String> {
+    let encoded = encoded.trim_end_matches('=').as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+
+    for chunk in encoded.chunks(4) {
+        if chunk.len() == 1 {
+            return Err("Share code has the wrong length.".to_string());
+        }
+
+        let mut group = 0;
+
+        for (index, &character) in chunk.iter().enumerate() {
+            let sextet = BASE64_ALPHABET
+                .iter()
+                .position(|&letter| letter == character)
+                .ok_or_else(|| {
+                    format!("Share code has an invalid character: {}", character as char)
+                })?;
+            group |= (sextet as u32) << (18 - index * 6);
+        }
+
+        for index in 0..chunk.len() - 1 {
+            bytes.push((group >> (16 - index * 8)) as u8);
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Pixels per cell at which wiring diagrams are shown by default; they are
+/// vectors, so this only decides their initial size.
+const DIAGRAM_PIXELS_PER_CELL: usize = 8;
+
+/// Name, stroke color, width and dash pattern of each wire color in wiring
+/// diagrams. Dashes are the same as the in-game wire patterns, in cells.
+fn diagram_wire_style(color: WireColor) -> (&'static str, &'static str, f32, &'static str) {
+    match color {
+        WireColor::Bundle => ("bundle", "#7f7f7f", 0.8, "none"),
+        WireColor::Purple => ("purple", "#a02080", 0.4, "none"),
+        WireColor::Brown => ("brown", "#8b4513", 0.4, "0.667 0.333"),
+        WireColor::Blue => ("blue", "#2050d0", 0.4, "0.167 0.167"),
+        WireColor::Green => ("green", "#20a020", 0.4, "1.333 0.333"),
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Draws a submarine's wires, objects and object ports as an SVG. One unit is
+/// one cell, with the origin on the submarine's top-left cell, the same as
+/// the in-game grid; so the diagram can be laid over a screenshot.
+pub(crate) fn save_wiring_diagram_to_svg(submarine: &SubmarineState) -> String {
+    let (width, height) = submarine.water_grid.size();
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+        viewBox=\"0 0 {} {}\">\n",
+        width * DIAGRAM_PIXELS_PER_CELL,
+        height * DIAGRAM_PIXELS_PER_CELL,
+        width,
+        height,
+    );
+
+    // Objects are drawn one cell to the right and below their position.
+    svg.push_str("<g id=\"objects\" fill=\"none\" stroke=\"#c0c0c0\" stroke-width=\"0.15\">\n");
+    for object in &submarine.objects {
+        let (object_width, object_height) = object_size(&object.object_type);
+        let name = object
+            .label
+            .as_deref()
+            .unwrap_or_else(|| object_type_name(&object.object_type));
+
+        svg.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"><title>{}</title></rect>\n",
+            object.position.0 + 1,
+            object.position.1 + 1,
+            object_width,
+            object_height,
+            escape_xml(name),
+        ));
+    }
+    svg.push_str("</g>\n");
+
+    // Wires run through the middle of their cells; bundles go first, so that
+    // thin wires on top of them stay visible.
+    svg.push_str("<g id=\"wires\" fill=\"none\" stroke-linecap=\"square\">\n");
+    for (color, points) in submarine.wire_grid.wire_points() {
+        let (first, rest) = match points.split_first() {
+            Some(split) => split,
+            None => continue,
+        };
+
+        // A single cell of wire is drawn as a zero-length line, which the
+        // square caps turn into a square.
+        let mut path = format!("M{}.5 {}.5", first.0, first.1);
+        for point in rest {
+            path.push_str(&format!(" L{}.5 {}.5", point.0, point.1));
+        }
+        if rest.is_empty() {
+            path.push_str(" h0");
+        }
+
+        let (name, stroke, stroke_width, dashes) = diagram_wire_style(color);
+        svg.push_str(&format!(
+            "<path class=\"{}\" d=\"{}\" stroke=\"{}\" stroke-width=\"{}\" \
+            stroke-dasharray=\"{}\"/>\n",
+            name, path, stroke, stroke_width, dashes,
+        ));
+    }
+    svg.push_str("</g>\n");
+
+    // Inputs are hollow, outputs filled.
+    svg.push_str("<g id=\"ports\" stroke=\"#ffd700\" stroke-width=\"0.1\">\n");
+    for object in &submarine.objects {
+        for port in object.object_type.wire_ports() {
+            let (x, y) = port_cell(object, port);
+            let (fill, direction) = match port.direction {
+                PortDirection::Input => ("none", "in"),
+                PortDirection::Output => ("#ffd700", "out"),
+            };
+
+            svg.push_str(&format!(
+                "<circle cx=\"{}.5\" cy=\"{}.5\" r=\"0.3\" fill=\"{}\">\
+                <title>{}: {} ({})</title></circle>\n",
+                x,
+                y,
+                fill,
+                escape_xml(object_type_name(&object.object_type)),
+                port.name,
+                direction,
+            ));
+        }
+    }
+    svg.push_str("</g>\n");
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Saves a wiring diagram next to saved submarines, as `<name>.svg`.
+pub(crate) fn save_wiring_diagram_to_file(
+    name: &str,
+    svg: &str,
+    overwrite: bool,
+) -> Result<(), String> {
+    let file_name = format!("{}.svg", name);
+
+    if !overwrite && Path::new(&file_name).exists() {
+        return Err(format!("Path already exists: {}", file_name));
+    }
+
+    std::fs::write(&file_name, svg)
+        .map_err(|err| format!("Could not save file {}: {}", file_name, err))
+}
+
+/// Browsers can't save files, but they can open data URLs.
+pub(crate) fn wiring_diagram_to_data_url(svg: &str) -> String {
+    format!(
+        "data:image/svg+xml;base64,{}",
+        encode_base64(svg.as_bytes())
+    )
+}
+
+/// Barotrauma positions are in pixels; this many of them make up one cell.
+const BAROTRAUMA_UNITS_PER_CELL: f32 = 8.0;
+
+/// Imported submarines wider or taller than this many cells are rejected; the
+/// biggest Barotrauma submarines are a few hundred cells long.
+const BAROTRAUMA_MAX_CELLS: usize = 4096;
+
+/// Decompressed .sub files larger than this are rejected instead of loaded.
+const BAROTRAUMA_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+pub(crate) fn load_barotrauma_from_file(path: &str) -> Result<SubmarineTemplate, String> {
+    let file_name = format!("{}.sub", path);
+    let bytes = std::fs::read(&file_name)
+        .map_err(|err| format!("Could not open file {}: {}", file_name, err))?;
+
+    load_barotrauma_sub(&bytes)
+}
+
+/// Imports a simplified subset of a Barotrauma submarine: hulls become the
+/// inside of the submarine, structures become walls, and items are replaced by
+/// the closest object type, if there is one. Wiring is not imported.
+pub(crate) fn load_barotrauma_sub(bytes: &[u8]) -> Result<SubmarineTemplate, String> {
+    // .sub files are gzipped XML, but plain XML is accepted too.
+    let xml = if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut xml = String::new();
+        GzDecoder::new(bytes)
+            .take(BAROTRAUMA_MAX_BYTES)
+            .read_to_string(&mut xml)
+            .map_err(|err| format!("Could not decompress submarine: {}", err))?;
+        xml
+    } else {
+        String::from_utf8(bytes.to_vec())
+            .map_err(|err| format!("Submarine is not valid UTF-8: {}", err))?
+    };
+
+    let mut hulls = Vec::new();
+    let mut walls = Vec::new();
+    let mut items = Vec::new();
+
+    for (tag, attributes) in xml_start_tags(&xml) {
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(key, _value)| key.eq_ignore_ascii_case(name))
+                .map(|(_key, value)| *value)
+        };
+        let rect = match attribute("rect").and_then(parse_barotrauma_rect) {
+            Some(rect) => rect,
+            None => continue,
+        };
+        let identifier = attribute("identifier").unwrap_or("").to_lowercase();
+
+        match tag {
+            "Hull" => hulls.push(rect),
+            // Background structures are only decoration, with no collision.
+            "Structure" if identifier.starts_with("bg") || identifier.contains("background") => (),
+            "Structure" => walls.push(rect),
+            "Item" => items.push((identifier, rect)),
+            _ => (),
+        }
+    }
+
+    if hulls.is_empty() {
+        return Err("No hulls found; is this a Barotrauma submarine?".to_string());
+    }
+
+    // Barotrauma's y axis points up, and rects are (left, top, width, height).
+    let rects = hulls.iter().chain(walls.iter());
+    let min_x = rects.clone().map(|r| r.0).fold(f32::MAX, f32::min);
+    let max_x = rects.clone().map(|r| r.0 + r.2).fold(f32::MIN, f32::max);
+    let max_y = rects.clone().map(|r| r.1).fold(f32::MIN, f32::max);
+    let min_y = rects.map(|r| r.1 - r.3).fold(f32::MAX, f32::min);
+
+    // A ring of sea around the submarine, so that its outer walls have an
+    // outside.
+    let margin = 2;
+    // Float to integer casts saturate, so items far outside the hulls end up
+    // on the last cell instead of wrapping around.
+    let to_cell_x =
+        |x: f32| (((x - min_x) / BAROTRAUMA_UNITS_PER_CELL) as usize).saturating_add(margin);
+    let to_cell_y =
+        |y: f32| (((max_y - y) / BAROTRAUMA_UNITS_PER_CELL) as usize).saturating_add(margin);
+
+    let width = to_cell_x(max_x).saturating_add(margin + 1);
+    let height = to_cell_y(min_y).saturating_add(margin + 1);
+
+    if width > BAROTRAUMA_MAX_CELLS || height > BAROTRAUMA_MAX_CELLS {
+        return Err(format!(
+            "Submarine is too big to import: {}x{} cells, at most {}x{} are allowed.",
+            width, height, BAROTRAUMA_MAX_CELLS, BAROTRAUMA_MAX_CELLS,
+        ));
+    }
+
+    let cells = width
+        .checked_mul(height)
+        .ok_or_else(|| "Submarine is too big to import.".to_string())?;
+    let pixels = cells
+        .checked_mul(4)
+        .ok_or_else(|| "Submarine is too big to import.".to_string())?;
+
+    let mut water_cells = vec![CellTemplate::Sea; cells];
+    let mut background_pixels = vec![0; pixels];
+
+    let mut fill = |rect: (f32, f32, f32, f32), cell_template: CellTemplate| {
+        for y in to_cell_y(rect.1)..to_cell_y(rect.1 - rect.3).min(height) {
+            for x in to_cell_x(rect.0)..to_cell_x(rect.0 + rect.2).min(width) {
+                water_cells[y * width + x] = cell_template.clone();
+
+                if let CellTemplate::Inside = cell_template {
+                    let pixel = (y * width + x) * 4;
+                    background_pixels[pixel..pixel + 4].copy_from_slice(&[40, 40, 48, 255]);
+                }
+            }
+        }
+    };
+
+    for &hull in &hulls {
+        fill(hull, CellTemplate::Inside);
+    }
+    for &wall in &walls {
+        fill(wall, CellTemplate::Wall);
+    }
+
+    let mut objects: Vec<Object> = Vec::new();
+    let mut skipped = BTreeMap::new();
+
+    for (identifier, rect) in items {
+        let object_type = match barotrauma_object_type(&identifier) {
+            Some(object_type) => object_type,
+            None => {
+                *skipped.entry(identifier).or_insert(0) += 1;
+                continue;
+            }
+        };
+
+        // Centered on the item; objects are drawn one cell to the right and
+        // below their position.
+        let (object_width, object_height) = object_size(&object_type);
+        let center_x = to_cell_x(rect.0 + rect.2 / 2.0);
+        let center_y = to_cell_y(rect.1 - rect.3 / 2.0);
+        let x = center_x.saturating_sub(object_width / 2 + 1);
+        let y = center_y.saturating_sub(object_height / 2 + 1);
+
+        if x.saturating_add(object_width + 1) > width
+            || y.saturating_add(object_height + 1) > height
+        {
+            warn!(
+                "Skipped {}, which doesn't fit in the submarine.",
+                identifier
+            );
+            continue;
+        }
+
+        let position = (x as u32, y as u32);
+
+        if overlapping_object(&objects, &object_type, position).is_some() {
+            warn!("Skipped {}, which overlaps another object.", identifier);
+            continue;
+        }
+
+        objects.push(Object {
+            object_type,
+            position,
+            powered: false,
+            label: None,
+            note: None,
+            interaction_cooldown: 0,
+        });
+    }
+
+    for (identifier, count) in skipped {
+        warn!(
+            "Skipped {} item(s) with no matching object: {}",
+            count, identifier
+        );
+    }
+
+    Ok(SubmarineTemplate {
+        size: (width, height),
+        water_cells,
+        background_pixels,
+        objects,
+        wire_points: Vec::new(),
+        wire_bridges: Vec::new(),
+    })
+}
+
+/// The object most like a Barotrauma item, by its identifier.
+fn barotrauma_object_type(identifier: &str) -> Option<ObjectType> {
+    let object_type = match identifier {
+        // Barotrauma's doors stand upright, and its hatches lie flat.
+        "door" | "windoweddoor" => ObjectType::VerticalDoor {
+            state: DoorState::Closing,
+            progress: 0,
+            required_code: None,
+            auto_close: None,
+            open_for: 0,
+        },
+        "hatch" | "windowedhatch" => ObjectType::Door {
+            state: DoorState::Closing,
+            progress: 0,
+            required_code: None,
+            auto_close: None,
+            open_for: 0,
+        },
+        "reactor1" | "outpostreactor" => ObjectType::Reactor { active: false },
+        "lamp" | "smalllamp" | "emergencylight" => ObjectType::Lamp,
+        "smallpump" => ObjectType::SmallPump {
+            target_speed: 0,
+            speed: 0,
+            progress: 0,
+        },
+        "pump" => ObjectType::LargePump {
+            target_speed: 0,
+            speed: 0,
+            progress: 0,
+        },
+        "junctionbox" => ObjectType::JunctionBox {
+            enabled: true,
+            progress: 0,
+        },
+        "navterminal" => ObjectType::NavController {
+            active: true,
+            progress: 0,
+        },
+        "sonarmonitor" => ObjectType::Sonar {
+            active: true,
+            navigation_target: None,
+        },
+        "engine" | "largeengine" => ObjectType::Engine {
+            target_speed: 0,
+            speed: 0,
+            progress: 0,
+        },
+        "battery" | "supercapacitor" => ObjectType::Battery { charge: 0 },
+        _ => return None,
+    };
+
+    Some(object_type)
+}
+
+/// Parses Barotrauma's "x,y,width,height" rects.
+fn parse_barotrauma_rect(rect: &str) -> Option<(f32, f32, f32, f32)> {
+    let mut values = rect
+        .split(',')
+        .map(|value| value.trim().parse::<f32>().ok());
+
+    match (
+        values.next()??,
+        values.next()??,
+        values.next()??,
+        values.next()??,
+    ) {
+        (x, y, width, height)
+            if x.is_finite()
+                && y.is_finite()
+                && width.is_finite()
+                && height.is_finite()
+                && width >= 0.0
+                && height >= 0.0 =>
+        {
+            Some((x, y, width, height))
+        }
+        _ => None,
+    }
+}
+
+/// Just enough of an XML reader to list every start tag with its attributes;
+/// closing tags, comments, declarations and text are skipped, and entities are
+/// left as they are.
+fn xml_start_tags(xml: &str) -> Vec<(&str, Vec<(&str, &str)>)> {
+    let mut tags = Vec::new();
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+
+        // Commented out tags are not part of the submarine.
+        if rest.starts_with("!--") {
+            rest = match rest.find("-->") {
+                Some(end) => &rest[end + 3..],
+                None => "",
+            };
+            continue;
+        }
+
+        if rest.starts_with('/') || rest.starts_with('?') || rest.starts_with('!') {
+            continue;
+        }
+
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+            .unwrap_or(rest.len());
+        let name = &rest[..name_end];
+        rest = &rest[name_end..];
+
+        let mut attributes = Vec::new();
+
+        loop {
+            rest = rest.trim_start();
+
+            if rest.is_empty() || rest.starts_with('>') || rest.starts_with("/>") {
+                break;
+            }
+
+            let equals = match rest.find('=') {
+                Some(equals) => equals,
+                None => break,
+            };
+            let key = rest[..equals].trim();
+            rest = rest[equals + 1..].trim_start();
+
+            let quote = match rest.chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => break,
+            };
+            let value_end = match rest[1..].find(quote) {
+                Some(value_end) => value_end + 1,
+                None => break,
+            };
+
+            attributes.push((key, &rest[1..value_end]));
+            rest = &rest[value_end + 1..];
+        }
+
+        tags.push((name, attributes));
+    }
+
+    tags
+}
+
+pub(crate) fn load_prefab_from_file(path: &str) -> Result<Prefab, String> {
+    let file_name = format!("{}.yaml", path);
+    let bytes = std::fs::read(&file_name)
+        .map_err(|err| format!("Could not open file {}: {}", file_name, err))?;
+
+    load_prefab_from_yaml(&bytes)
+}
+
+pub(crate) fn load_prefab_from_yaml(bytes: &[u8]) -> Result<Prefab, String> {
+    let template: PrefabTemplate = serde_yaml::from_slice(bytes)
+        .map_err(|err| format!("Error loading prefab from yaml: {}", err))?;
+
+    let objects: Vec<Object> = template
+        .objects
+        .iter()
+        .map(|object| object.to_object())
+        .collect();
+
+    // Objects are drawn one cell to the right and below their position
+    let objects_size = objects.iter().map(|object| {
+        let (width, height) = object_size(&object.object_type);
+        (
+            object.position.0 as usize + width + 1,
+            object.position.1 as usize + height + 1,
+        )
+    });
+
+    let wires_size = template
+        .wires
+        .iter()
+        .flat_map(|(_color, points)| points.iter())
+        .map(|&(x, y)| (x + 1, y + 1));
+
+    let size = objects_size
+        .chain(wires_size)
+        .fold((0, 0), |(max_x, max_y), (x, y)| {
+            (max_x.max(x), max_y.max(y))
+        });
+
+    Ok(Prefab {
+        size,
+        objects,
+        wire_points: template.wires,
+        wire_bridges: template.bridges,
+    })
+}
+
 pub(crate) fn load_rocks_from_png(bytes: &[u8]) -> RockGrid {
     let image = Image::from_file_with_format(bytes, Some(ImageFormat::Png));
     load_rocks_from_image(image)
@@ -359,3 +1345,144 @@ pub(crate) fn pixels_to_image(width: usize, height: usize, pixels: &[u8]) -> Ima
 
     image
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state::objects::ObjectTypeTemplate;
+
+    fn lamp_template() -> SubmarineTemplate {
+        let lamp = ObjectTemplate {
+            object_type: ObjectTypeTemplate::Lamp,
+            position: (1, 1),
+            label: Some("Light".to_string()),
+            note: None,
+        };
+
+        SubmarineTemplate {
+            size: (8, 6),
+            water_cells: vec![CellTemplate::Inside; 8 * 6],
+            background_pixels: vec![0; 8 * 6 * 4],
+            objects: vec![lamp.to_object()],
+            wire_points: vec![(WireColor::Purple, vec![(0, 5), (7, 5)])],
+            wire_bridges: Vec::new(),
+        }
+    }
+
+    fn assert_is_lamp_template(template: &SubmarineTemplate) {
+        assert_eq!(template.size, (8, 6));
+        assert_eq!(template.water_cells.len(), 8 * 6);
+        assert!(template.background_pixels == vec![0; 8 * 6 * 4]);
+        assert_eq!(template.objects.len(), 1);
+        assert!(template.objects[0].object_type == ObjectType::Lamp);
+        assert_eq!(template.objects[0].position, (1, 1));
+        assert_eq!(template.objects[0].label.as_deref(), Some("Light"));
+        assert!(template.wire_points == vec![(WireColor::Purple, vec![(0, 5), (7, 5)])]);
+        assert!(template.wire_bridges.is_empty());
+    }
+
+    #[test]
+    fn share_code_round_trip() {
+        let code = save_to_share_code(&lamp_template()).unwrap();
+        let template = load_from_share_code(&code).unwrap();
+
+        assert_is_lamp_template(&template);
+        assert_eq!(save_to_share_code(&template).unwrap(), code);
+    }
+
+    #[test]
+    fn share_codes_from_earlier_versions_still_load() {
+        // Made when objects were first kept as YAML in share codes; objects
+        // gaining fields or types since then must not break it.
+        let code = "cybersub2:H4sIAAAAAAAC/92RTQqAIBCFNegHTzEXGKit27bdITSkDEshN92uoyVkIN6gPhi+\
+            GRh4i9eQhyq6jaYfn4v8gz4aERmClaua/OhPpzgMYnMMwNlDe213HnYAhC6xEVKZ8KnnxTOaVFtkMWV0\
+            nd0vNz4YEbsnAgAA";
+
+        assert_is_lamp_template(&load_from_share_code(code).unwrap());
+    }
+
+    #[test]
+    fn share_codes_in_an_older_format_are_refused() {
+        let err = load_from_share_code("cybersub1:H4sIAAAAAAAA")
+            .err()
+            .unwrap();
+        assert!(err.contains("older version"));
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        for length in 0..=8 {
+            let bytes: Vec<u8> = (0..length).map(|index| (index * 37 + 250) as u8).collect();
+            let encoded = encode_base64(&bytes);
+
+            assert_eq!(encoded.len() % 4, 0);
+            assert_eq!(decode_base64(&encoded).unwrap(), bytes);
+        }
+
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(
+            decode_base64(&encode_base64(&all_bytes)).unwrap(),
+            all_bytes
+        );
+    }
+
+    #[test]
+    fn base64_matches_the_standard_alphabet() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"f"), "Zg==");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(encode_base64(b"foo"), "Zm9v");
+        assert_eq!(encode_base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(decode_base64("Zm9vYg==").unwrap(), b"foob");
+    }
+
+    #[test]
+    fn base64_rejects_damaged_codes() {
+        assert!(decode_base64("Zm9vY").is_err());
+        assert!(decode_base64("Zm9v!mFy").is_err());
+    }
+
+    #[test]
+    fn share_code_rejects_wires_outside_the_submarine() {
+        let mut template = SubmarineTemplate {
+            size: (4, 3),
+            water_cells: vec![CellTemplate::Inside; 4 * 3],
+            background_pixels: vec![0; 4 * 3 * 4],
+            objects: Vec::new(),
+            wire_points: vec![(WireColor::Purple, vec![(0, 1), (3, 1)])],
+            wire_bridges: Vec::new(),
+        };
+
+        let code = save_to_share_code(&template).unwrap();
+        assert!(load_from_share_code(&code).is_ok());
+
+        template.wire_points = vec![(WireColor::Purple, vec![(0, 1), (4, 1)])];
+        let code = save_to_share_code(&template).unwrap();
+        assert!(load_from_share_code(&code).is_err());
+    }
+
+    #[test]
+    fn wires_yaml_keeps_bridges_and_plain_crossings() {
+        let wire_points = vec![
+            (WireColor::Purple, vec![(1, 4), (8, 4)]),
+            (WireColor::Purple, vec![(4, 1), (4, 8)]),
+            (WireColor::Blue, vec![(1, 2), (6, 2), (6, 7)]),
+        ];
+        let mut wire_grid = WireGrid::from_wire_points(10, 10, &wire_points, &[]);
+
+        // A bridge on a corner, and one on a cell without a wire
+        wire_grid.toggle_bridge(6, 2, WireColor::Blue);
+        wire_grid.toggle_bridge(2, 7, WireColor::Blue);
+        assert_eq!(wire_grid.bridges().len(), 1);
+
+        let bytes = save_wires_to_yaml(&wire_grid).unwrap();
+        let (wire_points, bridges) = load_wire_points_from_yaml(&bytes).unwrap();
+        let loaded_grid = WireGrid::from_wire_points(10, 10, &wire_points, &bridges);
+
+        assert!(loaded_grid.bridges() == wire_grid.bridges());
+
+        let plain_wires = serde_yaml::to_vec(&wire_grid.wire_points()).unwrap();
+        let (_wire_points, bridges) = load_wire_points_from_yaml(&plain_wires).unwrap();
+        assert!(bridges.is_empty());
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
+    collections::BTreeMap,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         mpsc::TryRecvError,
         Arc, Mutex,
     },
@@ -9,13 +10,25 @@ use std::{
 
 use crate::client::NetEvent;
 use crate::game_state::{
+    objects::{object_size, object_type_name},
     state::GameState,
-    update::{update_game, Command, UpdateEvent},
+    update::{update_game, CellCommand, Command, CommandError, SubmarineTimings, UpdateEvent},
 };
+use crate::warn;
 use bus::{Bus, BusReader};
 use crossbeam::channel::{unbounded, Receiver, Sender};
 use quad_net::quad_socket::server::{Settings, SocketHandle};
 
+/// Commands a client sends beyond this many in a single tick are dropped, so
+/// that a flooding client can't stall the server or crowd out everyone else.
+const MAX_COMMANDS_PER_TICK: usize = 1024;
+
+/// Identifies where a command came from, so that only its sender is told when
+/// it gets rejected. The player running the server is always 0.
+type ClientId = usize;
+
+const LOCAL_CLIENT_ID: ClientId = 0;
+
 #[derive(Default)]
 struct NetState {
     local_state: Option<ClientToServer>,
@@ -30,44 +43,69 @@ struct Connection {
 }
 
 struct ClientToServer {
+    client_id: ClientId,
     receiver: Mutex<BusReader<NetEvent>>,
-    sender: Sender<NetEvent>,
+    sender: Sender<(ClientId, NetEvent)>,
 }
 
 struct ClientToServerTemplate {
     receiver_source: Arc<Mutex<Bus<NetEvent>>>,
-    sender: Sender<NetEvent>,
+    sender: Sender<(ClientId, NetEvent)>,
+    next_client_id: AtomicUsize,
 }
 
 struct ServerToClients {
-    receiver: Receiver<NetEvent>,
+    receiver: Receiver<(ClientId, NetEvent)>,
     sender: Arc<Mutex<Bus<NetEvent>>>,
 }
 
 pub(crate) struct Server {
     command_buffer: Vec<Command>,
+    /// Who sent each command in `command_buffer`.
+    command_origins: Vec<ClientId>,
+    /// How many commands each client sent this tick, including dropped ones.
+    command_counts: BTreeMap<ClientId, usize>,
     clients: ServerToClients,
     state_requested: bool,
 }
 
 pub(crate) struct LocalClient {
-    to_local_server: Sender<NetEvent>,
+    to_local_server: Sender<(ClientId, NetEvent)>,
 }
 
 impl LocalClient {
     pub fn send_commands(&mut self, commands: impl Iterator<Item = Command>) {
         for command in commands {
-            self.to_local_server.send(NetEvent::Command(command)).ok();
+            self.to_local_server
+                .send((LOCAL_CLIENT_ID, NetEvent::Command(command)))
+                .ok();
         }
     }
 }
 
 impl Server {
     pub fn relay_messages(&mut self) {
-        for message in self.clients.receiver.try_iter() {
+        for (client_id, message) in self.clients.receiver.try_iter() {
+            if let NetEvent::Command(_) = &message {
+                *self.command_counts.entry(client_id).or_insert(0) += 1;
+            }
+
             match &message {
-                NetEvent::Command(command) => self.command_buffer.push(command.clone()),
+                // Dropped commands are never broadcast, so clients still apply
+                // exactly what the server applies.
+                NetEvent::Command(_) if self.command_counts[&client_id] > MAX_COMMANDS_PER_TICK => {
+                    continue;
+                }
+                NetEvent::Command(command) if is_redundant_edit(&self.command_buffer, command) => {
+                    continue;
+                }
+                NetEvent::Command(command) => {
+                    self.command_buffer.push(command.clone());
+                    self.command_origins.push(client_id);
+                }
                 NetEvent::RequestState => self.state_requested = true,
+                // Only the server gets to send these
+                NetEvent::CommandRejected { .. } | NetEvent::TeammateAction { .. } => continue,
                 _ => (),
             }
             let mut sender = self.clients.sender.lock().unwrap();
@@ -75,11 +113,96 @@ impl Server {
         }
     }
 
-    pub fn tick(&mut self, game_state: &mut GameState, events: &mut Vec<UpdateEvent>) {
+    /// Send everyone the whole game state after the next tick, as is done when
+    /// someone joins; for when the state was replaced rather than changed by
+    /// commands.
+    ///
+    /// Commands received so far were meant for the old state, so they are
+    /// dropped, and everyone is told to end the tick they were in; clients
+    /// then only apply them to the state that is about to be replaced.
+    pub fn resend_state(&mut self) {
+        self.command_buffer.clear();
+        self.command_origins.clear();
+        self.command_counts.clear();
+
+        self.clients
+            .sender
+            .lock()
+            .unwrap()
+            .broadcast(NetEvent::Tick);
+
+        self.state_requested = true;
+    }
+
+    /// Apply the commands received so far. Rejected commands of the player
+    /// running the server are added to `rejections`; other players are sent
+    /// theirs, and only theirs.
+    pub fn tick(
+        &mut self,
+        game_state: &mut GameState,
+        events: &mut Vec<UpdateEvent>,
+        rejections: &mut Vec<String>,
+        sub_timings: &mut Vec<SubmarineTimings>,
+    ) {
+        for (client_id, count) in std::mem::take(&mut self.command_counts) {
+            if count > MAX_COMMANDS_PER_TICK {
+                warn!(
+                    "Dropped {} commands from client {} over the limit of {} per tick.",
+                    count - MAX_COMMANDS_PER_TICK,
+                    client_id,
+                    MAX_COMMANDS_PER_TICK
+                );
+            }
+        }
+
+        // Looked up before the commands are applied, while everything is
+        // still where the player who sent them saw it.
+        let mut teammate_actions = Vec::new();
+        for (command, &client_id) in self.command_buffer.iter().zip(&self.command_origins) {
+            if let Some((position, description)) = teammate_action(game_state, command) {
+                teammate_actions.push((client_id, position, description));
+            }
+        }
+
+        let mut command_errors = Vec::new();
         let commands = self.command_buffer.drain(..);
-        update_game(commands, game_state, events);
+        update_game(
+            commands,
+            game_state,
+            events,
+            &mut command_errors,
+            sub_timings,
+        );
 
         let mut sender = self.clients.sender.lock().unwrap();
+
+        for (client_id, position, description) in teammate_actions {
+            if client_id != LOCAL_CLIENT_ID {
+                events.push(UpdateEvent::TeammateAction {
+                    position,
+                    description: description.clone(),
+                });
+            }
+
+            sender.broadcast(NetEvent::TeammateAction {
+                client_id,
+                position,
+                description,
+            });
+        }
+
+        for CommandError {
+            command_index,
+            reason,
+        } in command_errors
+        {
+            match self.command_origins[command_index] {
+                LOCAL_CLIENT_ID => rejections.push(reason),
+                client_id => sender.broadcast(NetEvent::CommandRejected { client_id, reason }),
+            }
+        }
+        self.command_origins.clear();
+
         sender.broadcast(NetEvent::Tick);
 
         if self.state_requested {
@@ -91,6 +214,104 @@ impl Server {
     }
 }
 
+/// Where a command happened in the world (in 1/16 cells) and what it was, if
+/// it's something other players would want to know about. Edits are left out,
+/// since they come in a constant stream while someone is building.
+fn teammate_action(game_state: &GameState, command: &Command) -> Option<((i32, i32), String)> {
+    match command {
+        Command::Interact {
+            submarine_id,
+            object_id,
+        } => {
+            let submarine = game_state.submarines.get(*submarine_id)?;
+            let object = submarine.objects.get(*object_id)?;
+            let (width, height) = object_size(&object.object_type);
+            let name = object
+                .label
+                .as_deref()
+                .unwrap_or_else(|| object_type_name(&object.object_type));
+
+            // Objects are drawn one cell to the right and below their position.
+            let position = (
+                submarine.navigation.position.0
+                    + (object.position.0 as i32 + 1) * 16
+                    + width as i32 * 8,
+                submarine.navigation.position.1
+                    + (object.position.1 as i32 + 1) * 16
+                    + height as i32 * 8,
+            );
+
+            Some((position, format!("A teammate used the {}.", name)))
+        }
+        Command::CreateSubmarine {
+            submarine_template,
+            rock_position,
+        } => {
+            let (width, height) = submarine_template.size;
+            let position = (
+                rock_position.0 as i32 + width as i32 * 8,
+                rock_position.1 as i32 + height as i32 * 8,
+            );
+
+            Some((position, "A teammate created a submarine.".to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Whether a water or wall edit would change nothing, because the last command
+/// buffered for the same cell is the exact same edit. Held mouse drags send
+/// these on every frame.
+///
+/// Only edits that are idempotent are considered; anything else that isn't a
+/// cell command (like clearing water or removing a submarine) may change what
+/// the edit does, so it is kept.
+fn is_redundant_edit(buffered: &[Command], command: &Command) -> bool {
+    let (submarine_id, cell, cell_command) = match command {
+        Command::Cell {
+            submarine_id,
+            cell,
+            cell_command:
+                cell_command @ (CellCommand::EditWater { .. } | CellCommand::EditWalls { .. }),
+        } => (submarine_id, cell, cell_command),
+        _ => return false,
+    };
+
+    for earlier_command in buffered.iter().rev() {
+        match earlier_command {
+            Command::Cell {
+                submarine_id: earlier_submarine_id,
+                cell: earlier_cell,
+                cell_command: earlier_cell_command,
+            } => {
+                if earlier_submarine_id != submarine_id || earlier_cell != cell {
+                    continue;
+                }
+
+                return match (earlier_cell_command, cell_command) {
+                    (CellCommand::EditWater { add: a }, CellCommand::EditWater { add: b }) => {
+                        a == b
+                    }
+                    (
+                        CellCommand::EditWalls {
+                            add: a,
+                            material: material_a,
+                        },
+                        CellCommand::EditWalls {
+                            add: b,
+                            material: material_b,
+                        },
+                    ) => a == b && material_a == material_b,
+                    _ => false,
+                };
+            }
+            _ => return false,
+        }
+    }
+
+    false
+}
+
 pub(crate) fn serve(tcp_addr: String, ws_addr: String) -> (Server, LocalClient) {
     let (client_sender, client_receiver) = unbounded();
 
@@ -104,6 +325,7 @@ pub(crate) fn serve(tcp_addr: String, ws_addr: String) -> (Server, LocalClient)
     let local_server = ClientToServerTemplate {
         receiver_source: bus,
         sender: client_sender.clone(),
+        next_client_id: AtomicUsize::new(LOCAL_CLIENT_ID + 1),
     };
 
     let local_client = LocalClient {
@@ -131,6 +353,8 @@ pub(crate) fn serve(tcp_addr: String, ws_addr: String) -> (Server, LocalClient)
     let server = Server {
         clients,
         command_buffer: Vec::new(),
+        command_origins: Vec::new(),
+        command_counts: BTreeMap::new(),
         state_requested: false,
     };
 
@@ -168,15 +392,19 @@ fn local_on_message(
         Ok(message) => {
             if state.local_state.is_none() {
                 state.local_state = Some(ClientToServer {
+                    client_id: local_server.next_client_id.fetch_add(1, Ordering::Relaxed),
                     receiver: Mutex::new(local_server.receiver_source.lock().unwrap().add_rx()),
                     sender: local_server.sender.clone(),
                 });
             }
 
             let local_state = state.local_state.as_ref().unwrap();
-            local_state.sender.send(message).ok();
+            local_state
+                .sender
+                .send((local_state.client_id, message))
+                .ok();
         }
-        Err(err) => eprintln!("Message malformed: {}.", err),
+        Err(err) => warn!("Message malformed: {}.", err),
     };
 
     state.buffer.drain(0..message_size + 4);
@@ -202,6 +430,16 @@ fn on_timer(socket: &mut SocketHandle<'_>, state: &NetState) {
 
         drop(receiver_guard);
 
+        match &message {
+            NetEvent::CommandRejected { client_id, .. } if *client_id != local_state.client_id => {
+                continue;
+            }
+            NetEvent::TeammateAction { client_id, .. } if *client_id == local_state.client_id => {
+                continue;
+            }
+            _ => (),
+        }
+
         if matches!(message, NetEvent::State(_)) {
             state.received_state.store(true, Ordering::Release);
         }
@@ -225,6 +463,9 @@ fn on_timer(socket: &mut SocketHandle<'_>, state: &NetState) {
 
 fn on_disconnect(state: &NetState) {
     if let Some(local_state) = &state.local_state {
-        local_state.sender.send(NetEvent::Disconnected).ok();
+        local_state
+            .sender
+            .send((local_state.client_id, NetEvent::Disconnected))
+            .ok();
     }
 }
@@ -44,7 +44,11 @@ use std::{f32::consts::PI, fmt::Display};
 
 use macroquad::prelude::{vec2, Rect, Vec2};
 
-use crate::game_state::water::WaterGrid;
+use crate::{
+    game_state::water::WaterGrid,
+    logging::{log_enabled, LogLevel},
+    trace,
+};
 
 struct EdgeGrid {
     cells: Vec<Cell>,
@@ -490,7 +494,7 @@ pub(crate) fn find_shadow_triangles(
 
     let mut current_edges = Vec::new();
 
-    // eprintln!("Cursor: {}", cursor);
+    trace!("Cursor: {}", cursor);
 
     for (edge_index, edge) in edges.iter().enumerate() {
         // Radians go from -PI to PI, with 0 being straight up.
@@ -498,7 +502,7 @@ pub(crate) fn find_shadow_triangles(
         // straight upwards (starting point on the left and ending point on
         // the right).
         if edge.start_point.radians < 0.0 && edge.end_point.radians > 0.0 {
-            // eprintln!("Starting edge: {:?}", edge);
+            trace!("Starting edge: {:?}", edge);
             current_edges.push(edge_index);
         }
     }
@@ -517,21 +521,29 @@ pub(crate) fn find_shadow_triangles(
 
     let mut triangles = Vec::new();
 
-    // eprintln!(
-    //     "Starting with: {}",
-    //     current_edges
-    //         .iter()
-    //         .map(|i| format!("{} ({})", i, edges[*i].edge_type))
-    //         .collect::<Vec<_>>()
-    //         .join(", ")
-    // );
+    trace!(
+        "Starting with: {}",
+        current_edges
+            .iter()
+            .map(|i| format!("{} ({})", i, edges[*i].edge_type))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
 
     let mut three_points = Vec::new();
 
-    // eprintln!("Points:");
-    // for point in &points {
-    //     eprintln!(" * {} {}: {:?}", point.point, if point.starts_edge { "starts" } else { "ends" }, edges[point.edge_index]);
-    // }
+    if log_enabled(LogLevel::Trace) {
+        trace!("Points:");
+        for point in &points {
+            let starts_or_ends = if point.starts_edge { "starts" } else { "ends" };
+            trace!(
+                " * {} {}: {:?}",
+                point.point,
+                starts_or_ends,
+                edges[point.edge_index]
+            );
+        }
+    }
 
     for point in points {
         three_points.push(point.point);
@@ -541,20 +553,30 @@ pub(crate) fn find_shadow_triangles(
 
         if point.starts_edge {
             current_edges.push(point.edge_index);
-            // eprintln!("Added {} ({}): {:?}", point.edge_index, current_edges.len(), edges[point.edge_index]);
+            trace!(
+                "Added {} ({}): {:?}",
+                point.edge_index,
+                current_edges.len(),
+                edges[point.edge_index]
+            );
         } else {
             current_edges.retain(|edge_index| point.edge_index != *edge_index);
-            // eprintln!("Removed {} ({}): {:?}", point.edge_index, current_edges.len(), &edges[point.edge_index]);
+            trace!(
+                "Removed {} ({}): {:?}",
+                point.edge_index,
+                current_edges.len(),
+                &edges[point.edge_index]
+            );
         };
 
-        // eprintln!(
-        //     "Edges: {}",
-        //     current_edges
-        //         .iter()
-        //         .map(|i| format!("{} ({})", i, edges[*i].edge_type))
-        //         .collect::<Vec<_>>()
-        //         .join(", ")
-        // );
+        trace!(
+            "Edges: {}",
+            current_edges
+                .iter()
+                .map(|i| format!("{} ({})", i, edges[*i].edge_type))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
 
         // Calculate the distance of other edges' intersections on this ray
         for &edge_index in &current_edges {
@@ -571,12 +593,12 @@ pub(crate) fn find_shadow_triangles(
             .expect("Should have at least border edge lines");
 
         if point.starts_edge {
-            // eprintln!(
-            //     "[{}] Closest on add: {}/{}",
-            //     point.edge_index,
-            //     closest_edge,
-            //     edges.len()
-            // );
+            trace!(
+                "[{}] Closest on add: {}/{}",
+                point.edge_index,
+                closest_distance,
+                point_distance
+            );
 
             if point_distance <= closest_distance + 0.1 {
                 let next_closest = current_edges
@@ -593,10 +615,12 @@ pub(crate) fn find_shadow_triangles(
                 last_point = point.point;
             }
         } else {
-            // eprintln!(
-            //     "[{}] Closest on remove: {}/{}",
-            //     point.edge_index, next_closest_distance, point_distance
-            // );
+            trace!(
+                "[{}] Closest on remove: {}/{}",
+                point.edge_index,
+                closest_distance,
+                point_distance
+            );
 
             let was_closest = point_distance <= closest_distance + 0.1;
 
@@ -1,62 +1,249 @@
+use std::{cmp::Ordering, collections::BTreeMap};
+
 use egui::{
     plot::{Line, Plot, Value, Values},
-    vec2, Align2, Button, Color32, Label, Slider, Ui,
+    vec2, Align2, Button, Color32, Label, Pos2, Sense, Slider, TextEdit, Ui, Vec2,
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    app::{GameSettings, NetworkSettings, PlacingObject, Tool},
+    app::{GameSettings, HighlightedType, NetworkSettings, PlacingObject, Tool},
+    console::run_console_command,
     draw::DrawSettings,
-    game_state::objects::{compute_navigation, OBJECT_TYPES},
+    game_state::objects::{
+        compute_navigation, door_auto_close, door_code, keypad_code, object_category, object_size,
+        object_type_name, port_connected, pump_flow_rate, signal_map_ranges, unfed_wire_networks,
+        Object, PortDirection, UnfedWireNetwork, OBJECT_CATEGORIES, OBJECT_TYPES,
+    },
     game_state::state::{GameState, UpdateSettings},
-    game_state::update::Command,
-    game_state::wires::WireColor,
+    game_state::update::{compute_buoyancy, CellCommand, Command},
+    game_state::water::WallMaterial,
+    game_state::wires::{WireColor, THIN_COLORS},
+    input::{
+        apply_zoom_preset, plan_bus_wires, toggle_all_draw_layers, DRAW_LAYER_NAMES, LAYER_HOTKEYS,
+        ZOOM_PRESETS,
+    },
+    logging::{log_level, set_log_level, LOG_LEVELS},
     resources::MutableSubResources,
     saveload::{
-        load_from_directory, load_template_from_data, save_to_directory, save_to_file_data,
+        load_barotrauma_from_file, load_from_directory, load_from_share_code,
+        load_prefab_from_file, load_scenario_from_file, load_template_from_data,
+        load_update_settings_from_file, save_to_directory, save_to_file_data, save_to_share_code,
+        save_update_settings_to_file, save_window_layout_to_file, save_wiring_diagram_to_file,
+        save_wiring_diagram_to_svg, submarine_to_template, wiring_diagram_to_data_url,
+        SHARE_CODE_WARNING_LENGTH,
     },
-    Timings,
+    warn, Timings,
 };
 
 pub(crate) struct UiState {
     error_message: Option<String>,
+    teammate_notice: Option<TeammateNotice>,
     show_total_water: bool,
     show_bars: bool,
     show_main_settings: bool,
     show_toolbar: bool,
     show_help: bool,
     show_timings: bool,
+    show_systems_overview: bool,
     show_navigation_info: bool,
+    show_scenario: bool,
     show_draw_settings: bool,
     show_update_settings: bool,
+    show_console: bool,
     show_load_dialog: bool,
+    show_load_prefab_dialog: bool,
     show_save_dialog: bool,
     show_host_dialog: bool,
     show_join_dialog: bool,
     submarine_name: String,
+    prefab_name: String,
+    update_settings_name: String,
+    scenario_name: String,
     overwrite_save: bool,
+    binary_grids: bool,
+    share_code: String,
+    object_filter: String,
+    /// The submarine last checked for unfed wires, and what was found.
+    wiring_check: Option<(usize, Vec<UnfedWireNetwork>)>,
+    teleport_position: (i32, i32),
+    label_edit: String,
+    note_edit: String,
+    /// The object whose label and note are being edited.
+    label_edit_object: Option<(usize, usize)>,
+    console_input: String,
+    /// What the console printed, oldest first.
+    console_output: Vec<String>,
+    /// Top-left corner of each window that remembers where it was, by title.
+    window_positions: BTreeMap<String, (f32, f32)>,
+    /// The layout last saved to or loaded from the settings file.
+    saved_window_layout: Option<WindowLayout>,
+}
+
+/// The last thing a teammate did out of view.
+struct TeammateNotice {
+    description: String,
+    /// Where it happened in the world, in 1/16 cells.
+    position: (i32, i32),
+    shown_at: f64,
+    /// How many more actions happened since, without replacing this one.
+    more: u32,
+}
+
+/// A teammate's action replaces the one being shown at most this often, in
+/// seconds; the ones in between are only counted.
+const TEAMMATE_NOTICE_INTERVAL: f64 = 5.0;
+
+/// Updates a door stays open for when its auto-close is first turned on; 10
+/// seconds at 60 updates per second.
+const DEFAULT_DOOR_AUTO_CLOSE: u16 = 600;
+
+/// Simulation speeds offered as buttons, in quarters of the normal speed.
+const SIM_SPEED_PRESETS: [(u8, &str); 5] =
+    [(1, "0.25×"), (2, "0.5×"), (4, "1×"), (8, "2×"), (16, "4×")];
+
+/// Which windows are open and where they are, kept across sessions.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub(crate) struct WindowLayout {
+    open_windows: BTreeMap<String, bool>,
+    /// Top-left corner of each window, by title, in egui points.
+    positions: BTreeMap<String, (f32, f32)>,
 }
 
+/// Restored windows keep at least this much of their top-left corner on
+/// screen, so that a window saved on a larger screen can still be dragged.
+const MIN_VISIBLE_WINDOW: f32 = 64.0;
+
+/// The console forgets its oldest lines past this many.
+const CONSOLE_MAX_LINES: usize = 500;
+
 impl Default for UiState {
     fn default() -> Self {
         Self {
             error_message: None,
+            teammate_notice: None,
             show_total_water: false,
             show_bars: true,
             show_main_settings: true,
             show_toolbar: true,
             show_help: false,
             show_timings: false,
+            show_systems_overview: false,
             show_navigation_info: false,
+            show_scenario: false,
             show_draw_settings: false,
             show_update_settings: false,
+            show_console: false,
             show_load_dialog: false,
+            show_load_prefab_dialog: false,
             show_save_dialog: false,
             show_host_dialog: false,
             show_join_dialog: false,
             submarine_name: "NewSubmarine".to_string(),
+            prefab_name: "NewPrefab".to_string(),
+            update_settings_name: "update_settings".to_string(),
+            scenario_name: "scenario".to_string(),
             overwrite_save: false,
+            binary_grids: false,
+            share_code: String::new(),
+            object_filter: String::new(),
+            wiring_check: None,
+            teleport_position: (0, 0),
+            label_edit: String::new(),
+            note_edit: String::new(),
+            label_edit_object: None,
+            console_input: String::new(),
+            console_output: vec!["Type help for a list of commands.".to_string()],
+            window_positions: BTreeMap::new(),
+            saved_window_layout: None,
+        }
+    }
+}
+
+impl UiState {
+    pub(crate) fn show_error(&mut self, message: String) {
+        self.error_message = Some(message);
+    }
+
+    pub(crate) fn notify_teammate_action(
+        &mut self,
+        position: (i32, i32),
+        description: String,
+        time: f64,
+    ) {
+        match &mut self.teammate_notice {
+            Some(notice) if time - notice.shown_at < TEAMMATE_NOTICE_INTERVAL => {
+                notice.more += 1;
+            }
+            teammate_notice => {
+                *teammate_notice = Some(TeammateNotice {
+                    description,
+                    position,
+                    shown_at: time,
+                    more: 0,
+                });
+            }
+        }
+    }
+
+    /// Visibility flags of the windows and bars kept across sessions; dialogs
+    /// always start closed.
+    fn window_flags(&mut self) -> [(&'static str, &mut bool); 12] {
+        [
+            ("bars", &mut self.show_bars),
+            ("toolbar", &mut self.show_toolbar),
+            ("main_settings", &mut self.show_main_settings),
+            ("total_water", &mut self.show_total_water),
+            ("help", &mut self.show_help),
+            ("timings", &mut self.show_timings),
+            ("systems_overview", &mut self.show_systems_overview),
+            ("navigation_info", &mut self.show_navigation_info),
+            ("scenario", &mut self.show_scenario),
+            ("draw_settings", &mut self.show_draw_settings),
+            ("update_settings", &mut self.show_update_settings),
+            ("console", &mut self.show_console),
+        ]
+    }
+
+    pub(crate) fn restore_window_layout(&mut self, layout: WindowLayout) {
+        for (name, open) in self.window_flags() {
+            if let Some(saved_open) = layout.open_windows.get(name) {
+                *open = *saved_open;
+            }
+        }
+
+        self.window_positions = layout.positions.clone();
+        self.saved_window_layout = Some(layout);
+    }
+
+    fn window_layout(&mut self) -> WindowLayout {
+        let open_windows = self
+            .window_flags()
+            .iter()
+            .map(|(name, open)| (name.to_string(), **open))
+            .collect();
+
+        WindowLayout {
+            open_windows,
+            positions: self.window_positions.clone(),
         }
     }
+
+    fn save_window_layout(&mut self) {
+        let layout = self.window_layout();
+
+        if self.saved_window_layout.as_ref() == Some(&layout) {
+            return;
+        }
+
+        // Not worth interrupting anyone with an error window over this
+        if let Err(err) = save_window_layout_to_file(&layout) {
+            warn!("Could not save window layout: {}", err);
+        }
+
+        self.saved_window_layout = Some(layout);
+    }
 }
 
 /// Called each time the UI needs repainting, which may be many times per second.
@@ -72,21 +259,41 @@ pub(crate) fn draw_ui(
 ) {
     let UiState {
         error_message,
+        teammate_notice,
         show_total_water,
         show_bars,
         show_toolbar,
         show_main_settings,
         show_help,
         show_timings,
+        show_systems_overview,
         show_navigation_info,
+        show_scenario,
         show_draw_settings,
         show_update_settings,
+        show_console,
         show_load_dialog,
+        show_load_prefab_dialog,
         show_save_dialog,
         show_host_dialog,
         show_join_dialog,
         submarine_name,
+        prefab_name,
+        update_settings_name,
+        scenario_name,
         overwrite_save,
+        binary_grids,
+        share_code,
+        object_filter,
+        wiring_check,
+        teleport_position,
+        label_edit,
+        note_edit,
+        label_edit_object,
+        console_input,
+        console_output,
+        window_positions,
+        saved_window_layout: _,
     } = ui_state;
 
     let GameSettings {
@@ -96,13 +303,21 @@ pub(crate) fn draw_ui(
         current_submarine,
         current_tool,
         quit_game,
+        reset_world,
         submarine_templates,
+        prefabs,
+        inspected_object,
+        selection,
+        flashing_wires,
+        highlighted_type,
         ..
     } = settings;
 
     let GameState {
         submarines,
         update_settings,
+        scenario,
+        rock_grid,
         ..
     } = state;
 
@@ -110,19 +325,46 @@ pub(crate) fn draw_ui(
         draw_egui,
         draw_sea_dust,
         draw_sea_caustics,
+        sea_dust_density,
+        precise_sea_dust,
         draw_rocks,
         draw_background,
         draw_objects,
+        draw_object_labels,
+        draw_ports,
         draw_walls,
         draw_wires,
+        colorblind_wires,
+        wire_patterns,
+        animate_signals,
+        smooth_wires,
+        wires_above_objects,
         draw_water,
+        smooth_water,
         draw_sonar,
         draw_engine_turbulence,
+        max_turbulence_particles,
         draw_shadows,
+        depth_darkening,
+        day_length,
+        outline_current_submarine,
+        solo_inspected_object,
+        camera_momentum,
+        camera_pan_speed,
+        invert_pan_x,
+        invert_pan_y,
+        interact_radius,
         debug_shadows,
+        debug_cell_types,
+        trace_leaks,
+        skip_offscreen_submarines,
+        level_of_detail,
+        simplified_objects,
+        layer_hotkeys,
     } = draw_settings;
 
     let mut new_update_settings = update_settings.clone();
+    let mut loaded_update_settings = None;
 
     let UpdateSettings {
         update_water,
@@ -133,6 +375,24 @@ pub(crate) fn draw_ui(
         update_objects,
         update_position,
         update_collision,
+        collision_response,
+        collision_restitution,
+        collision_separation,
+        docking_proximity,
+        docking_snap,
+        docking_pull_speed,
+        radio_range,
+        wire_decay,
+        max_wire_range,
+        wire_iterations,
+        water_inertia,
+        water_sloshing,
+        water_resolution,
+        water_equalization,
+        update_temperature,
+        max_submarines,
+        sim_speed,
+        interaction_cooldown,
     } = &mut new_update_settings;
 
     let NetworkSettings {
@@ -147,6 +407,7 @@ pub(crate) fn draw_ui(
         network_status,
         network_error,
         download_progress,
+        notify_teammate_actions,
     } = network_settings;
 
     if *show_bars {
@@ -156,23 +417,38 @@ pub(crate) fn draw_ui(
                     if ui.button("Load submarine").clicked() {
                         *show_load_dialog = true;
                     }
+                    if ui.button("Load prefab").clicked() {
+                        *show_load_prefab_dialog = true;
+                    }
                     if submarines.len() > *current_submarine {
-                        ui.scope(|ui| {
-                            ui.set_enabled(!cfg!(target_arch = "wasm32"));
-                            if ui
-                                .button("Save submarine")
-                                .on_disabled_hover_text("Not available on browsers")
-                                .clicked()
-                            {
-                                *show_save_dialog = true;
-                            }
-                        });
+                        if ui.button("Save submarine").clicked() {
+                            *show_save_dialog = true;
+                        }
 
                         if ui.button("Clear water").clicked() {
                             commands.push(Command::ClearWater {
                                 submarine_id: *current_submarine,
                             });
                         }
+                        if ui
+                            .button("Clear objects")
+                            .on_hover_text("Remove every object from the submarine")
+                            .clicked()
+                        {
+                            commands.push(Command::ClearObjects {
+                                submarine_id: *current_submarine,
+                            });
+                        }
+
+                        if ui
+                            .button("Validate wiring")
+                            .on_hover_text("List wires that nothing sends a signal into")
+                            .clicked()
+                        {
+                            let submarine = &submarines[*current_submarine];
+                            *wiring_check =
+                                Some((*current_submarine, unfed_wire_networks(submarine)));
+                        }
                     } else {
                         ui.label("<no submarine selected>");
                     }
@@ -186,6 +462,19 @@ pub(crate) fn draw_ui(
                         *show_help = true;
                     }
                     ui.separator();
+                    let is_client = *client_connected;
+                    let reset_button = Button::new("Reset world").enabled(!is_client);
+                    if ui
+                        .add(reset_button)
+                        .on_hover_text(if is_client {
+                            "Only the host can reset the world"
+                        } else {
+                            "Start over with the world and submarines the game started with"
+                        })
+                        .clicked()
+                    {
+                        *reset_world = true;
+                    }
                     if ui.button("Quit").clicked() {
                         *quit_game = true;
                     }
@@ -209,17 +498,68 @@ pub(crate) fn draw_ui(
                     if ui.button("Show timings").clicked() {
                         *show_timings = !*show_timings;
                     }
+                    if ui.button("Show systems overview").clicked() {
+                        *show_systems_overview = !*show_systems_overview;
+                    }
+                    if ui.button("Show scenario").clicked() {
+                        *show_scenario = !*show_scenario;
+                    }
                 });
                 egui::menu::menu(ui, "Objects", |ui| {
-                    for (object_type_name, object_type) in OBJECT_TYPES {
-                        if ui.button(object_type_name).clicked() {
-                            *current_tool = Tool::PlaceObject(PlacingObject {
-                                submarine: 0,
-                                position: None,
-                                object_type: object_type.clone(),
-                            });
+                    // Focused right away, since clicking anywhere in the menu
+                    // closes it; while focused, egui keeps the keys from
+                    // moving the camera.
+                    ui.add(TextEdit::singleline(object_filter).hint_text("Filter"))
+                        .request_focus();
+
+                    let filter = object_filter.to_lowercase();
+                    let mut any_shown = false;
+
+                    for (category_name, category) in OBJECT_CATEGORIES {
+                        let mut object_types = OBJECT_TYPES
+                            .iter()
+                            .filter(|(_name, object_type)| {
+                                object_category(object_type) == *category
+                            })
+                            .filter(|(name, _object_type)| name.to_lowercase().contains(&filter))
+                            .peekable();
+
+                        if object_types.peek().is_none() {
+                            continue;
+                        }
+
+                        ui.separator();
+                        ui.colored_label(Color32::GRAY, *category_name);
+                        any_shown = true;
+
+                        for (object_type_name, object_type) in object_types {
+                            let button = ui
+                                .button(object_type_name)
+                                .on_hover_text("Shift+click to highlight all of them instead");
+
+                            if button.clicked() && ui.input().modifiers.shift {
+                                *highlighted_type = Some(HighlightedType {
+                                    submarine: *current_submarine,
+                                    object_type: object_type.clone(),
+                                    tool: current_tool.clone(),
+                                });
+                                object_filter.clear();
+                            } else if button.clicked() {
+                                *current_tool = Tool::PlaceObject(PlacingObject {
+                                    submarine: 0,
+                                    position: None,
+                                    object_type: object_type.clone(),
+                                    snapped: false,
+                                });
+                                *highlighted_type = None;
+                                object_filter.clear();
+                            }
                         }
                     }
+
+                    if !any_shown {
+                        ui.colored_label(Color32::GRAY, "No matching objects");
+                    }
                 });
                 egui::menu::menu(ui, "Submarines", |ui| {
                     for (template_id, (name, _)) in submarine_templates.iter().enumerate() {
@@ -230,6 +570,46 @@ pub(crate) fn draw_ui(
                             }
                         }
                     }
+                    ui.separator();
+                    ui.scope(|ui| {
+                        ui.set_enabled(submarines.len() > *current_submarine);
+                        if ui
+                            .button("Remove current submarine")
+                            .on_disabled_hover_text("No submarine selected")
+                            .clicked()
+                        {
+                            commands.push(Command::RemoveSubmarine {
+                                submarine_id: *current_submarine,
+                            });
+                        }
+                        if ui
+                            .button("Mirror current submarine")
+                            .on_hover_text(
+                                "Flip it left to right; objects keep facing the same way, \
+                                so it is refused while that would pull a port off its wire",
+                            )
+                            .on_disabled_hover_text("No submarine selected")
+                            .clicked()
+                        {
+                            commands.push(Command::MirrorSubmarine {
+                                submarine_id: *current_submarine,
+                            });
+                        }
+                    });
+                });
+                egui::menu::menu(ui, "Prefabs", |ui| {
+                    if prefabs.is_empty() {
+                        ui.label("<no prefabs loaded>");
+                    }
+                    for (prefab_id, (name, _)) in prefabs.iter().enumerate() {
+                        if ui.button(name).clicked() {
+                            *current_tool = Tool::PlacePrefab {
+                                prefab_id,
+                                submarine: 0,
+                                position: None,
+                            }
+                        }
+                    }
                 });
                 egui::menu::menu(ui, "Network", |ui| {
                     ui.scope(|ui| {
@@ -265,12 +645,15 @@ pub(crate) fn draw_ui(
                         "All submarines should have their own MutableSubResources instance",
                     );
 
-                    if let Some(cursor_tile) = mutable_resources.sub_cursor_tile {
-                        ui.label("x:".to_string());
-                        ui.colored_label(Color32::GREEN, cursor_tile.0.to_string());
-                        ui.label("y:".to_string());
-                        ui.colored_label(Color32::GREEN, cursor_tile.1.to_string());
-                    }
+                    // Shown even off-grid, so that the panel doesn't jump around.
+                    let (cursor_x, cursor_y, color) = match mutable_resources.sub_cursor_tile {
+                        Some((x, y)) => (x.to_string(), y.to_string(), Color32::GREEN),
+                        None => ("—".to_string(), "—".to_string(), Color32::GRAY),
+                    };
+                    ui.label("x:".to_string());
+                    ui.colored_label(color, cursor_x);
+                    ui.label("y:".to_string());
+                    ui.colored_label(color, cursor_y);
 
                     ui.label("speed:".to_string());
                     ui.colored_label(Color32::YELLOW, submarine.navigation.speed.0.to_string());
@@ -294,6 +677,34 @@ pub(crate) fn draw_ui(
                             "Total water: {}",
                             submarine.water_grid.total_water()
                         ));
+                        ui.label(format!(
+                            "Average temperature: {}°C",
+                            submarine.water_grid.average_temperature()
+                        ));
+
+                        let water_grid = &submarine.water_grid;
+                        ui.label(format!("Compartments: {}", water_grid.compartments().len()));
+
+                        let cursor_compartment = mutable_sub_resources
+                            .get(*current_submarine)
+                            .and_then(|resources| resources.sub_cursor_tile)
+                            .and_then(|(x, y)| water_grid.compartment_at(x, y));
+
+                        if let Some(id) = cursor_compartment {
+                            let totals = water_grid.compartment_totals();
+
+                            if let Some(totals) = totals.iter().find(|totals| totals.id == id) {
+                                let capacity = (totals.cells as u64 * 1024).max(1);
+                                let flooding = totals.water as u64 * 100 / capacity;
+
+                                ui.label(format!(
+                                    "Compartment #{}: {} cells, {}% flooded",
+                                    id,
+                                    totals.cells,
+                                    flooding.min(100),
+                                ));
+                            }
+                        }
                     }
                 }
             });
@@ -334,10 +745,99 @@ pub(crate) fn draw_ui(
                         };
                         *show_load_dialog = false;
                     }
+
+                    let import_button =
+                        Button::new("Import Barotrauma sub").enabled(!submarine_name.is_empty());
+
+                    if ui
+                        .add(import_button)
+                        .on_hover_text("Import hulls, walls and some items from a .sub file")
+                        .clicked()
+                    {
+                        let mut import = || {
+                            if cfg!(target_arch = "wasm32") {
+                                Err("Not yet implemented on browsers".to_string())
+                            } else {
+                                let template = load_barotrauma_from_file(submarine_name)?;
+                                submarine_templates.push((submarine_name.to_owned(), template));
+                                Ok(())
+                            }
+                        };
+
+                        *error_message = if let Err(err) = import() {
+                            Some(err)
+                        } else {
+                            Some(format!(
+                                "Template '{}' imported into Submarines menu.",
+                                submarine_name
+                            ))
+                        };
+                        *show_load_dialog = false;
+                    }
                     if ui.button("Cancel").clicked() {
                         *show_load_dialog = false;
                     }
                 });
+
+                ui.separator();
+
+                ui.label("Or paste a share code:");
+                ui.add(TextEdit::multiline(share_code).desired_rows(3));
+
+                let code_button = Button::new("Load from code").enabled(!share_code.is_empty());
+
+                if ui.add(code_button).clicked() {
+                    *error_message = match load_from_share_code(share_code) {
+                        Ok(template) => {
+                            submarine_templates.push((submarine_name.to_owned(), template));
+                            share_code.clear();
+                            *show_load_dialog = false;
+                            Some(format!(
+                                "Template '{}' added to Submarines menu.",
+                                submarine_name
+                            ))
+                        }
+                        Err(err) => Some(err),
+                    };
+                }
+            });
+    }
+
+    if *show_load_prefab_dialog {
+        egui::Window::new("Load prefab")
+            .anchor(Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(prefab_name);
+                    ui.label(".yaml");
+                });
+
+                ui.horizontal(|ui| {
+                    let load_button = Button::new("Load").enabled(!prefab_name.is_empty());
+
+                    if ui.add(load_button).clicked() {
+                        let mut load = || {
+                            if cfg!(target_arch = "wasm32") {
+                                Err("Not yet implemented on browsers".to_string())
+                            } else {
+                                let prefab = load_prefab_from_file(prefab_name)?;
+                                prefabs.push((prefab_name.to_owned(), prefab));
+                                Ok(())
+                            }
+                        };
+
+                        *error_message = if let Err(err) = load() {
+                            Some(err)
+                        } else {
+                            Some(format!("Prefab '{}' added to Prefabs menu.", prefab_name))
+                        };
+                        *show_load_prefab_dialog = false;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        *show_load_prefab_dialog = false;
+                    }
+                });
             });
     }
 
@@ -351,17 +851,24 @@ pub(crate) fn draw_ui(
                 });
 
                 ui.checkbox(overwrite_save, "Overwrite existing files");
+                ui.checkbox(binary_grids, "Save water and wire grids in binary format")
+                    .on_hover_text("Smaller and faster to load, but not human-readable. Objects are still saved as YAML.");
 
                 ui.horizontal(|ui| {
-                    let save_button = Button::new("Save").enabled(!submarine_name.is_empty());
+                    let save_button = Button::new("Save")
+                        .enabled(!submarine_name.is_empty() && !cfg!(target_arch = "wasm32"));
 
-                    if ui.add(save_button).clicked() {
+                    if ui
+                        .add(save_button)
+                        .on_disabled_hover_text("Saving files is not available on browsers")
+                        .clicked()
+                    {
                         let submarine = submarines.get(*current_submarine);
                         let resources = mutable_sub_resources.get(*current_submarine);
 
                         if let (Some(submarine), Some(resources)) = (submarine, resources) {
                             let save = || {
-                                let file_data = save_to_file_data(submarine, resources)?;
+                                let file_data = save_to_file_data(submarine, resources, *binary_grids)?;
                                 save_to_directory(submarine_name, file_data, *overwrite_save)
                             };
 
@@ -376,8 +883,75 @@ pub(crate) fn draw_ui(
                     }
                     if ui.button("Cancel").clicked() {
                         *show_save_dialog = false;
+                        share_code.clear();
                     }
                 });
+
+                ui.separator();
+
+                if ui
+                    .button("Copy share code")
+                    .on_hover_text("Encode the submarine as text that can be pasted into the Load dialog")
+                    .clicked()
+                {
+                    let submarine = submarines.get(*current_submarine);
+                    let resources = mutable_sub_resources.get(*current_submarine);
+
+                    if let (Some(submarine), Some(resources)) = (submarine, resources) {
+                        let template = submarine_to_template(submarine, resources);
+
+                        match save_to_share_code(&template) {
+                            Ok(code) => {
+                                ctx.output().copied_text = code.clone();
+                                *share_code = code;
+                            }
+                            Err(err) => *error_message = Some(err),
+                        }
+                    } else {
+                        *error_message = Some("No submarine selected.".to_string());
+                    }
+                }
+
+                if !share_code.is_empty() {
+                    ui.label(format!("Copied {} characters to the clipboard.", share_code.len()));
+
+                    if share_code.len() > SHARE_CODE_WARNING_LENGTH {
+                        ui.colored_label(
+                            Color32::YELLOW,
+                            "This code is large; consider saving to files instead.",
+                        );
+                    }
+
+                    ui.add(TextEdit::multiline(share_code).desired_rows(3));
+                }
+
+                ui.separator();
+
+                let export_hover_text = if cfg!(target_arch = "wasm32") {
+                    "Copy the wires and object ports as an SVG data URL, to open in a new tab"
+                } else {
+                    "Save the wires and object ports as an SVG file, named after the submarine"
+                };
+                let export_button = Button::new("Export wiring diagram")
+                    .enabled(!submarine_name.is_empty() || cfg!(target_arch = "wasm32"));
+
+                if ui.add(export_button).on_hover_text(export_hover_text).clicked() {
+                    if let Some(submarine) = submarines.get(*current_submarine) {
+                        let svg = save_wiring_diagram_to_svg(submarine);
+
+                        if cfg!(target_arch = "wasm32") {
+                            ctx.output().copied_text = wiring_diagram_to_data_url(&svg);
+                            *error_message =
+                                Some("Copied the wiring diagram to the clipboard.".to_string());
+                        } else if let Err(err) =
+                            save_wiring_diagram_to_file(submarine_name, &svg, *overwrite_save)
+                        {
+                            *error_message = Some(err);
+                        }
+                    } else {
+                        *error_message = Some("No submarine selected.".to_string());
+                    }
+                }
             });
     }
 
@@ -402,6 +976,10 @@ pub(crate) fn draw_ui(
             ui.separator();
 
             ui.label(format!("Status: {}", network_status));
+            ui.checkbox(notify_teammate_actions, "Notify me of teammate actions")
+                .on_hover_text(
+                    "Tell me when other players use objects or create submarines out of view",
+                );
             if ui.button("Close").clicked() {
                 *show_host_dialog = false;
             }
@@ -456,6 +1034,10 @@ pub(crate) fn draw_ui(
                     ui.colored_label(Color32::RED, error.as_str());
                 });
             }
+            ui.checkbox(notify_teammate_actions, "Notify me of teammate actions")
+                .on_hover_text(
+                    "Tell me when other players use objects or create submarines out of view",
+                );
             if ui.button("Close").clicked() {
                 *show_join_dialog = false;
             }
@@ -469,13 +1051,14 @@ pub(crate) fn draw_ui(
     }
 
     if *show_main_settings {
-        egui::Window::new("Settings").show(ctx, |ui| {
+        show_remembered_window(ctx, "Settings", window_positions, |ui| {
             ui.collapsing("Show windows", |ui| {
                 ui.checkbox(show_toolbar, "Show toolbar");
                 ui.checkbox(show_main_settings, "Show main settings");
                 ui.checkbox(show_navigation_info, "Show navigation info");
                 ui.checkbox(show_draw_settings, "Show draw settings");
                 ui.checkbox(show_update_settings, "Show update settings");
+                ui.checkbox(show_console, "Show console");
                 ui.checkbox(show_timings, "Show timings");
             });
             ui.collapsing("Performance settings", |ui| {
@@ -486,10 +1069,48 @@ pub(crate) fn draw_ui(
                 ui.checkbox(draw_egui, "Draw UI")
                     .on_hover_text("Click the top-left gear button to re-enable the UI");
             });
+            ui.collapsing("Log level", |ui| {
+                let mut level = log_level();
+                for &option in &LOG_LEVELS {
+                    ui.radio_value(&mut level, option, format!("{:?}", option));
+                }
+                if level != log_level() {
+                    set_log_level(level);
+                }
+            });
             ui.horizontal(|ui| {
                 ui.label("Zoom:");
                 ui.add(Slider::new(&mut camera.zoom, -512..=36));
             });
+            ui.horizontal(|ui| {
+                for (index, (name, zoom_preset)) in ZOOM_PRESETS.iter().enumerate() {
+                    let shortcut = format!("Shortcut: {}", index + 1);
+                    if ui.button(*name).on_hover_text(shortcut).clicked() {
+                        apply_zoom_preset(
+                            camera,
+                            *zoom_preset,
+                            submarines,
+                            *current_submarine,
+                            rock_grid.size(),
+                        );
+                    }
+                }
+            });
+            ui.scope(|ui| {
+                let submarine = submarines.get(*current_submarine);
+                ui.set_enabled(submarine.is_some());
+
+                if ui
+                    .button("Center on submarine")
+                    .on_hover_text("Shortcut: Home")
+                    .on_disabled_hover_text("No submarine selected")
+                    .clicked()
+                {
+                    if let Some(submarine) = submarine {
+                        camera.center_on_submarine(submarine.water_grid.size());
+                    }
+                }
+            });
         });
     }
 
@@ -506,6 +1127,15 @@ pub(crate) fn draw_ui(
                     if ui.button("Cancel").clicked() {
                         *current_tool = Tool::Interact;
                     }
+                } else if let Tool::PlacePrefab { position, .. } = current_tool {
+                    if position.is_some() {
+                        ui.label("Left-click to place prefab. Press 'Esc' to cancel. Hold shift to place more prefabs.");
+                    } else {
+                        ui.label("Point at a submarine large enough to fit the prefab. Press 'Esc' to cancel.");
+                    }
+                    if ui.button("Cancel").clicked() {
+                        *current_tool = Tool::Interact;
+                    }
                 } else if let Tool::PlaceSubmarine { .. } = current_tool {
                     ui.label("Left-click to place submarine. Press 'Esc' to cancel.");
                     if ui.button("Cancel").clicked() {
@@ -514,8 +1144,40 @@ pub(crate) fn draw_ui(
                 } else if let Tool::Interact = current_tool {
                     ui.radio_value(current_tool, Tool::Interact, "Interact");
                     ui.radio_value(current_tool, Tool::EditWater { add: true }, "Edit Water");
-                    ui.radio_value(current_tool, Tool::EditWalls { add: true }, "Edit Walls");
-                    ui.radio_value(current_tool, Tool::EditWires { color: WireColor::Brown }, "Edit Wires");
+                    ui.radio_value(
+                        current_tool,
+                        Tool::EditWalls {
+                            add: true,
+                            material: WallMaterial::Normal,
+                        },
+                        "Edit Walls",
+                    );
+                    ui.radio_value(
+                        current_tool,
+                        Tool::EditWires {
+                            color: WireColor::Brown,
+                            auto_route: false,
+                        },
+                        "Edit Wires",
+                    );
+                    ui.radio_value(
+                        current_tool,
+                        Tool::ConnectPorts {
+                            from: None,
+                            warning: None,
+                        },
+                        "Connect Ports",
+                    );
+                    ui.radio_value(current_tool, Tool::Measure, "Measure");
+                    ui.radio_value(current_tool, Tool::Inspect, "Inspect");
+                    ui.radio_value(
+                        current_tool,
+                        Tool::PaintBackground {
+                            color: [64, 64, 64, 255],
+                            brush_size: 1,
+                        },
+                        "Paint Background",
+                    );
                 } else if let Tool::EditWater { add } = current_tool {
                     ui.label("Edit water:");
                     ui.radio_value(add, true, "Add");
@@ -523,20 +1185,67 @@ pub(crate) fn draw_ui(
                     if ui.button("Cancel").clicked() {
                         *current_tool = Tool::Interact
                     }
-                } else if let Tool::EditWalls { add } = current_tool {
+                } else if let Tool::EditWalls { add, material } = current_tool {
                     ui.label("Edit walls:");
                     ui.radio_value(add, true, "Add");
                     ui.radio_value(add, false, "Remove");
+                    if *add {
+                        ui.separator();
+                        ui.radio_value(material, WallMaterial::Normal, "Wall");
+                        ui.radio_value(material, WallMaterial::Glass, "Glass");
+                    }
+                    if ui.button("Cancel").clicked() {
+                        *current_tool = Tool::Interact
+                    }
+                } else if let Tool::Measure = current_tool {
+                    ui.label("Left-click and drag to measure distances in cells.");
+                    if ui.button("Cancel").clicked() {
+                        *current_tool = Tool::Interact
+                    }
+                } else if let Tool::PaintBackground { color, brush_size } = current_tool {
+                    ui.label("Paint background:");
+                    ui.color_edit_button_srgba_unmultiplied(color);
+                    if ui.button("Erase").on_hover_text("Paint with transparency").clicked() {
+                        *color = [0, 0, 0, 0];
+                    }
+                    ui.add(Slider::new(brush_size, 1..=16).text("Brush size"));
+                    if ui.button("Cancel").clicked() {
+                        *current_tool = Tool::Interact
+                    }
+                } else if let Tool::Inspect = current_tool {
+                    ui.label("Left-click an object to inspect it.");
+                    ui.label("Shift+click an object, then the ones it should feed, to bus-connect them.");
+                    if ui.button("Cancel").clicked() {
+                        *current_tool = Tool::Interact
+                    }
+                } else if let Tool::ConnectPorts { from, warning } = current_tool {
+                    ui.label("Connect ports:");
+                    match from {
+                        Some(_) => ui.label("Now click the port to wire it to."),
+                        None => ui.label("Click an output port, then an input port."),
+                    };
+                    if let Some(warning) = warning {
+                        ui.colored_label(Color32::YELLOW, warning.as_str());
+                    }
                     if ui.button("Cancel").clicked() {
                         *current_tool = Tool::Interact
                     }
-                } else if let Tool::EditWires { color } = current_tool {
+                } else if let Tool::EditWires { color, auto_route } = current_tool {
                     ui.label("Edit wires:");
-                    ui.radio_value(color, WireColor::Bundle, "Bundle");
-                    ui.radio_value(color, WireColor::Purple, "Purple");
-                    ui.radio_value(color, WireColor::Brown, "Brown");
-                    ui.radio_value(color, WireColor::Blue, "Blue");
-                    ui.radio_value(color, WireColor::Green, "Green");
+                    for wire_color in &[
+                        WireColor::Bundle,
+                        WireColor::Purple,
+                        WireColor::Brown,
+                        WireColor::Blue,
+                        WireColor::Green,
+                    ] {
+                        let label = wire_color_label(*wire_color, *colorblind_wires, *wire_patterns);
+                        ui.radio_value(color, *wire_color, label);
+                    }
+                    ui.checkbox(auto_route, "Auto-route")
+                        .on_hover_text("Route wires around objects, through their ports only");
+                    ui.label("Ctrl+click to toggle a bridge.")
+                        .on_hover_text("Bridges let a horizontal and a vertical wire of the same color cross without connecting");
                     if ui.button("Cancel").clicked() {
                         *current_tool = Tool::Interact
                     }
@@ -545,57 +1254,754 @@ pub(crate) fn draw_ui(
         });
     }
 
-    if *show_navigation_info {
-        egui::Window::new("Navigation info").show(ctx, |ui| {
+    if *show_systems_overview {
+        show_remembered_window(ctx, "Systems overview", window_positions, |ui| {
             if let Some(submarine) = submarines.get(*current_submarine) {
-                fn add_info(ui: &mut Ui, label: &str, value: (i32, i32)) {
-                    ui.horizontal(|ui| {
-                        ui.label(format!("{}:", label));
-                        ui.colored_label(Color32::YELLOW, value.0.to_string());
-                        ui.label("/".to_string());
-                        ui.colored_label(Color32::YELLOW, value.1.to_string());
-                    });
-                }
+                let (width, height) = submarine.water_grid.size();
 
-                let navigation = &submarine.navigation;
-                add_info(ui, "Speed", navigation.speed);
-                add_info(ui, "Acceleration", navigation.acceleration);
-                add_info(ui, "Target", navigation.target);
-                add_info(ui, "Position", navigation.position);
+                // Fit the whole grid in the panel, keeping its proportions.
+                let scale = (300.0 / width.max(1) as f32).min(200.0 / height.max(1) as f32);
+                let size = vec2(width as f32 * scale, height as f32 * scale);
+                let (rect, response) = ui.allocate_exact_size(size, Sense::click());
 
-                ui.separator();
+                let object_center = |object: &Object| {
+                    let (object_width, object_height) = object_size(&object.object_type);
+                    // Objects are drawn one cell to the right and below their position.
+                    vec2(
+                        object.position.0 as f32 + 1.0 + object_width as f32 / 2.0,
+                        object.position.1 as f32 + 1.0 + object_height as f32 / 2.0,
+                    )
+                };
+                let to_panel = |cell: Vec2| rect.min + cell * scale;
 
-                let nav_control = compute_navigation(navigation);
-                add_info(ui, "Target speed", nav_control.target_speed);
-                add_info(ui, "Target acceleration", nav_control.target_acceleration);
-                add_info(
-                    ui,
-                    "Target engine/pump speed",
-                    nav_control.engine_and_pump_speed,
-                );
-            } else {
-                ui.label("No submarine selected.");
-            }
+                let painter = ui.painter();
+                painter.rect_stroke(rect, 0.0, (1.0, Color32::GRAY));
 
-            if ui.button("Close").clicked() {
-                *show_navigation_info = false;
-            }
-        });
+                for object in &submarine.objects {
+                    let color = if object.powered {
+                        Color32::GREEN
+                    } else {
+                        Color32::DARK_GRAY
+                    };
+                    painter.circle_filled(to_panel(object_center(object)), 3.0, color);
+                }
+
+                let object_at = |pos: Pos2| {
+                    submarine
+                        .objects
+                        .iter()
+                        .map(|object| (object, to_panel(object_center(object)).distance(pos)))
+                        .filter(|(_object, distance)| *distance <= 6.0)
+                        .min_by(|(_, distance1), (_, distance2)| {
+                            distance1.partial_cmp(distance2).unwrap_or(Ordering::Equal)
+                        })
+                        .map(|(object, _distance)| object)
+                };
+
+                let powered = submarine.objects.iter().filter(|o| o.powered).count();
+                ui.label(format!(
+                    "{} of {} objects powered",
+                    powered,
+                    submarine.objects.len()
+                ));
+
+                if let Some(object) = response.hover_pos().and_then(object_at) {
+                    let name = object
+                        .label
+                        .as_deref()
+                        .unwrap_or_else(|| object_type_name(&object.object_type));
+                    let status = if object.powered {
+                        "powered"
+                    } else {
+                        "unpowered"
+                    };
+                    ui.label(format!("{} ({}); click to center on it", name, status));
+                }
+
+                if response.clicked() {
+                    if let Some(object) = response.interact_pointer_pos().and_then(object_at) {
+                        let center = object_center(object);
+                        camera.center_on_cell((center.x, center.y));
+                    }
+                }
+            } else {
+                ui.label("No submarine selected.");
+            }
+
+            if ui.button("Close").clicked() {
+                *show_systems_overview = false;
+            }
+        });
+    }
+
+    if let Some((checked_submarine, networks)) = wiring_check {
+        let mut close_check = false;
+        let mut check_again = false;
+
+        egui::Window::new("Wiring check").show(ctx, |ui| {
+            if networks.is_empty() {
+                ui.label("Every wire is fed by an output.");
+            }
+
+            egui::ScrollArea::from_max_height(300.0).show(ui, |ui| {
+                for network in networks.iter() {
+                    ui.horizontal(|ui| {
+                        let (x, y) = network.cells[0];
+                        ui.label(format!(
+                            "{} wire at {}, {} ({} cells)",
+                            wire_color_label(network.color, *colorblind_wires, false),
+                            x,
+                            y,
+                            network.cells.len()
+                        ));
+
+                        if network.ports == 0 {
+                            ui.colored_label(Color32::GRAY, "not connected");
+                        } else {
+                            ui.colored_label(Color32::YELLOW, "no source");
+                        }
+
+                        if ui.button("Show").clicked() {
+                            *current_submarine = *checked_submarine;
+                            camera.center_on_cell((x as f32 + 0.5, y as f32 + 0.5));
+                            *flashing_wires = Some((*checked_submarine, network.cells.clone()));
+                        }
+                    });
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("Check again").clicked() {
+                    check_again = true;
+                }
+                if ui.button("Close").clicked() {
+                    close_check = true;
+                }
+            });
+        });
+
+        if check_again {
+            if let Some(submarine) = submarines.get(*checked_submarine) {
+                *networks = unfed_wire_networks(submarine);
+                *flashing_wires = None;
+            }
+        }
+
+        if close_check {
+            *wiring_check = None;
+            *flashing_wires = None;
+        }
+    }
+
+    if *show_scenario {
+        show_remembered_window(ctx, "Scenario", window_positions, |ui| {
+            if let Some(scenario) = scenario {
+                ui.heading(&scenario.name);
+
+                for objective in &scenario.objectives {
+                    ui.horizontal(|ui| {
+                        if objective.met {
+                            ui.colored_label(Color32::GREEN, "✔");
+                        } else {
+                            ui.colored_label(Color32::GRAY, "○");
+                        }
+                        ui.label(&objective.description);
+                    });
+                }
+            } else {
+                ui.label("No scenario loaded.");
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.text_edit_singleline(scenario_name);
+                ui.label(".yaml");
+            });
+            ui.horizontal(|ui| {
+                let load_button = Button::new("Load").enabled(!scenario_name.is_empty());
+
+                if ui.add(load_button).clicked() {
+                    match load_scenario_from_file(scenario_name) {
+                        Ok(scenario) => commands.push(Command::LoadScenario {
+                            scenario: Some(scenario),
+                        }),
+                        Err(err) => *error_message = Some(err),
+                    }
+                }
+
+                if ui
+                    .add(Button::new("Stop").enabled(scenario.is_some()))
+                    .clicked()
+                {
+                    commands.push(Command::LoadScenario { scenario: None });
+                }
+
+                if ui.button("Close").clicked() {
+                    *show_scenario = false;
+                }
+            });
+        });
+    }
+
+    if *show_navigation_info {
+        show_remembered_window(ctx, "Navigation info", window_positions, |ui| {
+            if let Some(submarine) = submarines.get(*current_submarine) {
+                fn add_info(ui: &mut Ui, label: &str, value: (i32, i32)) {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{}:", label));
+                        ui.colored_label(Color32::YELLOW, value.0.to_string());
+                        ui.label("/".to_string());
+                        ui.colored_label(Color32::YELLOW, value.1.to_string());
+                    });
+                }
+
+                let navigation = &submarine.navigation;
+                add_info(ui, "Speed", navigation.speed);
+                add_info(ui, "Acceleration", navigation.acceleration);
+                add_info(ui, "Target", navigation.target);
+                add_info(ui, "Position", navigation.position);
+
+                ui.horizontal(|ui| {
+                    ui.label("Teleport to:");
+                    ui.add(egui::DragValue::new(&mut teleport_position.0));
+                    ui.add(egui::DragValue::new(&mut teleport_position.1));
+                    if ui
+                        .button("Here")
+                        .on_hover_text("Use the current position")
+                        .clicked()
+                    {
+                        *teleport_position = navigation.position;
+                    }
+                    if ui
+                        .button("Teleport")
+                        .on_hover_text("Also moves any docked submarines along with it")
+                        .clicked()
+                    {
+                        commands.push(Command::MoveSubmarine {
+                            submarine_id: *current_submarine,
+                            position: *teleport_position,
+                        });
+                    }
+                });
+
+                ui.separator();
+
+                let buoyancy = compute_buoyancy(&submarine.water_grid);
+                for (label, value) in [
+                    ("Weight", buoyancy.weight),
+                    ("Buoyancy", buoyancy.buoyancy),
+                    ("Mass", buoyancy.mass),
+                    ("Vertical acceleration", buoyancy.y_acceleration),
+                ] {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{}:", label));
+                        ui.colored_label(Color32::YELLOW, value.to_string());
+                    });
+                }
+
+                ui.separator();
+
+                let nav_control = compute_navigation(navigation);
+                add_info(ui, "Target speed", nav_control.target_speed);
+                add_info(ui, "Target acceleration", nav_control.target_acceleration);
+                add_info(
+                    ui,
+                    "Target engine/pump speed",
+                    nav_control.engine_and_pump_speed,
+                );
+            } else {
+                ui.label("No submarine selected.");
+            }
+
+            if ui.button("Close").clicked() {
+                *show_navigation_info = false;
+            }
+        });
+    }
+
+    let inspected = inspected_object.as_ref().and_then(|inspected| {
+        let submarine = submarines.get(inspected.submarine)?;
+        let object = submarine.objects.get(inspected.object)?;
+        Some((submarine, object))
+    });
+
+    if let Some((submarine, object)) = inspected {
+        let mut close_inspector = false;
+
+        let inspected_key = inspected_object
+            .as_ref()
+            .map(|inspected| (inspected.submarine, inspected.object));
+
+        if *label_edit_object != inspected_key {
+            *label_edit_object = inspected_key;
+            *label_edit = object.label.clone().unwrap_or_default();
+            *note_edit = object.note.clone().unwrap_or_default();
+        }
+
+        egui::Window::new("Inspector").show(ctx, |ui| {
+            let inspected_object = inspected_object
+                .as_mut()
+                .expect("Only shown for an inspected object");
+
+            ui.horizontal(|ui| {
+                ui.label("Object:");
+                ui.colored_label(Color32::YELLOW, object_type_name(&object.object_type));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Position:");
+                ui.colored_label(
+                    Color32::YELLOW,
+                    format!("{}, {}", object.position.0, object.position.1),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Powered:");
+                ui.colored_label(Color32::YELLOW, object.powered.to_string());
+            });
+            if let Some(flow_rate) = pump_flow_rate(&object.object_type) {
+                // 1024 water in a full cell
+                let updates_per_second = update_settings.updates_per_second() as f32;
+                let cells_per_second = flow_rate as f32 * updates_per_second / 1024.0;
+
+                ui.horizontal(|ui| {
+                    ui.label("Flow:");
+                    ui.colored_label(
+                        Color32::YELLOW,
+                        format!(
+                            "{:+} per update ({:+.1} cells/s)",
+                            flow_rate, cells_per_second
+                        ),
+                    );
+                })
+                .response
+                .on_hover_text(
+                    "Water pumped in, or out if negative, at the pump's current speed; \
+                    a pump without power slows down to a stop",
+                );
+            }
+            if let Some(auto_close) = door_auto_close(&object.object_type) {
+                let mut enabled = auto_close.is_some();
+                let mut timeout = auto_close.unwrap_or(DEFAULT_DOOR_AUTO_CLOSE);
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut enabled, "Auto-close");
+                    ui.add(Slider::new(&mut timeout, 30..=3600).text("updates"));
+                })
+                .response
+                .on_hover_text(
+                    "Close the door by itself after it was left open this long; \
+                    a signal that keeps commanding it open holds it open",
+                );
+
+                let new_auto_close = if enabled { Some(timeout) } else { None };
+                if new_auto_close != auto_close {
+                    commands.push(Command::SetDoorAutoClose {
+                        submarine_id: inspected_object.submarine,
+                        object_id: inspected_object.object,
+                        auto_close: new_auto_close,
+                    });
+                }
+            }
+            if let Some(required_code) = door_code(&object.object_type) {
+                let mut locked = required_code.is_some();
+                let mut code = required_code.unwrap_or(1);
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut locked, "Code lock");
+                    ui.add(Slider::new(&mut code, -128..=127).text("code"));
+                })
+                .response
+                .on_hover_text(
+                    "Only open on this logic value at the first port; \
+                    any other value closes the door and is signalled on the second port",
+                );
+
+                let new_code = if locked { Some(code) } else { None };
+                if new_code != required_code {
+                    commands.push(Command::SetDoorCode {
+                        submarine_id: inspected_object.submarine,
+                        object_id: inspected_object.object,
+                        required_code: new_code,
+                    });
+                }
+            }
+            if let Some(entered) = keypad_code(&object.object_type) {
+                let mut new_entered = entered;
+
+                ui.horizontal(|ui| {
+                    ui.label("Code:");
+                    ui.colored_label(Color32::YELLOW, entered.to_string());
+                });
+                ui.horizontal(|ui| {
+                    for digit in 0..10 {
+                        if ui.button(digit.to_string()).clicked() {
+                            // Start over from the digit once the code would
+                            // no longer fit in a logic value.
+                            let digit = if entered < 0 { -digit } else { digit };
+                            new_entered = entered
+                                .checked_mul(10)
+                                .and_then(|code| code.checked_add(digit))
+                                .unwrap_or(digit);
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("+/-").clicked() {
+                        new_entered = entered.checked_neg().unwrap_or(entered);
+                    }
+                    if ui.button("Clear").clicked() {
+                        new_entered = 0;
+                    }
+                });
+
+                if new_entered != entered {
+                    commands.push(Command::SetKeypadCode {
+                        submarine_id: inspected_object.submarine,
+                        object_id: inspected_object.object,
+                        entered: new_entered,
+                    });
+                }
+            }
+            if let Some(ranges) = signal_map_ranges(&object.object_type) {
+                let ((mut in_low, mut in_high), (mut out_low, mut out_high)) = ranges;
+
+                ui.label("Input range:")
+                    .on_hover_text("The logic values read as the low and high ends");
+                ui.add(Slider::new(&mut in_low, -128..=127).text("low"));
+                ui.add(Slider::new(&mut in_high, -128..=127).text("high"));
+                ui.label("Output range:").on_hover_text(
+                    "What the low and high ends are sent as; \
+                    a high end lower than the low end inverts the signal",
+                );
+                ui.add(Slider::new(&mut out_low, -128..=127).text("low"));
+                ui.add(Slider::new(&mut out_high, -128..=127).text("high"));
+
+                let new_ranges = ((in_low, in_high), (out_low, out_high));
+                if new_ranges != ranges {
+                    commands.push(Command::SetSignalMapRanges {
+                        submarine_id: inspected_object.submarine,
+                        object_id: inspected_object.object,
+                        input: new_ranges.0,
+                        output: new_ranges.1,
+                    });
+                }
+            }
+            ui.horizontal(|ui| {
+                ui.label("Label:");
+                let response = ui.text_edit_singleline(label_edit);
+                let submitted = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+
+                if ui.button("Set").clicked() || submitted {
+                    let label = label_edit.trim();
+                    commands.push(Command::SetObjectLabel {
+                        submarine_id: inspected_object.submarine,
+                        object_id: inspected_object.object,
+                        label: if label.is_empty() {
+                            None
+                        } else {
+                            Some(label.to_string())
+                        },
+                    });
+                }
+            });
+            ui.collapsing("Note", |ui| {
+                ui.add(
+                    TextEdit::multiline(note_edit)
+                        .desired_rows(3)
+                        .hint_text("How this object is meant to be used"),
+                );
+
+                let unchanged = note_edit.trim() == object.note.as_deref().unwrap_or("");
+                if ui
+                    .add(Button::new("Set note").enabled(!unchanged))
+                    .clicked()
+                {
+                    let note = note_edit.trim();
+                    commands.push(Command::SetObjectNote {
+                        submarine_id: inspected_object.submarine,
+                        object_id: inspected_object.object,
+                        note: if note.is_empty() {
+                            None
+                        } else {
+                            Some(note.to_string())
+                        },
+                    });
+                }
+            });
+
+            let ports = object.object_type.wire_ports();
+
+            if !ports.is_empty() {
+                ui.separator();
+                ui.label("Ports:");
+            }
+
+            for (port_index, port) in ports.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let direction = match port.direction {
+                        PortDirection::Input => "in",
+                        PortDirection::Output => "out",
+                    };
+                    ui.label(format!("{} ({})", port.name, direction));
+
+                    if !port_connected(submarine, object, port) {
+                        ui.colored_label(Color32::GRAY, "unconnected");
+                    } else if inspected_object.traced_port == Some(port_index) {
+                        if ui.button("Stop tracing").clicked() {
+                            inspected_object.traced_port = None;
+                            inspected_object.traced_cells = None;
+                        }
+                    } else if ui
+                        .button("Trace")
+                        .on_hover_text("Highlight every wire connected to this port")
+                        .clicked()
+                    {
+                        inspected_object.traced_port = Some(port_index);
+                        inspected_object.traced_cells = None;
+                    }
+                });
+            }
+
+            ui.separator();
+            ui.checkbox(&mut inspected_object.camera_attached, "Attach camera")
+                .on_hover_text("Keep the camera centered on this object while it is inspected");
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Reset")
+                    .on_hover_text("Put the object back in the state it was placed in")
+                    .clicked()
+                {
+                    commands.push(Command::ResetObject {
+                        submarine_id: inspected_object.submarine,
+                        object_id: inspected_object.object,
+                    });
+                }
+
+                if ui.button("Close").clicked() {
+                    close_inspector = true;
+                }
+            });
+        });
+
+        if close_inspector {
+            *inspected_object = None;
+        }
+    } else {
+        // The object or its submarine was removed.
+        *inspected_object = None;
+    }
+
+    let selected = selection.as_ref().and_then(|selection| {
+        let submarine = submarines.get(selection.submarine)?;
+        let first_object = submarine.objects.get(*selection.objects.first()?)?;
+        Some((selection, submarine, first_object))
+    });
+
+    if let Some((selection_ref, submarine, first_object)) = selected {
+        let mut clear_selection = false;
+
+        egui::Window::new("Selection").show(ctx, |ui| {
+            ui.label(format!("{} objects selected.", selection_ref.objects.len()));
+            ui.label(format!(
+                "The {} selected first drives the bus.",
+                object_type_name(&first_object.object_type)
+            ));
+
+            let receiver = selection_ref
+                .objects
+                .get(1)
+                .and_then(|&object_id| submarine.objects.get(object_id));
+
+            let ports = |object: &Object, direction| {
+                let ports = object.object_type.wire_ports().iter().enumerate();
+                ports.filter(move |(_index, port)| port.direction == direction)
+            };
+
+            // Receivers share a port layout, so the second object stands for all
+            let port_pairs = receiver.into_iter().flat_map(|receiver| {
+                ports(first_object, PortDirection::Output).flat_map(move |output| {
+                    ports(receiver, PortDirection::Input).map(move |input| (output, input))
+                })
+            });
+
+            if receiver.is_none() {
+                ui.label("Shift+click the objects it should feed.");
+            }
+
+            for ((output_port, output), (input_port, input)) in port_pairs {
+                if !output.signal.compatible_with(input.signal) {
+                    continue;
+                }
+
+                if ui
+                    .button(format!(
+                        "Bus-connect \"{}\" to \"{}\"",
+                        output.name, input.name
+                    ))
+                    .on_hover_text(
+                        "Wire this output to this input of every other selected object, \
+                        one wire each",
+                    )
+                    .clicked()
+                {
+                    let wires =
+                        plan_bus_wires(submarine, &selection_ref.objects, output_port, input_port);
+
+                    match wires {
+                        Ok(wires) => {
+                            for (color, cells) in wires {
+                                for cell in cells {
+                                    commands.push(Command::Cell {
+                                        cell_command: CellCommand::EditWires { add: true, color },
+                                        cell,
+                                        submarine_id: selection_ref.submarine,
+                                    });
+                                }
+                            }
+                        }
+                        Err(err) => *error_message = Some(err),
+                    }
+                }
+            }
+
+            if ui.button("Clear selection").clicked() {
+                clear_selection = true;
+            }
+        });
+
+        if clear_selection {
+            *selection = None;
+        }
+    } else {
+        // Nothing selected, or the objects or their submarine were removed.
+        *selection = None;
     }
 
     if *show_update_settings {
-        egui::Window::new("Update settings").show(ctx, |ui| {
+        show_remembered_window(ctx, "Update settings", window_positions, |ui| {
+            ui.add(Slider::new(sim_speed, 1..=32).text("Simulation speed"))
+                .on_hover_text("In quarters of the normal speed; set by the server in multiplayer");
+            ui.horizontal(|ui| {
+                for (speed, label) in SIM_SPEED_PRESETS.iter() {
+                    ui.selectable_value(sim_speed, *speed, *label);
+                }
+            });
+            ui.separator();
+
             ui.checkbox(update_water, "Update water");
             ui.vertical(|ui| {
                 ui.set_enabled(*update_water);
                 ui.checkbox(enable_gravity, "Enable gravity");
                 ui.checkbox(enable_inertia, "Enable inertia");
+                ui.add(Slider::new(water_inertia, 0..=15).text("Water inertia"))
+                    .on_hover_text("How much velocity water keeps each update, out of 16");
+                ui.add(Slider::new(water_sloshing, 0..=16).text("Water sloshing"))
+                    .on_hover_text(
+                        "How strongly the submarine's acceleration pushes water around; \
+                        0 for not at all",
+                    );
+                ui.add(Slider::new(water_resolution, 1..=4).text("Water block size"))
+                    .on_hover_text(
+                        "Simulate water in blocks of this many cells per side; faster, \
+                        but blocks with any wall in them stop taking part",
+                    );
+                ui.scope(|ui| {
+                    ui.set_enabled(!*enable_inertia);
+                    ui.add(Slider::new(water_equalization, 0..=16).text("Pressure equalization"))
+                        .on_hover_text(
+                            "How fast connected water settles to an even level each update, \
+                            out of 16; only with inertia disabled",
+                        );
+                });
             });
+            ui.checkbox(update_temperature, "Update temperature")
+                .on_hover_text("Spread heat from reactors and engines through the submarine");
             ui.checkbox(update_wires, "Update wires");
+            ui.add(Slider::new(wire_iterations, 1..=16).text("Wire iterations"))
+                .on_hover_text(
+                    "Steps signals travel through wires on each update; more steps let \
+                    signals cross large circuits without lagging behind",
+                );
+            ui.collapsing("Wire signal decay", |ui| {
+                for (color, decay) in THIN_COLORS.iter().zip(wire_decay.iter_mut()) {
+                    ui.add(Slider::new(decay, 1..=32).text(wire_color_label(
+                        *color,
+                        *colorblind_wires,
+                        false,
+                    )))
+                    .on_hover_text("Signal lost per cell; signals start at 256");
+                }
+                ui.add(Slider::new(max_wire_range, 0..=256).text("Max range"))
+                    .on_hover_text(
+                        "Maximum cells a signal can travel from its source; 0 for no limit",
+                    );
+            });
             ui.checkbox(update_sonar, "Update sonar");
             ui.checkbox(update_objects, "Update objects");
             ui.checkbox(update_position, "Update position");
             ui.checkbox(update_collision, "Update collision");
+            ui.checkbox(collision_response, "Collision response")
+                .on_hover_text("Bounce submarines off whatever they collide with");
+            ui.scope(|ui| {
+                ui.set_enabled(*collision_response);
+                ui.add(Slider::new(collision_restitution, 0..=16).text("Restitution"))
+                    .on_hover_text(
+                        "Out of 16, how much speed a submarine keeps when bouncing off something",
+                    );
+                ui.add(Slider::new(collision_separation, 0..=512).text("Separation"))
+                    .on_hover_text(
+                        "Least speed at which colliding submarines are pushed apart, \
+                        in 1/256 of a 1/16 cell per update",
+                    );
+            });
+
+            ui.separator();
+            ui.add(Slider::new(docking_proximity, 16..=1024).text("Docking proximity"))
+                .on_hover_text("Distance at which docking connectors start pulling, in 1/16 cells");
+            ui.add(Slider::new(docking_snap, 1..=64).text("Docking snap"))
+                .on_hover_text("Distance at which docking connectors connect, in 1/16 cells");
+            ui.add(Slider::new(docking_pull_speed, 1..=16).text("Docking pull speed"));
+            ui.add(Slider::new(radio_range, 256..=65536).text("Radio range"))
+                .on_hover_text("Distance between submarines at which radios still hear each other, in 1/16 cells");
+            ui.add(Slider::new(interaction_cooldown, 0..=60).text("Interaction cooldown"))
+                .on_hover_text(
+                    "Updates after an interaction during which an object ignores further ones, \
+                    so that players clicking the same control at once don't make it flip-flop",
+                );
+            ui.add(Slider::new(max_submarines, 1..=256).text("Max submarines"))
+                .on_hover_text("Creating more submarines than this is refused, to save memory");
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.text_edit_singleline(update_settings_name);
+                ui.label(".yaml");
+            });
+            ui.horizontal(|ui| {
+                let enabled = !update_settings_name.is_empty() && !cfg!(target_arch = "wasm32");
+
+                if ui
+                    .add(Button::new("Save").enabled(enabled))
+                    .on_disabled_hover_text("Saving files is not available on browsers")
+                    .clicked()
+                {
+                    *error_message =
+                        save_update_settings_to_file(update_settings_name, update_settings).err();
+                }
+
+                if ui
+                    .add(Button::new("Load").enabled(enabled))
+                    .on_disabled_hover_text("Loading files is not available on browsers")
+                    .clicked()
+                {
+                    match load_update_settings_from_file(update_settings_name) {
+                        Ok(settings) => loaded_update_settings = Some(settings),
+                        Err(err) => *error_message = Some(err),
+                    }
+                }
+            });
 
             if ui.button("Close").clicked() {
                 *show_update_settings = false;
@@ -603,23 +2009,139 @@ pub(crate) fn draw_ui(
         });
     }
 
+    if *show_console {
+        show_remembered_window(ctx, "Console", window_positions, |ui| {
+            egui::ScrollArea::from_max_height(300.0)
+                .stick_to_bottom()
+                .show(ui, |ui| {
+                    for line in console_output.iter() {
+                        ui.monospace(line);
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.label(">");
+                let response = ui.add(TextEdit::singleline(console_input).hint_text("help"));
+                let submitted = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+
+                if (ui.button("Run").clicked() || submitted) && !console_input.trim().is_empty() {
+                    let line = std::mem::take(console_input);
+                    console_output.push(format!("> {}", line.trim()));
+
+                    match run_console_command(&line, state, submarine_templates, commands) {
+                        Ok(output) => console_output.extend(output),
+                        Err(err) => console_output.push(err),
+                    }
+
+                    let excess = console_output.len().saturating_sub(CONSOLE_MAX_LINES);
+                    console_output.drain(..excess);
+
+                    // Keep typing without clicking back into the input
+                    response.request_focus();
+                }
+            });
+
+            if ui.button("Close").clicked() {
+                *show_console = false;
+            }
+        });
+    }
+
     if *show_draw_settings {
-        egui::Window::new("Draw settings").show(ctx, |ui| {
+        show_remembered_window(ctx, "Draw settings", window_positions, |ui| {
             ui.checkbox(draw_egui, "Draw egui widgets")
                 .on_hover_text("Click the top-left gear button to re-enable the UI");
             ui.checkbox(draw_sea_dust, "Draw sea dust");
             ui.checkbox(draw_sea_caustics, "Draw sea caustics");
+            ui.add(Slider::new(sea_dust_density, 0.0..=1.0).text("Sea dust density"));
+            ui.checkbox(precise_sea_dust, "Precise sea dust")
+                .on_hover_text(
+                    "Fixes sea dust specks looking much larger on some phones and browsers",
+                );
             ui.checkbox(draw_rocks, "Draw rocks");
             ui.checkbox(draw_background, "Draw background");
             ui.checkbox(draw_objects, "Draw objects");
+            ui.checkbox(draw_object_labels, "Draw object labels");
+            ui.checkbox(draw_ports, "Draw ports")
+                .on_hover_text("Green dots for inputs, orange dots for outputs");
             ui.checkbox(draw_walls, "Draw walls");
             ui.checkbox(draw_wires, "Draw wires");
+            ui.checkbox(colorblind_wires, "Colorblind-safe wire colors");
+            ui.checkbox(wire_patterns, "Draw wire patterns")
+                .on_hover_text("Draw each wire color with its own dashed or dotted pattern");
+            ui.checkbox(animate_signals, "Animate wire signals")
+                .on_hover_text("Show which way signals flow by sending pulses along lit wires");
+            ui.checkbox(smooth_wires, "Smooth wires")
+                .on_hover_text("Anti-alias the edges of wires, so they look clean when zoomed in");
+            ui.checkbox(wires_above_objects, "Draw wires above objects");
             ui.checkbox(draw_water, "Draw water");
+            ui.checkbox(smooth_water, "Smooth water")
+                .on_hover_text("Blend the water levels of neighbouring cells, instead of drawing a square per cell");
             ui.checkbox(draw_sonar, "Draw sonar");
             ui.checkbox(draw_engine_turbulence, "Draw engine turbulence");
+            ui.add(
+                Slider::new(max_turbulence_particles, 100..=10000).text("Max turbulence particles"),
+            )
+            .on_hover_text("Per submarine; the oldest particles fade out early past this");
             ui.checkbox(draw_shadows, "Draw shadows");
+            ui.add(Slider::new(depth_darkening, 0.0..=1.0).text("Depth darkening"))
+                .on_hover_text("How much darker the sea gets towards the bottom of the world");
+            ui.add(Slider::new(day_length, 0.0..=600.0).text("Day length"))
+                .on_hover_text("Seconds per day and night cycle; 0 keeps it always day");
+            ui.checkbox(outline_current_submarine, "Outline current submarine");
+            ui.checkbox(solo_inspected_object, "Solo inspected object")
+                .on_hover_text("Dim the rest of the submarine around the object being inspected");
+            ui.checkbox(camera_momentum, "Camera momentum")
+                .on_hover_text("Keep the camera coasting for a bit after a quick drag");
+            ui.add(Slider::new(camera_pan_speed, 10.0..=240.0).text("Keyboard pan speed"))
+                .on_hover_text("Cells per second when panning with WASD or the arrow keys");
+            ui.horizontal(|ui| {
+                ui.checkbox(invert_pan_x, "Invert panning X");
+                ui.checkbox(invert_pan_y, "Invert panning Y");
+            })
+            .response
+            .on_hover_text("Move the view the other way when dragging or using the keyboard");
+            ui.add(Slider::new(interact_radius, 0.0..=24.0).text("Interact radius"))
+                .on_hover_text("Pixels around an object where clicks still reach it");
 
             ui.checkbox(debug_shadows, "Debug shadows");
+            ui.checkbox(debug_cell_types, "Debug cell types")
+                .on_hover_text(
+                    "Inside: green, sea: blue, wall: white, glass: cyan, invisible wall: magenta",
+                );
+            ui.checkbox(trace_leaks, "Trace leaks").on_hover_text(
+                "Show the openings and pumps that water reaches the flooding cell under the \
+                cursor from, and the way it takes",
+            );
+            ui.checkbox(skip_offscreen_submarines, "Skip off-screen submarines")
+                .on_hover_text("Don't draw or animate submarines that are far outside the view");
+            ui.checkbox(level_of_detail, "Zoom level of detail")
+                .on_hover_text("Simplify water and hide wires when zoomed far out");
+            ui.checkbox(simplified_objects, "Simplified objects when zoomed out")
+                .on_hover_text("Draw objects as boxes colored by category, bright while active");
+
+            ui.horizontal(|ui| {
+                ui.label("Layer hotkeys:");
+                for (name, option) in LAYER_HOTKEYS {
+                    ui.radio_value(layer_hotkeys, *option, *name);
+                }
+            })
+            .response
+            .on_hover_text(format!(
+                "Toggle drawing {}, in that order; the last key toggles all of them",
+                DRAW_LAYER_NAMES.join(", "),
+            ));
+            if ui.button("Toggle all layers").clicked() {
+                toggle_all_draw_layers([
+                    &mut *draw_rocks,
+                    &mut *draw_background,
+                    &mut *draw_objects,
+                    &mut *draw_walls,
+                    &mut *draw_wires,
+                    &mut *draw_water,
+                    &mut *draw_sonar,
+                ]);
+            }
 
             if ui.button("Close").clicked() {
                 *show_draw_settings = false;
@@ -628,7 +2150,7 @@ pub(crate) fn draw_ui(
     }
 
     if *show_timings {
-        egui::Window::new("Timings").show(ctx, |ui| {
+        show_remembered_window(ctx, "Timings", window_positions, |ui| {
             let mut show_timer = |name: &str, value: u32| {
                 ui.horizontal(|ui| {
                     ui.label(format!("{}:", name));
@@ -651,6 +2173,29 @@ pub(crate) fn draw_ui(
             show_timer("FPS", timings.fps);
             show_timer("FPS average", timings.fps_average);
 
+            if !cfg!(target_arch = "wasm32") {
+                ui.collapsing("Submarine updates", |ui| {
+                    for (sub_index, sub_timings) in timings.submarines.iter().enumerate() {
+                        ui.label(format!("Submarine {}:", sub_index));
+                        ui.horizontal(|ui| {
+                            for (name, value) in [
+                                ("water", sub_timings.water),
+                                ("wires", sub_timings.wires),
+                                ("objects", sub_timings.objects),
+                                ("sonar", sub_timings.sonar),
+                            ] {
+                                ui.label(format!("{}:", name));
+                                ui.add(
+                                    Label::new(format!("{:5}", value))
+                                        .text_color(Color32::GREEN)
+                                        .monospace(),
+                                );
+                            }
+                        });
+                    }
+                });
+            }
+
             ui.collapsing("Graphs", |ui| {
                 let first_timing = timings.fps_history.front().map(|(x, _y)| *x).unwrap_or(0.0);
 
@@ -709,8 +2254,35 @@ pub(crate) fn draw_ui(
             });
     }
 
+    if let Some(notice) = teammate_notice {
+        let mut close_notice = false;
+
+        egui::Window::new("Teammate activity")
+            .anchor(Align2::RIGHT_BOTTOM, vec2(-10.0, -10.0))
+            .show(ctx, |ui| {
+                ui.label(&notice.description);
+                if notice.more > 0 {
+                    ui.colored_label(Color32::GRAY, format!("...and {} more since.", notice.more));
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Jump to").clicked() {
+                        camera.glide_to(notice.position);
+                        close_notice = true;
+                    }
+                    if ui.button("Dismiss").clicked() {
+                        close_notice = true;
+                    }
+                });
+            });
+
+        if close_notice {
+            *teammate_notice = None;
+        }
+    }
+
     if *show_help {
-        egui::Window::new("Cybersub prototype").show(ctx, |ui| {
+        show_remembered_window(ctx, "Cybersub prototype", window_positions, |ui| {
             egui::ScrollArea::from_max_height(300.0).show(ui, |ui| {
                 ui.label("This is a water simulation prototype used in the context of a game heavily inspired by Barotrauma.");
                 ui.label("Zoom in on the sonar and click inside it to set a nagivation target; for now there's not much else to do.");
@@ -728,12 +2300,14 @@ pub(crate) fn draw_ui(
                 ui.label(
                     "Regardless of the selected tool, you can use WASD, arrow keys, or hold the right mouse button to move camera."
                 );
+                ui.label("Press Home to center the camera back on the current submarine.");
+                ui.label("Press 1, 2 or 3 to zoom to fit the submarine, to 1:1 pixels, or out to the whole world.");
                 ui.label(
                     "Use the tool controls (Add Water, Add Walls, etc) at the bottom to switch what left-click does."
                 );
                 ui.label(
-                    "On some devices, the sea dust shader is acting very strangely, with the dust specs looking much larger. \
-                    This is related to the float precision (highp vs lowp). It can be fixed, just need to figure out how."
+                    "On some devices, the sea dust specs can look much larger, due to the float precision (highp vs mediump) \
+                    of their shaders. 'Precise sea dust' in the draw settings fixes this, and is on by default."
                 );
                 ui.label(
                     "If you're getting low FPS, disable the caustics shader and/or updating water. I plan to revamp \
@@ -747,9 +2321,75 @@ pub(crate) fn draw_ui(
         });
     }
 
-    if new_update_settings != *update_settings {
+    // Loaded settings go through the same command as any other change, so that
+    // networked clients get them too.
+    if let Some(update_settings) = loaded_update_settings {
+        commands.push(Command::ChangeUpdateSettings { update_settings });
+    } else if new_update_settings != *update_settings {
         commands.push(Command::ChangeUpdateSettings {
             update_settings: new_update_settings,
         });
     }
+
+    // Saved once windows are let go of, rather than on every frame of a drag
+    if !ctx.input().pointer.any_down() {
+        ui_state.save_window_layout();
+    }
+}
+
+/// Show a window where it was last left, even in a previous session, and
+/// remember where it is now.
+fn show_remembered_window<R>(
+    ctx: &egui::CtxRef,
+    title: &str,
+    window_positions: &mut BTreeMap<String, (f32, f32)>,
+    add_contents: impl FnOnce(&mut Ui) -> R,
+) {
+    let mut window = egui::Window::new(title);
+
+    // Only used when egui doesn't already know where the window is, which is
+    // the first time it's shown.
+    if let Some(&(x, y)) = window_positions.get(title) {
+        let screen = ctx.input().screen_rect();
+        let x = x.min(screen.max.x - MIN_VISIBLE_WINDOW).max(screen.min.x);
+        let y = y.min(screen.max.y - MIN_VISIBLE_WINDOW).max(screen.min.y);
+        window = window.default_pos(Pos2::new(x, y));
+    }
+
+    if let Some(response) = window.show(ctx, add_contents) {
+        let position = response.response.rect.min;
+        let position = (position.x, position.y);
+
+        if window_positions.get(title) != Some(&position) {
+            window_positions.insert(title.to_string(), position);
+        }
+    }
+}
+
+/// Label for a wire color, named after the color it is drawn with in the
+/// current palette (see wires.glsl); with patterns enabled, the label also
+/// shows the pattern that color is drawn with, so it can be told apart without
+/// relying on hue alone.
+fn wire_color_label(color: WireColor, colorblind: bool, patterns: bool) -> String {
+    let name = match (color, colorblind) {
+        (WireColor::Bundle, _) => "Bundle",
+        (WireColor::Purple, false) => "Purple",
+        (WireColor::Brown, false) => "Brown",
+        (WireColor::Blue, false) => "Blue",
+        (WireColor::Green, false) => "Green",
+        (WireColor::Purple, true) => "Orange",
+        (WireColor::Brown, true) => "Sky blue",
+        (WireColor::Blue, true) => "Yellow",
+        (WireColor::Green, true) => "Reddish purple",
+    };
+
+    let pattern = match (color, patterns) {
+        (WireColor::Bundle, _) | (_, false) => "",
+        (WireColor::Purple, true) => " (solid)",
+        (WireColor::Brown, true) => " (- -)",
+        (WireColor::Blue, true) => " (...)",
+        (WireColor::Green, true) => " (-- --)",
+    };
+
+    format!("{}{}", name, pattern)
 }
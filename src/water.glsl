@@ -1,6 +1,12 @@
 #version 100
 
+// Not every device has highp in fragment shaders; the dust coordinates are
+// kept small enough (see dust_origin) that mediump is enough for them.
+#ifdef GL_FRAGMENT_PRECISION_HIGH
 precision highp float;
+#else
+precision mediump float;
+#endif
 
 varying vec2 uv;
 
@@ -10,8 +16,12 @@ uniform sampler2D sea_dust;
 uniform vec2 time_offset;
 uniform vec2 camera_offset;
 uniform float time;
-uniform vec2 world_size;
-uniform vec2 sea_dust_size;
+uniform float ambient;
+uniform float dust_density;
+// Dust texture coordinates at the top-left corner of the drawn rectangle,
+// wrapped to 0-1, and how many dust tiles the rectangle spans.
+uniform vec2 dust_origin;
+uniform vec2 dust_scale;
 
 
 #define TAU 6.28318530718
@@ -23,10 +33,10 @@ uniform vec2 sea_dust_size;
 vec4 caustics(vec2 dust_uv) {
 	float scaled_time = time * 0.1 + 23.0;
     // uv should be the 0-1 uv of texture...
-	highp vec2 uv = dust_uv;
+	vec2 uv = dust_uv;
     
-    highp vec2 p = mod(uv*TAU, TAU)-250.0;
-	highp vec2 i = vec2(p);
+    vec2 p = mod(uv*TAU, TAU)-250.0;
+	vec2 i = vec2(p);
 	float c = 1.0;
 	float inten = .005;
 
@@ -47,8 +57,7 @@ vec4 caustics(vec2 dust_uv) {
 void main() {
 	vec4 dust_color = vec4(0.0, 0.0, 0.0, 1.0);
 	vec4 caustics_color = vec4(0.0, 0.0, 0.0, 0.0);
-	highp vec2 uv = uv;
-	vec2 dust_uv = fract(uv * sea_dust_size / world_size);
+	vec2 dust_uv = fract(dust_origin + uv * dust_scale);
 
 	if (enable_caustics == 1.0) {
 		caustics_color = caustics(fract(dust_uv + time_offset / 3.0)) * 0.3;
@@ -59,9 +68,16 @@ void main() {
 		vec4 b = texture2D(sea_dust, fract(dust_uv + time_offset / 2.0 + camera_offset * 1.5).yx);
 		vec4 c = texture2D(sea_dust, fract(-(dust_uv + time_offset / 3.0 + camera_offset * 2.0)));
 
+		// Layers fade in one after the other as the density goes up
+		float layers = dust_density * 3.0;
+		a *= clamp(layers, 0.0, 1.0);
+		b *= clamp(layers - 1.0, 0.0, 1.0);
+		c *= clamp(layers - 2.0, 0.0, 1.0);
+
 		dust_color = max(max(a, b), c);
 	}
 
 	vec4 background_color = vec4(0.0235, 0.0235, 0.1255, 0.0);
-	gl_FragColor = background_color + dust_color + caustics_color;
+	vec4 sea_color = background_color + dust_color + caustics_color;
+	gl_FragColor = vec4(sea_color.rgb * ambient, sea_color.a);
 }
@@ -1,42 +1,143 @@
 #version 100
 
-precision lowp float;
+// Smoothing needs positions within single texels of the whole wire texture,
+// which lowp can't tell apart.
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
+precision mediump float;
+#endif
 
-varying lowp vec2 uv;
+varying vec2 uv;
 
 uniform vec2 grid_size;
+uniform float colorblind;
+uniform float patterns;
+uniform float animate_signals;
+uniform float smooth_wires;
+uniform float pixels_per_texel;
+uniform mediump float flow_time;
 uniform sampler2D sub_wires;
 uniform sampler2D sub_signals;
+uniform sampler2D sub_signal_flow;
+
+// Returns 1.0 on the "gap" pixels of a wire's pattern, 0.0 elsewhere.
+// Pixels are counted diagonally so that the same pattern shows up along both
+// horizontal and vertical wires.
+float pattern_gap(float dash_length, float gap_length) {
+    vec2 pixel = floor(uv * grid_size * 6.0);
+    float position = mod(pixel.x + pixel.y, dash_length + gap_length);
+    return step(dash_length, position);
+}
+
+// Brightness of the signal flow pulse, from -1.0 to 1.0, given the raw signal
+// strength of the cell. Signals get weaker away from their source, so as time
+// passes, pulses move towards weaker signals. One stripe every 8 signal
+// strength, which divides the 256 at which the strength wraps around.
+float flow_pulse(mediump float flow) {
+    mediump float phase = fract(flow * 255.0 / 8.0 + flow_time);
+    return sin(phase * 6.2832);
+}
+
+vec4 wire_texel_at(vec2 texel) {
+    return texture2D(sub_wires, (texel + 0.5) / (grid_size * 6.0));
+}
 
 void main() {
-    vec4 wire_texel = texture2D(sub_wires, uv);
+    vec2 texel = floor(uv * grid_size * 6.0);
+    vec4 wire_texel = wire_texel_at(texel);
+    float coverage = 1.0;
+
+    if (smooth_wires > 0.5) {
+        // Blend whether each of the 4 nearest texels has a wire, and sharpen
+        // that so the edge is about a pixel wide. Colors themselves are never
+        // blended, since wires are told apart by their exact color below.
+        vec2 position = uv * grid_size * 6.0 - 0.5;
+        vec2 base = floor(position);
+        vec2 weight = position - base;
+
+        vec4 texel00 = wire_texel_at(base);
+        vec4 texel10 = wire_texel_at(base + vec2(1.0, 0.0));
+        vec4 texel01 = wire_texel_at(base + vec2(0.0, 1.0));
+        vec4 texel11 = wire_texel_at(base + vec2(1.0, 1.0));
+
+        float blended = mix(
+            mix(step(0.5, texel00.a), step(0.5, texel10.a), weight.x),
+            mix(step(0.5, texel01.a), step(0.5, texel11.a), weight.x),
+            weight.y
+        );
+        coverage = clamp((blended - 0.5) * max(pixels_per_texel, 1.0) + 0.5, 0.0, 1.0);
+
+        // Just outside a wire, fade out with the color of the wire next to it
+        if (wire_texel.a < 0.5) {
+            if (texel00.a >= 0.5) {
+                texel = base;
+            } else if (texel10.a >= 0.5) {
+                texel = base + vec2(1.0, 0.0);
+            } else if (texel01.a >= 0.5) {
+                texel = base + vec2(0.0, 1.0);
+            } else {
+                texel = base + vec2(1.0, 1.0);
+            }
+            wire_texel = wire_texel_at(texel);
+        }
+    }
+
+    // Signals are sampled at the middle of the wire texel's cell, so that
+    // they never bleed into the cells next to it.
+    vec2 cell_uv = (floor(texel / 6.0) + 0.5) / grid_size;
+
     vec3 wire_color = wire_texel.rgb;
-    vec4 wire_signals = texture2D(sub_signals, uv);
+    vec4 wire_signals = texture2D(sub_signals, cell_uv);
+    mediump vec4 wire_flow = texture2D(sub_signal_flow, cell_uv);
 
     vec3 color;
+    vec3 colorblind_color;
+    float gap;
     float signal;
+    mediump float flow;
 
     if (wire_color == vec3(1.0, 1.0, 0.0)) {
-        // Purple
+        // Purple; solid
         color = vec3(0.3333, 0.0431, 0.2588);
+        colorblind_color = vec3(0.6, 0.33, 0.0);
+        gap = 0.0;
         signal = wire_signals.r;
+        flow = wire_flow.r;
     } else if (wire_color == vec3(0.0, 1.0, 1.0)) {
-        // Brown
+        // Brown; dashed
         color = vec3(0.22, 0.07, 0.03);
+        colorblind_color = vec3(0.2, 0.45, 0.6);
+        gap = pattern_gap(4.0, 2.0);
         signal = wire_signals.g;
+        flow = wire_flow.g;
     } else if (wire_color == vec3(0.0, 0.0, 1.0)) {
-        // Blue
+        // Blue; dotted
         color = vec3(0.1, 0.1, 0.4);
+        colorblind_color = vec3(0.6, 0.57, 0.13);
+        gap = pattern_gap(1.0, 1.0);
         signal = wire_signals.b;
+        flow = wire_flow.b;
     } else if (wire_color == vec3(0.0, 1.0, 0.0)) {
-        // Green
+        // Green; long dashes
         color = vec3(0.1, 0.4, 0.1);
+        colorblind_color = vec3(0.5, 0.25, 0.4);
+        gap = pattern_gap(8.0, 2.0);
         signal = wire_signals.a;
+        flow = wire_flow.a;
     } else {
         // Bundle wire, or nothing at all
-        gl_FragColor = wire_texel;
+        gl_FragColor = vec4(wire_texel.rgb, wire_texel.a * coverage);
         return;
     }
 
-    gl_FragColor = vec4(color + vec3(0.2, 0.2, 0.2) * signal, 1.0);
+    color = mix(color, colorblind_color, colorblind);
+
+    // Light up the wire first, and only then darken the gaps, so that lit
+    // wires keep the same pattern as unlit ones.
+    signal = signal * (1.0 + 0.5 * flow_pulse(flow) * animate_signals);
+    color = color + vec3(0.2, 0.2, 0.2) * signal;
+    color = color * (1.0 - 0.6 * gap * patterns);
+
+    gl_FragColor = vec4(color, coverage);
 }